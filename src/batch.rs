@@ -0,0 +1,446 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, OpenOptions},
+    io::Write,
+    mem,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use color_eyre::{
+    eyre::{bail, eyre, Context, ContextCompat},
+    Result,
+};
+use reqwest::{
+    multipart::{Form, Part},
+    Client,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{
+    sync::mpsc::Sender,
+    time::{self, Instant},
+};
+
+use crate::{
+    journal::Journal,
+    scheduler::{Job, Translation},
+    translator::{
+        request_id, with_request_id, Completion, Error, Request, Response, Sampling,
+        COMPLETIONS_PATH,
+    },
+    warnings::DedupLogger,
+};
+
+/// The longest the poll interval is allowed to grow to.
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug)]
+pub struct Options {
+    pub poll_interval: Duration,
+    pub timeout: Duration,
+    pub request_id_prefix: Option<String>,
+    /// What the files and batches endpoints are relative to, like `https://api.openai.com/v1`.
+    pub api_base: String,
+    /// The worksheet the jobs are from.
+    pub sheet: String,
+    /// Where the batch is recorded instead of waited for, with `--batch-detach`.
+    pub detach: Option<PathBuf>,
+    pub sampling: Sampling,
+}
+
+/// A batch submitted with `--batch-detach`, to be fetched later with `batch fetch`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Submitted {
+    id: String,
+    sheet: String,
+    /// The keys of the values, by the custom ids of their requests.
+    keys: Vec<String>,
+    request_id: Option<String>,
+}
+
+/// One line of the uploaded JSONL file.
+#[derive(Debug, Serialize)]
+struct BatchRequest<'a> {
+    custom_id: String,
+    method: &'static str,
+    url: &'static str,
+    body: &'a Request,
+}
+
+/// One line of the output file of a completed batch.
+#[derive(Debug, Deserialize)]
+struct BatchResponse {
+    custom_id: String,
+    response: Option<BatchResponseBody>,
+    error: Option<Error>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchResponseBody {
+    body: Response,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchStatus {
+    id: String,
+    status: String,
+    output_file_id: Option<String>,
+    request_counts: Option<RequestCounts>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RequestCounts {
+    total: usize,
+    completed: usize,
+    failed: usize,
+}
+
+impl BatchStatus {
+    fn is_finished(&self) -> bool {
+        matches!(
+            self.status.as_str(),
+            "completed" | "failed" | "expired" | "cancelled"
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct FileObject {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ApiResponse<T> {
+    Ok(T),
+    Err { error: Error },
+}
+
+async fn parse<T: DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+    match response.json::<ApiResponse<T>>().await? {
+        ApiResponse::Ok(value) => Ok(value),
+        ApiResponse::Err { error } => bail!("{}", error.message),
+    }
+}
+
+/// Where the batches submitted by a run writing to `destination` are recorded.
+pub fn path(destination: &Path) -> PathBuf {
+    let mut path = destination.as_os_str().to_owned();
+    path.push(".batches");
+    PathBuf::from(path)
+}
+
+/// The batches recorded in `path`, one JSON object per line.
+pub fn load(path: &Path) -> Result<Vec<Submitted>> {
+    let text = fs::read_to_string(path)
+        .wrap_err_with(|| format!("No batches were submitted for {}", path.display()))?;
+
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).wrap_err("Invalid batch record"))
+        .collect()
+}
+
+fn append(path: &Path, submitted: &Submitted) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+
+    writeln!(file, "{}", serde_json::to_string(submitted)?)?;
+
+    Ok(())
+}
+
+async fn status(
+    client: &Client,
+    api_base: &str,
+    id: &str,
+    request_id: Option<&str>,
+) -> Result<BatchStatus> {
+    parse(
+        with_request_id(
+            client.get(format!("{}/batches/{}", api_base, id)),
+            request_id,
+        )
+        .send()
+        .await?,
+    )
+    .await
+    .wrap_err("Failed to poll the batch status")
+}
+
+/// The completions of a finished batch by the custom ids of their requests.
+async fn results(
+    client: &Client,
+    api_base: &str,
+    batch: &BatchStatus,
+    request_id: Option<&str>,
+) -> Result<Vec<(usize, Result<Completion>)>> {
+    // Expired batches still deliver whatever had been completed until then.
+    let Some(output_file_id) = &batch.output_file_id else {
+        bail!("Batch {} {} without output", batch.id, batch.status);
+    };
+
+    let output = with_request_id(
+        client.get(format!("{}/files/{}/content", api_base, output_file_id)),
+        request_id,
+    )
+    .send()
+    .await?
+    .error_for_status()?
+    .text()
+    .await?;
+
+    let mut results = vec![];
+
+    for line in output.lines().filter(|l| !l.trim().is_empty()) {
+        let response: BatchResponse = serde_json::from_str(line)?;
+        let i: usize = response.custom_id.parse()?;
+
+        let completion = match (response.response, response.error) {
+            (_, Some(error)) => Err(eyre!(error.message)),
+            (Some(response), None) => response.body.into_completion(),
+            (None, None) => Err(eyre!("Empty batch response")),
+        };
+
+        results.push((i, completion));
+    }
+
+    Ok(results)
+}
+
+/// Prints the status of every batch recorded in `path`.
+pub async fn print_status(
+    path: &Path,
+    client: &Client,
+    api_base: &str,
+    request_id_prefix: Option<&str>,
+) -> Result<()> {
+    for submitted in load(path)? {
+        let id = request_id(request_id_prefix);
+        let batch = status(client, api_base, &submitted.id, id.as_deref()).await?;
+
+        match batch.request_counts {
+            Some(counts) => eprintln!(
+                "Batch {} of sheet {}: {}, {} of {} requests done, {} failed",
+                batch.id,
+                submitted.sheet,
+                batch.status,
+                counts.completed,
+                counts.total,
+                counts.failed
+            ),
+            None => eprintln!(
+                "Batch {} of sheet {}: {}",
+                batch.id, submitted.sheet, batch.status
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Records the translations of the finished batches of `path` in the journal, for a run with
+/// `--resume` to write, and forgets about those batches. Returns how many translations there
+/// were and how many batches are still running.
+pub async fn fetch(
+    path: &Path,
+    journal: &mut Journal,
+    client: &Client,
+    api_base: &str,
+    request_id_prefix: Option<&str>,
+) -> Result<(usize, usize)> {
+    let mut running = vec![];
+    let mut translations = 0;
+
+    for submitted in load(path)? {
+        let id = || request_id(request_id_prefix);
+        let batch = status(client, api_base, &submitted.id, id().as_deref()).await?;
+
+        if !batch.is_finished() {
+            running.push(submitted);
+            continue;
+        }
+
+        for (i, completion) in results(client, api_base, &batch, id().as_deref()).await? {
+            let key = submitted.keys.get(i).wrap_err("Unknown batch request id")?;
+
+            match completion {
+                Ok(mut completion) => {
+                    completion.request_id = submitted.request_id.clone();
+                    journal.record(&submitted.sheet, key, &completion)?;
+                    translations += 1;
+                }
+                Err(e) => eprintln!("Batch {}: {:#}", batch.id, e),
+            }
+        }
+    }
+
+    if running.is_empty() {
+        fs::remove_file(path)?;
+    } else {
+        let mut text = String::new();
+
+        for submitted in &running {
+            text.push_str(&serde_json::to_string(submitted)?);
+            text.push('\n');
+        }
+
+        fs::write(path, text)?;
+    }
+
+    Ok((translations, running.len()))
+}
+
+/// Submits the jobs as a single batch, waits for it to finish and sends the translations
+/// through `tx`. Jobs that got translated are removed from `jobs`, so whatever is left when
+/// this returns (because of a timeout, a failed batch or failed requests) still has to be
+/// translated through the regular API.
+pub async fn run(
+    jobs: &mut Vec<Job>,
+    options: &Options,
+    client: &Client,
+    tx: &Sender<Translation>,
+    log: &DedupLogger,
+) -> Result<()> {
+    if jobs.is_empty() {
+        return Ok(());
+    }
+
+    let mut input = String::new();
+
+    for (i, job) in jobs.iter().enumerate() {
+        let request = BatchRequest {
+            custom_id: i.to_string(),
+            method: "POST",
+            url: COMPLETIONS_PATH,
+            body: &Request::new(
+                job.prompt.clone(),
+                job.temperature,
+                &job.model,
+                &options.sampling,
+            )?,
+        };
+
+        input.push_str(&serde_json::to_string(&request)?);
+        input.push('\n');
+    }
+
+    let form = Form::new().text("purpose", "batch").part(
+        "file",
+        Part::bytes(input.into_bytes()).file_name("batch.jsonl"),
+    );
+
+    let id = || request_id(options.request_id_prefix.as_deref());
+
+    let file: FileObject = parse(
+        with_request_id(
+            client
+                .post(format!("{}/files", options.api_base))
+                .multipart(form),
+            id().as_deref(),
+        )
+        .send()
+        .await?,
+    )
+    .await
+    .wrap_err("Failed to upload the batch input")?;
+
+    // The completions of the batch are attributed to the request that created it.
+    let batch_request_id = id();
+
+    let mut batch: BatchStatus = parse(
+        with_request_id(
+            client
+                .post(format!("{}/batches", options.api_base))
+                .json(&serde_json::json!({
+                    "input_file_id": file.id,
+                    "endpoint": COMPLETIONS_PATH,
+                    "completion_window": "24h",
+                })),
+            batch_request_id.as_deref(),
+        )
+        .send()
+        .await?,
+    )
+    .await
+    .wrap_err("Failed to create the batch")?;
+
+    if let Some(path) = &options.detach {
+        let submitted = Submitted {
+            id: batch.id.clone(),
+            sheet: options.sheet.clone(),
+            keys: jobs.iter().map(|job| job.key.clone()).collect(),
+            request_id: batch_request_id,
+        };
+
+        append(path, &submitted)?;
+        log.println(format!("Submitted batch {}, not waiting for it", batch.id));
+        jobs.clear();
+
+        return Ok(());
+    }
+
+    log.println(format!("Submitted batch {}", batch.id));
+
+    let deadline = Instant::now() + options.timeout;
+    let mut poll_interval = options.poll_interval;
+
+    loop {
+        if batch.is_finished() {
+            break;
+        }
+
+        if Instant::now() >= deadline {
+            log.println(format!("Batch {} timed out, cancelling it", batch.id));
+
+            with_request_id(
+                client.post(format!("{}/batches/{}/cancel", options.api_base, batch.id)),
+                id().as_deref(),
+            )
+            .send()
+            .await?;
+
+            return Ok(());
+        }
+
+        time::sleep(poll_interval.min(deadline - Instant::now())).await;
+        poll_interval = (poll_interval * 2).min(MAX_POLL_INTERVAL);
+
+        let previous = batch.status;
+        batch = status(client, &options.api_base, &batch.id, id().as_deref()).await?;
+
+        if batch.status != previous {
+            log.println(format!("Batch {}: {}", batch.id, batch.status));
+        }
+    }
+
+    let mut done = BTreeSet::new();
+
+    for (i, completion) in results(client, &options.api_base, &batch, id().as_deref()).await? {
+        let job = jobs.get(i).wrap_err("Unknown batch request id")?;
+
+        match completion {
+            Ok(mut completion) => {
+                completion.request_id = batch_request_id.clone();
+
+                tx.send((job.key.clone(), job.source.clone(), Ok(completion)))
+                    .await?;
+
+                done.insert(i);
+            }
+            Err(e) => log.warn(Some(job.cell), format_args!("{:#}", e)),
+        }
+    }
+
+    *jobs = mem::take(jobs)
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !done.contains(i))
+        .map(|(_, job)| job)
+        .collect();
+
+    Ok(())
+}
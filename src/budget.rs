@@ -0,0 +1,174 @@
+//! What a run has spent on the API so far, and the caps of `--max-cost` and `--max-requests`.
+
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    estimate::{self, Price},
+    translator::Completion,
+};
+
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Spent {
+    pub(crate) requests: usize,
+    pub(crate) input_tokens: usize,
+    pub(crate) output_tokens: usize,
+    /// In USD, at the list price of the models that answered, or `price` for the others.
+    pub(crate) cost: f64,
+}
+
+#[derive(Debug)]
+pub(crate) struct Budget {
+    max_cost: Option<f64>,
+    max_requests: Option<usize>,
+    /// The price of the model asked for, in case the API answers with a name that has none.
+    price: Option<Price>,
+    spent: Mutex<Spent>,
+    /// When the requests of the last minute were answered.
+    recent: Mutex<VecDeque<Instant>>,
+}
+
+impl Budget {
+    pub(crate) fn new(
+        max_cost: Option<f64>,
+        max_requests: Option<usize>,
+        price: Option<Price>,
+    ) -> Self {
+        Self {
+            max_cost,
+            max_requests,
+            price,
+            spent: Mutex::default(),
+            recent: Mutex::default(),
+        }
+    }
+
+    /// Counts a request, with the completions it was answered with, none if it failed.
+    pub(crate) fn record<'a>(&self, completions: impl IntoIterator<Item = &'a Completion>) {
+        let mut spent = self.spent.lock().unwrap();
+        spent.requests += 1;
+        self.recent.lock().unwrap().push_back(Instant::now());
+
+        for completion in completions {
+            let input = completion.input_tokens;
+            let output = completion.tokens.saturating_sub(input);
+            spent.input_tokens += input;
+            spent.output_tokens += output;
+
+            if let Some(price) = estimate::price(&completion.model).or(self.price) {
+                spent.cost +=
+                    (input as f64 * price.input + output as f64 * price.output) / 1_000_000.;
+            }
+        }
+    }
+
+    /// Whether one of the caps has been reached, after which no more requests are sent. Those
+    /// already in flight still finish, so the caps can be overshot by as many of them.
+    pub(crate) fn exhausted(&self) -> bool {
+        let spent = self.spent();
+
+        self.max_cost.is_some_and(|max| spent.cost >= max)
+            || self.max_requests.is_some_and(|max| spent.requests >= max)
+    }
+
+    /// The requests answered in the last minute.
+    pub(crate) fn rpm(&self) -> usize {
+        let mut recent = self.recent.lock().unwrap();
+
+        while recent
+            .front()
+            .is_some_and(|time| time.elapsed() > Duration::from_secs(60))
+        {
+            recent.pop_front();
+        }
+
+        recent.len()
+    }
+
+    pub(crate) fn spent(&self) -> Spent {
+        *self.spent.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn completion(model: &str, input_tokens: usize, tokens: usize) -> Completion {
+        Completion {
+            text: String::new(),
+            tokens,
+            input_tokens,
+            model: model.to_string(),
+            request_id: None,
+            confidence: None,
+        }
+    }
+
+    #[test]
+    fn counts_requests_and_tokens() {
+        let budget = Budget::new(None, None, None);
+        budget.record([&completion("local", 100, 150)]);
+        budget.record(None);
+
+        let spent = budget.spent();
+        assert_eq!(spent.requests, 2);
+        assert_eq!(spent.input_tokens, 100);
+        assert_eq!(spent.output_tokens, 50);
+        assert_eq!(spent.cost, 0.);
+        assert_eq!(budget.rpm(), 2);
+    }
+
+    #[test]
+    fn prices_models_by_their_list_price() {
+        let budget = Budget::new(None, None, None);
+        budget.record([&completion("gpt-4o-mini-2024-07-18", 1_000_000, 2_000_000)]);
+
+        assert!((budget.spent().cost - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn prices_unknown_models_at_the_price_asked_for() {
+        let price = Price {
+            input: 1.,
+            output: 2.,
+        };
+        let budget = Budget::new(None, None, Some(price));
+        budget.record([&completion("local", 500_000, 1_000_000)]);
+
+        assert!((budget.spent().cost - 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn is_exhausted_at_the_caps() {
+        let budget = Budget::new(None, Some(2), None);
+        budget.record(None);
+        assert!(!budget.exhausted());
+        budget.record(None);
+        assert!(budget.exhausted());
+
+        let price = Price {
+            input: 1.,
+            output: 1.,
+        };
+        let budget = Budget::new(Some(1.), None, Some(price));
+        budget.record([&completion("local", 600_000, 900_000)]);
+        assert!(!budget.exhausted());
+        budget.record([&completion("local", 100_000, 200_000)]);
+        assert!(budget.exhausted());
+    }
+
+    #[test]
+    fn never_runs_out_without_caps() {
+        let budget = Budget::new(None, None, None);
+
+        for _ in 0..100 {
+            budget.record(None);
+        }
+
+        assert!(!budget.exhausted());
+    }
+}
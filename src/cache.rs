@@ -0,0 +1,215 @@
+//! A SQLite store of earlier API translations, so re-runs on updated workbooks don't pay for
+//! them again.
+
+use std::{path::Path, time::Duration};
+
+use chrono::Utc;
+use color_eyre::{eyre::Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+
+/// Translations are kept in tiers with their own time to live.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    /// Header row values, which change far less often than the data.
+    Header,
+    /// Everything else.
+    Data,
+}
+
+impl Tier {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Header => "header",
+            Self::Data => "data",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Cache {
+    connection: Connection,
+    /// Entries of caches without one never expire.
+    ttl: Option<Duration>,
+    tier: Tier,
+}
+
+impl Cache {
+    pub fn open(path: &Path, tier: Tier, ttl: Option<Duration>) -> Result<Self> {
+        let connection = Connection::open(path)
+            .wrap_err_with(|| format!("Failed to open the cache {}", path.display()))?;
+
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS translations (
+                cache_tier TEXT NOT NULL,
+                source TEXT NOT NULL,
+                target_language TEXT NOT NULL,
+                model TEXT NOT NULL,
+                translation TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (cache_tier, source, target_language, model)
+            )",
+        )?;
+
+        Ok(Self {
+            connection,
+            ttl,
+            tier,
+        })
+    }
+
+    /// The cached translation of a value, unless it's older than the time to live.
+    pub fn get(&self, source: &str, target_language: &str, model: &str) -> Result<Option<String>> {
+        let oldest = self.ttl.map_or(i64::MIN, |ttl| {
+            Utc::now().timestamp() - ttl.as_secs() as i64
+        });
+
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT translation FROM translations
+                 WHERE cache_tier = ?1 AND source = ?2 AND target_language = ?3 AND model = ?4
+                    AND created_at >= ?5",
+                params![self.tier.as_str(), source, target_language, model, oldest],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Every translation into the language by the model that isn't older than the time to live,
+    /// by source.
+    pub fn entries(&self, target_language: &str, model: &str) -> Result<Vec<(String, String)>> {
+        let oldest = self.ttl.map_or(i64::MIN, |ttl| {
+            Utc::now().timestamp() - ttl.as_secs() as i64
+        });
+
+        let mut statement = self.connection.prepare(
+            "SELECT source, translation FROM translations
+             WHERE cache_tier = ?1 AND target_language = ?2 AND model = ?3 AND created_at >= ?4",
+        )?;
+
+        let entries = statement
+            .query_map(
+                params![self.tier.as_str(), target_language, model, oldest],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(entries)
+    }
+
+    pub fn put(
+        &self,
+        source: &str,
+        target_language: &str,
+        model: &str,
+        translation: &str,
+    ) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO translations
+                (cache_tier, source, target_language, model, translation, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                self.tier.as_str(),
+                source,
+                target_language,
+                model,
+                translation,
+                Utc::now().timestamp()
+            ],
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn cache(tier: Tier, ttl: Option<Duration>) -> Cache {
+        Cache::open(Path::new(":memory:"), tier, ttl).unwrap()
+    }
+
+    /// Makes every entry older by `age`.
+    fn age(cache: &Cache, age: Duration) {
+        cache
+            .connection
+            .execute(
+                "UPDATE translations SET created_at = created_at - ?1",
+                params![age.as_secs() as i64],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn gets_what_was_put() {
+        let cache = cache(Tier::Data, None);
+        cache.put("Hello", "de", "gpt-4o", "Hallo").unwrap();
+
+        assert_eq!(
+            cache.get("Hello", "de", "gpt-4o").unwrap().as_deref(),
+            Some("Hallo")
+        );
+        assert_eq!(cache.get("Hello", "fr", "gpt-4o").unwrap(), None);
+        assert_eq!(cache.get("Hello", "de", "gpt-4o-mini").unwrap(), None);
+    }
+
+    #[test]
+    fn replaces_earlier_translations() {
+        let cache = cache(Tier::Data, None);
+        cache.put("Hello", "de", "gpt-4o", "Hallo").unwrap();
+        cache.put("Hello", "de", "gpt-4o", "Guten Tag").unwrap();
+
+        assert_eq!(
+            cache.get("Hello", "de", "gpt-4o").unwrap().as_deref(),
+            Some("Guten Tag")
+        );
+        assert_eq!(cache.entries("de", "gpt-4o").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn keeps_the_tiers_apart() {
+        let headers = cache(Tier::Header, None);
+        headers.put("Name", "de", "gpt-4o", "Name").unwrap();
+        headers.put("Price", "de", "gpt-4o", "Preis").unwrap();
+
+        let data = Cache {
+            tier: Tier::Data,
+            ..headers
+        };
+
+        assert_eq!(data.get("Price", "de", "gpt-4o").unwrap(), None);
+        assert!(data.entries("de", "gpt-4o").unwrap().is_empty());
+    }
+
+    #[test]
+    fn expires_entries_older_than_the_ttl() {
+        let cache = cache(Tier::Data, Some(DAY));
+        cache.put("Hello", "de", "gpt-4o", "Hallo").unwrap();
+        age(&cache, DAY / 2);
+
+        assert_eq!(
+            cache.get("Hello", "de", "gpt-4o").unwrap().as_deref(),
+            Some("Hallo")
+        );
+
+        age(&cache, DAY);
+
+        assert_eq!(cache.get("Hello", "de", "gpt-4o").unwrap(), None);
+        assert!(cache.entries("de", "gpt-4o").unwrap().is_empty());
+    }
+
+    #[test]
+    fn never_expires_without_a_ttl() {
+        let cache = cache(Tier::Data, None);
+        cache.put("Hello", "de", "gpt-4o", "Hallo").unwrap();
+        age(&cache, 3650 * DAY);
+
+        assert_eq!(
+            cache.entries("de", "gpt-4o").unwrap(),
+            [("Hello".to_string(), "Hallo".to_string())]
+        );
+    }
+}
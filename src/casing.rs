@@ -0,0 +1,122 @@
+//! The capitalization of cell values, detected on the source and applied to the translation for
+//! `--restore-case`. Dictionary keys are lowercase and cells differing only in case share a
+//! request, so a translation otherwise comes in the case of whichever value it was made for.
+
+use crate::rich_text;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Case {
+    /// `TOTAL GENERAL`
+    Upper,
+    /// `Total General`, with two words or more.
+    Title,
+    /// `Total general`
+    Sentence,
+    /// Anything else, left as the translation has it.
+    Other,
+}
+
+fn capitalized(word: &str) -> String {
+    let mut chars = word.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+impl Case {
+    pub(crate) fn of(text: &str) -> Self {
+        let letters = text
+            .chars()
+            .filter(|c| c.is_alphabetic())
+            .collect::<Vec<_>>();
+
+        // A single letter is as much a capital as the start of a sentence.
+        if letters.len() > 1 && letters.iter().all(|c| c.is_uppercase()) {
+            return Self::Upper;
+        }
+
+        let words = text
+            .split_whitespace()
+            .filter(|word| word.starts_with(char::is_alphabetic))
+            .collect::<Vec<_>>();
+
+        if words.len() > 1
+            && words
+                .iter()
+                .all(|word| word.starts_with(char::is_uppercase))
+        {
+            return Self::Title;
+        }
+
+        match letters.first() {
+            Some(first) if first.is_uppercase() && text.starts_with(char::is_alphabetic) => {
+                Self::Sentence
+            }
+            _ => Self::Other,
+        }
+    }
+
+    /// The translation in this case. Translations with the tags of runs are left as they are.
+    pub(crate) fn apply(self, translation: &str) -> String {
+        if rich_text::has_tags(translation) {
+            return translation.to_string();
+        }
+
+        match self {
+            Self::Upper => translation.to_uppercase(),
+            Self::Title => translation
+                .split(' ')
+                .map(capitalized)
+                .collect::<Vec<_>>()
+                .join(" "),
+            Self::Sentence => {
+                let start = translation.len() - translation.trim_start().len();
+                let (space, text) = translation.split_at(start);
+                format!("{}{}", space, capitalized(text))
+            }
+            Self::Other => translation.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_the_case_of_values() {
+        assert_eq!(Case::of("TOTAL GENERAL"), Case::Upper);
+        assert_eq!(Case::of("Total General"), Case::Title);
+        assert_eq!(Case::of("Total general"), Case::Sentence);
+        assert_eq!(Case::of("total general"), Case::Other);
+    }
+
+    #[test]
+    fn single_letters_and_words_are_not_upper_or_title() {
+        assert_eq!(Case::of("A"), Case::Sentence);
+        assert_eq!(Case::of("Total"), Case::Sentence);
+    }
+
+    #[test]
+    fn values_starting_with_other_than_a_letter_are_other() {
+        assert_eq!(Case::of("1. Total"), Case::Other);
+    }
+
+    #[test]
+    fn applies_the_case_to_translations() {
+        assert_eq!(Case::Upper.apply("gesamt allgemein"), "GESAMT ALLGEMEIN");
+        assert_eq!(Case::Title.apply("gesamt allgemein"), "Gesamt Allgemein");
+        assert_eq!(
+            Case::Sentence.apply(" gesamt allgemein"),
+            " Gesamt allgemein"
+        );
+        assert_eq!(Case::Other.apply("gesamt Allgemein"), "gesamt Allgemein");
+    }
+
+    #[test]
+    fn leaves_translations_with_tags_alone() {
+        assert_eq!(Case::Upper.apply("<r1>gesamt</r1>"), "<r1>gesamt</r1>");
+    }
+}
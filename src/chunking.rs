@@ -0,0 +1,468 @@
+//! Values too long to be translated with a single request, for `--max-chunk-tokens`. They are
+//! split at the ends of paragraphs and sentences, the pieces are translated one after the other
+//! with the end of the piece before as context, and their translations are joined again.
+
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{Arc, Mutex},
+};
+
+use color_eyre::{eyre::Context, Report};
+use regex::Regex;
+use tiktoken_rs::CoreBPE;
+use tokio::sync::OnceCell;
+
+use crate::{
+    limiter::Limiter,
+    scheduler::{estimate_tokens, Chunk, Job},
+    translator::{Completion, Translating, Translator},
+};
+
+/// The characters of the piece before that a piece gets as context.
+const OVERLAP: usize = 200;
+
+/// The tokens of a text, or a guess at four characters a token without a tokenizer.
+pub(crate) fn tokens(text: &str, bpe: Option<&CoreBPE>) -> usize {
+    bpe.map_or(text.chars().count().div_ceil(4), |bpe| {
+        bpe.encode_with_special_tokens(text).len()
+    })
+}
+
+/// Splits the text at the ends of paragraphs and sentences, every sentence with the whitespace
+/// after it.
+fn sentences(text: &str) -> Vec<&str> {
+    let boundaries = Regex::new(r#"\n\s*|[.!?…。！？]+["'”’)\]]*\s+"#).unwrap();
+
+    let mut sentences = vec![];
+    let mut start = 0;
+
+    for boundary in boundaries.find_iter(text) {
+        sentences.push(&text[start..boundary.end()]);
+        start = boundary.end();
+    }
+
+    sentences.push(&text[start..]);
+    sentences.retain(|sentence| !sentence.is_empty());
+    sentences
+}
+
+/// Splits the text into pieces of at most `max_tokens` tokens, at the ends of paragraphs and
+/// sentences or, within sentences too long by themselves, between words. Every piece keeps the
+/// whitespace it ends with, so that they add up to the text.
+fn split(text: &str, max_tokens: usize, bpe: Option<&CoreBPE>) -> Vec<String> {
+    let mut pieces = vec![];
+    let mut piece = String::new();
+    let mut piece_tokens = 0;
+
+    for sentence in sentences(text) {
+        let parts = if tokens(sentence, bpe) > max_tokens {
+            sentence.split_inclusive(char::is_whitespace).collect()
+        } else {
+            vec![sentence]
+        };
+
+        for part in parts {
+            let part_tokens = tokens(part, bpe);
+
+            if !piece.is_empty() && piece_tokens + part_tokens > max_tokens {
+                pieces.push(mem::take(&mut piece));
+                piece_tokens = 0;
+            }
+
+            piece.push_str(part);
+            piece_tokens += part_tokens;
+        }
+    }
+
+    if !piece.is_empty() {
+        pieces.push(piece);
+    }
+
+    pieces
+}
+
+/// The last words of a piece, for the context of the next one.
+fn tail(piece: &str) -> &str {
+    let piece = piece.trim_end();
+    let start = piece
+        .char_indices()
+        .rev()
+        .nth(OVERLAP)
+        .map_or(0, |(start, _)| start);
+
+    match piece[start..].find(char::is_whitespace) {
+        Some(space) if start > 0 => piece[start + space..].trim_start(),
+        _ => &piece[start..],
+    }
+}
+
+/// The chunks of a value longer than `max_tokens` tokens, none for the others. The prompt of
+/// every piece is built by `prompt` from the text of the piece and the end of the one before.
+pub(crate) fn chunks(
+    value: &str,
+    max_tokens: usize,
+    bpe: Option<&CoreBPE>,
+    prompt: impl Fn(&str, Option<&str>) -> String,
+) -> Vec<Chunk> {
+    if tokens(value, bpe) <= max_tokens {
+        return vec![];
+    }
+
+    prompted(&split(value, max_tokens, bpe), bpe, prompt)
+}
+
+/// The sentences of a value of several, for `--dedupe-sentences`, each translated by itself so
+/// that the ones repeated across cells are translated once. Sentences too long for a single
+/// request are split further, and values of a single sentence are chunked as by `chunks`.
+pub(crate) fn sentence_chunks(
+    value: &str,
+    max_tokens: usize,
+    bpe: Option<&CoreBPE>,
+    prompt: impl Fn(&str, Option<&str>) -> String,
+) -> Vec<Chunk> {
+    let pieces = sentences(value)
+        .into_iter()
+        .flat_map(|sentence| match tokens(sentence, bpe) > max_tokens {
+            true => split(sentence, max_tokens, bpe),
+            false => vec![sentence.to_string()],
+        })
+        .collect::<Vec<_>>();
+
+    match pieces.len() {
+        0 | 1 => chunks(value, max_tokens, bpe, prompt),
+        _ => prompted(&pieces, bpe, prompt),
+    }
+}
+
+fn prompted(
+    pieces: &[String],
+    bpe: Option<&CoreBPE>,
+    prompt: impl Fn(&str, Option<&str>) -> String,
+) -> Vec<Chunk> {
+    pieces
+        .iter()
+        .enumerate()
+        .map(|(i, piece)| {
+            let source = piece.trim_end();
+            let overlap = i.checked_sub(1).map(|i| tail(&pieces[i]));
+            let prompt = prompt(source, overlap);
+
+            Chunk {
+                estimated_tokens: estimate_tokens(&prompt, bpe),
+                separator: piece[source.len()..].to_string(),
+                source: source.to_string(),
+                prompt,
+                translation: None,
+            }
+        })
+        .collect()
+}
+
+/// The pieces translated so far by model, language and source, each filled in by the first job
+/// to send it, which the others with the same piece wait for.
+type Translated = Mutex<HashMap<(String, String, String), Arc<OnceCell<String>>>>;
+
+/// Translates the jobs of long values chunk by chunk, and the others as they are.
+pub(crate) struct Chunked {
+    translator: Arc<dyn Translator>,
+    limiter: Arc<Limiter>,
+    /// With `--dedupe-sentences`, so that no piece is sent twice.
+    translated: Option<Translated>,
+}
+
+impl Chunked {
+    pub(crate) fn new(translator: Arc<dyn Translator>, limiter: Arc<Limiter>) -> Self {
+        Self {
+            translator,
+            limiter,
+            translated: None,
+        }
+    }
+
+    /// Translates every piece once, whatever values it is part of.
+    pub(crate) fn dedupe(self) -> Self {
+        Self {
+            translated: Some(Mutex::default()),
+            ..self
+        }
+    }
+
+    fn translated(&self, job: &Job, chunk: &Chunk) -> Option<Arc<OnceCell<String>>> {
+        let key = (
+            job.model.clone(),
+            job.language.clone(),
+            chunk.source.clone(),
+        );
+        let mut translated = self.translated.as_ref()?.lock().unwrap();
+        Some(translated.entry(key).or_default().clone())
+    }
+}
+
+impl Translator for Chunked {
+    fn translate<'a>(&'a self, job: &'a Job) -> Translating<'a> {
+        if job.chunks.is_empty() {
+            return self.translator.translate(job);
+        }
+
+        Box::pin(async move {
+            let mut completion = Completion {
+                text: String::new(),
+                tokens: 0,
+                input_tokens: 0,
+                model: job.model.clone(),
+                request_id: None,
+                confidence: None,
+            };
+
+            for (i, chunk) in job.chunks.iter().enumerate() {
+                let send = || async {
+                    // The share of the rate limit of the job went to the first chunk.
+                    if i > 0 {
+                        self.limiter.acquire(chunk.estimated_tokens).await;
+                    }
+
+                    let piece = Job {
+                        key: job.key.clone(),
+                        source: chunk.source.clone(),
+                        prompt: chunk.prompt.clone(),
+                        temperature: job.temperature,
+                        model: job.model.clone(),
+                        estimated_tokens: chunk.estimated_tokens,
+                        language: job.language.clone(),
+                        context: job.context.clone(),
+                        cell: job.cell,
+                        chunks: vec![],
+                        max_length: None,
+                    };
+
+                    self.translator
+                        .translate(&piece)
+                        .await
+                        .wrap_err_with(|| format!("Chunk {} of {}", i + 1, job.chunks.len()))
+                };
+
+                let translation = match (&chunk.translation, self.translated(job, chunk)) {
+                    (Some(translation), _) => translation.clone(),
+                    (None, Some(translated)) => {
+                        let mut sent = None;
+
+                        let text = translated
+                            .get_or_try_init(|| async {
+                                let translation = send().await?;
+                                let text = translation.text.trim().to_string();
+                                sent = Some(translation);
+                                Ok::<_, Report>(text)
+                            })
+                            .await?
+                            .clone();
+
+                        if let Some(translation) = sent {
+                            completion.add(translation);
+                        }
+
+                        text
+                    }
+                    (None, None) => {
+                        let translation = send().await?;
+                        let text = translation.text.trim().to_string();
+                        completion.add(translation);
+                        text
+                    }
+                };
+
+                completion.text.push_str(&translation);
+                completion.text.push_str(&chunk.separator);
+            }
+
+            Ok(completion)
+        })
+    }
+}
+
+impl Completion {
+    /// Counts the tokens of the translation of a chunk towards the completion of its value.
+    fn add(&mut self, translation: Completion) {
+        self.tokens += translation.tokens;
+        self.input_tokens += translation.input_tokens;
+        self.model = translation.model;
+        self.request_id = translation.request_id.or(self.request_id.take());
+
+        // A text is as sure as its least sure chunk.
+        self.confidence = match (self.confidence, translation.confidence) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::scheduler::RateLimit;
+
+    /// Translates every piece into itself in capitals, counting the requests.
+    #[derive(Default)]
+    struct Shouting {
+        requests: AtomicUsize,
+    }
+
+    impl Translator for Shouting {
+        fn translate<'a>(&'a self, job: &'a Job) -> Translating<'a> {
+            self.requests.fetch_add(1, Ordering::Relaxed);
+
+            Box::pin(async move {
+                Ok(Completion {
+                    text: job.source.to_uppercase(),
+                    tokens: 2,
+                    input_tokens: 1,
+                    model: job.model.clone(),
+                    request_id: None,
+                    confidence: None,
+                })
+            })
+        }
+    }
+
+    fn job(source: &str, chunks: Vec<Chunk>) -> Job {
+        Job {
+            key: source.to_lowercase(),
+            source: source.to_string(),
+            prompt: source.to_string(),
+            temperature: 0.,
+            model: "model".to_string(),
+            estimated_tokens: 0,
+            language: "de".to_string(),
+            context: None,
+            cell: (0, 0),
+            chunks,
+            max_length: None,
+        }
+    }
+
+    fn chunked(translator: &Arc<Shouting>) -> Chunked {
+        let rate_limit = RateLimit {
+            requests: 1000,
+            window: Duration::from_secs(60),
+        };
+
+        Chunked::new(translator.clone(), Arc::new(Limiter::new(rate_limit, None)))
+    }
+
+    fn prompt(text: &str, overlap: Option<&str>) -> String {
+        format!("{}|{}", overlap.unwrap_or_default(), text)
+    }
+
+    #[test]
+    fn splits_at_the_ends_of_sentences_and_paragraphs() {
+        assert_eq!(
+            sentences("One. Two!  Three?\n\nFour"),
+            ["One. ", "Two!  ", "Three?\n\n", "Four"]
+        );
+        assert_eq!(
+            sentences("He said \"no.\" Then"),
+            ["He said \"no.\" ", "Then"]
+        );
+        assert_eq!(sentences("3.5 apples"), ["3.5 apples"]);
+    }
+
+    #[test]
+    fn pieces_add_up_to_the_text() {
+        let text = "The first sentence is here. The second one is a little longer than it. \
+                    Short. And a last sentence without a stop";
+        let pieces = split(text, 8, None);
+
+        assert!(pieces.len() > 1);
+        assert_eq!(pieces.concat(), text);
+        assert!(pieces.iter().all(|piece| tokens(piece, None) <= 8));
+    }
+
+    #[test]
+    fn splits_long_sentences_between_words() {
+        let text = "a sentence far too long for a single piece of the text";
+        let pieces = split(text, 4, None);
+
+        assert!(pieces.len() > 1);
+        assert_eq!(pieces.concat(), text);
+        assert!(pieces.iter().all(|piece| !piece.starts_with(' ')));
+    }
+
+    #[test]
+    fn tail_starts_at_a_word() {
+        let piece = "word ".repeat(100);
+        let end = tail(&piece);
+
+        assert!(end.chars().count() <= OVERLAP);
+        assert!(end.starts_with("word"));
+        assert_eq!(tail("Short piece. "), "Short piece.");
+    }
+
+    #[test]
+    fn short_values_have_no_chunks() {
+        assert!(chunks("Short", 100, None, prompt).is_empty());
+    }
+
+    #[test]
+    fn chunks_get_the_end_of_the_piece_before() {
+        let chunks = chunks("First part here. Second part here.", 5, None, prompt);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].source, "First part here.");
+        assert_eq!(chunks[0].separator, " ");
+        assert_eq!(chunks[0].prompt, "|First part here.");
+        assert_eq!(chunks[1].prompt, "First part here.|Second part here.");
+        assert_eq!(chunks[1].separator, "");
+    }
+
+    #[test]
+    fn sentences_are_chunks_of_their_own() {
+        let chunks = sentence_chunks("Yes. No. Yes.", 100, None, prompt);
+        let sources = chunks.iter().map(|chunk| chunk.source.as_str());
+
+        assert_eq!(sources.collect::<Vec<_>>(), ["Yes.", "No.", "Yes."]);
+        assert!(sentence_chunks("Just one.", 100, None, prompt).is_empty());
+    }
+
+    #[tokio::test]
+    async fn joins_the_translations_of_the_chunks() {
+        let translator = Arc::new(Shouting::default());
+        let value = "First part here. Second part here.";
+        let job = job(value, chunks(value, 5, None, prompt));
+
+        let completion = chunked(&translator).translate(&job).await.unwrap();
+
+        assert_eq!(completion.text, "FIRST PART HERE. SECOND PART HERE.");
+        assert_eq!(completion.tokens, 4);
+        assert_eq!(translator.requests.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn sends_repeated_pieces_once_when_deduped() {
+        let translator = Arc::new(Shouting::default());
+        let value = "Yes. No. Yes.";
+        let mut chunks = sentence_chunks(value, 100, None, prompt);
+        chunks[1].translation = Some("Nein.".to_string());
+        let job = job(value, chunks);
+
+        let completion = chunked(&translator).dedupe().translate(&job).await.unwrap();
+
+        assert_eq!(completion.text, "YES. Nein. YES.");
+        assert_eq!(translator.requests.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn translates_values_without_chunks_as_they_are() {
+        let translator = Arc::new(Shouting::default());
+        let completion = chunked(&translator)
+            .translate(&job("short", vec![]))
+            .await
+            .unwrap();
+
+        assert_eq!(completion.text, "SHORT");
+    }
+}
@@ -0,0 +1,161 @@
+//! The Anthropic Messages API (<https://docs.anthropic.com/en/api/messages>), prompted the same
+//! way as the OpenAI chat models.
+
+use std::sync::Arc;
+
+use color_eyre::{
+    eyre::{bail, ContextCompat},
+    Result,
+};
+use reqwest::{header::HeaderName, Client};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    limiter::Limiter,
+    retry,
+    scheduler::Job,
+    translator::{self, Completion, Connection, Sampling},
+};
+
+/// The model used unless `--model` names another Claude model.
+pub const MODEL: &str = "claude-3-5-sonnet-latest";
+
+const API_URL: &str = "https://api.anthropic.com/v1/messages";
+
+const API_VERSION: &str = "2023-06-01";
+
+/// The API requires a limit on the length of the answer. Cell values never come close to it.
+const MAX_TOKENS: usize = 4096;
+
+#[derive(Debug, Serialize)]
+struct Request<'a> {
+    model: &'a str,
+    max_tokens: usize,
+    system: &'a str,
+    messages: [Message<'a>; 1],
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+struct Message<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Response {
+    Message {
+        #[serde(default)]
+        id: Option<String>,
+        model: String,
+        content: Vec<Content>,
+        usage: Usage,
+    },
+    Error {
+        error: Error,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Content {
+    Text {
+        text: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct Usage {
+    input_tokens: usize,
+    output_tokens: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct Error {
+    message: String,
+}
+
+pub struct Claude {
+    client: Client,
+    retry: retry::Policy,
+    limiter: Arc<Limiter>,
+    sampling: Sampling,
+}
+
+impl Claude {
+    pub fn new(
+        api_key: &str,
+        connection: &Connection,
+        retry: retry::Policy,
+        limiter: Arc<Limiter>,
+    ) -> Result<Self> {
+        Ok(Self {
+            client: translator::client(
+                Some((HeaderName::from_static("x-api-key"), api_key)),
+                connection,
+            )?,
+            retry,
+            limiter,
+            sampling: Sampling::default(),
+        })
+    }
+
+    pub fn with_sampling(self, sampling: Sampling) -> Self {
+        Self { sampling, ..self }
+    }
+
+    pub async fn translate(&self, job: &Job) -> Result<Completion> {
+        let request = Request {
+            model: &job.model,
+            max_tokens: MAX_TOKENS,
+            system: &self.sampling.system_prompt,
+            messages: [Message {
+                role: "user",
+                content: &job.prompt,
+            }],
+            temperature: job.temperature,
+            top_p: self.sampling.top_p,
+        };
+
+        let request = self
+            .client
+            .post(API_URL)
+            .header("anthropic-version", API_VERSION)
+            .json(&request);
+
+        let response = retry::send(request, &self.retry).await?;
+        self.limiter.observe_anthropic(response.headers());
+
+        let (id, model, content, usage) = match response.json::<Response>().await? {
+            Response::Message {
+                id,
+                model,
+                content,
+                usage,
+            } => (id, model, content, usage),
+            Response::Error { error } => bail!("Anthropic: {}", error.message),
+        };
+
+        let text = content
+            .into_iter()
+            .find_map(|content| match content {
+                Content::Text { text } => Some(text),
+                Content::Other => None,
+            })
+            .wrap_err("No text received")?;
+
+        Ok(Completion {
+            text,
+            tokens: usage.input_tokens + usage.output_tokens,
+            input_tokens: usage.input_tokens,
+            model,
+            request_id: id,
+            confidence: None,
+        })
+    }
+}
@@ -0,0 +1,232 @@
+//! Picking the columns to translate by header name, letter or position.
+
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
+use calamine::{DataType, Range};
+use color_eyre::{eyre::bail, Report, Result};
+use serde::{Serialize, Serializer};
+
+use crate::workbook::column_letter;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColumnSelector {
+    /// The text of a header cell, compared case-insensitively.
+    Name(String),
+    /// The zero-based columns from the first to the last, e.g. `B` or `D:F`.
+    Letters(u16, u16),
+    /// The 1-based position of the column in the worksheet.
+    Index(u16),
+}
+
+/// The zero-based index of a column given in upper case letters, like `A` or `AB`.
+pub(crate) fn parse_letters(s: &str) -> Option<u16> {
+    if s.is_empty() || s.len() > 3 || !s.bytes().all(|b| b.is_ascii_uppercase()) {
+        return None;
+    }
+
+    let column = s
+        .bytes()
+        .fold(0u32, |n, b| n * 26 + u32::from(b - b'A' + 1));
+
+    u16::try_from(column - 1).ok()
+}
+
+/// Parses a cell reference like `B3` into its zero-based row and column.
+pub(crate) fn parse_reference(reference: &str) -> Option<(u32, u16)> {
+    let split = reference.find(|c: char| c.is_ascii_digit())?;
+    let (letters, digits) = reference.split_at(split);
+    let row = digits.parse::<u32>().ok()?.checked_sub(1)?;
+
+    Some((row, parse_letters(letters)?))
+}
+
+impl ColumnSelector {
+    /// Whether the column at the zero-based `column` of the worksheet, headed by `header`, is
+    /// meant.
+    pub fn matches(&self, column: u16, header: Option<&str>) -> bool {
+        match self {
+            Self::Name(name) => {
+                header.is_some_and(|header| header.trim().to_lowercase() == name.to_lowercase())
+            }
+            Self::Letters(first, last) => (*first..=*last).contains(&column),
+            Self::Index(index) => *index == column + 1,
+        }
+    }
+}
+
+impl FromStr for ColumnSelector {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+
+        if s.is_empty() {
+            bail!("Empty column selector");
+        }
+
+        if let Ok(index) = s.parse::<u16>() {
+            if index == 0 {
+                bail!("Column positions start at 1");
+            }
+
+            return Ok(Self::Index(index));
+        }
+
+        let letters = match s.split_once(':') {
+            Some((first, last)) => parse_letters(first).zip(parse_letters(last)),
+            None => parse_letters(s).map(|column| (column, column)),
+        };
+
+        Ok(match letters {
+            Some((first, last)) => Self::Letters(first.min(last), first.max(last)),
+            None => Self::Name(s.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for ColumnSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "{}", name),
+            Self::Letters(first, last) if first == last => write!(f, "{}", column_letter(*first)),
+            Self::Letters(first, last) => {
+                write!(f, "{}:{}", column_letter(*first), column_letter(*last))
+            }
+            Self::Index(index) => write!(f, "{}", index),
+        }
+    }
+}
+
+impl Serialize for ColumnSelector {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// The columns of the range, relative to its first one, that any of the selectors picks, with
+/// the header names looked up in `header_row`. Selectors picking nothing are warned about, as
+/// they are likely misspelled.
+pub fn resolve(
+    selectors: &[ColumnSelector],
+    range: &Range<DataType>,
+    header_row: Option<u32>,
+    sheet: &str,
+) -> BTreeSet<u16> {
+    let first_column = range.start().map_or(0, |(_, column)| column as u16);
+
+    let columns = (0..range.width() as u16)
+        .map(|column| {
+            let header = match header_row.and_then(|row| range.get((row as usize, column as usize)))
+            {
+                Some(DataType::String(header)) => Some(header.as_str()),
+                _ => None,
+            };
+
+            (column, first_column + column, header)
+        })
+        .collect::<Vec<_>>();
+
+    for selector in selectors {
+        if !columns
+            .iter()
+            .any(|(_, column, header)| selector.matches(*column, *header))
+        {
+            eprintln!("Warning: sheet {} has no column '{}'", sheet, selector);
+        }
+    }
+
+    columns
+        .into_iter()
+        .filter(|(_, column, header)| selectors.iter().any(|s| s.matches(*column, *header)))
+        .map(|(relative, _, _)| relative)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selector(s: &str) -> ColumnSelector {
+        s.parse().unwrap()
+    }
+
+    /// A range starting at `first_column` with a header row.
+    fn range(first_column: u32, headers: &[&str]) -> Range<DataType> {
+        let last_column = first_column + headers.len() as u32 - 1;
+        let mut range = Range::new((0, first_column), (1, last_column));
+
+        for (i, header) in headers.iter().enumerate() {
+            let column = first_column + i as u32;
+            range.set_value((0, column), DataType::String(header.to_string()));
+            range.set_value((1, column), DataType::String("value".to_string()));
+        }
+
+        range
+    }
+
+    #[test]
+    fn parses_letters() {
+        assert_eq!(parse_letters("A"), Some(0));
+        assert_eq!(parse_letters("AB"), Some(27));
+        assert_eq!(parse_letters("XFD"), Some(16_383));
+        assert_eq!(parse_letters("a"), None);
+        assert_eq!(parse_letters("ABCD"), None);
+        assert_eq!(parse_letters(""), None);
+    }
+
+    #[test]
+    fn parses_references() {
+        assert_eq!(parse_reference("B3"), Some((2, 1)));
+        assert_eq!(parse_reference("AA1"), Some((0, 26)));
+        assert_eq!(parse_reference("B0"), None);
+        assert_eq!(parse_reference("3"), None);
+        assert_eq!(parse_reference("B"), None);
+    }
+
+    #[test]
+    fn parses_selectors() {
+        assert_eq!(selector("3"), ColumnSelector::Index(3));
+        assert_eq!(selector("B"), ColumnSelector::Letters(1, 1));
+        assert_eq!(selector("F:D"), ColumnSelector::Letters(3, 5));
+        assert_eq!(selector(" Name "), ColumnSelector::Name("Name".to_string()));
+        assert_eq!(
+            selector("Description"),
+            ColumnSelector::Name("Description".to_string())
+        );
+        assert!("0".parse::<ColumnSelector>().is_err());
+        assert!(" ".parse::<ColumnSelector>().is_err());
+    }
+
+    #[test]
+    fn displays_selectors_the_way_they_are_parsed() {
+        for s in ["3", "B", "D:F", "Name"] {
+            assert_eq!(selector(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn matches_columns() {
+        assert!(selector("name").matches(4, Some(" Name ")));
+        assert!(!selector("Name").matches(4, None));
+        assert!(selector("B:C").matches(2, None));
+        assert!(!selector("B:C").matches(3, None));
+        assert!(selector("2").matches(1, None));
+    }
+
+    #[test]
+    fn resolves_columns_relative_to_the_range() {
+        let range = range(2, &["Id", "Name", "Description"]);
+        let selectors = [selector("Description"), selector("D")];
+
+        let columns = resolve(&selectors, &range, Some(0), "Sheet1");
+
+        assert_eq!(columns.into_iter().collect::<Vec<_>>(), [1, 2]);
+    }
+
+    #[test]
+    fn resolves_names_only_with_a_header_row() {
+        let range = range(0, &["Id", "Name"]);
+
+        assert!(resolve(&[selector("Name")], &range, None, "Sheet1").is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+//! TOML config files holding the command line options.
+
+use std::{fs, path::Path};
+
+use clap::{Command, CommandFactory};
+use color_eyre::{
+    eyre::{bail, Context, ContextCompat},
+    Result,
+};
+use serde_json::Value;
+
+use crate::cli::Args;
+
+/// The config file used unless `--config` names another, if it exists in the working directory.
+pub(crate) const FILE_NAME: &str = "xlsx-translator.toml";
+
+/// Formats a value as TOML. `None` stands for options that aren't set.
+fn toml_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        // JSON string escapes are a subset of the TOML basic string ones.
+        Value::String(s) => Some(Value::String(s.clone()).to_string()),
+        Value::Array(values) => Some(format!(
+            "[{}]",
+            values
+                .iter()
+                .filter_map(toml_value)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        Value::Object(_) => None,
+    }
+}
+
+/// Renders the options as a config file, with each one documented by its help text. Options
+/// without a value are included commented out.
+pub fn to_toml(args: &Args) -> Result<String> {
+    let values = serde_json::to_value(args)?;
+    let values = values
+        .as_object()
+        .wrap_err("Options must serialize to a table")?;
+
+    let mut toml = String::from("# xlsx-translator configuration\n");
+
+    // The files of a run are given on the command line.
+    for argument in Args::command()
+        .get_arguments()
+        .filter(|argument| !argument.is_positional())
+    {
+        let id = argument.get_id().as_str();
+
+        let Some(value) = values.get(id) else {
+            continue;
+        };
+
+        toml.push('\n');
+
+        if let Some(help) = argument.get_long_help().or(argument.get_help()) {
+            for line in help.to_string().lines() {
+                toml.push_str(format!("# {}", line).trim_end());
+                toml.push('\n');
+            }
+        }
+
+        match toml_value(value) {
+            Some(value) => toml.push_str(&format!("{} = {}\n", id, value)),
+            None => toml.push_str(&format!("# {} =\n", id)),
+        }
+    }
+
+    Ok(toml)
+}
+
+/// The values of an option as they would be given on the command line.
+fn arguments(value: toml::Value) -> Option<Vec<String>> {
+    match value {
+        toml::Value::String(s) => Some(vec![s]),
+        toml::Value::Integer(i) => Some(vec![i.to_string()]),
+        toml::Value::Float(f) => Some(vec![f.to_string()]),
+        toml::Value::Boolean(b) => Some(vec![b.to_string()]),
+        toml::Value::Array(values) => values
+            .into_iter()
+            .map(|value| match value {
+                toml::Value::Array(_) => None,
+                value => arguments(value)?.pop(),
+            })
+            .collect(),
+        toml::Value::Datetime(_) | toml::Value::Table(_) => None,
+    }
+}
+
+/// Makes the options set in the config file the defaults of the command, so that the ones given
+/// on the command line win.
+pub(crate) fn apply(mut command: Command, path: &Path) -> Result<Command> {
+    let text = fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read the config {}", path.display()))?;
+    let table = toml::from_str::<toml::Table>(&text)
+        .wrap_err_with(|| format!("Invalid config {}", path.display()))?;
+
+    for (key, value) in table {
+        if !command
+            .get_arguments()
+            .any(|argument| argument.get_id() == key.as_str() && !argument.is_positional())
+        {
+            bail!("Unknown option '{}' in {}", key, path.display());
+        }
+
+        let values = arguments(value)
+            .wrap_err_with(|| format!("Invalid value of '{}' in {}", key, path.display()))?;
+
+        command = command.mut_arg(key, |argument| argument.default_values(values));
+    }
+
+    Ok(command)
+}
@@ -0,0 +1,96 @@
+//! How consistently the translations use the dictionary terms occurring in their sources.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct Term {
+    pub translation: String,
+    /// Translated cells whose source contains the term.
+    pub occurrences: usize,
+    /// Those of them whose translation contains the dictionary translation of the term.
+    pub consistent: usize,
+    pub score: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub overall: f64,
+    pub terms: BTreeMap<String, Term>,
+}
+
+fn percentage(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        100.
+    } else {
+        part as f64 * 100. / whole as f64
+    }
+}
+
+/// The dictionary terms occurring in the lowercase source whose dictionary translation the
+/// translation lacks, for `--enforce-glossary`.
+pub fn missing_terms<'a>(
+    dictionary: &'a BTreeMap<String, String>,
+    source: &str,
+    translation: &str,
+) -> Vec<(&'a str, &'a str)> {
+    let translation = translation.to_lowercase();
+
+    dictionary
+        .iter()
+        .filter(|(key, value)| {
+            source.contains(key.as_str()) && !translation.contains(&value.to_lowercase())
+        })
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect()
+}
+
+/// Scores the API translations, given as lowercase source, translation and the number of cells
+/// sharing them, against the dictionary. Terms that don't occur in any source are left out.
+pub fn score(
+    dictionary: &BTreeMap<String, String>,
+    translations: &[(String, String, usize)],
+) -> Report {
+    let translations = translations
+        .iter()
+        .map(|(source, translation, cells)| (source, translation.to_lowercase(), *cells))
+        .collect::<Vec<_>>();
+
+    let mut terms = BTreeMap::new();
+    let (mut occurrences, mut consistent) = (0, 0);
+
+    for (key, value) in dictionary {
+        let expected = value.to_lowercase();
+        let mut term = Term {
+            translation: value.clone(),
+            occurrences: 0,
+            consistent: 0,
+            score: 0.,
+        };
+
+        for (source, translation, cells) in &translations {
+            if source.contains(key.as_str()) {
+                term.occurrences += cells;
+
+                if translation.contains(&expected) {
+                    term.consistent += cells;
+                }
+            }
+        }
+
+        if term.occurrences == 0 {
+            continue;
+        }
+
+        term.score = percentage(term.consistent, term.occurrences);
+        occurrences += term.occurrences;
+        consistent += term.consistent;
+        terms.insert(key.clone(), term);
+    }
+
+    Report {
+        overall: percentage(consistent, occurrences),
+        terms,
+    }
+}
@@ -0,0 +1,311 @@
+//! The DeepL REST API (<https://developers.deepl.com/docs/api-reference/translate>), and its
+//! glossaries for `glossary sync`.
+
+use color_eyre::{
+    eyre::{bail, ContextCompat},
+    Result,
+};
+use reqwest::{header::AUTHORIZATION, Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    dictionary::Dictionary,
+    prompt::LANGUAGES,
+    retry,
+    scheduler::Job,
+    translator::{self, Completion, Connection},
+};
+
+/// What the history records as the model of DeepL translations.
+pub const MODEL: &str = "deepl";
+
+const API_URL: &str = "https://api.deepl.com";
+
+/// Keys of the free plan end in `:fx` and only work with this endpoint.
+const FREE_API_URL: &str = "https://api-free.deepl.com";
+
+/// DeepL dropped the plain codes of languages it distinguishes variants of as targets.
+const TARGET_VARIANTS: [(&str, &str); 3] = [("en", "EN-US"), ("pt", "PT-PT"), ("no", "NB")];
+
+#[derive(Debug, Serialize)]
+struct Request<'a> {
+    text: [&'a str; 1],
+    target_lang: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_lang: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    glossary_id: Option<&'a str>,
+    show_billed_characters: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Translations {
+    translations: Vec<Translation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Translation {
+    text: String,
+    billed_characters: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Error {
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct NewGlossary<'a> {
+    name: &'a str,
+    source_lang: &'a str,
+    target_lang: &'a str,
+    entries: String,
+    entries_format: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct Glossaries {
+    glossaries: Vec<Glossary>,
+}
+
+/// A glossary of a single language pair.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Glossary {
+    pub glossary_id: String,
+    pub name: String,
+    source_lang: String,
+    target_lang: String,
+    pub entry_count: usize,
+}
+
+impl Glossary {
+    fn translates(&self, name: &str, source_lang: &str, target_lang: &str) -> bool {
+        self.name == name
+            && self.source_lang.eq_ignore_ascii_case(source_lang)
+            && self.target_lang.eq_ignore_ascii_case(target_lang)
+    }
+}
+
+pub struct DeepL {
+    client: Client,
+    url: &'static str,
+    source_lang: Option<String>,
+    retry: retry::Policy,
+    /// The name of the glossaries requests go through, one for each target language.
+    glossary: Option<String>,
+    /// The glossaries of the account, listed once for the first request.
+    glossaries: Mutex<Option<Vec<Glossary>>>,
+}
+
+/// The DeepL code of a language name or ISO 639-1 code, if it is one of the known languages.
+fn language_code(language: &str) -> Option<&'static str> {
+    let language = language.trim();
+
+    LANGUAGES
+        .iter()
+        .find(|(code, name)| {
+            code.eq_ignore_ascii_case(language) || name.eq_ignore_ascii_case(language)
+        })
+        .map(|(code, _)| *code)
+}
+
+impl DeepL {
+    pub fn new(
+        auth_key: &str,
+        source_language: &str,
+        connection: &Connection,
+        retry: retry::Policy,
+    ) -> Result<Self> {
+        let url = if auth_key.ends_with(":fx") {
+            FREE_API_URL
+        } else {
+            API_URL
+        };
+
+        Ok(Self {
+            client: translator::client(
+                Some((AUTHORIZATION, &format!("DeepL-Auth-Key {}", auth_key))),
+                connection,
+            )?,
+            url,
+            // DeepL detects the source language of values it isn't told about.
+            source_lang: language_code(source_language).map(str::to_ascii_uppercase),
+            retry,
+            glossary: None,
+            glossaries: Mutex::new(None),
+        })
+    }
+
+    pub fn with_glossary(self, glossary: Option<String>) -> Self {
+        Self { glossary, ..self }
+    }
+
+    /// The response if it is a success, the error DeepL gave otherwise.
+    async fn check(response: Response) -> Result<Response> {
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let message = response
+            .json::<Error>()
+            .await
+            .map_or_else(|_| status.to_string(), |error| error.message);
+
+        match status {
+            StatusCode::TOO_MANY_REQUESTS => bail!("DeepL rate limit exceeded: {}", message),
+            // DeepL's own status for a used up character quota.
+            _ if status.as_u16() == 456 => bail!("DeepL quota exceeded: {}", message),
+            _ => bail!("DeepL: {}", message),
+        }
+    }
+
+    async fn list_glossaries(&self) -> Result<Vec<Glossary>> {
+        let request = self.client.get(format!("{}/v2/glossaries", self.url));
+        let response = Self::check(retry::send(request, &self.retry).await?).await?;
+
+        Ok(response.json::<Glossaries>().await?.glossaries)
+    }
+
+    fn source_lang(&self) -> Result<&str> {
+        self.source_lang
+            .as_deref()
+            .wrap_err("DeepL glossaries need a --source-lang that DeepL translates from")
+    }
+
+    /// The ID of the glossary of the given name for translating into the language.
+    async fn glossary_id(&self, name: &str, target_lang: &str) -> Result<String> {
+        let mut glossaries = self.glossaries.lock().await;
+
+        if glossaries.is_none() {
+            *glossaries = Some(self.list_glossaries().await?);
+        }
+
+        let source_lang = self.source_lang()?;
+
+        glossaries
+            .iter()
+            .flatten()
+            .find(|glossary| glossary.translates(name, source_lang, target_lang))
+            .map(|glossary| glossary.glossary_id.clone())
+            .wrap_err_with(|| {
+                format!(
+                    "No DeepL glossary '{}' from {} into {}, `glossary sync` makes it",
+                    name, source_lang, target_lang
+                )
+            })
+    }
+
+    /// Replaces the glossary of the given name for the language with one of the dictionary
+    /// entries. DeepL glossaries can't be edited, so a new one is made before the old ones are
+    /// deleted. Entries with tabs or line breaks, which the format has no room for, are left out.
+    pub async fn sync_glossary(
+        &self,
+        name: &str,
+        target_language: &str,
+        dictionary: &Dictionary,
+    ) -> Result<Glossary> {
+        let source_lang = self.source_lang()?;
+        let target_lang = language_code(target_language)
+            .wrap_err_with(|| format!("DeepL doesn't translate into {}", target_language))?
+            .to_ascii_uppercase();
+
+        let entries = dictionary
+            .iter()
+            .filter(|(key, value)| {
+                !key.is_empty()
+                    && !value.trim().is_empty()
+                    && !format!("{}{}", key, value).contains(['\t', '\n', '\r'])
+            })
+            .map(|(key, value)| format!("{}\t{}\n", key, value.trim()))
+            .collect::<String>();
+
+        let old = self
+            .list_glossaries()
+            .await?
+            .into_iter()
+            .filter(|glossary| glossary.translates(name, source_lang, &target_lang))
+            .collect::<Vec<_>>();
+
+        let glossary = NewGlossary {
+            name,
+            source_lang,
+            target_lang: &target_lang,
+            entries,
+            entries_format: "tsv",
+        };
+
+        let request = self
+            .client
+            .post(format!("{}/v2/glossaries", self.url))
+            .json(&glossary);
+        let response = Self::check(retry::send(request, &self.retry).await?).await?;
+        let glossary = response.json::<Glossary>().await?;
+
+        for old in old {
+            let request = self
+                .client
+                .delete(format!("{}/v2/glossaries/{}", self.url, old.glossary_id));
+            Self::check(retry::send(request, &self.retry).await?).await?;
+        }
+
+        Ok(glossary)
+    }
+
+    pub async fn translate(&self, job: &Job) -> Result<Completion> {
+        let code = language_code(&job.language)
+            .wrap_err_with(|| format!("DeepL doesn't translate into {}", job.language))?;
+
+        let target_lang = TARGET_VARIANTS
+            .iter()
+            .find(|(plain, _)| *plain == code)
+            .map_or_else(
+                || code.to_ascii_uppercase(),
+                |(_, variant)| variant.to_string(),
+            );
+
+        // Glossaries are made for the plain languages, not their variants.
+        let glossary_id = match &self.glossary {
+            Some(name) => Some(self.glossary_id(name, code).await?),
+            None => None,
+        };
+
+        let request = Request {
+            text: [&job.source],
+            target_lang,
+            source_lang: self.source_lang.as_deref(),
+            context: job.context.as_deref(),
+            glossary_id: glossary_id.as_deref(),
+            show_billed_characters: true,
+        };
+
+        let request = self
+            .client
+            .post(format!("{}/v2/translate", self.url))
+            .json(&request);
+
+        let response = Self::check(retry::send(request, &self.retry).await?).await?;
+
+        let translation = response
+            .json::<Translations>()
+            .await?
+            .translations
+            .pop()
+            .wrap_err("No translation received")?;
+
+        let characters = translation.billed_characters.unwrap_or(0);
+
+        Ok(Completion {
+            text: translation.text,
+            tokens: characters,
+            input_tokens: characters,
+            model: MODEL.to_string(),
+            request_id: None,
+            confidence: None,
+        })
+    }
+}
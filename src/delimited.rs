@@ -0,0 +1,104 @@
+//! CSV and TSV files, read into a single worksheet and written from the first worksheet of the
+//! translated workbook.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use calamine::{open_workbook, DataType, Range, Reader, Xlsx};
+use color_eyre::{
+    eyre::{Context, ContextCompat},
+    Result,
+};
+
+use crate::{
+    dictionary::{csv_field, csv_records},
+    workbook::{FileFormat, Sheet, WORKSHEET},
+};
+
+/// A field as the value it stands for, numbers included, as long as it reads back the same.
+fn value(field: &str) -> DataType {
+    if let Some(n) = field.parse::<i64>().ok().filter(|n| n.to_string() == field) {
+        return DataType::Int(n);
+    }
+
+    match field.parse::<f64>() {
+        Ok(n) if n.is_finite() && n.to_string() == field => DataType::Float(n),
+        _ => DataType::String(field.to_string()),
+    }
+}
+
+pub(crate) fn read(path: &Path, format: FileFormat) -> Result<Sheet> {
+    let text =
+        fs::read_to_string(path).wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+
+    let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+
+    let records = match format {
+        FileFormat::Tsv => text
+            .lines()
+            .map(|line| line.split('\t').map(str::to_string).collect())
+            .collect(),
+        _ => csv_records(text),
+    };
+
+    let height = records.len();
+    let width = records.iter().map(Vec::len).max().unwrap_or(0);
+
+    let mut range = if height == 0 || width == 0 {
+        Range::empty()
+    } else {
+        Range::new((0, 0), (height as u32 - 1, width as u32 - 1))
+    };
+
+    for (row, record) in records.iter().enumerate() {
+        for (column, field) in record.iter().enumerate() {
+            if !field.is_empty() {
+                range.set_value((row as u32, column as u32), value(field));
+            }
+        }
+    }
+
+    Ok(Sheet {
+        name: WORKSHEET.to_string(),
+        range,
+        formulas: Range::empty(),
+        tab_color: None,
+        comments: BTreeMap::new(),
+    })
+}
+
+/// Writes the first worksheet of the translated workbook at `source` to `destination`.
+pub(crate) fn write(source: &Path, destination: &Path, format: FileFormat) -> Result<()> {
+    let mut workbook: Xlsx<_> = open_workbook(source)?;
+
+    let range = workbook
+        .worksheet_range_at(0)
+        .wrap_err("The translated workbook has no worksheets")??;
+
+    let separator = if format == FileFormat::Tsv { "\t" } else { "," };
+    let mut text = String::new();
+
+    // Rows and columns before the first value count too, calamine leaves them out of the range.
+    if let Some((last_row, last_column)) = range.end() {
+        for row in 0..=last_row {
+            let fields = (0..=last_column)
+                .map(|column| {
+                    let field = range
+                        .get_value((row, column))
+                        .map(DataType::to_string)
+                        .unwrap_or_default();
+
+                    match format {
+                        FileFormat::Tsv => field.replace(['\t', '\r', '\n'], " "),
+                        _ => csv_field(&field).into_owned(),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            text.push_str(&fields.join(separator));
+            text.push('\n');
+        }
+    }
+
+    fs::write(destination, text)
+        .wrap_err_with(|| format!("Failed to write {}", destination.display()))
+}
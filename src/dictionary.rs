@@ -0,0 +1,656 @@
+//! The glossary of fixed translations given as the first argument, as plain text with one
+//! `source – translation` pair per line, as CSV or TSV with the source in the first column and
+//! the translation in the second, or as a JSON object mapping sources to translations.
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs, io, mem,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use clap::ValueEnum;
+use color_eyre::{
+    eyre::{eyre, Context, ContextCompat},
+    Result,
+};
+use serde::Serialize;
+
+/// Source terms, with their whitespace normalized unless it is preserved and keyed as the
+/// [`MatchMode`] says, mapped to their translations.
+pub type Dictionary = BTreeMap<String, String>;
+
+/// How the keys of dictionaries, translation memories and the cache are made of their sources,
+/// and the cell values looked up in them of theirs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchMode {
+    /// The text as it is, so that `IT` and `it` are different entries
+    Exact,
+    /// The text lowercased
+    #[default]
+    CaseInsensitive,
+    /// The text lowercased and without its punctuation, so that `Total:` finds `total`
+    Normalized,
+}
+
+/// What `normalized` matching leaves out. Symbols like currency signs tell values apart.
+const PUNCTUATION: &[char] = &[
+    '.', ',', ';', ':', '!', '?', '¿', '¡', '\'', '"', '‘', '’', '“', '”', '«', '»', '…', '-', '–',
+    '—', '(', ')', '[', ']', '{', '}',
+];
+
+impl MatchMode {
+    pub fn key(self, text: &str) -> String {
+        match self {
+            Self::Exact => text.to_string(),
+            Self::CaseInsensitive => text.to_lowercase(),
+            Self::Normalized => normalize_whitespace(&text.replace(PUNCTUATION, "")).to_lowercase(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// One `source – translation` pair per line
+    Text,
+    Csv,
+    Tsv,
+    /// An object mapping sources to translations
+    Json,
+}
+
+impl Format {
+    /// The format going by the extension of the file, plain text for anything else.
+    pub fn detect(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+
+        match extension.as_deref() {
+            Some("csv") => Self::Csv,
+            Some("tsv" | "tab") => Self::Tsv,
+            Some("json") => Self::Json,
+            _ => Self::Text,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Detected from the file extension if not given.
+    pub format: Option<Format>,
+    /// What separates sources from translations in plain text dictionaries, the first en dash
+    /// or hyphen by default.
+    pub separator: Option<String>,
+    pub preserve_whitespace: bool,
+    pub match_mode: MatchMode,
+}
+
+/// Trims the text and collapses every run of whitespace inside it to a single space.
+pub(crate) fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Splits CSV text into records, with `"`-quoted fields that can hold commas, line breaks and
+/// doubled quotes.
+pub(crate) fn csv_records(text: &str) -> Vec<Vec<String>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                record.push(mem::take(&mut field));
+                records.push(mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    records
+}
+
+/// The pairs of the dictionary, in the order they appear in, with their line numbers unless the
+/// dictionary is a JSON one.
+fn entries(
+    text: &str,
+    format: Format,
+    separator: Option<&str>,
+) -> Result<Vec<(Option<usize>, String, String)>> {
+    let rows = match format {
+        Format::Json => {
+            let entries = serde_json::from_str::<BTreeMap<String, String>>(text)
+                .wrap_err("The dictionary is not a JSON object of strings")?;
+
+            return Ok(entries
+                .into_iter()
+                .map(|(key, value)| (None, key, value))
+                .collect());
+        }
+        Format::Csv => csv_records(text),
+        Format::Tsv => text
+            .lines()
+            .map(|line| line.split('\t').map(str::to_string).collect())
+            .collect(),
+        Format::Text => text.lines().map(|line| vec![line.to_string()]).collect(),
+    };
+
+    let mut entries = vec![];
+
+    for (i, row) in rows.iter().enumerate() {
+        if row.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+
+        let invalid = || eyre!("Invalid entry at line #{}", i + 1);
+
+        let (key, value) = match format {
+            Format::Text => {
+                let line = row[0].trim();
+
+                match separator {
+                    Some(separator) => line.split_once(separator),
+                    None => line.split_once(['–', '-']),
+                }
+                .wrap_err_with(invalid)?
+            }
+            _ => match row.as_slice() {
+                [key, value, ..] => (key.as_str(), value.as_str()),
+                _ => return Err(invalid()),
+            },
+        };
+
+        entries.push((
+            Some(i + 1),
+            key.trim_start().to_string(),
+            value.trim().to_string(),
+        ));
+    }
+
+    Ok(entries)
+}
+
+/// The entries of the dictionary file, in the format its extension stands for unless one is given.
+fn read(path: &Path, options: &Options) -> Result<Vec<(Option<usize>, String, String)>> {
+    let text = fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read the dictionary {}", path.display()))?;
+
+    // Spreadsheet programs like to start their CSV exports with a byte order mark.
+    let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+
+    let format = options.format.unwrap_or_else(|| Format::detect(path));
+    entries(text, format, options.separator.as_deref())
+        .wrap_err_with(|| format!("Invalid dictionary {}", path.display()))
+}
+
+/// The source of an entry as it is written, with its whitespace normalized unless it is
+/// preserved. Keyed by the match mode, it is what values are looked up by.
+fn source(key: &str, preserve_whitespace: bool) -> String {
+    if preserve_whitespace {
+        key.trim_end().to_string()
+    } else {
+        normalize_whitespace(key)
+    }
+}
+
+pub fn load(path: &Path, options: &Options) -> Result<Dictionary> {
+    Ok(read(path, options)?
+        .into_iter()
+        .map(|(_, key, value)| {
+            (
+                options
+                    .match_mode
+                    .key(&source(&key, options.preserve_whitespace)),
+                value,
+            )
+        })
+        .collect())
+}
+
+/// Merges the dictionaries, with the entries of later ones replacing those of earlier ones.
+pub fn load_all(paths: &[&Path], options: &Options) -> Result<Dictionary> {
+    let mut merged = Dictionary::new();
+    let mut origins = BTreeMap::new();
+
+    for &path in paths {
+        for (key, value) in load(path, options)? {
+            if let Some(previous) = merged.get(&key).filter(|&previous| *previous != value) {
+                eprintln!(
+                    "Warning: '{}' is translated as '{}' in {} and as '{}' in {}, using the latter",
+                    key,
+                    previous,
+                    origins[&key],
+                    value,
+                    path.display()
+                );
+            }
+
+            origins.insert(key.clone(), path.display());
+            merged.insert(key, value);
+        }
+    }
+
+    Ok(merged)
+}
+
+/// The merged dictionaries of a run, which `--reload-dictionary` reads again when their files
+/// change, for the entries added in the meantime to be used by the rest of the run.
+pub(crate) struct Live {
+    paths: Vec<PathBuf>,
+    options: Options,
+    modified: Mutex<Vec<Option<SystemTime>>>,
+    current: Mutex<Arc<Dictionary>>,
+}
+
+fn modified(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .collect()
+}
+
+impl Live {
+    pub(crate) fn load(paths: &[&Path], options: Options) -> Result<Self> {
+        let paths = paths
+            .iter()
+            .map(|path| path.to_path_buf())
+            .collect::<Vec<_>>();
+        let modified = modified(&paths);
+        let dictionary = load_all(
+            &paths.iter().map(PathBuf::as_path).collect::<Vec<_>>(),
+            &options,
+        )?;
+
+        Ok(Self {
+            paths,
+            options,
+            modified: Mutex::new(modified),
+            current: Mutex::new(Arc::new(dictionary)),
+        })
+    }
+
+    /// The dictionary as it was last read.
+    pub(crate) fn current(&self) -> Arc<Dictionary> {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Reads the files again if any of them changed since, and gives the number of entries that
+    /// are new or translated differently. A dictionary that doesn't load, e.g. because it is
+    /// being saved, is kept as it was and read again the next time.
+    pub(crate) fn reload(&self) -> Option<usize> {
+        let modified = modified(&self.paths);
+
+        if *self.modified.lock().unwrap() == modified {
+            return None;
+        }
+
+        let paths = self.paths.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+        let dictionary = match load_all(&paths, &self.options) {
+            Ok(dictionary) => dictionary,
+            Err(e) => {
+                eprintln!("Warning: failed to reload the dictionary: {:#}", e);
+                return None;
+            }
+        };
+
+        let mut current = self.current.lock().unwrap();
+        let changed = dictionary
+            .iter()
+            .filter(|(key, value)| current.get(*key) != Some(value))
+            .count();
+
+        *current = Arc::new(dictionary);
+        *self.modified.lock().unwrap() = modified;
+        Some(changed)
+    }
+}
+
+/// What `validate-dictionary` found in the dictionaries.
+#[derive(Debug, Default)]
+pub struct Validation {
+    /// Each with the file and line of the entry it is about.
+    pub problems: Vec<String>,
+    /// The merged entries with their sources normalized, the later ones of duplicates winning
+    /// like they do when the dictionaries are loaded.
+    pub entries: Vec<(String, String)>,
+}
+
+/// Goes through the entries of the dictionaries for those that loading them would silently merge
+/// or take as they are: duplicates and conflicting translations of the same source, sources that
+/// differ only in whitespace or case, empty translations, sources translated as themselves and,
+/// in plain text dictionaries without `--dict-separator`, lines split at a hyphen of the source
+/// rather than at their en dash.
+pub fn validate(paths: &[&Path], options: &Options) -> Result<Validation> {
+    let mut validation = Validation::default();
+    // The last entry of every lowercased source, with where it is.
+    let mut seen = BTreeMap::<String, (String, String, String)>::new();
+
+    for &path in paths {
+        let format = options.format.unwrap_or_else(|| Format::detect(path));
+
+        for (line, key, value) in read(path, options)? {
+            let location = match line {
+                Some(line) => format!("{}:{}", path.display(), line),
+                None => path.display().to_string(),
+            };
+
+            let source = source(&key, options.preserve_whitespace);
+            let lowercase = options.match_mode.key(&source);
+            let mut problem = |message: String| {
+                validation
+                    .problems
+                    .push(format!("{}: {}", location, message));
+            };
+
+            if value.is_empty() {
+                problem(format!("'{}' has an empty translation", source));
+            } else if value.to_lowercase() == lowercase {
+                problem(format!("'{}' is translated as itself", source));
+            }
+
+            if format == Format::Text && options.separator.is_none() && value.contains('–') {
+                problem(format!(
+                    "'{}' was split off at a hyphen, though the line has an en dash",
+                    source
+                ));
+            }
+
+            match seen.get(&lowercase) {
+                Some((other, _, translation)) if *translation != value => problem(format!(
+                    "'{}' is translated as '{}', but as '{}' at {}",
+                    source, value, translation, other
+                )),
+                Some((other, written, _)) if *written != key => problem(format!(
+                    "'{}' differs from '{}' at {} only in whitespace or case",
+                    key, written, other
+                )),
+                Some((other, ..)) => problem(format!("'{}' is repeated from {}", source, other)),
+                None => {}
+            }
+
+            seen.insert(lowercase, (location, key, value));
+        }
+    }
+
+    validation.entries = seen
+        .into_values()
+        .map(|(_, key, value)| (source(&key, options.preserve_whitespace), value))
+        .collect();
+
+    Ok(validation)
+}
+
+/// API translations to add to a dictionary file once the run is over, for `--learn`.
+#[derive(Debug)]
+pub struct Learned {
+    path: PathBuf,
+    format: Format,
+    separator: Option<String>,
+    /// Keyed by the lowercased source, which is what the dictionary looks values up by.
+    entries: BTreeMap<String, (String, String)>,
+}
+
+pub(crate) fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+impl Learned {
+    /// Learns into the file at `path`, written in the format its extension stands for or, if it
+    /// has none of the known ones, in `format`.
+    pub fn new(path: PathBuf, format: Format, separator: Option<String>) -> Self {
+        let format = match Format::detect(&path) {
+            Format::Text => format,
+            detected => detected,
+        };
+
+        Self {
+            path,
+            format,
+            separator,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, source: &str, translation: &str) {
+        self.entries
+            .entry(source.to_lowercase())
+            .or_insert_with(|| (source.to_string(), translation.to_string()));
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// A line of the file, unless the entry can't be told apart from its neighbours or its
+    /// separator in the format.
+    fn line(&self, source: &str, translation: &str) -> Option<String> {
+        let multiline = source.contains('\n') || translation.contains('\n');
+
+        match self.format {
+            Format::Text if multiline => None,
+            Format::Text => {
+                let separated = match &self.separator {
+                    Some(separator) => source.contains(separator.as_str()),
+                    None => source.contains(['–', '-']),
+                };
+
+                let separator = self.separator.as_deref().unwrap_or(" – ");
+                (!separated).then(|| format!("{}{}{}", source, separator, translation))
+            }
+            Format::Tsv if multiline || source.contains('\t') || translation.contains('\t') => None,
+            Format::Tsv => Some(format!("{}\t{}", source, translation)),
+            Format::Csv => Some(format!("{},{}", csv_field(source), csv_field(translation))),
+            Format::Json => unreachable!(),
+        }
+    }
+
+    /// Appends the entries to the file, or merges them into the object of a JSON one, and
+    /// returns how many were written.
+    pub fn write(&self) -> Result<usize> {
+        let existing = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let (text, written) = if self.format == Format::Json {
+            let mut object = if existing.trim().is_empty() {
+                BTreeMap::new()
+            } else {
+                serde_json::from_str::<BTreeMap<String, String>>(&existing)
+                    .wrap_err("The learned dictionary is not a JSON object of strings")?
+            };
+
+            for (source, translation) in self.entries.values() {
+                object.insert(source.clone(), translation.clone());
+            }
+
+            (
+                serde_json::to_string_pretty(&object)? + "\n",
+                self.entries.len(),
+            )
+        } else {
+            let lines = self
+                .entries
+                .values()
+                .filter_map(|(source, translation)| self.line(source, translation))
+                .collect::<Vec<_>>();
+
+            let mut text = existing;
+
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+
+            for line in &lines {
+                text.push_str(line);
+                text.push('\n');
+            }
+
+            (text, lines.len())
+        };
+
+        fs::write(&self.path, text)
+            .wrap_err_with(|| format!("Failed to write {}", self.path.display()))?;
+
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn pairs(text: &str, format: Format, separator: Option<&str>) -> Vec<(String, String)> {
+        entries(text, format, separator)
+            .unwrap()
+            .into_iter()
+            .map(|(_, key, value)| (source(&key, false), value))
+            .collect()
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn detects_the_format_by_extension() {
+        assert_eq!(Format::detect(Path::new("terms.CSV")), Format::Csv);
+        assert_eq!(Format::detect(Path::new("terms.tab")), Format::Tsv);
+        assert_eq!(Format::detect(Path::new("terms.json")), Format::Json);
+        assert_eq!(Format::detect(Path::new("terms.txt")), Format::Text);
+        assert_eq!(Format::detect(Path::new("terms")), Format::Text);
+    }
+
+    #[test]
+    fn reads_text_dictionaries() {
+        assert_eq!(
+            pairs(
+                "Total – Gesamt\n\nSub-total - Zwischensumme\n",
+                Format::Text,
+                None
+            ),
+            [
+                pair("Total", "Gesamt"),
+                pair("Sub", "total - Zwischensumme")
+            ]
+        );
+        assert_eq!(
+            pairs("Sub-total => Zwischensumme", Format::Text, Some("=>")),
+            [pair("Sub-total", "Zwischensumme")]
+        );
+    }
+
+    #[test]
+    fn reads_csv_and_tsv_dictionaries() {
+        assert_eq!(
+            pairs(
+                "Total,Gesamt\r\n\"Total, net\",\"Netto \"\"gesamt\"\"\",note\n",
+                Format::Csv,
+                None
+            ),
+            [
+                pair("Total", "Gesamt"),
+                pair("Total, net", "Netto \"gesamt\"")
+            ]
+        );
+        assert_eq!(
+            pairs("Total\tGesamt\n", Format::Tsv, None),
+            [pair("Total", "Gesamt")]
+        );
+    }
+
+    #[test]
+    fn reads_json_dictionaries() {
+        assert_eq!(
+            pairs(r#"{"Total": "Gesamt"}"#, Format::Json, None),
+            [pair("Total", "Gesamt")]
+        );
+        assert!(entries("[]", Format::Json, None).is_err());
+    }
+
+    #[test]
+    fn reports_the_line_of_invalid_entries() {
+        let error = entries("Total – Gesamt\nTotal", Format::Text, None).unwrap_err();
+
+        assert_eq!(error.to_string(), "Invalid entry at line #2");
+    }
+
+    #[test]
+    fn match_modes_key_the_sources() {
+        assert_eq!(MatchMode::Exact.key("Total:"), "Total:");
+        assert_eq!(MatchMode::CaseInsensitive.key("Total:"), "total:");
+        assert_eq!(MatchMode::Normalized.key(" «Total» : "), "total");
+    }
+
+    #[test]
+    fn load_skips_the_byte_order_mark_and_keys_the_entries() {
+        let path = env::temp_dir().join(format!("dictionary-{}.csv", process::id()));
+        fs::write(&path, "\u{feff}Total  general,Gesamt\n").unwrap();
+        let dictionary = load(&path, &Options::default());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            dictionary.unwrap(),
+            Dictionary::from([pair("total general", "Gesamt")])
+        );
+    }
+
+    #[test]
+    fn learned_lines_leave_out_entries_the_format_cannot_hold() {
+        let learned = Learned::new(PathBuf::from("learned.txt"), Format::Text, None);
+
+        assert_eq!(
+            learned.line("Total", "Gesamt").as_deref(),
+            Some("Total – Gesamt")
+        );
+        assert_eq!(learned.line("Sub-total", "Zwischensumme"), None);
+        assert_eq!(learned.line("Total\ngeneral", "Gesamt"), None);
+
+        let learned = Learned::new(PathBuf::from("learned.csv"), Format::Text, None);
+
+        assert_eq!(
+            learned.line("Total, net", "Netto").as_deref(),
+            Some("\"Total, net\",Netto")
+        );
+    }
+}
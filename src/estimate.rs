@@ -0,0 +1,166 @@
+//! What a run would send to the API and roughly cost, for the `estimate` subcommand.
+
+use tiktoken_rs::CoreBPE;
+
+use crate::scheduler::Job;
+
+/// What a model costs in USD per million tokens, or per million characters for DeepL and Google.
+#[derive(Debug, Clone, Copy)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// List prices of the OpenAI and Anthropic chat models, the more specific names first.
+const PRICES: [(&str, Price); 10] = [
+    (
+        "gpt-4o-mini",
+        Price {
+            input: 0.15,
+            output: 0.6,
+        },
+    ),
+    (
+        "gpt-4o",
+        Price {
+            input: 2.5,
+            output: 10.,
+        },
+    ),
+    (
+        "gpt-4-turbo",
+        Price {
+            input: 10.,
+            output: 30.,
+        },
+    ),
+    (
+        "gpt-4",
+        Price {
+            input: 30.,
+            output: 60.,
+        },
+    ),
+    (
+        "gpt-3.5-turbo",
+        Price {
+            input: 0.5,
+            output: 1.5,
+        },
+    ),
+    (
+        "claude-3-5-sonnet",
+        Price {
+            input: 3.,
+            output: 15.,
+        },
+    ),
+    (
+        "claude-3-5-haiku",
+        Price {
+            input: 0.8,
+            output: 4.,
+        },
+    ),
+    (
+        "claude-3-opus",
+        Price {
+            input: 15.,
+            output: 75.,
+        },
+    ),
+    (
+        "claude-3-sonnet",
+        Price {
+            input: 3.,
+            output: 15.,
+        },
+    ),
+    (
+        "claude-3-haiku",
+        Price {
+            input: 0.25,
+            output: 1.25,
+        },
+    ),
+];
+
+/// The list price of the model, if it is a known one.
+pub fn price(model: &str) -> Option<Price> {
+    PRICES
+        .iter()
+        .find(|(name, _)| model.starts_with(name))
+        .map(|(_, price)| *price)
+}
+
+#[derive(Debug, Default)]
+pub struct Estimate {
+    /// The unique values that would be sent.
+    pub values: usize,
+    /// The cells holding them.
+    pub cells: usize,
+    /// The values translated from the dictionary or the caches instead.
+    pub hits: usize,
+    pub input_tokens: usize,
+    /// Assuming the translations are about as long as their sources.
+    pub output_tokens: usize,
+    pub characters: usize,
+    /// The instructions every prompt is sent with.
+    pub system_prompt: String,
+}
+
+impl Estimate {
+    /// Counts a job that would be sent, for the given number of cells.
+    pub fn add(&mut self, job: &Job, cells: usize, bpe: &CoreBPE) {
+        self.values += 1;
+        self.cells += cells;
+
+        // Long values are sent a chunk at a time.
+        let prompts = match job.chunks.as_slice() {
+            [] => vec![job.prompt.as_str()],
+            chunks => chunks.iter().map(|chunk| chunk.prompt.as_str()).collect(),
+        };
+
+        for prompt in prompts {
+            self.input_tokens += bpe.encode_with_special_tokens(&self.system_prompt).len()
+                + bpe.encode_with_special_tokens(prompt).len();
+        }
+
+        self.output_tokens += bpe.encode_with_special_tokens(&job.source).len();
+        self.characters += job.source.chars().count();
+    }
+
+    /// The cost in USD at the given price, per token or per character of the source.
+    pub fn cost(&self, price: Price, per_character: bool) -> f64 {
+        if per_character {
+            self.characters as f64 * price.input / 1_000_000.
+        } else {
+            (self.input_tokens as f64 * price.input + self.output_tokens as f64 * price.output)
+                / 1_000_000.
+        }
+    }
+
+    pub fn print(&self, model: &str, cost: Option<f64>) {
+        let rows = [
+            ("Values to translate", self.values.to_string()),
+            ("Cells", self.cells.to_string()),
+            ("Dictionary and cache hits", self.hits.to_string()),
+            ("Input tokens", self.input_tokens.to_string()),
+            ("Output tokens", self.output_tokens.to_string()),
+            ("Characters", self.characters.to_string()),
+            (
+                "Estimated cost",
+                match cost {
+                    Some(cost) => format!("${:.2} ({})", cost, model),
+                    None => format!("unknown, no price for {}", model),
+                },
+            ),
+        ];
+
+        let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+
+        for (label, value) in rows {
+            println!("{:width$}  {}", label, value, width = width);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! The cells whose translation still failed after the second attempt, for `--failures`.
+
+use std::{fs, path::Path};
+
+use calamine::{DataType, Range};
+use color_eyre::{eyre::Context, Result};
+
+use crate::{dictionary::csv_field, workbook::cell_reference};
+
+#[derive(Debug)]
+pub(crate) struct Failure {
+    sheet: String,
+    /// Where the cell is in its worksheet, like `B7`.
+    cell: String,
+    source: String,
+    error: String,
+}
+
+impl Failure {
+    pub(crate) fn new(
+        sheet: &str,
+        range: &Range<DataType>,
+        cell: (u32, u16),
+        source: &str,
+        error: impl ToString,
+    ) -> Self {
+        Self {
+            sheet: sheet.to_string(),
+            cell: cell_reference(range, cell),
+            source: source.to_string(),
+            error: error.to_string(),
+        }
+    }
+}
+
+/// Writes the failures as CSV, one line per cell.
+pub(crate) fn write(path: &Path, failures: &[Failure]) -> Result<()> {
+    let mut text = String::from("sheet,cell,source,error\n");
+
+    for failure in failures {
+        let fields = [
+            &failure.sheet,
+            &failure.cell,
+            &failure.source,
+            &failure.error,
+        ];
+
+        text.push_str(&fields.map(|field| csv_field(field)).join(","));
+        text.push('\n');
+    }
+
+    fs::write(path, text).wrap_err_with(|| format!("Failed to write {}", path.display()))
+}
@@ -0,0 +1,66 @@
+//! The models of `--fallback-model`, which a value is sent to in turn when the model it was for
+//! fails on it, like when its content filter trips or its requests keep failing.
+
+use std::sync::Arc;
+
+use color_eyre::eyre::Context;
+use tracing::debug;
+
+use crate::{
+    limiter::Limiter,
+    scheduler::Job,
+    translator::{Translating, Translator},
+};
+
+pub(crate) struct Fallback {
+    translator: Arc<dyn Translator>,
+    limiter: Arc<Limiter>,
+    models: Vec<String>,
+}
+
+impl Fallback {
+    pub(crate) fn new(
+        translator: Arc<dyn Translator>,
+        limiter: Arc<Limiter>,
+        models: Vec<String>,
+    ) -> Self {
+        Self {
+            translator,
+            limiter,
+            models,
+        }
+    }
+}
+
+impl Translator for Fallback {
+    fn translate<'a>(&'a self, job: &'a Job) -> Translating<'a> {
+        Box::pin(async move {
+            let mut result = self.translator.translate(job).await;
+            let mut failed = job.model.as_str();
+
+            for model in self.models.iter().filter(|model| **model != job.model) {
+                let Err(e) = &result else {
+                    break;
+                };
+
+                debug!(cell = ?job.cell, failed, model, error = %e, "Falling back");
+
+                // Every model is paid for, and counted towards the rate limit.
+                self.limiter.acquire(job.estimated_tokens).await;
+
+                let job = Job {
+                    model: model.clone(),
+                    ..job.clone()
+                };
+
+                result =
+                    self.translator.translate(&job).await.wrap_err_with(|| {
+                        format!("Fell back to {} after {} failed", model, failed)
+                    });
+                failed = model;
+            }
+
+            result
+        })
+    }
+}
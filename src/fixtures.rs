@@ -0,0 +1,194 @@
+//! The responses of the APIs saved to a directory by `--record` and read back from it by
+//! `--replay`, which reruns a job without a network or an account, the same way every time. Every
+//! request has a file named after a hash of its method, URL and body, so that it is found again
+//! whatever runs before it.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use color_eyre::{
+    eyre::{bail, Context},
+    Result,
+};
+use reqwest::{Request, Response};
+use serde::{Deserialize, Serialize};
+
+/// The query parameters carrying API keys, left out of the URLs hashed and saved.
+const SECRET_PARAMETERS: [&str; 1] = ["key"];
+
+/// The response headers not worth saving.
+const SKIPPED_HEADERS: [&str; 3] = ["set-cookie", "content-length", "transfer-encoding"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Record,
+    Replay,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Fixture {
+    method: String,
+    url: String,
+    /// The body of the request, only there to tell what the response was for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request: Option<String>,
+    status: u16,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    body: String,
+}
+
+#[derive(Debug)]
+pub struct Fixtures {
+    dir: PathBuf,
+    mode: Mode,
+    /// How many times every request was sent so far, since the same request can get a different
+    /// response the next time, like when a translation is asked for again.
+    sent: Mutex<HashMap<u64, usize>>,
+}
+
+/// FNV-1a, which unlike the hashers of the standard library is the same on every build.
+fn fnv(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The URL of the request without the API keys in its query.
+fn url(request: &Request) -> String {
+    let mut url = request.url().clone();
+    let pairs = url
+        .query_pairs()
+        .filter(|(name, _)| !SECRET_PARAMETERS.contains(&name.as_ref()))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+
+    url.set_query(None);
+
+    if !pairs.is_empty() {
+        url.query_pairs_mut().extend_pairs(pairs);
+    }
+
+    url.to_string()
+}
+
+fn body(request: &Request) -> Option<&[u8]> {
+    request.body().and_then(|body| body.as_bytes())
+}
+
+impl Fixtures {
+    /// Saves the responses to the directory, creating it if need be.
+    pub fn record(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir).wrap_err_with(|| {
+            format!("Failed to create the fixture directory {}", dir.display())
+        })?;
+
+        Ok(Self::new(dir, Mode::Record))
+    }
+
+    /// Answers the requests from the responses saved to the directory.
+    pub fn replay(dir: &Path) -> Result<Self> {
+        if !dir.is_dir() {
+            bail!("No fixture directory {}", dir.display());
+        }
+
+        Ok(Self::new(dir, Mode::Replay))
+    }
+
+    fn new(dir: &Path, mode: Mode) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            mode,
+            sent: Mutex::default(),
+        }
+    }
+
+    pub(crate) fn replaying(&self) -> bool {
+        self.mode == Mode::Replay
+    }
+
+    /// The file of the request, `<hash>-<n>.json` for the `n`th time it is sent.
+    fn path(&self, request: &Request) -> PathBuf {
+        let method = request.method().as_str().bytes();
+        let url = url(request).into_bytes();
+        let body = body(request).unwrap_or_default().iter().copied();
+        let hash = fnv(method.chain([0]).chain(url).chain([0]).chain(body));
+
+        let mut sent = self.sent.lock().unwrap();
+        let n = sent.entry(hash).or_default();
+        *n += 1;
+
+        self.dir.join(format!("{:016x}-{}.json", hash, n))
+    }
+
+    /// The saved response to the request. One sent more times than it was recorded gets the
+    /// response of the first time.
+    pub(crate) fn respond(&self, request: &Request) -> Result<Response> {
+        let mut path = self.path(request);
+
+        if !path.exists() {
+            let name = path.file_name().unwrap().to_string_lossy();
+            let first = format!("{}-1.json", name.split('-').next().unwrap());
+            path.set_file_name(first);
+        }
+
+        let json = fs::read_to_string(&path).wrap_err_with(|| {
+            format!(
+                "No fixture for {} {} in {}",
+                request.method(),
+                url(request),
+                self.dir.display()
+            )
+        })?;
+
+        let fixture = serde_json::from_str::<Fixture>(&json)
+            .wrap_err_with(|| format!("Invalid fixture {}", path.display()))?;
+
+        let mut builder = http::Response::builder().status(fixture.status);
+
+        for (name, value) in &fixture.headers {
+            builder = builder.header(name, value);
+        }
+
+        Ok(builder
+            .body(fixture.body)
+            .wrap_err_with(|| format!("Invalid fixture {}", path.display()))?
+            .into())
+    }
+
+    /// Saves the response to the request, which is read for that and given back to the caller.
+    pub(crate) async fn save(&self, request: &Request, response: Response) -> Result<Response> {
+        let path = self.path(request);
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = response.bytes().await?;
+
+        let fixture = Fixture {
+            method: request.method().to_string(),
+            url: url(request),
+            request: body(request).map(|body| String::from_utf8_lossy(body).into_owned()),
+            status: status.as_u16(),
+            headers: headers
+                .iter()
+                .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+                .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.into())))
+                .collect(),
+            body: String::from_utf8_lossy(&bytes).into_owned(),
+        };
+
+        fs::write(&path, serde_json::to_string_pretty(&fixture)?)
+            .wrap_err_with(|| format!("Failed to write the fixture {}", path.display()))?;
+
+        let mut builder = http::Response::builder().status(status);
+
+        if let Some(saved) = builder.headers_mut() {
+            *saved = headers;
+        }
+
+        Ok(builder.body(bytes).unwrap().into())
+    }
+}
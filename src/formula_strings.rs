@@ -0,0 +1,164 @@
+//! Finding and replacing the string literals of Excel formulas, such as the `"Positive"` and
+//! `"Negative"` in `IF(A1>0, "Positive", "Negative")`.
+
+use std::ops::Range;
+
+/// A string literal of a formula, with the byte range it spans (quotes included) and its
+/// unescaped value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Literal {
+    pub span: Range<usize>,
+    pub value: String,
+}
+
+/// Extracts the string literals of a formula. Quotes inside literals are escaped by doubling
+/// them, and quoted sheet names like `'My Sheet'!A1` are skipped. An unterminated literal ends
+/// the search.
+pub fn extract(formula: &str) -> Vec<Literal> {
+    let mut literals = vec![];
+    let mut chars = formula.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '"' => {
+                let mut value = String::new();
+                let mut end = None;
+
+                while let Some((i, c)) = chars.next() {
+                    if c != '"' {
+                        value.push(c);
+                    } else if chars.next_if(|&(_, c)| c == '"').is_some() {
+                        value.push('"');
+                    } else {
+                        end = Some(i + 1);
+                        break;
+                    }
+                }
+
+                let Some(end) = end else {
+                    break;
+                };
+
+                literals.push(Literal {
+                    span: start..end,
+                    value,
+                });
+            }
+            '\'' => {
+                // Quoted sheet names escape their quotes the same way.
+                while let Some((_, c)) = chars.next() {
+                    if c == '\'' && chars.next_if(|&(_, c)| c == '\'').is_none() {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    literals
+}
+
+/// Quotes a value as a formula string literal.
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+/// Replaces the literals, as returned by [`extract`] for the same formula, with the given
+/// values.
+pub fn replace<'a>(
+    formula: &str,
+    replacements: impl IntoIterator<Item = (&'a Literal, &'a str)>,
+) -> String {
+    let mut result = String::with_capacity(formula.len());
+    let mut position = 0;
+
+    for (literal, value) in replacements {
+        result.push_str(&formula[position..literal.span.start]);
+        result.push_str(&quote(value));
+        position = literal.span.end;
+    }
+
+    result.push_str(&formula[position..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(formula: &str) -> Vec<String> {
+        extract(formula)
+            .into_iter()
+            .map(|literal| literal.value)
+            .collect()
+    }
+
+    #[test]
+    fn extracts_literals_with_their_spans() {
+        let formula = r#"IF(A1>0, "Positive", "Negative")"#;
+        let literals = extract(formula);
+
+        assert_eq!(values(formula), ["Positive", "Negative"]);
+        assert_eq!(&formula[literals[0].span.clone()], r#""Positive""#);
+        assert_eq!(&formula[literals[1].span.clone()], r#""Negative""#);
+    }
+
+    #[test]
+    fn unescapes_doubled_quotes() {
+        assert_eq!(values(r#"="Say ""hi"" now""#), [r#"Say "hi" now"#]);
+        assert_eq!(values(r#"="""""#), [r#"""#]);
+        assert_eq!(values(r#"=CONCAT("a""", """b")"#), [r#"a""#, r#""b"#]);
+    }
+
+    #[test]
+    fn extracts_empty_literals() {
+        let formula = r#"IF(A1="", "", "x")"#;
+        let literals = extract(formula);
+
+        assert_eq!(values(formula), ["", "", "x"]);
+        assert_eq!(&formula[literals[0].span.clone()], r#""""#);
+    }
+
+    #[test]
+    fn skips_quoted_sheet_references() {
+        assert_eq!(values(r#"='My Sheet'!A1&"x""#), ["x"]);
+        assert_eq!(values(r#"='It''s'!A1&"x""#), ["x"]);
+        assert_eq!(values(r#"='Say "hi"'!A1"#), Vec::<String>::new());
+    }
+
+    #[test]
+    fn stops_at_unterminated_literals() {
+        assert_eq!(values(r#"="done"&"open"#), ["done"]);
+        assert_eq!(values(r#"="open ""quoted"""#), Vec::<String>::new());
+        assert_eq!(values(r#"='Open!A1&"x""#), Vec::<String>::new());
+    }
+
+    #[test]
+    fn quotes_values() {
+        assert_eq!(quote("plain"), r#""plain""#);
+        assert_eq!(quote(r#"Say "hi""#), r#""Say ""hi""""#);
+        assert_eq!(quote(""), r#""""#);
+    }
+
+    #[test]
+    fn replaces_literals() {
+        let formula = r#"IF('It''s'!A1>0, "Positive", "Say ""no""")"#;
+        let literals = extract(formula);
+        let replaced = replace(formula, literals.iter().zip(["Pozitiv", r#"Spune "nu""#]));
+
+        assert_eq!(replaced, r#"IF('It''s'!A1>0, "Pozitiv", "Spune ""nu""")"#);
+        assert_eq!(values(&replaced), ["Pozitiv", r#"Spune "nu""#]);
+    }
+
+    #[test]
+    fn round_trips_unchanged_literals() {
+        let formula = r#"=IF(A1="", "Empty ""cell""", 'It''s'!B2&"!")"#;
+        let literals = extract(formula);
+        let unchanged = literals
+            .iter()
+            .map(|literal| (literal, literal.value.as_str()));
+
+        assert_eq!(replace(formula, unchanged), formula);
+    }
+}
@@ -0,0 +1,84 @@
+//! Entries of the dictionary and the translation memories that come close to a value without an
+//! exact match, for `--fuzzy-threshold`.
+
+/// How alike two texts are, from 0 for nothing in common to 1 for identical ones: one minus
+/// their Levenshtein distance in characters relative to the length of the longer one.
+pub(crate) fn similarity(a: &str, b: &str) -> f64 {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    let longest = a.len().max(b.len());
+
+    if longest == 0 {
+        return 1.;
+    }
+
+    // The distances from the start of `a` read so far to every start of `b`.
+    let mut distances = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, x) in a.iter().enumerate() {
+        let mut diagonal = distances[0];
+        distances[0] = i + 1;
+
+        for (j, y) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(x != y);
+            diagonal = distances[j + 1];
+            distances[j + 1] = substitution.min(distances[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    1. - distances[b.len()] as f64 / longest as f64
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Match<'a> {
+    pub(crate) source: &'a str,
+    pub(crate) translation: &'a str,
+    pub(crate) similarity: f64,
+}
+
+impl Match<'_> {
+    /// The match as context for the prompt.
+    pub(crate) fn hint(&self) -> String {
+        format!(
+            "A similar text, \"{}\", was translated as \"{}\"",
+            self.source, self.translation
+        )
+    }
+}
+
+/// The entry whose lowercased source is the most similar to `key`, as long as it is at least
+/// `threshold` similar.
+pub(crate) fn closest<'a>(
+    key: &str,
+    entries: impl IntoIterator<Item = (&'a String, &'a String)>,
+    threshold: f64,
+) -> Option<Match<'a>> {
+    let length = key.chars().count();
+    let mut closest = None::<Match>;
+
+    for (source, translation) in entries {
+        let minimum = closest.map_or(threshold, |closest| closest.similarity);
+
+        // The distance is at least the difference in length, which is much quicker to tell.
+        let other = source.chars().count();
+        let bound = 1. - length.abs_diff(other) as f64 / length.max(other).max(1) as f64;
+
+        if bound < minimum {
+            continue;
+        }
+
+        let similarity = similarity(key, source);
+
+        if similarity >= threshold
+            && !matches!(closest, Some(closest) if similarity <= closest.similarity)
+        {
+            closest = Some(Match {
+                source,
+                translation,
+                similarity,
+            });
+        }
+    }
+
+    closest
+}
@@ -0,0 +1,93 @@
+//! The quality gates of `--fail-on`, like `any-error`, `missing>5%` or `cost>2.50`, which make
+//! the run exit with an error for CI pipelines to hold back the translated files.
+
+use std::{fmt, str::FromStr};
+
+use color_eyre::{
+    eyre::{bail, eyre},
+    Report, Result,
+};
+use serde::{Serialize, Serializer};
+
+use crate::{budget::Spent, report::Cells};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gate {
+    /// Any cell that could not be translated.
+    AnyError,
+    /// More than this percentage of the cells to translate left without a translation.
+    Missing(f64),
+    /// More than this many dollars spent on the API.
+    Cost(f64),
+}
+
+fn parse_number(s: &str, gate: &str) -> Result<f64> {
+    match s.trim().parse::<f64>() {
+        Ok(n) if n.is_finite() && n >= 0. => Ok(n),
+        _ => Err(eyre!("Invalid threshold '{}' of the {} gate", s, gate)),
+    }
+}
+
+impl FromStr for Gate {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+
+        if s == "any-error" {
+            return Ok(Self::AnyError);
+        }
+
+        match s.split_once('>') {
+            Some(("missing", percent)) => match percent.trim().strip_suffix('%') {
+                Some(percent) => Ok(Self::Missing(parse_number(percent, "missing")?)),
+                None => bail!("The missing gate is a percentage, like 'missing>5%'"),
+            },
+            Some(("cost", cost)) => Ok(Self::Cost(parse_number(cost, "cost")?)),
+            _ => bail!(
+                "Invalid gate '{}', expected 'any-error', 'missing>N%' or 'cost>X'",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Gate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AnyError => write!(f, "any-error"),
+            Self::Missing(percent) => write!(f, "missing>{}%", percent),
+            Self::Cost(cost) => write!(f, "cost>{}", cost),
+        }
+    }
+}
+
+impl Serialize for Gate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Gate {
+    /// Why the run violates the gate, if it does. The cost gate only holds for models whose
+    /// price is known.
+    pub(crate) fn violation(self, cells: &Cells, spent: Spent) -> Option<String> {
+        match self {
+            Self::AnyError => (cells.failed > 0)
+                .then(|| format!("{} cells could not be translated", cells.failed)),
+            Self::Missing(percent) => {
+                let missing = cells.failed + cells.untranslated;
+                let share = 100. * missing as f64 / cells.translatable().max(1) as f64;
+
+                (share > percent).then(|| {
+                    format!(
+                        "{:.1}% of the cells are without a translation, more than {}%",
+                        share, percent
+                    )
+                })
+            }
+            Self::Cost(cost) => (spent.cost > cost)
+                .then(|| format!("The run cost ${:.2}, more than ${:.2}", spent.cost, cost)),
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Gettext PO and POT files, read into a single worksheet of their messages and written by
+//! filling in the messages of the source file that have no translation yet. Everything else,
+//! comments, flags and contexts included, is written as it was read.
+
+use std::{collections::BTreeMap, fs, mem, path::Path};
+
+use calamine::{open_workbook, DataType, Range, Reader, Xlsx};
+use color_eyre::{
+    eyre::{eyre, Context, ContextCompat},
+    Result,
+};
+
+use crate::{
+    strings::{po_string, po_unquote},
+    workbook::{Sheet, WORKSHEET},
+};
+
+/// The first row of the worksheet, followed by a row for every message in the order of the file.
+const HEADERS: [&str; 2] = ["msgid", "msgid_plural"];
+
+/// A message with the lines it was read from.
+#[derive(Debug, Default)]
+struct Message {
+    /// The comments, the flags, the `msgctxt` and the `msgid` lines.
+    head: Vec<String>,
+    /// The `msgstr` lines.
+    tail: Vec<String>,
+    msgid: String,
+    plural: Option<String>,
+    msgstr: Vec<String>,
+}
+
+impl Message {
+    /// Whether the message is to be translated. The header entry, of the empty `msgid`, is not.
+    fn untranslated(&self) -> bool {
+        !self.msgid.is_empty() && self.msgstr.iter().all(String::is_empty)
+    }
+
+    fn text(&mut self, field: Field) -> Option<&mut String> {
+        match field {
+            Field::Context => None,
+            Field::Id => Some(&mut self.msgid),
+            Field::Plural => Some(self.plural.get_or_insert_with(String::new)),
+            Field::Str(index) => self.msgstr.get_mut(index),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Item {
+    /// A blank line, or a comment of no message like the obsolete ones.
+    Line(String),
+    Message(Message),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Context,
+    Id,
+    Plural,
+    Str(usize),
+}
+
+fn parse(text: &str) -> Result<Vec<Item>> {
+    let mut items = vec![];
+    let mut comments = vec![];
+    let mut message = None::<Message>;
+    let mut field = Field::Id;
+
+    for (i, raw) in text.lines().enumerate() {
+        let invalid = || eyre!("Invalid line #{}", i + 1);
+        let line = raw.trim();
+
+        if line.is_empty() {
+            items.extend(message.take().map(Item::Message));
+            items.extend(comments.drain(..).map(Item::Line));
+            items.push(Item::Line(raw.to_string()));
+            continue;
+        }
+
+        // Comments after a `msgstr` belong to the next message.
+        if line.starts_with('#') {
+            match message.as_mut().filter(|message| message.tail.is_empty()) {
+                Some(message) => message.head.push(raw.to_string()),
+                None => {
+                    items.extend(message.take().map(Item::Message));
+                    comments.push(raw.to_string());
+                }
+            }
+
+            continue;
+        }
+
+        let value = if line.starts_with('"') {
+            po_unquote(line).ok_or_else(invalid)?
+        } else {
+            let (keyword, rest) = line.split_once(' ').ok_or_else(invalid)?;
+
+            field = match keyword {
+                "msgctxt" => Field::Context,
+                "msgid" => Field::Id,
+                "msgid_plural" => Field::Plural,
+                "msgstr" => Field::Str(0),
+                _ => keyword
+                    .strip_prefix("msgstr[")
+                    .and_then(|index| index.strip_suffix(']')?.parse().ok())
+                    .map(Field::Str)
+                    .ok_or_else(invalid)?,
+            };
+
+            // A `msgctxt` or `msgid` after a `msgstr` starts the next message.
+            if matches!(field, Field::Context | Field::Id)
+                && message
+                    .as_ref()
+                    .is_some_and(|message| !message.tail.is_empty())
+            {
+                items.extend(message.take().map(Item::Message));
+            }
+
+            if let Field::Str(index) = field {
+                let message = message.as_mut().ok_or_else(invalid)?;
+
+                if message.msgstr.len() <= index {
+                    message.msgstr.resize(index + 1, String::new());
+                }
+            }
+
+            po_unquote(rest).ok_or_else(invalid)?
+        };
+
+        let message = match field {
+            Field::Context | Field::Id if !line.starts_with('"') => {
+                message.get_or_insert_with(|| Message {
+                    head: mem::take(&mut comments),
+                    ..Message::default()
+                })
+            }
+            _ => message.as_mut().ok_or_else(invalid)?,
+        };
+
+        match field {
+            Field::Str(_) => message.tail.push(raw.to_string()),
+            _ => message.head.push(raw.to_string()),
+        }
+
+        if let Some(text) = message.text(field) {
+            text.push_str(&value);
+        }
+    }
+
+    items.extend(message.map(Item::Message));
+    items.extend(comments.into_iter().map(Item::Line));
+
+    Ok(items)
+}
+
+fn load(path: &Path) -> Result<Vec<Item>> {
+    let text =
+        fs::read_to_string(path).wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+
+    parse(text.strip_prefix('\u{feff}').unwrap_or(&text))
+        .wrap_err_with(|| format!("Invalid PO file {}", path.display()))
+}
+
+fn messages(items: &[Item]) -> impl Iterator<Item = &Message> {
+    items.iter().filter_map(|item| match item {
+        Item::Message(message) => Some(message),
+        Item::Line(_) => None,
+    })
+}
+
+/// The number of plural forms of the language, from the `Plural-Forms` of the header entry.
+fn plural_forms(items: &[Item]) -> Option<usize> {
+    let header = messages(items).find(|message| message.msgid.is_empty())?;
+    let (_, rest) = header.msgstr.first()?.split_once("nplurals=")?;
+
+    rest.split(';').next()?.trim().parse().ok()
+}
+
+/// Reads the messages without a translation into their rows, leaving the rows of the others
+/// empty.
+pub(crate) fn read(path: &Path) -> Result<Sheet> {
+    let items = load(path)?;
+
+    let height = messages(&items).count() as u32 + 1;
+    let mut range = Range::new((0, 0), (height - 1, HEADERS.len() as u32 - 1));
+
+    for (column, header) in HEADERS.iter().enumerate() {
+        range.set_value((0, column as u32), DataType::String(header.to_string()));
+    }
+
+    for (row, message) in messages(&items).enumerate() {
+        if !message.untranslated() {
+            continue;
+        }
+
+        let row = row as u32 + 1;
+        range.set_value((row, 0), DataType::String(message.msgid.clone()));
+
+        if let Some(plural) = &message.plural {
+            range.set_value((row, 1), DataType::String(plural.clone()));
+        }
+    }
+
+    Ok(Sheet {
+        name: WORKSHEET.to_string(),
+        range,
+        formulas: Range::empty(),
+        tab_color: None,
+        comments: BTreeMap::new(),
+    })
+}
+
+/// Writes the PO file at `source` to `destination` with the translations of the first worksheet
+/// of the translated workbook at `translated`. Plural messages get the singular translation in
+/// `msgstr[0]` and the plural one in all the other forms the header or the message has, at least
+/// two. Messages left without a translation keep their empty `msgstr`.
+pub(crate) fn write(translated: &Path, source: &Path, destination: &Path) -> Result<()> {
+    let mut workbook: Xlsx<_> = open_workbook(translated)?;
+
+    let range = workbook
+        .worksheet_range_at(0)
+        .wrap_err("The translated workbook has no worksheets")??;
+
+    let items = load(source)?;
+    let forms = plural_forms(&items).unwrap_or(2);
+    let mut text = String::new();
+    let mut row = 0;
+
+    for item in &items {
+        let message = match item {
+            Item::Line(line) => {
+                text.push_str(line);
+                text.push('\n');
+                continue;
+            }
+            Item::Message(message) => message,
+        };
+
+        row += 1;
+
+        let translation = |column| {
+            range
+                .get_value((row, column))
+                .map(DataType::to_string)
+                .filter(|translation| !translation.is_empty())
+        };
+
+        for line in &message.head {
+            text.push_str(line);
+            text.push('\n');
+        }
+
+        match translation(0).filter(|_| message.untranslated()) {
+            Some(singular) if message.plural.is_some() => {
+                let plural = translation(1).unwrap_or_else(|| singular.clone());
+
+                for i in 0..forms.max(message.msgstr.len()).max(2) {
+                    let form = if i == 0 { &singular } else { &plural };
+                    text.push_str(&format!("msgstr[{}] {}\n", i, po_string(form)));
+                }
+            }
+            Some(singular) => text.push_str(&format!("msgstr {}\n", po_string(&singular))),
+            None => {
+                for line in &message.tail {
+                    text.push_str(line);
+                    text.push('\n');
+                }
+            }
+        }
+    }
+
+    fs::write(destination, text)
+        .wrap_err_with(|| format!("Failed to write {}", destination.display()))
+}
@@ -0,0 +1,105 @@
+//! Bootstrapping a dictionary from a workbook and its existing human translation.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+};
+
+use calamine::{open_workbook, DataType, Reader, Xlsx};
+use color_eyre::{eyre::ContextCompat, Result};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::workbook::WORKSHEET;
+
+/// How strongly a source and a translated word have to be associated (by their Dice
+/// coefficient) to make it into the glossary.
+const MIN_ASSOCIATION: f64 = 0.5;
+
+fn strings(path: &Path) -> Result<BTreeMap<(u32, u32), String>> {
+    let mut workbook: Xlsx<_> = open_workbook(path)?;
+
+    let range = workbook
+        .worksheet_range(WORKSHEET)
+        .wrap_err_with(|| format!("No worksheet named '{}' in {}", WORKSHEET, path.display()))??;
+
+    Ok(range
+        .cells()
+        .filter_map(|(row, column, data)| match data {
+            DataType::String(s) => Some(((row as u32, column as u32), s.to_string())),
+            _ => None,
+        })
+        .collect())
+}
+
+fn words(text: &str) -> BTreeSet<String> {
+    text.unicode_words()
+        .filter(|w| w.chars().any(char::is_alphabetic))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Aligns the cells of the two workbooks by position and pairs every source word with the
+/// translated word it co-occurs with most consistently. Pairs that co-occur in fewer than
+/// `min_frequency` cells are left out.
+pub fn auto_populate(
+    source: &Path,
+    translated: &Path,
+    min_frequency: usize,
+) -> Result<BTreeMap<String, String>> {
+    let translated = strings(translated)?;
+
+    let mut source_counts = HashMap::<String, usize>::new();
+    let mut translated_counts = HashMap::<String, usize>::new();
+    let mut pair_counts = HashMap::<(String, String), usize>::new();
+
+    for (cell, text) in strings(source)? {
+        let Some(translation) = translated.get(&cell) else {
+            continue;
+        };
+
+        let (source_words, translated_words) = (words(&text), words(translation));
+
+        for word in &source_words {
+            *source_counts.entry(word.clone()).or_default() += 1;
+        }
+
+        for word in &translated_words {
+            *translated_counts.entry(word.clone()).or_default() += 1;
+        }
+
+        for source_word in &source_words {
+            for translated_word in &translated_words {
+                *pair_counts
+                    .entry((source_word.clone(), translated_word.clone()))
+                    .or_default() += 1;
+            }
+        }
+    }
+
+    let mut best = HashMap::<String, (f64, String)>::new();
+
+    for ((source_word, translated_word), count) in pair_counts {
+        if count < min_frequency {
+            continue;
+        }
+
+        let dice = 2. * count as f64
+            / (source_counts[&source_word] + translated_counts[&translated_word]) as f64;
+
+        if dice < MIN_ASSOCIATION {
+            continue;
+        }
+
+        match best.get(&source_word) {
+            Some((score, word)) if (*score, word) >= (dice, &translated_word) => {}
+            _ => {
+                best.insert(source_word, (dice, translated_word));
+            }
+        }
+    }
+
+    Ok(best
+        .into_iter()
+        .map(|(source_word, (_, translated_word))| (source_word, translated_word))
+        .collect())
+}
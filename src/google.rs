@@ -0,0 +1,283 @@
+//! The Google Cloud Translation API
+//! (<https://cloud.google.com/translate/docs/reference/rest/v3/projects/translateText>), which
+//! gets the plain values like DeepL.
+
+use std::time::{Duration, Instant};
+
+use color_eyre::{
+    eyre::{bail, ContextCompat},
+    Result,
+};
+use reqwest::{
+    header::AUTHORIZATION, Client, RequestBuilder, Response as HttpResponse, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    estimate::Price,
+    prompt::{language_code, LANGUAGES},
+    retry,
+    scheduler::Job,
+    translator::{self, Completion, Connection},
+};
+
+/// What the history records as the model of Google translations.
+pub const MODEL: &str = "google";
+
+/// The list price of the Translation API, per million characters.
+pub const PRICE: Price = Price {
+    input: 20.,
+    output: 0.,
+};
+
+const API_URL: &str = "https://translation.googleapis.com/v3";
+
+/// Where the service account of a Compute Engine VM, Cloud Run service or GKE workload gets its
+/// access tokens.
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+/// How Google is told who is asking.
+#[derive(Debug, Clone)]
+pub enum Credentials {
+    ApiKey(String),
+    /// An OAuth access token, like the ones `gcloud auth print-access-token` prints for the
+    /// account or the service account it is logged in as.
+    AccessToken(String),
+    /// The service account of the machine, from the metadata server.
+    Metadata,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request<'a> {
+    contents: [&'a str; 1],
+    target_language_code: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_language_code: Option<&'a str>,
+    mime_type: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    glossary_config: Option<GlossaryConfig<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GlossaryConfig<'a> {
+    glossary: &'a str,
+    /// Dictionary keys are matched regardless of case, and so are the glossary terms.
+    ignore_case: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    translations: Vec<Translation>,
+    #[serde(default)]
+    glossary_translations: Vec<Translation>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Translation {
+    translated_text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: Error,
+}
+
+#[derive(Debug, Deserialize)]
+struct Error {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Token {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// The credentials of the requests to Google APIs, with the access token they last got from the
+/// metadata server.
+pub(crate) struct Auth {
+    credentials: Credentials,
+    /// The OAuth scope of the metadata server's tokens, the default ones of the service account
+    /// if `None`.
+    scope: Option<&'static str>,
+    /// The last access token of the metadata server and when it expires.
+    token: Mutex<Option<(String, Instant)>>,
+    retry: retry::Policy,
+}
+
+impl Auth {
+    pub(crate) fn new(
+        credentials: Credentials,
+        scope: Option<&'static str>,
+        retry: retry::Policy,
+    ) -> Self {
+        Self {
+            credentials,
+            scope,
+            token: Mutex::new(None),
+            retry,
+        }
+    }
+
+    /// An access token of the service account, fetched again shortly before it expires.
+    async fn metadata_token(&self, client: &Client) -> Result<String> {
+        let mut token = self.token.lock().await;
+
+        if let Some((access_token, expires)) = &*token {
+            if Instant::now() < *expires {
+                return Ok(access_token.clone());
+            }
+        }
+
+        let mut request = client
+            .get(METADATA_TOKEN_URL)
+            .header("Metadata-Flavor", "Google");
+
+        if let Some(scope) = self.scope {
+            request = request.query(&[("scopes", scope)]);
+        }
+
+        let response = retry::send(request, &self.retry).await?;
+
+        if !response.status().is_success() {
+            bail!(
+                "Google: the metadata server has no access token ({}), give --google-api-key or \
+                 --google-access-token when not running on Google Cloud",
+                response.status()
+            );
+        }
+
+        let fetched = response.json::<Token>().await?;
+        let expires = Instant::now() + Duration::from_secs(fetched.expires_in.saturating_sub(60));
+        *token = Some((fetched.access_token.clone(), expires));
+
+        Ok(fetched.access_token)
+    }
+
+    /// The request with the credentials.
+    pub(crate) async fn authorize(
+        &self,
+        client: &Client,
+        request: RequestBuilder,
+    ) -> Result<RequestBuilder> {
+        Ok(match &self.credentials {
+            Credentials::ApiKey(key) => request.query(&[("key", key)]),
+            Credentials::AccessToken(token) => {
+                request.header(AUTHORIZATION, format!("Bearer {}", token))
+            }
+            Credentials::Metadata => request.header(
+                AUTHORIZATION,
+                format!("Bearer {}", self.metadata_token(client).await?),
+            ),
+        })
+    }
+}
+
+/// Fails with the message of the error response, if it is one.
+pub(crate) async fn check(response: HttpResponse) -> Result<HttpResponse> {
+    let status = response.status();
+
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let message = response
+        .json::<ErrorResponse>()
+        .await
+        .map_or_else(|_| status.to_string(), |response| response.error.message);
+
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => bail!("Google quota exceeded: {}", message),
+        _ => bail!("Google: {}", message),
+    }
+}
+
+pub struct Google {
+    client: Client,
+    auth: Auth,
+    /// `projects/{project}/locations/{location}`.
+    parent: String,
+    glossary: Option<String>,
+    source_lang: Option<&'static str>,
+    retry: retry::Policy,
+}
+
+impl Google {
+    /// A client for the project, in the `global` location unless a glossary needs a regional
+    /// one. The glossary is given by its ID and has to be in the same location.
+    pub fn new(
+        credentials: Credentials,
+        project: &str,
+        location: &str,
+        glossary: Option<&str>,
+        source_language: &str,
+        connection: &Connection,
+        retry: retry::Policy,
+    ) -> Result<Self> {
+        let parent = format!("projects/{}/locations/{}", project, location);
+
+        Ok(Self {
+            client: translator::client(None, connection)?,
+            auth: Auth::new(credentials, None, retry.clone()),
+            glossary: glossary.map(|glossary| format!("{}/glossaries/{}", parent, glossary)),
+            parent,
+            // Google detects the source language of values it isn't told about.
+            source_lang: LANGUAGES
+                .iter()
+                .find(|(code, name)| {
+                    code.eq_ignore_ascii_case(source_language.trim())
+                        || name.eq_ignore_ascii_case(source_language.trim())
+                })
+                .map(|(code, _)| *code),
+            retry,
+        })
+    }
+
+    pub async fn translate(&self, job: &Job) -> Result<Completion> {
+        let request = Request {
+            contents: [&job.source],
+            target_language_code: language_code(&job.language),
+            source_language_code: self.source_lang,
+            mime_type: "text/plain",
+            glossary_config: self.glossary.as_deref().map(|glossary| GlossaryConfig {
+                glossary,
+                ignore_case: true,
+            }),
+        };
+
+        let request = self
+            .client
+            .post(format!("{}/{}:translateText", API_URL, self.parent))
+            .json(&request);
+
+        let request = self.auth.authorize(&self.client, request).await?;
+        let response = check(retry::send(request, &self.retry).await?).await?;
+        let response = response.json::<Response>().await?;
+
+        // Without a glossary match, the glossary translation is the same as the other one.
+        let translation = response
+            .glossary_translations
+            .into_iter()
+            .chain(response.translations)
+            .next()
+            .wrap_err("No translation received")?;
+
+        // Google bills the characters sent.
+        let characters = job.source.chars().count();
+
+        Ok(Completion {
+            text: translation.translated_text,
+            tokens: characters,
+            input_tokens: characters,
+            model: MODEL.to_string(),
+            request_id: None,
+            confidence: None,
+        })
+    }
+}
@@ -0,0 +1,288 @@
+//! Google Sheets (<https://developers.google.com/sheets/api/reference/rest>), for
+//! `--source-gsheet` and `--dest-gsheet`. A spreadsheet is read through the values API into an
+//! xlsx workbook the translation starts from, and the translated workbook is written back into
+//! one the same way.
+
+use std::path::Path;
+
+use calamine::{open_workbook, DataType, Reader, Xlsx};
+use color_eyre::{
+    eyre::{Context, ContextCompat},
+    Result,
+};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use xlsxwriter::Workbook;
+
+use crate::{
+    google::{self, Auth, Credentials},
+    retry,
+    translator::{self, Connection},
+    workbook,
+};
+
+const API_URL: &str = "https://sheets.googleapis.com/v4/spreadsheets";
+
+/// The OAuth scope of reading and writing spreadsheets, asked of the metadata server.
+const SCOPE: &str = "https://www.googleapis.com/auth/spreadsheets";
+
+#[derive(Debug, Deserialize)]
+struct Spreadsheet {
+    sheets: Vec<SheetEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SheetEntry {
+    properties: Properties,
+}
+
+#[derive(Debug, Deserialize)]
+struct Properties {
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchGet {
+    value_ranges: Vec<ValueRange>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ValueRange {
+    range: String,
+    /// The rows, without the empty ones at the end.
+    #[serde(default)]
+    values: Vec<Vec<Value>>,
+}
+
+pub(crate) struct Sheets {
+    client: Client,
+    auth: Auth,
+    retry: retry::Policy,
+}
+
+/// The A1 notation of a whole sheet.
+fn quoted(title: &str) -> String {
+    format!("'{}'", title.replace('\'', "''"))
+}
+
+/// A value of a translated cell, `null` to leave the cell of the spreadsheet as it is.
+fn value(data: &DataType) -> Value {
+    match data {
+        DataType::Int(n) => json!(n),
+        DataType::Float(n) | DataType::DateTime(n) | DataType::Duration(n) => json!(n),
+        DataType::String(s) | DataType::DateTimeIso(s) | DataType::DurationIso(s) => json!(s),
+        DataType::Bool(b) => json!(b),
+        DataType::Error(_) | DataType::Empty => Value::Null,
+    }
+}
+
+impl Sheets {
+    pub(crate) fn new(
+        credentials: Credentials,
+        connection: &Connection,
+        retry: retry::Policy,
+    ) -> Result<Self> {
+        Ok(Self {
+            client: translator::client(None, connection)?,
+            auth: Auth::new(credentials, Some(SCOPE), retry.clone()),
+            retry,
+        })
+    }
+
+    async fn titles(&self, id: &str) -> Result<Vec<String>> {
+        let request = self
+            .client
+            .get(format!("{}/{}", API_URL, id))
+            .query(&[("fields", "sheets.properties.title")]);
+
+        let request = self.auth.authorize(&self.client, request).await?;
+        let response = google::check(retry::send(request, &self.retry).await?).await?;
+        let spreadsheet = response.json::<Spreadsheet>().await?;
+
+        Ok(spreadsheet
+            .sheets
+            .into_iter()
+            .map(|sheet| sheet.properties.title)
+            .collect())
+    }
+
+    /// The cells of every sheet, rendered as `render` says.
+    async fn values(&self, id: &str, titles: &[String], render: &str) -> Result<Vec<ValueRange>> {
+        let mut query = titles
+            .iter()
+            .map(|title| ("ranges", quoted(title)))
+            .collect::<Vec<_>>();
+
+        query.push(("valueRenderOption", render.to_string()));
+
+        let request = self
+            .client
+            .get(format!("{}/{}/values:batchGet", API_URL, id))
+            .query(&query);
+
+        let request = self.auth.authorize(&self.client, request).await?;
+        let response = google::check(retry::send(request, &self.retry).await?).await?;
+
+        Ok(response.json::<BatchGet>().await?.value_ranges)
+    }
+
+    async fn batch_update(&self, url: String, body: &Value) -> Result<()> {
+        let request = self.client.post(url).json(body);
+        let request = self.auth.authorize(&self.client, request).await?;
+        google::check(retry::send(request, &self.retry).await?).await?;
+
+        Ok(())
+    }
+
+    /// Writes the spreadsheet into an xlsx workbook at `path`, with a worksheet for every sheet
+    /// and the formulas along with their values.
+    pub(crate) async fn download(&self, id: &str, path: &Path) -> Result<()> {
+        let titles = self.titles(id).await?;
+        let values = self.values(id, &titles, "UNFORMATTED_VALUE").await?;
+        let formulas = self.values(id, &titles, "FORMULA").await?;
+
+        let workbook = Workbook::new(path.to_str().wrap_err("Invalid temporary filename")?)?;
+
+        for ((title, values), formulas) in titles.iter().zip(values).zip(formulas) {
+            let mut worksheet = workbook
+                .add_worksheet(Some(title))
+                .wrap_err_with(|| format!("The sheet '{}' can't be an xlsx worksheet", title))?;
+
+            for (row, cells) in values.values.iter().enumerate() {
+                for (column, cell) in cells.iter().enumerate() {
+                    let (row, column) = (row as u32, column as u16);
+
+                    let formula = formulas
+                        .values
+                        .get(row as usize)
+                        .and_then(|cells| cells.get(column as usize))
+                        .and_then(Value::as_str)
+                        .and_then(|formula| formula.strip_prefix('='));
+
+                    let data = match cell {
+                        Value::Number(n) => DataType::Float(n.as_f64().unwrap_or_default()),
+                        Value::Bool(b) => DataType::Bool(*b),
+                        Value::String(s) if !s.is_empty() => DataType::String(s.clone()),
+                        _ => DataType::Empty,
+                    };
+
+                    match (formula, &data) {
+                        (Some(formula), _) => workbook::write_formula(
+                            &mut workbook::Output::new(&mut worksheet, false),
+                            (row, column),
+                            formula,
+                            Some(&data),
+                            None,
+                        )?,
+                        (None, DataType::Float(n)) => {
+                            worksheet.write_number(row, column, *n, None)?
+                        }
+                        (None, DataType::Bool(b)) => {
+                            worksheet.write_boolean(row, column, *b, None)?
+                        }
+                        (None, DataType::String(s)) => {
+                            worksheet.write_string(row, column, s, None)?
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        workbook.close()?;
+
+        Ok(())
+    }
+
+    /// Writes the worksheets of the xlsx workbook at `path` into the sheets of the same names of
+    /// the spreadsheet, adding the ones it doesn't have. Values are written as they are and
+    /// formulas as if typed in, and cells the workbook has nothing in are left as they were.
+    pub(crate) async fn upload(&self, path: &Path, id: &str) -> Result<()> {
+        let mut workbook: Xlsx<_> = open_workbook(path)?;
+        let titles = self.titles(id).await?;
+
+        let added = workbook
+            .sheet_names()
+            .iter()
+            .filter(|name| !titles.contains(name))
+            .map(|name| json!({ "addSheet": { "properties": { "title": name } } }))
+            .collect::<Vec<_>>();
+
+        if !added.is_empty() {
+            let url = format!("{}/{}:batchUpdate", API_URL, id);
+            self.batch_update(url, &json!({ "requests": added }))
+                .await?;
+        }
+
+        let mut values = vec![];
+        let mut formulas = vec![];
+
+        for name in workbook.sheet_names().to_owned() {
+            let range = workbook
+                .worksheet_range(&name)
+                .wrap_err_with(|| format!("No worksheet '{}'", name))??;
+            let formula_range = workbook
+                .worksheet_formula(&name)
+                .wrap_err_with(|| format!("No worksheet '{}'", name))??;
+
+            // Rows and columns before the first cell count too, calamine leaves them out.
+            let Some((last_row, last_column)) = range.end() else {
+                continue;
+            };
+
+            let mut value_rows = vec![];
+            let mut formula_rows = vec![];
+
+            for row in 0..=last_row {
+                let mut value_row = vec![];
+                let mut formula_row = vec![];
+
+                for column in 0..=last_column {
+                    let formula = formula_range
+                        .get_value((row, column))
+                        .filter(|formula| !formula.is_empty());
+
+                    match formula {
+                        Some(formula) => {
+                            value_row.push(Value::Null);
+                            formula_row.push(json!(format!("={}", formula)));
+                        }
+                        None => {
+                            value_row
+                                .push(range.get_value((row, column)).map_or(Value::Null, value));
+                            formula_row.push(Value::Null);
+                        }
+                    }
+                }
+
+                value_rows.push(value_row);
+                formula_rows.push(formula_row);
+            }
+
+            values.push(ValueRange {
+                range: quoted(&name),
+                values: value_rows,
+            });
+
+            if formula_rows.iter().flatten().any(|cell| !cell.is_null()) {
+                formulas.push(ValueRange {
+                    range: quoted(&name),
+                    values: formula_rows,
+                });
+            }
+        }
+
+        let url = format!("{}/{}/values:batchUpdate", API_URL, id);
+
+        for (input, data) in [("RAW", values), ("USER_ENTERED", formulas)] {
+            if !data.is_empty() {
+                let body = json!({ "valueInputOption": input, "data": data });
+                self.batch_update(url.clone(), &body).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! The commands of `--pre-hook` and `--post-hook`, which the source strings and the translations
+//! are piped through for the house rules of a team, like normalizing product names or checking
+//! the terminology, without a fork of the crate. A hook gets a JSON array of objects, one per
+//! string, on its standard input, and writes a JSON array with a string for each of them to its
+//! standard output.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::Write,
+    process::{Command, Stdio},
+    sync::Arc,
+};
+
+use calamine::DataType;
+use color_eyre::{
+    eyre::{bail, Context},
+    Result,
+};
+use serde::Serialize;
+
+use crate::{
+    scheduler::Job,
+    translator::{Translating, Translator},
+    workbook::Sheet,
+};
+
+#[derive(Serialize)]
+pub(crate) struct Item<'a> {
+    pub(crate) text: &'a str,
+    /// What the translation is of, for the post-hook.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) source: Option<&'a str>,
+    /// The language the translation is in, for the post-hook.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) language: Option<&'a str>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Hook {
+    command: String,
+}
+
+impl Hook {
+    pub(crate) fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+        }
+    }
+
+    fn shell(&self) -> Command {
+        let mut command = match cfg!(windows) {
+            true => Command::new("cmd"),
+            false => Command::new("sh"),
+        };
+
+        command.arg(if cfg!(windows) { "/C" } else { "-c" });
+        command.arg(&self.command);
+        command
+    }
+
+    /// The texts the command gives back for the items, in their order.
+    pub(crate) fn run(&self, items: &[Item<'_>]) -> Result<Vec<String>> {
+        if items.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut child = self
+            .shell()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .wrap_err_with(|| format!("Failed to run the hook `{}`", self.command))?;
+
+        // Written from another thread, for a command writing as it reads not to block on a full
+        // pipe.
+        let input = serde_json::to_vec(items)?;
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+        let output = child.wait_with_output()?;
+        let written = writer.join().unwrap();
+
+        if !output.status.success() {
+            bail!("The hook `{}` exited with {}", self.command, output.status);
+        }
+
+        written.wrap_err_with(|| format!("Failed to write to the hook `{}`", self.command))?;
+
+        let texts = serde_json::from_slice::<Vec<String>>(&output.stdout).wrap_err_with(|| {
+            format!(
+                "The hook `{}` didn't write a JSON array of strings",
+                self.command
+            )
+        })?;
+
+        if texts.len() != items.len() {
+            bail!(
+                "The hook `{}` wrote {} strings for {}",
+                self.command,
+                texts.len(),
+                items.len()
+            );
+        }
+
+        Ok(texts)
+    }
+}
+
+/// Pipes the text cells of the worksheets through the pre-hook, every distinct string once.
+pub(crate) fn preprocess(hook: &Hook, sheets: &mut [Sheet]) -> Result<()> {
+    let strings = sheets
+        .iter()
+        .flat_map(|sheet| sheet.range.used_cells())
+        .filter_map(|(_, _, data)| match data {
+            DataType::String(value) => Some(value.as_str()),
+            _ => None,
+        })
+        .collect::<BTreeSet<_>>();
+
+    let items = strings
+        .iter()
+        .map(|text| Item {
+            text,
+            source: None,
+            language: None,
+        })
+        .collect::<Vec<_>>();
+
+    let processed = strings
+        .iter()
+        .map(|text| text.to_string())
+        .zip(hook.run(&items)?)
+        .collect::<HashMap<_, _>>();
+
+    for sheet in sheets {
+        let changed = sheet
+            .range
+            .used_cells()
+            .filter_map(|(row, column, data)| match data {
+                DataType::String(value) => {
+                    let text = &processed[value];
+                    (text != value).then(|| (row, column, text.clone()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        for (row, column, text) in changed {
+            sheet.range[(row, column)] = DataType::String(text);
+        }
+    }
+
+    Ok(())
+}
+
+/// Pipes every translation through the post-hook.
+pub(crate) struct Hooked {
+    translator: Arc<dyn Translator>,
+    hook: Hook,
+}
+
+impl Hooked {
+    pub(crate) fn new(translator: Arc<dyn Translator>, hook: Hook) -> Self {
+        Self { translator, hook }
+    }
+}
+
+impl Translator for Hooked {
+    fn translate<'a>(&'a self, job: &'a Job) -> Translating<'a> {
+        Box::pin(async move {
+            let mut completion = self.translator.translate(job).await?;
+
+            let hook = self.hook.clone();
+            let (text, source, language) =
+                (completion.text, job.source.clone(), job.language.clone());
+
+            let mut texts = tokio::task::spawn_blocking(move || {
+                hook.run(&[Item {
+                    text: &text,
+                    source: Some(&source),
+                    language: Some(&language),
+                }])
+            })
+            .await??;
+
+            completion.text = texts.remove(0);
+            Ok(completion)
+        })
+    }
+}
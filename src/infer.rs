@@ -0,0 +1,95 @@
+//! Guessing how to treat each column from a sample of its data rows.
+
+use std::collections::BTreeMap;
+
+use calamine::{DataType, Range};
+use serde::Serialize;
+
+/// The number of data rows looked at per column.
+const SAMPLE_ROWS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColumnType {
+    AllNumeric,
+    AllEmpty,
+    MixedStringNumeric,
+    AllString,
+    /// Identifiers like `ABC-123`.
+    CodePattern,
+}
+
+impl ColumnType {
+    /// Whether a string value of a column of this type gets translated.
+    pub fn translates(self, value: &str) -> bool {
+        match self {
+            Self::AllString => true,
+            Self::MixedStringNumeric => !is_numeric(value),
+            Self::AllNumeric | Self::AllEmpty | Self::CodePattern => false,
+        }
+    }
+}
+
+impl std::fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::AllNumeric => "all-numeric",
+            Self::AllEmpty => "all-empty",
+            Self::MixedStringNumeric => "mixed-string-numeric",
+            Self::AllString => "all-string",
+            Self::CodePattern => "code-pattern",
+        })
+    }
+}
+
+fn is_numeric(value: &str) -> bool {
+    value.trim().parse::<f64>().is_ok()
+}
+
+/// Matches `^[A-Z]{2,5}-\d+$`.
+fn is_code(value: &str) -> bool {
+    let Some((prefix, number)) = value.split_once('-') else {
+        return false;
+    };
+
+    (2..=5).contains(&prefix.len())
+        && prefix.bytes().all(|b| b.is_ascii_uppercase())
+        && !number.is_empty()
+        && number.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Infers the type of every column from the data rows following the header rows.
+pub fn infer(range: &Range<DataType>, header_rows: u32) -> BTreeMap<u16, ColumnType> {
+    let rows = range
+        .rows()
+        .skip(header_rows as usize)
+        .take(SAMPLE_ROWS)
+        .collect::<Vec<_>>();
+
+    (0..range.width())
+        .map(|column| {
+            let (mut numbers, mut codes, mut strings) = (0, 0, 0);
+
+            for row in &rows {
+                match &row[column] {
+                    DataType::Empty => {}
+                    DataType::String(s) if s.trim().is_empty() => {}
+                    DataType::String(s) if is_numeric(s) => numbers += 1,
+                    DataType::String(s) if is_code(s.trim()) => codes += 1,
+                    DataType::String(_) => strings += 1,
+                    _ => numbers += 1,
+                }
+            }
+
+            let column_type = match (numbers, codes, strings) {
+                (0, 0, 0) => ColumnType::AllEmpty,
+                (_, 0, 0) => ColumnType::AllNumeric,
+                (0, _, 0) => ColumnType::CodePattern,
+                (0, _, _) => ColumnType::AllString,
+                _ => ColumnType::MixedStringNumeric,
+            };
+
+            (column as u16, column_type)
+        })
+        .collect()
+}
@@ -0,0 +1,218 @@
+//! The record of a run behind `--resume`: every translation and segment class is appended to
+//! the journal as soon as it arrives, so that a run that died halfway can pick up where it
+//! stopped instead of paying for everything again.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{eyre::Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{prompt::Segment, translator::Completion};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+enum Entry {
+    Translation {
+        sheet: String,
+        key: String,
+        text: String,
+        model: String,
+        tokens: usize,
+        #[serde(default)]
+        input_tokens: usize,
+        request_id: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        confidence: Option<f32>,
+    },
+    Segment {
+        sheet: String,
+        key: String,
+        segment: Segment,
+    },
+}
+
+#[derive(Debug)]
+struct Translation {
+    text: String,
+    model: String,
+    tokens: usize,
+    input_tokens: usize,
+    request_id: Option<String>,
+    confidence: Option<f32>,
+}
+
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    translations: HashMap<(String, String), Translation>,
+    segments: HashMap<(String, String), Segment>,
+}
+
+/// Where the journal of a run writing to `destination` goes.
+pub fn path(destination: &Path) -> PathBuf {
+    let mut path = destination.as_os_str().to_owned();
+    path.push(".journal");
+    PathBuf::from(path)
+}
+
+impl Journal {
+    /// Opens the journal at `path`, reading back whatever an earlier run recorded in it.
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let mut translations = HashMap::new();
+        let mut segments = HashMap::new();
+
+        if path.exists() {
+            let file = File::open(&path)
+                .wrap_err_with(|| format!("Failed to read the journal {}", path.display()))?;
+
+            for line in BufReader::new(file).lines() {
+                // Only the last line can be broken, by the process dying while writing it.
+                let Ok(entry) = serde_json::from_str(&line?) else {
+                    break;
+                };
+
+                match entry {
+                    Entry::Translation {
+                        sheet,
+                        key,
+                        text,
+                        model,
+                        tokens,
+                        input_tokens,
+                        request_id,
+                        confidence,
+                    } => {
+                        let translation = Translation {
+                            text,
+                            model,
+                            tokens,
+                            input_tokens,
+                            request_id,
+                            confidence,
+                        };
+
+                        translations.insert((sheet, key), translation);
+                    }
+                    Entry::Segment {
+                        sheet,
+                        key,
+                        segment,
+                    } => {
+                        segments.insert((sheet, key), segment);
+                    }
+                }
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .wrap_err_with(|| format!("Failed to open the journal {}", path.display()))?;
+
+        Ok(Self {
+            path,
+            file,
+            translations,
+            segments,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of translations recorded by earlier runs.
+    pub fn len(&self) -> usize {
+        self.translations.len()
+    }
+
+    /// The translation of the value with the given key, if it has been recorded.
+    pub fn completion(&self, sheet: &str, key: &str) -> Option<Completion> {
+        let translation = self
+            .translations
+            .get(&(sheet.to_string(), key.to_string()))?;
+
+        Some(Completion {
+            text: translation.text.clone(),
+            tokens: translation.tokens,
+            input_tokens: translation.input_tokens,
+            model: translation.model.clone(),
+            request_id: translation.request_id.clone(),
+            confidence: translation.confidence,
+        })
+    }
+
+    pub fn segment(&self, sheet: &str, key: &str) -> Option<Segment> {
+        self.segments
+            .get(&(sheet.to_string(), key.to_string()))
+            .copied()
+    }
+
+    /// Records a translation, unless it has been already.
+    pub fn record(&mut self, sheet: &str, key: &str, completion: &Completion) -> Result<()> {
+        let id = (sheet.to_string(), key.to_string());
+
+        if self.translations.contains_key(&id) {
+            return Ok(());
+        }
+
+        self.write(&Entry::Translation {
+            sheet: sheet.to_string(),
+            key: key.to_string(),
+            text: completion.text.clone(),
+            model: completion.model.clone(),
+            tokens: completion.tokens,
+            input_tokens: completion.input_tokens,
+            request_id: completion.request_id.clone(),
+            confidence: completion.confidence,
+        })?;
+
+        let translation = Translation {
+            text: completion.text.clone(),
+            model: completion.model.clone(),
+            tokens: completion.tokens,
+            input_tokens: completion.input_tokens,
+            request_id: completion.request_id.clone(),
+            confidence: completion.confidence,
+        };
+
+        self.translations.insert(id, translation);
+
+        Ok(())
+    }
+
+    pub fn record_segment(&mut self, sheet: &str, key: &str, segment: Segment) -> Result<()> {
+        self.write(&Entry::Segment {
+            sheet: sheet.to_string(),
+            key: key.to_string(),
+            segment,
+        })?;
+
+        self.segments
+            .insert((sheet.to_string(), key.to_string()), segment);
+
+        Ok(())
+    }
+
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Deletes the journal once the run it belongs to has finished.
+    pub fn remove(self) -> Result<()> {
+        fs::remove_file(&self.path)?;
+
+        Ok(())
+    }
+}
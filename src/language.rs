@@ -0,0 +1,14 @@
+//! Telling which language a text is in, for `--skip-if-target` and `--detect-source-lang`.
+
+/// The English name of the language the text is in, if it can be told reliably. Short texts
+/// rarely can.
+pub(crate) fn detect(text: &str) -> Option<&'static str> {
+    whatlang::detect(text)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang().eng_name())
+}
+
+/// Whether the text is in the language of the given English name, as far as it can be told.
+pub(crate) fn is_in(text: &str, language: &str) -> bool {
+    detect(text).is_some_and(|detected| detected.eq_ignore_ascii_case(language))
+}
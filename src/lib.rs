@@ -0,0 +1,5147 @@
+//! Translates the text of xlsx workbooks through OpenAI or DeepL, keeping everything else about
+//! them as it was.
+//!
+//! [`TranslateJob`] drives a translation from other programs the way the command line tool does.
+
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    env,
+    ffi::OsString,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Cursor, Read, Write},
+    mem,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process, slice,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+mod batch;
+mod budget;
+mod cache;
+mod casing;
+mod chunking;
+mod claude;
+pub mod cli;
+mod columns;
+mod config;
+mod consistency;
+mod deepl;
+mod delimited;
+pub mod dictionary;
+mod estimate;
+mod failures;
+mod fallback;
+mod fixtures;
+mod formula_strings;
+mod fuzzy;
+mod gates;
+mod gettext;
+mod glossary;
+mod google;
+mod gsheets;
+mod guard;
+mod hooks;
+mod infer;
+mod journal;
+mod language;
+mod libretranslate;
+mod limiter;
+mod locale;
+mod logging;
+mod merge;
+mod office;
+mod package;
+mod pii;
+mod previous;
+mod prompt;
+mod protect;
+mod pseudo;
+mod qa;
+mod queue;
+mod report;
+mod retry;
+mod rich_text;
+mod rows;
+pub mod scheduler;
+mod schema;
+pub mod secrets;
+mod selection;
+mod semantic;
+mod serve;
+mod sheets;
+mod sidecar;
+mod skip;
+mod strings;
+mod styles;
+mod terminology;
+mod tmx;
+pub mod translator;
+mod tui;
+mod warnings;
+mod watch;
+mod whitespace;
+pub mod workbook;
+mod xliff;
+
+use budget::Budget;
+use cache::{Cache, Tier};
+use calamine::{open_workbook, DataType, Ods, Range, Reader, Xlsx};
+use casing::Case;
+use chunking::Chunked;
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+use claude::Claude;
+use cli::{
+    confirm_overwrite, Args, AuthCommand, BatchCommand, Command, FuzzyMode, GlossaryCommand,
+    OfflineFill, OnError, QaMode,
+};
+use color_eyre::{
+    eyre::{bail, eyre, Context, ContextCompat},
+    Result,
+};
+use deepl::DeepL;
+use dictionary::{csv_field, csv_records, normalize_whitespace, Dictionary, Learned, MatchMode};
+use estimate::{Estimate, Price};
+use failures::Failure;
+use fallback::Fallback;
+use fixtures::Fixtures;
+use futures_util::{future, stream::FuturesUnordered, StreamExt};
+use google::Google;
+use guard::Guarded;
+use hooks::{Hook, Hooked};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use journal::Journal;
+use libretranslate::LibreTranslate;
+use limiter::Limiter;
+use locale::{Locale, Localizer};
+use package::{ListSource, ListValidation};
+use pii::Redacted;
+use prompt::{
+    adjacent_context, build_prompt, enforce_terms, language_code, language_name, position_context,
+    shorten, Segment, Template,
+};
+use protect::Protected;
+use pseudo::Pseudo;
+use qa::Review;
+use regex::Regex;
+use report::Report;
+use reqwest::header::AUTHORIZATION;
+use scheduler::{classify, estimate_tokens, next_job, receive, Job, RateLimit, Translation};
+use schema::{Schema, Target};
+use semantic::Index;
+use sidecar::Sidecar;
+use skip::Skip;
+use styles::{Geometry, RichText, Styles};
+use terminology::Terminology;
+use tokio::{
+    signal,
+    sync::{mpsc, Notify, Semaphore},
+    task::JoinHandle,
+    time,
+};
+use tracing::{debug, debug_span, trace, Instrument};
+use translator::{
+    bpe, Azure, Backend, Completion, Connection, OpenAi, Style, Translator, API_BASE,
+};
+use warnings::DedupLogger;
+use whitespace::{normalize_lines, Envelope};
+use workbook::{
+    cell_reference, column_label, column_letter, in_place_path, load_tab_color_legend,
+    read_translations, read_worksheets, select_worksheets, verify, write_formula, write_label,
+    write_text, write_value, FileFormat, History, Layout, Output, Provenance, Sheet, Unread,
+    ValueFormats, Written,
+};
+use xlsxwriter::{
+    format::FormatColor,
+    worksheet::validation::{
+        DataValidation, DataValidationErrorType, DataValidationType, ErrorAlertOptions,
+        InputMessageOptions,
+    },
+    Format, Workbook,
+};
+
+/// A translation of one workbook, set up with the same options as the command line tool and
+/// defaulting to the same values, environment variables included.
+///
+/// ```no_run
+/// # async fn translate() -> color_eyre::Result<()> {
+/// xlsx_translator::TranslateJob::new("dictionary.txt", "prices.xlsx")?
+///     .destination("prices.de.xlsx")
+///     .target_language("de")
+///     .api_key("sk-...")
+///     .run()
+///     .await
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TranslateJob {
+    args: Args,
+}
+
+impl TranslateJob {
+    pub fn new(dictionary: impl Into<PathBuf>, source: impl Into<PathBuf>) -> Result<Self> {
+        let mut args = Args::try_parse_from([
+            OsString::from(env!("CARGO_PKG_NAME")),
+            // Until a destination is given, which the arguments can't go without.
+            OsString::from("--in-place"),
+            OsString::from("--"),
+            dictionary.into().into_os_string(),
+            source.into().into_os_string(),
+        ])?;
+
+        // Nobody is there to answer.
+        args.yes = true;
+
+        Ok(Self { args })
+    }
+
+    /// Where to write the translation, over the source by default.
+    pub fn destination(mut self, path: impl Into<PathBuf>) -> Self {
+        self.args.in_place = false;
+        self.args.destination_path = Some(path.into());
+        self
+    }
+
+    /// Merges another dictionary over the ones given so far.
+    pub fn dictionary(mut self, path: impl Into<PathBuf>) -> Self {
+        self.args.dictionary.push(path.into());
+        self
+    }
+
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.args.backend = backend;
+        self
+    }
+
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.args.api_key = vec![key.into()];
+        self
+    }
+
+    /// An OpenAI-compatible API to use instead of OpenAI, like `http://localhost:11434/v1`.
+    pub fn api_base(mut self, url: impl Into<String>) -> Self {
+        self.args.api_base = Some(url.into());
+        self
+    }
+
+    pub fn deepl_auth_key(mut self, key: impl Into<String>) -> Self {
+        self.args.deepl_auth_key = Some(key.into());
+        self
+    }
+
+    /// The language name or ISO 639-1 code to translate into.
+    pub fn target_language(mut self, language: impl Into<String>) -> Self {
+        self.args.target_lang = vec![language.into()];
+        self
+    }
+
+    /// Keeps the progress of the cells in the bar, which is only drawn if it was made to be.
+    pub fn progress(mut self, bar: ProgressBar) -> Self {
+        self.args.progress = Some(bar);
+        self
+    }
+
+    pub fn source_language(mut self, language: impl Into<String>) -> Self {
+        self.args.source_lang = language.into();
+        self
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.args.model = model.into();
+        self
+    }
+
+    /// Adds a worksheet to translate, by name, glob pattern or 1-based position.
+    pub fn sheet(mut self, pattern: &str) -> Result<Self> {
+        self.args.sheet.push(pattern.parse()?);
+        Ok(self)
+    }
+
+    pub fn all_sheets(mut self) -> Self {
+        self.args.all_sheets = true;
+        self
+    }
+
+    /// Keeps a translation memory at the given path, reused by later jobs.
+    pub fn cache(mut self, path: impl Into<PathBuf>) -> Self {
+        self.args.cache = Some(path.into());
+        self
+    }
+
+    pub fn resume(mut self) -> Self {
+        self.args.resume = true;
+        self
+    }
+
+    pub fn requests_per_minute(mut self, rpm: usize) -> Self {
+        self.args.rpm = Some(rpm);
+        self
+    }
+
+    pub fn tokens_per_minute(mut self, tpm: usize) -> Self {
+        self.args.tpm = Some(tpm);
+        self
+    }
+
+    pub fn pack_size(mut self, size: u16) -> Self {
+        self.args.pack_size = Some(size);
+        self
+    }
+
+    /// The options for everything without a method of its own.
+    pub fn args_mut(&mut self) -> &mut Args {
+        &mut self.args
+    }
+
+    pub async fn run(self) -> Result<()> {
+        run(self.args).await
+    }
+}
+
+/// The error of a run stopped by Ctrl-C or the stop file before all the cells are translated, which
+/// the command line tool exits with code 130 on.
+#[derive(Debug)]
+pub struct Stopped;
+
+impl fmt::Display for Stopped {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Stopped before all the cells were translated")
+    }
+}
+
+impl std::error::Error for Stopped {}
+
+/// Carries out whatever the arguments ask for, as the command line tool does.
+pub async fn run(args: Args) -> Result<()> {
+    if args.print_config {
+        print!("{}", config::to_toml(&args)?);
+        return Ok(());
+    }
+
+    logging::init(args.verbose, args.log_file.as_deref())?;
+
+    match &args.command {
+        Some(Command::GenerateConfig { output }) => {
+            if output.exists() && !args.yes && !confirm_overwrite(output)? {
+                return Ok(());
+            }
+
+            fs::write(output, config::to_toml(&args)?)?;
+            eprintln!("Wrote {}", output.display());
+
+            return Ok(());
+        }
+        Some(Command::Completions { shell }) => {
+            let mut command = Args::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(*shell, &mut command, name, &mut io::stdout());
+
+            return Ok(());
+        }
+        Some(Command::GenerateMan { output }) => {
+            if output.exists() && !args.yes && !confirm_overwrite(output)? {
+                return Ok(());
+            }
+
+            let mut file = BufWriter::new(File::create(output)?);
+            clap_mangen::Man::new(Args::command()).render(&mut file)?;
+            file.flush()?;
+            eprintln!("Wrote {}", output.display());
+
+            return Ok(());
+        }
+        Some(Command::GlossaryAutoPopulate {
+            source,
+            translated,
+            output_glossary,
+            min_frequency,
+        }) => {
+            let glossary = glossary::auto_populate(source, translated, *min_frequency)?;
+            let mut file = BufWriter::new(File::create(output_glossary)?);
+
+            for (key, value) in &glossary {
+                writeln!(file, "{} – {}", key, value)?;
+            }
+
+            file.flush()?;
+            eprintln!(
+                "Wrote {} entries to {}",
+                glossary.len(),
+                output_glossary.display()
+            );
+
+            return Ok(());
+        }
+        Some(Command::Extract {
+            source,
+            xliff,
+            strings,
+        }) => {
+            if args.target_lang.len() > 1 {
+                Args::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "extract writes a single --target-lang into its files",
+                    )
+                    .exit();
+            }
+
+            let workbook = open_workbook::<Xlsx<_>, _>(source)?;
+            let sheets = read_worksheets(workbook, &args.sheet, args.all_sheets)?;
+
+            if let Some(path) = xliff {
+                let units = xliff::extract(
+                    path,
+                    source,
+                    &sheets,
+                    &xliff::Options {
+                        source_lang: &args.source_lang,
+                        target_lang: args.target_language(),
+                        header_rows: args.header_rows,
+                        translate_headers: args.translate_headers,
+                        preserve_whitespace: args.preserve_whitespace,
+                    },
+                )?;
+
+                eprintln!("Wrote {} units to {}", units, path.display());
+            }
+
+            if let Some(path) = strings {
+                let dictionaries = args
+                    .dictionary_path
+                    .iter()
+                    .chain(&args.dictionary)
+                    .map(PathBuf::as_path)
+                    .collect::<Vec<_>>();
+
+                let dictionary = dictionary::load_all(
+                    &dictionaries,
+                    &dictionary::Options {
+                        format: args.dict_format,
+                        separator: args.dict_separator.clone(),
+                        preserve_whitespace: args.preserve_whitespace,
+                        match_mode: args.match_mode,
+                    },
+                )?;
+
+                let count = strings::extract(
+                    path,
+                    &sheets,
+                    &dictionary,
+                    &strings::Options {
+                        target_lang: args.target_language(),
+                        header_rows: args.header_rows,
+                        translate_headers: args.translate_headers,
+                        preserve_whitespace: args.preserve_whitespace,
+                        match_mode: args.match_mode,
+                    },
+                )?;
+
+                eprintln!("Wrote {} strings to {}", count, path.display());
+            }
+
+            return Ok(());
+        }
+        Some(Command::Apply {
+            files,
+            translations,
+            ..
+        }) => {
+            if translations.is_some() && args.target_lang.len() > 1 {
+                Args::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "apply takes the translations into a single --target-lang",
+                    )
+                    .exit();
+            }
+
+            let mut args = args.clone();
+            args.source_path = Some(files.source.clone());
+            args.destination_path = Some(files.destination.clone());
+            // The strings without a translation are left like offline.
+            args.offline |= translations.is_some();
+
+            return translate(args).await;
+        }
+        Some(Command::Validate) => return validate(&args),
+        Some(Command::ValidateWorkbook {
+            source,
+            preview,
+            long_cell,
+        }) => return validate_workbook(&args, source, *preview, *long_cell),
+        Some(Command::Batch { command }) => return batch_command(&args, command).await,
+        Some(Command::Auth {
+            command: AuthCommand::Login { key },
+        }) => return secrets::login(*key),
+        Some(Command::Auth {
+            command: AuthCommand::Logout { key },
+        }) => return secrets::logout(*key),
+        Some(Command::Glossary {
+            command: GlossaryCommand::Sync,
+        }) => return glossary_sync(&args).await,
+        Some(Command::Serve {
+            port,
+            host,
+            data_dir,
+            max_jobs,
+        }) => {
+            let data_dir = data_dir
+                .clone()
+                .unwrap_or_else(|| env::temp_dir().join(env!("CARGO_PKG_NAME")));
+
+            let address = SocketAddr::new(*host, *port);
+            return serve::serve(args.clone(), address, data_dir, *max_jobs).await;
+        }
+        Some(Command::TranslateText { delimiter }) => {
+            return translate_text(&args, delimiter.as_deref()).await
+        }
+        Some(Command::ValidateDictionary { normalized }) => {
+            return validate_dictionary(&args, normalized.as_deref())
+        }
+        Some(Command::Translate { dictionary, files }) => {
+            if args.in_place || !args.sources.is_empty() || args.watch.is_some() {
+                Args::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "translate takes a single source and destination, give the files \
+                         without it for --in-place, --source or --watch",
+                    )
+                    .exit();
+            }
+
+            let mut args = args.clone();
+            args.command = None;
+            args.dictionary_path = Some(dictionary.clone());
+            args.source_path = Some(files.source.clone());
+            args.destination_path = Some(files.destination.clone());
+
+            return translate(args).await;
+        }
+        Some(Command::Estimate { .. }) | None => {}
+    }
+
+    if args.source_gsheet.is_some() || args.dest_gsheet.is_some() {
+        return translate_gsheet(&args).await;
+    }
+
+    if !args.sources.is_empty() {
+        return translate_files(args).await;
+    }
+
+    if let (Some(dir), Some(output_dir)) = (&args.watch, &args.output_dir) {
+        return watch::watch(dir, output_dir, |source, destination| {
+            let mut args = args.clone();
+            args.watch = None;
+            args.source_path = Some(source);
+            args.destination_path = Some(destination);
+            translate(args)
+        })
+        .await;
+    }
+
+    translate(args).await
+}
+
+/// Aborts the task once dropped.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Translates every file matching the `--source` patterns into `--output-dir`. The dictionary
+/// and the caches are the same for all of them, so a value translated in one file is taken from
+/// the cache in the next.
+async fn translate_files(args: Args) -> Result<()> {
+    let output_dir = args
+        .output_dir
+        .as_deref()
+        .wrap_err("--source requires --output-dir")?;
+
+    let mut paths = vec![];
+
+    for pattern in &args.sources {
+        let matches = glob::glob(pattern)
+            .wrap_err_with(|| format!("Invalid source pattern '{}'", pattern))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if matches.is_empty() {
+            bail!("No files match '{}'", pattern);
+        }
+
+        paths.extend(matches);
+    }
+
+    paths.sort();
+    paths.dedup();
+
+    let mut names = BTreeSet::new();
+
+    for path in &paths {
+        let name = path.file_name().wrap_err("Invalid source filename")?;
+
+        if !names.insert(name) {
+            bail!(
+                "Several sources are named {}, their translations would overwrite each other",
+                name.to_string_lossy()
+            );
+        }
+    }
+
+    fs::create_dir_all(output_dir)?;
+
+    for (i, path) in paths.iter().enumerate() {
+        eprintln!("File {}/{}: {}", i + 1, paths.len(), path.display());
+
+        let mut args = args.clone();
+        args.sources.clear();
+        args.destination_path = path.file_name().map(|name| output_dir.join(name));
+        args.source_path = Some(path.clone());
+
+        translate(args)
+            .await
+            .wrap_err_with(|| format!("Failed to translate {}", path.display()))?;
+    }
+
+    eprintln!(
+        "Translated {} files into {}",
+        paths.len(),
+        output_dir.display()
+    );
+
+    Ok(())
+}
+
+/// Checks on the batches submitted with `--batch-detach` for a destination, with the key and
+/// the connection options of the run that submitted them.
+async fn batch_command(args: &Args, command: &BatchCommand) -> Result<()> {
+    let connection = Connection {
+        tls_min_version: args.tls_min_version.map(Into::into),
+        proxy: args.proxy.clone(),
+        ca_cert: args.ca_cert.clone(),
+    };
+
+    let authorization = api_keys(args)?
+        .first()
+        .map(|api_key| format!("Bearer {}", api_key));
+    let client = translator::client(
+        authorization.as_deref().map(|value| (AUTHORIZATION, value)),
+        &connection,
+    )?;
+
+    let api_base = args
+        .api_base
+        .as_deref()
+        .unwrap_or(API_BASE)
+        .trim_end_matches('/');
+    let prefix = args.api_request_id_prefix.as_deref();
+
+    match command {
+        BatchCommand::Status { destination } => {
+            batch::print_status(&batch::path(destination), &client, api_base, prefix).await
+        }
+        BatchCommand::Fetch { destination } => {
+            let mut journal = Journal::open(journal::path(destination))?;
+            let (translations, running) = batch::fetch(
+                &batch::path(destination),
+                &mut journal,
+                &client,
+                api_base,
+                prefix,
+            )
+            .await?;
+
+            eprintln!(
+                "Recorded {} translations in {}",
+                translations,
+                journal.path().display()
+            );
+
+            if running > 0 {
+                eprintln!("{} batches are still running", running);
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Translates the values of stdin as the cells of a single-column CSV file, and writes the
+/// translations of that file to stdout with the same delimiters.
+async fn translate_text(args: &Args, delimiter: Option<&str>) -> Result<()> {
+    if args.target_lang.len() > 1 {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "translate-text writes a single --target-lang to stdout",
+            )
+            .exit();
+    }
+
+    let delimiter = match delimiter {
+        Some("\\0") => "\0",
+        Some("") => Args::command()
+            .error(ErrorKind::InvalidValue, "--delimiter can't be empty")
+            .exit(),
+        Some(delimiter) => delimiter,
+        None => "\n",
+    };
+
+    let mut input = String::new();
+    io::stdin()
+        .read_to_string(&mut input)
+        .wrap_err("Failed to read stdin")?;
+
+    // A delimiter at the end ends the last value rather than starting another one.
+    let terminated = input.ends_with(delimiter);
+    let input = input.strip_suffix(delimiter).unwrap_or(&input);
+
+    if input.is_empty() && !terminated {
+        return Ok(());
+    }
+
+    let values = input
+        .split(delimiter)
+        .map(|value| match delimiter {
+            "\n" => value.strip_suffix('\r').unwrap_or(value),
+            _ => value,
+        })
+        .collect::<Vec<_>>();
+
+    let dir = env::temp_dir().join(format!("xlsx-translator-text-{}", process::id()));
+    fs::create_dir_all(&dir)
+        .wrap_err_with(|| format!("Failed to create the directory {}", dir.display()))?;
+
+    let source = dir.join("source.csv");
+    let destination = dir.join("destination.csv");
+
+    let result = async {
+        let csv = values
+            .iter()
+            .map(|value| csv_field(value) + "\n")
+            .collect::<String>();
+        fs::write(&source, csv)?;
+
+        let mut args = args.clone();
+        args.command = None;
+        args.source_path = Some(source.clone());
+        args.destination_path = Some(destination.clone());
+        args.format = None;
+        args.header_rows = 0;
+        args.yes = true;
+        translate(args).await?;
+
+        fs::read_to_string(&destination).wrap_err("Failed to read the translations")
+    }
+    .await;
+
+    fs::remove_dir_all(&dir).ok();
+
+    let records = csv_records(&result?);
+    let mut stdout = io::stdout().lock();
+
+    for i in 0..values.len() {
+        if i > 0 {
+            stdout.write_all(delimiter.as_bytes())?;
+        }
+
+        // The destination ends at the last row with a value.
+        let translation = records
+            .get(i)
+            .and_then(|record| record.first())
+            .map_or("", String::as_str);
+
+        stdout.write_all(translation.as_bytes())?;
+    }
+
+    if terminated {
+        stdout.write_all(delimiter.as_bytes())?;
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// The OpenAI keys of `--api-key`, then those of the `--api-key-file`, one per line.
+fn api_keys(args: &Args) -> Result<Vec<String>> {
+    let mut keys = args.api_key.clone();
+
+    if let Some(path) = &args.api_key_file {
+        let text = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read the API keys {}", path.display()))?;
+
+        keys.extend(
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string),
+        );
+    }
+
+    Ok(keys)
+}
+
+/// The fixtures of `--record` or `--replay`, if either is given.
+fn fixtures(args: &Args) -> Result<Option<Arc<Fixtures>>> {
+    let fixtures = match (&args.record, &args.replay) {
+        (Some(dir), _) => Fixtures::record(dir)?,
+        (None, Some(dir)) => Fixtures::replay(dir)?,
+        (None, None) => return Ok(None),
+    };
+
+    Ok(Some(Arc::new(fixtures)))
+}
+
+fn google_credentials(args: &Args) -> google::Credentials {
+    match (&args.google_api_key, &args.google_access_token) {
+        (Some(key), _) => google::Credentials::ApiKey(key.clone()),
+        (None, Some(token)) => google::Credentials::AccessToken(token.clone()),
+        (None, None) => google::Credentials::Metadata,
+    }
+}
+
+/// Translates over xlsx workbooks in the temporary directory, the `--source-gsheet` downloaded
+/// into the source and the destination uploaded to the `--dest-gsheet`.
+async fn translate_gsheet(args: &Args) -> Result<()> {
+    let connection = Connection {
+        tls_min_version: args.tls_min_version.map(Into::into),
+        proxy: args.proxy.clone(),
+        ca_cert: args.ca_cert.clone(),
+    };
+    let retry = retry::Policy {
+        max_attempts: args.max_attempts,
+        initial_backoff: Duration::from_millis(args.retry_initial_backoff),
+        fixtures: fixtures(args)?,
+    };
+    let sheets = gsheets::Sheets::new(google_credentials(args), &connection, retry)?;
+
+    let mut args = args.clone();
+    // The workbooks are xlsx whatever the format of the other file.
+    args.format = None;
+
+    if args.source_gsheet.is_some() {
+        if args.destination_path.is_some() {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--source-gsheet takes the place of the source file, give only the \
+                     destination after the dictionary",
+                )
+                .exit();
+        }
+
+        args.destination_path = args.source_path.take();
+    }
+
+    if args.dest_gsheet.is_some() && args.destination_path.is_some() {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--dest-gsheet takes the place of the destination file",
+            )
+            .exit();
+    }
+
+    if args.destination_path.is_none() && args.dest_gsheet.is_none() {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--source-gsheet requires a destination file or --dest-gsheet",
+            )
+            .exit();
+    }
+
+    let dir = env::temp_dir().join(format!("xlsx-translator-gsheet-{}", process::id()));
+    fs::create_dir_all(&dir)
+        .wrap_err_with(|| format!("Failed to create the directory {}", dir.display()))?;
+
+    let result = async {
+        if let Some(id) = &args.source_gsheet {
+            let source = dir.join("source.xlsx");
+            sheets.download(id, &source).await?;
+            args.source_path = Some(source);
+        }
+
+        let destination = dir.join("destination.xlsx");
+
+        if args.dest_gsheet.is_some() {
+            args.destination_path = Some(destination.clone());
+        }
+
+        // Estimates write nothing to upload.
+        let dest_gsheet = args
+            .dest_gsheet
+            .clone()
+            .filter(|_| !matches!(args.command, Some(Command::Estimate { .. })));
+        translate(args).await?;
+
+        if let Some(id) = dest_gsheet {
+            sheets.upload(&destination, &id).await?;
+            eprintln!("Wrote the translation to the Google Sheet {}", id);
+        }
+
+        Ok(())
+    }
+    .await;
+
+    fs::remove_dir_all(&dir).ok();
+
+    result
+}
+
+/// Makes the merged dictionaries into a DeepL glossary for every target language.
+async fn glossary_sync(args: &Args) -> Result<()> {
+    let Some(name) = &args.deepl_glossary else {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "glossary sync requires --deepl-glossary",
+            )
+            .exit();
+    };
+
+    let Some(auth_key) = &args.deepl_auth_key else {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "glossary sync requires --deepl-auth-key",
+            )
+            .exit();
+    };
+
+    let dictionaries = args
+        .dictionary_path
+        .iter()
+        .chain(&args.dictionary)
+        .map(PathBuf::as_path)
+        .collect::<Vec<_>>();
+
+    let dictionary = dictionary::load_all(
+        &dictionaries,
+        &dictionary::Options {
+            format: args.dict_format,
+            separator: args.dict_separator.clone(),
+            preserve_whitespace: args.preserve_whitespace,
+            match_mode: args.match_mode,
+        },
+    )?;
+
+    let connection = Connection {
+        tls_min_version: args.tls_min_version.map(Into::into),
+        proxy: args.proxy.clone(),
+        ca_cert: args.ca_cert.clone(),
+    };
+    let retry = retry::Policy {
+        max_attempts: args.max_attempts,
+        initial_backoff: Duration::from_millis(args.retry_initial_backoff),
+        fixtures: fixtures(args)?,
+    };
+    let deepl = DeepL::new(auth_key, &args.source_lang, &connection, retry)?;
+
+    for language in &args.target_lang {
+        let glossary = deepl.sync_glossary(name, language, &dictionary).await?;
+        eprintln!(
+            "Wrote {} entries to the DeepL glossary '{}' into {} ({})",
+            glossary.entry_count,
+            glossary.name,
+            language_name(language),
+            glossary.glossary_id
+        );
+    }
+
+    Ok(())
+}
+
+/// Loads every file the options name the way a translation would, failing on the first one that
+/// can't be.
+fn validate(args: &Args) -> Result<()> {
+    let dictionaries = args
+        .dictionary_path
+        .iter()
+        .chain(&args.dictionary)
+        .map(PathBuf::as_path)
+        .collect::<Vec<_>>();
+
+    let dictionary = dictionary::load_all(
+        &dictionaries,
+        &dictionary::Options {
+            format: args.dict_format,
+            separator: args.dict_separator.clone(),
+            preserve_whitespace: args.preserve_whitespace,
+            match_mode: args.match_mode,
+        },
+    )?;
+
+    eprintln!(
+        "{} dictionary entries in {} files",
+        dictionary.len(),
+        dictionaries.len()
+    );
+
+    for path in &args.tmx {
+        let memory = tmx::load(
+            path,
+            &args.source_lang,
+            args.target_language(),
+            args.preserve_whitespace,
+            args.match_mode,
+        )?;
+        eprintln!("{} translation units in {}", memory.len(), path.display());
+    }
+
+    if let Some(path) = &args.schema_file {
+        Schema::load(path)?;
+        eprintln!("Schema {} is valid", path.display());
+    }
+
+    Protected::new(
+        args.protect.as_deref(),
+        args.protect_placeholders,
+        args.redact_pii,
+    )?;
+
+    if let Some(path) = &args.prompt_template {
+        Template::load(path)?;
+        eprintln!("Prompt template {} is valid", path.display());
+    }
+
+    if let Some(path) = &args.source_path {
+        let format = args.format.unwrap_or_else(|| FileFormat::detect(path));
+
+        let sheets = match format {
+            _ if format.is_delimited() => vec![delimited::read(path, format)?],
+            FileFormat::Po => vec![gettext::read(path)?],
+            FileFormat::Docx | FileFormat::Pptx => vec![office::read(path, format)?],
+            FileFormat::Ods => read_worksheets(
+                open_workbook::<Ods<_>, _>(path)?,
+                &args.sheet,
+                args.all_sheets,
+            )?,
+            _ => read_worksheets(
+                open_workbook::<Xlsx<_>, _>(path)?,
+                &args.sheet,
+                args.all_sheets,
+            )?,
+        };
+
+        for sheet in &sheets {
+            eprintln!(
+                "Sheet {}: {} rows, {} columns",
+                sheet.name,
+                sheet.range.height(),
+                sheet.range.width()
+            );
+        }
+    }
+
+    eprintln!("No problems found");
+
+    Ok(())
+}
+
+/// Lists the worksheets of the source and what a translation would send of the selected ones.
+fn validate_workbook(args: &Args, source: &Path, preview: usize, long_cell: usize) -> Result<()> {
+    if package::is_encrypted(source)? {
+        bail!(
+            "{} is encrypted, it has to be saved without a password to be translated",
+            source.display()
+        );
+    }
+
+    let mut archive = package::open(source)?;
+
+    if package::workbook_protected(&mut archive)? {
+        eprintln!("Warning: the structure of the workbook is protected");
+    }
+
+    let workbook = open_workbook::<Xlsx<_>, _>(source)?;
+    let names = workbook.sheet_names().to_owned();
+    let sheets = read_worksheets(workbook, &args.sheet, args.all_sheets)?;
+    let skip = Skip::new(&args.skip_pattern, &args.skip_content)?;
+
+    for name in &names {
+        let selected = sheets.iter().any(|sheet| sheet.name == *name);
+        eprintln!("{} {}", if selected { "*" } else { " " }, name);
+    }
+
+    let first_row = if args.translate_headers {
+        0
+    } else {
+        args.header_rows
+    };
+
+    let mut total = 0;
+
+    for sheet in &sheets {
+        let range = &sheet.range;
+        let columns = (!args.columns.is_empty()).then(|| {
+            columns::resolve(
+                &args.columns,
+                range,
+                args.header_rows.checked_sub(1),
+                &sheet.name,
+            )
+        });
+
+        let strings = range
+            .used_cells()
+            .filter(|(row, column, _)| {
+                *row as u32 >= first_row
+                    && columns
+                        .as_ref()
+                        .is_none_or(|columns| columns.contains(&(*column as u16)))
+            })
+            .filter_map(|(row, column, data)| match data {
+                DataType::String(value)
+                    if value.chars().any(char::is_alphabetic) && !skip.matches(value) =>
+                {
+                    Some((row as u32, column as u16, value))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let protected = match package::worksheet_part(&mut archive, &sheet.name)? {
+            Some(part) => package::sheet_protected(&mut archive, &part)?,
+            None => false,
+        };
+
+        eprintln!(
+            "\nSheet {}: {} rows, {} columns, {} text cells to translate{}",
+            sheet.name,
+            range.height(),
+            range.width(),
+            strings.len(),
+            if protected { ", protected" } else { "" }
+        );
+
+        let reference = |row: u32, column: u16| cell_reference(range, (row, column));
+
+        for (row, column, value) in &strings {
+            let length = value.chars().count();
+
+            if length >= long_cell {
+                eprintln!(
+                    "  {} is very long, {} characters",
+                    reference(*row, *column),
+                    length
+                );
+            }
+        }
+
+        for (row, column, value) in strings.iter().take(preview) {
+            let text = value.chars().take(80).collect::<String>();
+            let ellipsis = if text.len() < value.len() { "…" } else { "" };
+            eprintln!("  {}: {}{}", reference(*row, *column), text, ellipsis);
+        }
+
+        total += strings.len();
+    }
+
+    eprintln!(
+        "\n{} text cells to translate in {} of {} worksheets",
+        total,
+        sheets.len(),
+        names.len()
+    );
+
+    Ok(())
+}
+
+/// Reports the problems of the dictionaries, after writing their normalized merge if asked to.
+fn validate_dictionary(args: &Args, normalized: Option<&Path>) -> Result<()> {
+    let dictionaries = args
+        .dictionary_path
+        .iter()
+        .chain(&args.dictionary)
+        .map(PathBuf::as_path)
+        .collect::<Vec<_>>();
+
+    let Some(&first) = dictionaries.first() else {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "The dictionary path is required",
+            )
+            .exit();
+    };
+
+    let validation = dictionary::validate(
+        &dictionaries,
+        &dictionary::Options {
+            format: args.dict_format,
+            separator: args.dict_separator.clone(),
+            preserve_whitespace: args.preserve_whitespace,
+            match_mode: args.match_mode,
+        },
+    )?;
+
+    for problem in &validation.problems {
+        eprintln!("{}", problem);
+    }
+
+    if let Some(path) = normalized {
+        if path.exists() && !args.yes && !confirm_overwrite(path)? {
+            return Ok(());
+        }
+
+        // The file is written from scratch rather than added to.
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+
+        let format = args
+            .dict_format
+            .unwrap_or_else(|| dictionary::Format::detect(first));
+        let mut learned = Learned::new(path.to_path_buf(), format, args.dict_separator.clone());
+
+        for (source, translation) in &validation.entries {
+            if !translation.is_empty() {
+                learned.insert(source, translation);
+            }
+        }
+
+        let written = learned.write()?;
+        eprintln!("Wrote {} entries to {}", written, path.display());
+    }
+
+    if !validation.problems.is_empty() {
+        bail!(
+            "{} problems found in {} dictionaries",
+            validation.problems.len(),
+            dictionaries.len()
+        );
+    }
+
+    eprintln!("No problems found");
+
+    Ok(())
+}
+
+/// What the translations into several `--target-lang` languages have in common.
+#[derive(Default)]
+struct Shared {
+    /// The worksheets of the source, read for the language that gets to them first.
+    sheets: Option<Vec<Sheet>>,
+    limiter: Option<Arc<Limiter>>,
+}
+
+/// The path of the file of a language, filling in a `--lang-destination` template with the name
+/// and extension of the path.
+fn language_path(path: &Path, template: &str, language: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+
+    let name = template
+        .replace("{stem}", &stem)
+        .replace("{ext}", &extension)
+        .replace("{lang}", language_code(language));
+
+    path.with_file_name(name.trim_end_matches('.'))
+}
+
+/// Translates the source into every `--target-lang` language, into a destination of its own when
+/// there are several. The languages are translated at the same time, each with a progress bar of
+/// its own, but the worksheets are only read once and the requests of all of them go through the
+/// same rate limiter.
+async fn translate(args: Args) -> Result<()> {
+    if args.target_lang.len() < 2 {
+        return translate_into(args, None).await;
+    }
+
+    if args.in_place {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--in-place takes a single --target-lang",
+            )
+            .exit();
+    }
+
+    if !args.lang_destination.contains("{lang}") {
+        Args::command()
+            .error(
+                ErrorKind::InvalidValue,
+                "--lang-destination needs {lang} for the files of several languages to differ",
+            )
+            .exit();
+    }
+
+    let shared = RefCell::new(Shared::default());
+    let bars = MultiProgress::new();
+    let path = |path: &Option<PathBuf>, template: &str, language: &str| {
+        path.as_deref()
+            .map(|path| language_path(path, template, language))
+    };
+
+    let mut runs = vec![];
+
+    for (i, language) in args.target_lang.iter().enumerate() {
+        eprintln!(
+            "Language {}/{}: {}",
+            i + 1,
+            args.target_lang.len(),
+            language_name(language)
+        );
+
+        // The other files only get the language code added.
+        let suffixed = "{stem}.{lang}.{ext}";
+        let mut args = args.clone();
+        args.destination_path = path(&args.destination_path, &args.lang_destination, language);
+        args.previous_output = path(&args.previous_output, &args.lang_destination, language);
+        args.merge_into = path(&args.merge_into, &args.lang_destination, language);
+        args.report = path(&args.report, suffixed, language);
+        args.sidecar = path(&args.sidecar, suffixed, language);
+        args.failures = path(&args.failures, suffixed, language);
+        args.learn = path(&args.learn, suffixed, language);
+        args.glossary_consistency_report =
+            path(&args.glossary_consistency_report, suffixed, language);
+        args.terminology_report = path(&args.terminology_report, suffixed, language);
+        args.qa_report = path(&args.qa_report, suffixed, language);
+        args.target_lang = vec![language.clone()];
+
+        if args.progress.is_none() {
+            args.progress = Some(bars.add(ProgressBar::new(0)));
+        }
+
+        let shared = &shared;
+        runs.push(async move {
+            translate_into(args, Some(shared))
+                .await
+                .wrap_err_with(|| format!("Failed to translate into {}", language))
+        });
+    }
+
+    future::try_join_all(runs).await?;
+
+    Ok(())
+}
+
+/// Translates the source into the first `--target-lang`, taking what the translations into the
+/// other ones have read and set up already from `shared`.
+async fn translate_into(args: Args, shared: Option<&RefCell<Shared>>) -> Result<()> {
+    let quit = Arc::new(Notify::new());
+
+    tokio::select! {
+        result = translate_workbook(args, shared, quit.clone()) => result,
+        () = quit.notified() => Err(Stopped.into()),
+    }
+}
+
+/// Does the work of [`translate_into`], which `quit` gives up on without waiting for the
+/// translations in flight.
+async fn translate_workbook(
+    args: Args,
+    shared: Option<&RefCell<Shared>>,
+    quit: Arc<Notify>,
+) -> Result<()> {
+    let started = Instant::now();
+
+    // Estimates go through the whole run, only without sending requests or writing anything.
+    let estimating = matches!(args.command, Some(Command::Estimate { .. }));
+    let mut sampling = args.style.map(Style::sampling).unwrap_or_default();
+    sampling.top_p = args.top_p.or(sampling.top_p);
+    sampling.logprobs = args.confidence_threshold.is_some();
+
+    if let Some(prompt) = &args.system_prompt {
+        sampling.system_prompt = if Path::new(prompt).is_file() {
+            fs::read_to_string(prompt)
+                .wrap_err_with(|| format!("Failed to read the system prompt {}", prompt))?
+        } else {
+            prompt.clone()
+        };
+    }
+
+    let preset_temperature = args.temperature.or(args.style.map(Style::temperature));
+    let temperature = preset_temperature.unwrap_or(0.);
+
+    // The language the prompts say a value is in.
+    let source_lang = language_name(&args.source_lang);
+    let source_language = |text: &str| {
+        args.detect_source_lang
+            .then(|| language::detect(text))
+            .flatten()
+            .unwrap_or(source_lang)
+    };
+
+    let estimate = estimating.then(|| Estimate {
+        system_prompt: sampling.system_prompt.clone(),
+        ..Estimate::default()
+    });
+
+    // The translations of `apply` come from its XLIFF file instead of the API.
+    let xliff = match &args.command {
+        Some(Command::Apply {
+            xliff: Some(xliff), ..
+        }) => Some(xliff::load(xliff)?),
+        _ => None,
+    };
+
+    let previous = match (&args.previous, &args.previous_output) {
+        (Some(source), Some(output)) => previous::load(source, output)?,
+        _ => BTreeMap::new(),
+    };
+
+    let merged = match &args.merge_into {
+        Some(path) => merge::load(path)?,
+        None => BTreeMap::new(),
+    };
+
+    let reviewed = match &args.command {
+        Some(Command::Apply {
+            translations: Some(path),
+            ..
+        }) => Some(strings::load(
+            path,
+            args.preserve_whitespace,
+            args.match_mode,
+        )?),
+        _ => None,
+    };
+
+    // Only the other subcommands can do without these, and `apply` without a dictionary.
+    let (dictionary_path, Some(source_path)) =
+        (args.dictionary_path.as_deref(), args.source_path.as_deref())
+    else {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "The dictionary and source paths are required",
+            )
+            .exit();
+    };
+
+    if dictionary_path.is_none() && xliff.is_none() && reviewed.is_none() {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "The dictionary path is required",
+            )
+            .exit();
+    }
+
+    // Estimates don't need a key, as they never send anything, and compatible servers may not
+    // either.
+    if args.backend.is_openai()
+        && args.api_key.is_empty()
+        && args.api_key_file.is_none()
+        && (args.api_base.is_none() || args.backend == Backend::Azure)
+        && !estimating
+        && xliff.is_none()
+        && !args.pseudo
+        && !args.offline
+    {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--api-key is required with the OpenAI and Azure backends",
+            )
+            .exit();
+    }
+
+    for (used, flag) in [
+        (args.prompt_template.is_some(), "--prompt-template"),
+        (args.system_prompt.is_some(), "--system-prompt"),
+        (args.temperature.is_some(), "--temperature"),
+        (args.top_p.is_some(), "--top-p"),
+        (args.style.is_some(), "--style"),
+        (args.detect_source_lang, "--detect-source-lang"),
+    ] {
+        if used && !args.backend.is_prompted() {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    format!("{} requires a backend that is sent prompts", flag),
+                )
+                .exit();
+        }
+    }
+
+    // These rely on the OpenAI chat completions API.
+    for (used, flag) in [
+        (args.ai_segment_classify, "--ai-segment-classify"),
+        (args.pack_size.is_some(), "--pack-size"),
+    ] {
+        if used && !args.backend.is_openai() {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    format!("{} requires the OpenAI or Azure backend", flag),
+                )
+                .exit();
+        }
+    }
+
+    if args.semantic_index.is_some() && args.backend != Backend::OpenAi {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--semantic-index requires the OpenAI backend",
+            )
+            .exit();
+    }
+
+    if args.qa == Some(QaMode::BackTranslate) && !args.backend.is_prompted() {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--qa back-translate requires a backend that is sent prompts",
+            )
+            .exit();
+    }
+
+    if args.deepl_glossary.is_some() && args.backend != Backend::DeepL {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--deepl-glossary requires the DeepL backend",
+            )
+            .exit();
+    }
+
+    if args.use_batch_api && args.backend != Backend::OpenAi {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--use-batch-api requires the OpenAI backend",
+            )
+            .exit();
+    }
+
+    let dictionaries = dictionary_path
+        .into_iter()
+        .chain(args.dictionary.iter().map(PathBuf::as_path))
+        .collect::<Vec<_>>();
+
+    let live = dictionary::Live::load(
+        &dictionaries,
+        dictionary::Options {
+            format: args.dict_format,
+            separator: args.dict_separator.clone(),
+            preserve_whitespace: args.preserve_whitespace,
+            match_mode: args.match_mode,
+        },
+    )?;
+
+    // Read again by `--reload-dictionary` as the run goes.
+    let dictionary = live.current();
+
+    let mut memory = Dictionary::new();
+
+    for path in &args.tmx {
+        for (key, translation) in tmx::load(
+            path,
+            &args.source_lang,
+            args.target_language(),
+            args.preserve_whitespace,
+            args.match_mode,
+        )? {
+            memory.entry(key).or_insert(translation);
+        }
+    }
+
+    // Cell values translated without asking, by the reviewed strings of `apply`, the dictionary or
+    // else the translation memories.
+    let exact = |key: &str| {
+        reviewed
+            .as_ref()
+            .and_then(|reviewed| reviewed.get(key))
+            .map(|translation| (translation.clone(), "reviewed"))
+            .or_else(|| {
+                live.current()
+                    .get(key)
+                    .map(|translation| (translation.clone(), "dictionary"))
+            })
+            .or_else(|| {
+                memory
+                    .get(key)
+                    .map(|translation| (translation.clone(), "tmx"))
+            })
+            // Pseudo-translations are for every value.
+            .filter(|_| !args.pseudo)
+    };
+
+    if args.in_place && !args.yes && !estimating && !confirm_overwrite(source_path)? {
+        return Ok(());
+    }
+
+    let source_format = args
+        .format
+        .unwrap_or_else(|| FileFormat::detect(source_path));
+
+    let destination_format = args.format.unwrap_or_else(|| {
+        args.destination_path
+            .as_deref()
+            .map_or(source_format, FileFormat::detect)
+    });
+
+    if destination_format == FileFormat::Ods {
+        Args::command()
+            .error(
+                ErrorKind::InvalidValue,
+                "ODS files can only be read, write the translation as xlsx, CSV or TSV",
+            )
+            .exit();
+    }
+
+    if destination_format.is_filled_in() && source_format != destination_format {
+        Args::command()
+            .error(
+                ErrorKind::InvalidValue,
+                "PO, DOCX and PPTX files are written by filling in a source of the same format, \
+                 not from a workbook",
+            )
+            .exit();
+    }
+
+    if args.preserve_structure
+        && (source_format != FileFormat::Xlsx || destination_format != FileFormat::Xlsx)
+    {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--preserve-structure only works with xlsx sources and destinations",
+            )
+            .exit();
+    }
+
+    // The rewrites go into the worksheets once all of them are translated, the rows of which
+    // `--stream-rows` has written already. Clap only checks this for the command line.
+    if args.harmonize && args.stream_rows.is_some() {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--harmonize can't go back to the rows written with --stream-rows",
+            )
+            .exit();
+    }
+
+    let streamed = args.stream_rows.is_some();
+    // With `--stream-rows` the worksheets of workbooks are only read once they are translated.
+    let mut unread = None;
+
+    let mut sheets = match shared.and_then(|shared| shared.borrow().sheets.clone()) {
+        Some(sheets) => sheets,
+        None => {
+            let transcoded = if args.auto_detect_xlsx_encoding && source_format == FileFormat::Xlsx
+            {
+                package::transcode_to_utf8(source_path)?
+            } else {
+                None
+            };
+
+            let mut sheets = match transcoded {
+                _ if source_format.is_delimited() => {
+                    vec![delimited::read(source_path, source_format)?]
+                }
+                _ if source_format == FileFormat::Po => vec![gettext::read(source_path)?],
+                _ if matches!(source_format, FileFormat::Docx | FileFormat::Pptx) => {
+                    vec![office::read(source_path, source_format)?]
+                }
+                // OpenFormula is no use to xlsx, the calculated values are kept instead.
+                _ if source_format == FileFormat::Ods => {
+                    let workbook = open_workbook::<Ods<_>, _>(source_path)?;
+                    let (mut sheets, reader) =
+                        select_worksheets(workbook, &args.sheet, args.all_sheets, streamed)?;
+
+                    for sheet in &mut sheets {
+                        sheet.formulas = Range::empty();
+                    }
+
+                    unread = reader.map(Unread::without_formulas);
+                    sheets
+                }
+                Some(transcoded) => {
+                    for (part, encoding) in &transcoded.parts {
+                        eprintln!(
+                            "Warning: {} is encoded as {}, reading it as UTF-8",
+                            part, encoding
+                        );
+                    }
+
+                    let workbook = Xlsx::new(Cursor::new(transcoded.package))?;
+                    let (sheets, reader) =
+                        select_worksheets(workbook, &args.sheet, args.all_sheets, streamed)?;
+                    unread = reader;
+                    sheets
+                }
+                None => {
+                    let workbook = open_workbook::<Xlsx<_>, _>(source_path)?;
+                    let (sheets, reader) =
+                        select_worksheets(workbook, &args.sheet, args.all_sheets, streamed)?;
+                    unread = reader;
+                    sheets
+                }
+            };
+
+            // Before anything is looked up, for the dictionary and the cache to see the strings
+            // the way the hook leaves them. Worksheets read later go through it then.
+            if let Some(command) = args.pre_hook.as_ref().filter(|_| unread.is_none()) {
+                hooks::preprocess(&Hook::new(command), &mut sheets)?;
+            }
+
+            sheets
+        }
+    };
+
+    // The later languages take the worksheets as they were read, before anything is added.
+    if let Some(shared) = shared.filter(|_| unread.is_none()) {
+        shared
+            .borrow_mut()
+            .sheets
+            .get_or_insert_with(|| sheets.clone());
+    }
+
+    let mut schema = args.schema_file.as_deref().map(Schema::load).transpose()?;
+
+    if !args.column_lang.is_empty() {
+        schema
+            .get_or_insert_with(Schema::default)
+            .set_languages(&args.column_lang);
+    }
+
+    if !args.max_length.is_empty() {
+        schema
+            .get_or_insert_with(Schema::default)
+            .set_max_lengths(&args.max_length);
+    }
+
+    // Values shared by cells of several columns have to fit the narrowest of them.
+    let max_length = |cells: &[(u32, u16)]| {
+        cells
+            .iter()
+            .filter_map(|&(_, column)| schema::column(schema.as_ref(), column).max_length())
+            .min()
+    };
+
+    let target = Target {
+        language: language_name(args.target_language()),
+        model: match args.backend {
+            _ if args.pseudo => pseudo::MODEL,
+            Backend::OpenAi | Backend::Azure => &args.model,
+            // The default model is an OpenAI one.
+            Backend::Claude if args.model == translator::MODEL => claude::MODEL,
+            Backend::Claude => &args.model,
+            Backend::DeepL => deepl::MODEL,
+            Backend::Google => google::MODEL,
+            Backend::LibreTranslate => libretranslate::MODEL,
+        },
+    };
+
+    // DeepL has no list price to count --max-cost against.
+    let price = match args.backend {
+        Backend::OpenAi | Backend::Azure | Backend::Claude => estimate::price(target.model),
+        Backend::DeepL => None,
+        Backend::Google => Some(google::PRICE),
+        Backend::LibreTranslate => Some(libretranslate::PRICE),
+    };
+
+    if args.max_cost.is_some() && price.is_none() {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "--max-cost needs a model with a known price, not {}",
+                    target.model
+                ),
+            )
+            .exit();
+    }
+
+    let budget = Arc::new(Budget::new(args.max_cost, args.max_requests, price));
+
+    let template = args
+        .prompt_template
+        .as_deref()
+        .map(Template::load)
+        .transpose()?;
+
+    let protected = Protected::new(
+        args.protect.as_deref(),
+        args.protect_placeholders,
+        args.redact_pii,
+    )?;
+    let skip = Skip::new(&args.skip_pattern, &args.skip_content)?;
+
+    let pivot = match args.pivot_translate.as_slice() {
+        [selector, offset] => {
+            let selector = Regex::new(selector).wrap_err("Invalid source row selector")?;
+            let offset: u32 = offset.parse().wrap_err("Invalid target row offset")?;
+            Some((selector, offset))
+        }
+        _ => None,
+    };
+
+    let header_cache = args
+        .header_translation_cache
+        .as_deref()
+        .map(|path| {
+            let ttl = Duration::from_secs(args.header_cache_ttl * 24 * 60 * 60);
+            Cache::open(path, Tier::Header, Some(ttl))
+        })
+        .transpose()?;
+
+    let translation_cache = args
+        .cache
+        .as_deref()
+        .map(|path| {
+            let ttl = Duration::from_secs(args.cache_ttl * 24 * 60 * 60);
+            Cache::open(path, Tier::Data, Some(ttl))
+        })
+        .transpose()?;
+
+    // The formats and geometry are only read from xlsx packages.
+    let (styles, rich_text, mut archive) = if source_format != FileFormat::Xlsx {
+        (Styles::default(), RichText::default(), None)
+    } else {
+        let mut archive = package::open(source_path)?;
+        let styles = Styles::read(&mut archive)?;
+
+        let rich_text = if args.rich_text {
+            RichText::read(&mut archive)?
+        } else {
+            RichText::default()
+        };
+
+        (styles, rich_text, Some(archive))
+    };
+
+    let mut geometries = vec![];
+
+    for sheet in &mut sheets {
+        geometries.push(match &mut archive {
+            Some(archive) if unread.is_none() => {
+                read_geometry(archive, sheet, &rich_text, args.translate_comments)?
+            }
+            _ => Geometry::default(),
+        });
+    }
+
+    let destination_path = match args.destination_path {
+        _ if estimating => {
+            env::temp_dir().join(format!("xlsx-translator-estimate-{}.xlsx", process::id()))
+        }
+        Some(ref path) if destination_format.is_converted() => in_place_path(path)?,
+        Some(ref path) => path.clone(),
+        None => in_place_path(source_path)?,
+    };
+
+    let filename = destination_path
+        .to_str()
+        .wrap_err("Invalid destination filename")?;
+
+    // Rows are written out as soon as the ones below them are, rather than all of them kept
+    // until the workbook is closed.
+    let workbook = match streamed {
+        true => Workbook::new_with_options(filename, true, None, false)?,
+        false => Workbook::new(filename)?,
+    };
+
+    let mut worksheets = sheets
+        .iter()
+        .map(|sheet| workbook.add_worksheet(Some(&sheet.name)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let journal = if args.resume && !estimating {
+        let journal = Journal::open(journal::path(&destination_path))?;
+
+        if journal.len() > 0 {
+            eprintln!(
+                "Resuming from {} with {} translations",
+                journal.path().display(),
+                journal.len()
+            );
+        }
+
+        Some(journal)
+    } else {
+        None
+    };
+
+    // Only added after the translated worksheets, which have to keep the positions of the
+    // source ones for the passthrough.
+    let history = if args.version_history {
+        Some(History::new(&workbook)?)
+    } else {
+        None
+    };
+
+    let provenance = if args.provenance {
+        Some(Provenance::new(&workbook)?)
+    } else {
+        None
+    };
+
+    let sidecar = args.sidecar.as_ref().map(|_| Sidecar::default());
+
+    let connection = Connection {
+        tls_min_version: args.tls_min_version.map(Into::into),
+        proxy: args.proxy.clone(),
+        ca_cert: args.ca_cert.clone(),
+    };
+
+    if let Some(version) = args.tls_min_version {
+        eprintln!(
+            "Requiring TLS {} or newer for API connections",
+            version.to_possible_value().unwrap().get_name()
+        );
+    }
+
+    let retry = retry::Policy {
+        max_attempts: args.max_attempts,
+        initial_backoff: Duration::from_millis(args.retry_initial_backoff),
+        fixtures: fixtures(&args)?,
+    };
+
+    let backend_limit = if args.pseudo {
+        pseudo::RATE_LIMIT
+    } else {
+        args.backend.rate_limit()
+    };
+
+    let rate_limit = RateLimit {
+        requests: args.rpm.unwrap_or(backend_limit.requests),
+        window: args
+            .rate_limit_window
+            .map_or(backend_limit.window, Duration::from_secs),
+    };
+
+    // Every key has the rate limits, so the run has them as many times over.
+    let api_keys = api_keys(&args)?;
+    let keys = api_keys.len().max(1);
+    let run_limit = RateLimit {
+        requests: rate_limit.requests * keys,
+        ..rate_limit
+    };
+    let run_tpm = args.tpm.map(|tpm| tpm * keys);
+    let key_limiter = || Limiter::new(rate_limit, args.tpm);
+
+    let limiter = match shared {
+        Some(shared) => shared
+            .borrow_mut()
+            .limiter
+            .get_or_insert_with(|| Arc::new(Limiter::new(run_limit, run_tpm)))
+            .clone(),
+        None => Arc::new(Limiter::new(run_limit, run_tpm)),
+    };
+
+    // Kept around separately for the parts that only work with OpenAI.
+    let mut openai = None;
+
+    let translator: Arc<dyn Translator> = match args.backend {
+        _ if args.pseudo => Arc::new(Pseudo::new(args.pseudo_expansion)),
+        Backend::OpenAi => {
+            let prefix = args.api_request_id_prefix.clone();
+            let client = OpenAi::new(
+                api_keys.first().map(String::as_str),
+                args.api_base.as_deref(),
+                &connection,
+                prefix,
+                retry,
+                limiter.clone(),
+            )?
+            .with_sampling(sampling.clone());
+            let client = Arc::new(match api_keys.len() > 1 {
+                true => client.with_keys(&api_keys, &connection, key_limiter)?,
+                false => client,
+            });
+            openai = Some(client.clone());
+            client
+        }
+        Backend::Azure => {
+            let azure = Azure {
+                endpoint: args.azure_endpoint.clone().unwrap_or_default(),
+                deployment: args.azure_deployment.clone().unwrap_or_default(),
+                api_version: args.api_version.clone(),
+            };
+            let client = OpenAi::azure(
+                api_keys.first().map_or("", String::as_str),
+                &azure,
+                &connection,
+                args.api_request_id_prefix.clone(),
+                retry,
+                limiter.clone(),
+            )?
+            .with_sampling(sampling.clone());
+            let client = Arc::new(match api_keys.len() > 1 {
+                true => client.with_keys(&api_keys, &connection, key_limiter)?,
+                false => client,
+            });
+            openai = Some(client.clone());
+            client
+        }
+        Backend::Claude => Arc::new(
+            Claude::new(
+                args.anthropic_api_key.as_deref().unwrap_or_default(),
+                &connection,
+                retry,
+                limiter.clone(),
+            )?
+            .with_sampling(sampling.clone()),
+        ),
+        Backend::DeepL => {
+            let auth_key = args.deepl_auth_key.as_deref().unwrap_or_default();
+            Arc::new(
+                DeepL::new(auth_key, &args.source_lang, &connection, retry)?
+                    .with_glossary(args.deepl_glossary.clone()),
+            )
+        }
+        Backend::Google => Arc::new(Google::new(
+            google_credentials(&args),
+            args.google_project.as_deref().unwrap_or_default(),
+            &args.google_location,
+            args.google_glossary.as_deref(),
+            &args.source_lang,
+            &connection,
+            retry,
+        )?),
+        Backend::LibreTranslate => Arc::new(LibreTranslate::new(
+            args.api_base.as_deref(),
+            args.libretranslate_api_key.clone(),
+            &args.source_lang,
+            &connection,
+            retry,
+        )?),
+    };
+
+    let chat = matches!(
+        args.backend,
+        Backend::OpenAi | Backend::Azure | Backend::Claude
+    );
+
+    let translator: Arc<dyn Translator> = match chat && !args.pseudo && !args.raw_responses {
+        true => Arc::new(Guarded::new(translator, limiter.clone())),
+        false => translator,
+    };
+
+    let translator: Arc<dyn Translator> = match chat && !args.fallback_model.is_empty() {
+        true => Arc::new(Fallback::new(
+            translator,
+            limiter.clone(),
+            args.fallback_model.clone(),
+        )),
+        false => translator,
+    };
+
+    let translator: Arc<dyn Translator> = match args.dedupe_sentences {
+        true => Arc::new(Chunked::new(translator, limiter.clone()).dedupe()),
+        false => Arc::new(Chunked::new(translator, limiter.clone())),
+    };
+
+    let translator: Arc<dyn Translator> = match &args.post_hook {
+        Some(command) => Arc::new(Hooked::new(translator, Hook::new(command))),
+        None => translator,
+    };
+
+    let semantic = match (&args.semantic_index, &openai) {
+        (Some(path), Some(openai)) if !estimating && !args.offline => {
+            let cached = translation_cache
+                .as_ref()
+                .map(|cache| cache.entries(target.language, target.model))
+                .transpose()?
+                .unwrap_or_default();
+            let entries = dictionary.iter().chain(&memory).chain(
+                cached
+                    .iter()
+                    .map(|(source, translation)| (source, translation)),
+            );
+            let index = Index::build(path, &args.embedding_model, entries, openai).await?;
+            eprintln!("{} entries in the semantic index", index.len());
+            Some(index)
+        }
+        _ => None,
+    };
+
+    let legend = args
+        .tab_color_legend
+        .as_deref()
+        .map(load_tab_color_legend)
+        .transpose()?;
+
+    let cells = sheets
+        .iter()
+        .map(|sheet| sheet.range.width() * sheet.range.height())
+        .sum::<usize>();
+
+    let bar = match &args.progress {
+        Some(progress) => {
+            progress.set_length(cells as u64);
+            progress.reset();
+            progress.clone()
+        }
+        None => ProgressBar::new(cells as u64),
+    }
+    .with_style(ProgressStyle::with_template("{bar:40} {pos}/{len} {msg}")?);
+    let log = DedupLogger::new(bar.clone(), args.suppress_duplicate_warnings);
+
+    let layout = Layout {
+        origin: (0, 0),
+        interleave_source: args.interleave_source_language,
+        bilingual: None,
+        column_labels: args.output_column_labels,
+        label_row: args.header_rows.checked_sub(1),
+        pivot_offset: pivot.as_ref().map_or(0, |(_, offset)| *offset),
+    };
+
+    let value_formats = ValueFormats::new();
+
+    let localizer = match args.localize_numbers {
+        true => match (
+            Locale::of(&args.source_lang),
+            Locale::of(args.target_language()),
+        ) {
+            (Some(source), Some(_)) => Some(Localizer::new(source)),
+            (None, _) => bail!("No number and date formats known for {}", args.source_lang),
+            (_, None) => bail!(
+                "No number and date formats known for {}",
+                args.target_language()
+            ),
+        },
+        false => None,
+    };
+
+    let mut label_format = Format::new();
+    label_format
+        .set_bold()
+        .set_bg_color(FormatColor::Custom(0xD9E1F2));
+
+    // The position of a cell alone says little without the values next to it.
+    let context_columns = if args.context {
+        args.context_adjacent_columns.max(1)
+    } else {
+        args.context_adjacent_columns
+    };
+
+    let bpe = if context_columns > 0 || args.tpm.is_some() || estimating {
+        Some(bpe(target.model)?)
+    } else {
+        None
+    };
+
+    let no_dictionary = BTreeMap::new();
+
+    let learned = args.learn.clone().map(|path| {
+        let format = args.dict_format.unwrap_or_else(|| {
+            dictionary_path.map_or(dictionary::Format::Text, dictionary::Format::detect)
+        });
+
+        Learned::new(path, format, args.dict_separator.clone())
+    });
+
+    let mut error_format = Format::new();
+
+    if let Some(color) = args.error_highlight_color {
+        error_format.set_bg_color(FormatColor::Custom(color));
+    }
+
+    let score_consistency =
+        args.glossary_consistency_score || args.glossary_consistency_report.is_some();
+    let translated = vec![];
+
+    // The API translations of the run by group, for the values already translated on an earlier
+    // worksheet or block to cost no more requests. All the worksheets share the rate limiter and
+    // the caches as well.
+    let session = HashMap::<String, String>::new();
+
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    // The first Ctrl-C stops like the stop file does, a second one quits right away. Both are
+    // only watched for until the run is over, so that the next file of --source or --watch gets
+    // them.
+    let _interrupts = {
+        let stopped = stopped.clone();
+        let bar = bar.clone();
+
+        AbortOnDrop(tokio::spawn(async move {
+            if signal::ctrl_c().await.is_ok() {
+                stopped.store(true, Ordering::Relaxed);
+                bar.println(
+                    "Interrupted, waiting for in-flight translations (Ctrl-C again to quit)",
+                );
+
+                if signal::ctrl_c().await.is_ok() {
+                    quit.notify_one();
+                }
+            }
+        }))
+    };
+
+    let _stop_file = args.emergency_stop_file.clone().map(|path| {
+        let stopped = stopped.clone();
+        let bar = bar.clone();
+        let mut interval = time::interval(Duration::from_secs(args.emergency_check_interval_secs));
+
+        AbortOnDrop(tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+
+                if path.exists() {
+                    let _ = fs::remove_file(&path);
+                    stopped.store(true, Ordering::Relaxed);
+                    bar.println("Stop file found, waiting for in-flight translations");
+                    return;
+                }
+            }
+        }))
+    });
+
+    let column_types_report = BTreeMap::new();
+
+    let passthrough = args.zero_copy_passthrough
+        && source_format == FileFormat::Xlsx
+        && history.is_none()
+        && provenance.is_none()
+        && !args.interleave_source_language
+        && !args.bilingual
+        && !args.output_column_labels;
+
+    let written = vec![];
+
+    // Shared by the requests of all the worksheets, for the progress bar to count.
+    let in_flight = Arc::new(Semaphore::new(args.concurrency));
+    let progress = |cell_counts: &report::Cells| {
+        let spent = budget.spent();
+
+        if let Some(shared) = &args.spent {
+            *shared.lock().unwrap() = spent;
+        }
+
+        cell_counts.progress(
+            spent,
+            price.is_some(),
+            budget.rpm(),
+            args.concurrency - in_flight.available_permits(),
+        )
+    };
+    let failures = vec![];
+
+    // With `--on-error abort`, the first cell that can't be translated ends the run.
+    let abort = |sheet: &str, range: &Range<DataType>, cell: (u32, u16), error: &str| {
+        if args.on_error == Some(OnError::Abort) {
+            bail!(
+                "Failed to translate {}!{}: {}",
+                sheet,
+                cell_reference(range, cell),
+                error
+            );
+        }
+
+        Ok(())
+    };
+    let cell_counts = report::Cells::default();
+    let offline_untranslated = BTreeSet::new();
+    let no_targets = BTreeMap::new();
+    let terminology =
+        (args.terminology_report.is_some() || args.harmonize).then(Terminology::default);
+    let review = (args.qa == Some(QaMode::BackTranslate)).then(Review::default);
+
+    // The worksheets are translated at the same time, their requests all going through the same
+    // rate limiter and `--concurrency` permits. What they keep track of together is only borrowed
+    // for as long as it takes to update it.
+    let estimate = &RefCell::new(estimate);
+    let journal = &RefCell::new(journal);
+    let history = &RefCell::new(history);
+    let provenance = &RefCell::new(provenance);
+    let sidecar = &RefCell::new(sidecar);
+    let learned = &RefCell::new(learned);
+    let translated = &RefCell::new(translated);
+    let session = &RefCell::new(session);
+    // The translations of the values one of the worksheets is asking for by group, for the others
+    // to wait for rather than to ask for them as well. The sender is dropped if the value fails.
+    let requested = &RefCell::new(HashMap::<
+        String,
+        tokio::sync::watch::Receiver<Option<String>>,
+    >::new());
+    let column_types_report = &RefCell::new(column_types_report);
+    let passthrough = &Cell::new(passthrough);
+    let written = &RefCell::new(written);
+    let failures = &RefCell::new(failures);
+    let cell_counts = &RefCell::new(cell_counts);
+    let offline_untranslated = &RefCell::new(offline_untranslated);
+    let terminology = &RefCell::new(terminology);
+    let review = &RefCell::new(review);
+
+    // Counts the job in the estimate instead of sending it, when estimating.
+    let estimated = |job: &Job, cells| match (&mut *estimate.borrow_mut(), &bpe) {
+        (Some(estimate), Some(bpe)) => {
+            estimate.add(job, cells, bpe);
+            true
+        }
+        _ => false,
+    };
+
+    // Translates a string that isn't a cell on its own rather than through the scheduler: a tab
+    // color label, a formula literal, a comment, a list value or a drawing text. What the
+    // dictionaries don't have is kept as it is if `verbatim`, or else sent as a request of its
+    // own, which is only counted when estimating and left out once the run is stopped or over
+    // budget. `method` is what a translation of the API is recorded as.
+    let translate_text = {
+        let (args, exact, estimated, source_language) =
+            (&args, &exact, &estimated, &source_language);
+        let (bpe, protected, target, template) = (&bpe, &protected, &target, &template);
+        let (budget, limiter, stopped, translator) = (&budget, &limiter, &stopped, &translator);
+
+        move |value: &str,
+              dictionary: &BTreeMap<String, String>,
+              cell: (u32, u16),
+              verbatim: bool,
+              method: &'static str| {
+            let key = args.match_mode.key(value);
+
+            // The request to send, or what the string is left with without one.
+            let request = match exact(&key) {
+                Some(settled) => Err(settled),
+                None if verbatim => Err((value.to_string(), "verbatim")),
+                None => {
+                    let masked = protected.as_ref().and_then(|p| p.mask(value));
+                    let source = masked.as_ref().map_or(value, |m| m.text.as_str());
+
+                    let prompt = build_prompt(
+                        source,
+                        dictionary,
+                        None,
+                        None,
+                        source_language(source),
+                        target.language,
+                        template.as_ref(),
+                    );
+
+                    let job = Job {
+                        estimated_tokens: estimate_tokens(&prompt, bpe.as_ref()),
+                        prompt,
+                        key,
+                        source: source.to_string(),
+                        temperature,
+                        model: target.model.to_string(),
+                        language: target.language.to_string(),
+                        context: None,
+                        cell,
+                        chunks: vec![],
+                        max_length: None,
+                    };
+
+                    match estimated(&job, 1) {
+                        true => Err((value.to_string(), "estimate")),
+                        false => Ok((job, masked)),
+                    }
+                }
+            };
+            let value = value.to_string();
+
+            async move {
+                let (job, masked) = match request {
+                    Ok(request) => request,
+                    Err((translation, method)) => return Ok((translation, method, 0)),
+                };
+
+                limiter.acquire(job.estimated_tokens).await;
+
+                if stopped.load(Ordering::Relaxed) || budget.exhausted() {
+                    return Ok((value, "verbatim", 0));
+                }
+
+                let completion = translator.translate(&job).await;
+                budget.record(completion.as_ref().ok());
+                let completion = completion?;
+
+                let text = completion.text.trim();
+                let text = match &masked {
+                    Some(masked) => masked.unmask(text)?,
+                    None => text.to_string(),
+                };
+
+                Ok::<_, color_eyre::Report>((text, method, completion.tokens))
+            }
+        }
+    };
+
+    {
+        // Borrowed by the runs of all the worksheets rather than moved into the first one.
+        let args = &args;
+        let bar = &bar;
+        let bpe = &bpe;
+        let budget = &budget;
+        let destination_path = &destination_path;
+        let error_format = &error_format;
+        let header_cache = &header_cache;
+        let in_flight = &in_flight;
+        let label_format = &label_format;
+        let legend = &legend;
+        let limiter = &limiter;
+        let live = &live;
+        let localizer = &localizer;
+        let log = &log;
+        let memory = &memory;
+        let merged = &merged;
+        let no_dictionary = &no_dictionary;
+        let no_targets = &no_targets;
+        let openai = &openai;
+        let pivot = &pivot;
+        let previous = &previous;
+        let protected = &protected;
+        let rich_text = &rich_text;
+        let sampling = &sampling;
+        let schema = &schema;
+        let semantic = &semantic;
+        let skip = &skip;
+        let stopped = &stopped;
+        let styles = &styles;
+        let template = &template;
+        let translation_cache = &translation_cache;
+        let translator = &translator;
+        let value_formats = &value_formats;
+        let xliff = &xliff;
+        let unread = &unread.map(RefCell::new);
+        let archive = &RefCell::new(archive);
+
+        let mut sheet_runs = FuturesUnordered::new();
+
+        for (index, ((sheet, worksheet), geometry)) in sheets
+            .iter()
+            .zip(&mut worksheets)
+            .zip(&geometries)
+            .enumerate()
+        {
+            let mut dictionary = dictionary.clone();
+
+            let run = async move {
+                if stopped.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+
+                // Read only now with `--stream-rows`, and let go of once it is translated.
+                let read;
+                let (sheet, geometry) = match unread {
+                    Some(unread) => {
+                        let mut sheet = unread.borrow_mut().read(sheet)?;
+
+                        if let Some(command) = &args.pre_hook {
+                            hooks::preprocess(&Hook::new(command), slice::from_mut(&mut sheet))?;
+                        }
+
+                        let geometry = match &mut *archive.borrow_mut() {
+                            Some(archive) => read_geometry(
+                                archive,
+                                &mut sheet,
+                                rich_text,
+                                args.translate_comments,
+                            )?,
+                            None => Geometry::default(),
+                        };
+
+                        bar.inc_length((sheet.range.width() * sheet.range.height()) as u64);
+                        read = (sheet, geometry);
+                        (&read.0, &read.1)
+                    }
+                    None => (sheet, geometry),
+                };
+
+                let worksheet = &mut Output::new(worksheet, streamed);
+                let range = &sheet.range;
+
+                if let Some(terminology) = &mut *terminology.borrow_mut() {
+                    terminology.add_sheet(index, &sheet.name, range);
+                }
+                let mut written_cells = vec![];
+
+                let targets = xliff
+                    .as_ref()
+                    .map(|xliff| xliff.get(&sheet.name).unwrap_or(no_targets));
+                let previous = previous.get(&sheet.name);
+                let merged = merged.get(&sheet.name);
+
+                let column_types = if args.infer_column_types {
+                    infer::infer(range, args.header_rows)
+                } else {
+                    BTreeMap::new()
+                };
+
+                for (column, column_type) in &column_types {
+                    eprintln!(
+                        "Sheet {}, column {}: {}",
+                        sheet.name,
+                        column_letter(*column),
+                        column_type
+                    );
+                }
+
+                column_types_report.borrow_mut().insert(
+                    sheet.name.clone(),
+                    column_types
+                        .iter()
+                        .map(|(column, column_type)| (column_letter(*column), *column_type))
+                        .collect::<BTreeMap<_, _>>(),
+                );
+
+                let selected_columns = (!args.columns.is_empty())
+                    .then(|| columns::resolve(&args.columns, range, layout.label_row, &sheet.name));
+
+                // The columns that get one for their translations, those with text that may be
+                // translated.
+                let first_row = if args.translate_headers {
+                    0
+                } else {
+                    args.header_rows as usize
+                };
+
+                let bilingual = args.bilingual.then(|| {
+                    bilingual_columns(range, selected_columns.as_ref(), schema.as_ref(), first_row)
+                });
+
+                let layout = Layout {
+                    origin: range
+                        .start()
+                        .map_or((0, 0), |(row, column)| (row, column as u16)),
+                    bilingual: bilingual.as_ref(),
+                    ..layout
+                };
+
+                if let (Some(bilingual), Some(row)) = (&bilingual, layout.label_row) {
+                    if !args.translate_headers {
+                        for &column in bilingual {
+                            let (r, c) = layout.translation_cell(row, column);
+                            worksheet.write_string(r, c, target.language, None)?;
+                        }
+                    }
+                }
+
+                if let Some(color) = sheet.tab_color {
+                    worksheet.set_tab_color(FormatColor::Custom(color));
+                }
+
+                geometry.apply(worksheet, styles, layout)?;
+                let format = |cell| geometry.format(styles, cell);
+                let runs = |cell| geometry.runs(rich_text, cell);
+
+                // The whitespace of the cell to write its translation with, unless it has runs.
+                let envelope =
+                    |cell: (u32, u16)| match range.get((cell.0 as usize, cell.1 as usize)) {
+                        Some(DataType::String(value))
+                            if (args.preserve_whitespace || args.preserve_line_breaks)
+                                && runs(cell).is_none() =>
+                        {
+                            Envelope::of(value)
+                        }
+                        _ => Envelope::default(),
+                    };
+
+                // The format of the cell with another fill, the yellow of unsure translations or
+                // the one of `--mark-translated`.
+                let filled = |cell, color| {
+                    let mut filled = format(cell).cloned().unwrap_or_else(Format::new);
+                    filled.set_bg_color(color);
+                    filled
+                };
+                let mark = FormatColor::Custom(args.mark_translated_color);
+
+                let case = |cell: (u32, u16)| match range.get((cell.0 as usize, cell.1 as usize)) {
+                    Some(DataType::String(value)) if args.restore_case => Case::of(value),
+                    _ => Case::Other,
+                };
+
+                if let (Some(legend), Some(color)) = (&legend, sheet.tab_color) {
+                    match legend.get(&color) {
+                        Some(label) if args.translate_tab_color => {
+                            // The label doesn't come from a cell, but errors about it are fatal rather than reported
+                            // per cell anyway.
+                            let (translation, method, tokens) =
+                                translate_text(label, &dictionary, (0, 0), args.offline, "api")
+                                    .await?;
+
+                            eprintln!(
+                                "Sheet {}, tab color #{:06X}: {} → {}",
+                                sheet.name, color, label, translation
+                            );
+
+                            if let Some(history) = &mut *history.borrow_mut() {
+                                history.record(
+                                    label,
+                                    &translation,
+                                    target.model,
+                                    method,
+                                    tokens,
+                                    None,
+                                )?;
+                            }
+                        }
+                        Some(label) => {
+                            eprintln!("Sheet {}, tab color #{:06X}: {}", sheet.name, color, label)
+                        }
+                        None => eprintln!(
+                            "Sheet {}, tab color #{:06X} is not in the legend",
+                            sheet.name, color
+                        ),
+                    }
+                }
+
+                if layout.column_labels {
+                    let width = range.width() as u16;
+                    write_column_labels(worksheet, layout, width, &args.source_lang, label_format)?;
+                }
+
+                let pivot_rows = match &pivot {
+                    Some((selector, _)) => pivot_rows(range, selector),
+                    None => BTreeSet::new(),
+                };
+
+                // The links go in first, the texts written over them later keep them.
+                write_hyperlinks(worksheet, layout, geometry, styles, log);
+
+                let mut formula_cells = BTreeSet::new();
+
+                // Formulas are written back as formulas, with their string literals translated if
+                // asked for, instead of as the values they were last calculated to.
+                {
+                    let formulas = &sheet.formulas;
+                    let (first_row, first_column) = range.start().unwrap_or((0, 0));
+                    let (formula_row, formula_column) = formulas.start().unwrap_or((0, 0));
+                    let mut translations = BTreeMap::new();
+
+                    for (row, column, formula) in formulas.used_cells() {
+                        let literals =
+                            if args.formula_translate_strings && xliff.is_none() && !args.offline {
+                                formula_strings::extract(formula)
+                            } else {
+                                vec![]
+                            };
+
+                        let position = (formula_row + row as u32, formula_column + column as u32);
+
+                        // Formulas that were never calculated have no value in the range, which
+                        // can start after them, and are copied to the same cell they are in.
+                        let (Some(row), Some(column)) = (
+                            position.0.checked_sub(first_row),
+                            position.1.checked_sub(first_column),
+                        ) else {
+                            let (row, column) = (position.0, position.1 as u16);
+                            write_formula(worksheet, (row, column), formula, None, None)?;
+                            cell_counts.borrow_mut().formulas += 1;
+                            continue;
+                        };
+
+                        let cell = (row, column as u16);
+                        let mut values = vec![];
+
+                        for literal in &literals {
+                            let value = normalize_whitespace(&literal.value);
+
+                            if !value.chars().any(char::is_alphabetic)
+                                || protected.as_ref().is_some_and(|p| p.covers(&value))
+                            {
+                                values.push(literal.value.clone());
+                                continue;
+                            }
+
+                            let key = args.match_mode.key(&value);
+
+                            if !translations.contains_key(&key) {
+                                let (translation, method, tokens) = match translate_text(
+                                    &value,
+                                    &dictionary,
+                                    cell,
+                                    false,
+                                    "formula",
+                                )
+                                .await
+                                {
+                                    Ok(translated) => translated,
+                                    Err(e) => {
+                                        let error = format!("{:#}", e);
+                                        abort(&sheet.name, range, cell, &error)?;
+                                        log.warn(Some(cell), &error);
+                                        failures.borrow_mut().push(Failure::new(
+                                            &sheet.name,
+                                            range,
+                                            cell,
+                                            &value,
+                                            error,
+                                        ));
+
+                                        (value.clone(), "error", 0)
+                                    }
+                                };
+
+                                if let Some(history) = &mut *history.borrow_mut() {
+                                    history.record(
+                                        &value,
+                                        &translation,
+                                        target.model,
+                                        method,
+                                        tokens,
+                                        None,
+                                    )?;
+                                }
+
+                                translations.insert(key.clone(), translation);
+                            }
+
+                            values.push(translations[&key].clone());
+                        }
+
+                        // The last calculated value doesn't hold anymore once a literal is
+                        // translated.
+                        let result = literals
+                            .iter()
+                            .zip(&values)
+                            .all(|(literal, value)| literal.value == *value)
+                            .then(|| range.get((cell.0 as usize, cell.1 as usize)))
+                            .flatten();
+
+                        let formula = formula_strings::replace(
+                            formula,
+                            literals.iter().zip(values.iter().map(String::as_str)),
+                        );
+
+                        let target_cell = layout.cell(cell.0, cell.1);
+                        write_formula(worksheet, target_cell, &formula, result, format(cell))?;
+                        formula_cells.insert(cell);
+                        cell_counts.borrow_mut().formulas += 1;
+                    }
+                }
+
+                // Notes are attached to the translated cells, on which they are shown in the same
+                // place relative to the start of the worksheet as in the source.
+                for (&(row, column), comment) in &sheet.comments {
+                    let (first_row, first_column) = range.start().unwrap_or((0, 0));
+
+                    let (Some(row), Some(column)) = (
+                        row.checked_sub(first_row),
+                        u32::from(column).checked_sub(first_column),
+                    ) else {
+                        continue;
+                    };
+
+                    let cell = (row, column as u16);
+                    let value = comment.trim();
+
+                    let verbatim = xliff.is_some() || args.offline;
+
+                    let (translation, method, tokens) =
+                        match translate_text(value, &dictionary, cell, verbatim, "comment").await {
+                            Ok(translated) => translated,
+                            Err(e) => {
+                                let error = format!("{:#}", e);
+                                abort(&sheet.name, range, cell, &error)?;
+                                log.warn(Some(cell), &error);
+                                failures.borrow_mut().push(Failure::new(
+                                    &sheet.name,
+                                    range,
+                                    cell,
+                                    value,
+                                    error,
+                                ));
+
+                                (value.to_string(), "error", 0)
+                            }
+                        };
+
+                    debug!(
+                        sheet = %sheet.name,
+                        cell = ?cell,
+                        method,
+                        source = value,
+                        %translation,
+                        "Translated comment"
+                    );
+
+                    if let Some(history) = &mut *history.borrow_mut() {
+                        history.record(value, &translation, target.model, method, tokens, None)?;
+                    }
+
+                    let (r, c) = layout.translation_cell(cell.0, cell.1);
+                    worksheet.write_comment(r, c, &translation)?;
+                }
+
+                // The values of the lists are translated on their own, and the cells holding one of
+                // them get the same translation in the end so that they still validate.
+                let mut lists = vec![];
+
+                for validation in geometry.validations() {
+                    let ListSource::Values(values) = &validation.source else {
+                        lists.push((validation, BTreeMap::new()));
+                        continue;
+                    };
+
+                    let cell = validation.ranges[0].0;
+                    let mut translations = BTreeMap::new();
+
+                    for value in values {
+                        let value = value.trim();
+                        let key = args.match_mode.key(value);
+
+                        let verbatim = xliff.is_some()
+                            || args.offline
+                            || !value.chars().any(char::is_alphabetic)
+                            || protected.as_ref().is_some_and(|p| p.covers(value));
+
+                        let translation = match translate_text(
+                            value,
+                            &dictionary,
+                            cell,
+                            verbatim,
+                            "list",
+                        )
+                        .await
+                        {
+                            Ok((text, method, tokens)) => {
+                                if let Some(history) =
+                                    history.borrow_mut().as_mut().filter(|_| method == "list")
+                                {
+                                    history.record(
+                                        value,
+                                        &text,
+                                        target.model,
+                                        method,
+                                        tokens,
+                                        None,
+                                    )?;
+                                }
+
+                                text
+                            }
+                            Err(e) => {
+                                let error = format!("{:#}", e);
+                                abort(&sheet.name, range, cell, &error)?;
+                                log.warn(Some(cell), &error);
+                                failures.borrow_mut().push(Failure::new(
+                                    &sheet.name,
+                                    range,
+                                    cell,
+                                    value,
+                                    error,
+                                ));
+                                value.to_string()
+                            }
+                        };
+
+                        translations.insert(key, translation);
+                    }
+                    lists.push((validation, translations));
+                }
+
+                // Without `--stream-rows` the whole worksheet is a single block.
+                let block_rows = args.stream_rows.unwrap_or(range.height()).max(1);
+
+                for block in (0..range.height()).step_by(block_rows) {
+                    if stopped.load(Ordering::Relaxed) {
+                        break;
+                    }
+
+                    if args.reload_dictionary {
+                        if let Some(changed) = live.reload() {
+                            bar.println(format!(
+                                "Reloaded the dictionary, {} entries new or changed",
+                                changed
+                            ));
+                        }
+
+                        dictionary = live.current();
+                    }
+
+                    let (tx, mut rx) = mpsc::channel(rate_limit.requests);
+                    let mut untranslated = BTreeMap::<String, Vec<(u32, u16)>>::new();
+                    let mut pending = vec![];
+                    let mut hits = 0;
+
+                    let cells = range
+                        .rows()
+                        .enumerate()
+                        .skip(block)
+                        .take(block_rows)
+                        .flat_map(|(row, cells)| {
+                            cells
+                                .iter()
+                                .enumerate()
+                                .map(move |(column, data)| (row, column, data))
+                        });
+
+                    for (row, column, data) in cells {
+                        let row = row as u32;
+                        let column = column as u16;
+
+                        if formula_cells.contains(&(row, column)) {
+                            bar.inc(1);
+                            continue;
+                        }
+
+                        let DataType::String(value) = data else {
+                            if let Some(cell) = layout.source_cell(row, column) {
+                                write_value(
+                                    worksheet,
+                                    cell,
+                                    data,
+                                    format((row, column)),
+                                    value_formats,
+                                )?;
+                            }
+
+                            // Dates get the date format of the language they are translated into.
+                            let localized = match data {
+                                DataType::DateTime(days) if localizer.is_some() => {
+                                    let settings = schema::column(schema.as_ref(), column);
+                                    Locale::of(settings.target(target).language).map(|locale| {
+                                        let mut localized = format((row, column))
+                                            .cloned()
+                                            .unwrap_or_else(Format::new);
+                                        localized.set_num_format(
+                                            &locale.date_format(days.fract() != 0.),
+                                        );
+                                        localized
+                                    })
+                                }
+                                _ => None,
+                            };
+
+                            let cell = layout.cell(row, column);
+                            let cell_format = localized.as_ref().or(format((row, column)));
+                            write_value(worksheet, cell, data, cell_format, value_formats)?;
+                            bar.inc(1);
+                            continue;
+                        };
+
+                        let value = if args.preserve_whitespace {
+                            Cow::Borrowed(value.trim())
+                        } else if args.preserve_line_breaks {
+                            Cow::Owned(normalize_lines(value))
+                        } else {
+                            Cow::Owned(normalize_whitespace(value))
+                        };
+
+                        let value = value.as_ref();
+
+                        let header = row < args.header_rows;
+
+                        if let Some((r, c)) = layout.source_cell(row, column) {
+                            let source = if layout.label_row == Some(row) {
+                                &args.source_lang
+                            } else {
+                                value
+                            };
+
+                            worksheet.write_string(r, c, source, format((row, column)))?;
+                        }
+
+                        let (r, c) = layout.cell(row, column);
+                        let settings = schema::column(schema.as_ref(), column);
+
+                        let outside_window = !header && {
+                            let data_row = row - args.header_rows;
+
+                            data_row < args.target_row_offset
+                                || args
+                                    .target_row_limit
+                                    .is_some_and(|limit| data_row - args.target_row_offset >= limit)
+                        };
+
+                        let (first_row, first_column) = range.start().unwrap_or((0, 0));
+                        let position = (first_row + row, first_column as u16 + column);
+
+                        let outside_selection = !args.translate_selection.is_empty()
+                            && !args
+                                .translate_selection
+                                .iter()
+                                .any(|selection| selection.contains(&sheet.name, position));
+
+                        let outside_rows = args.rows.is_some_and(|rows| !rows.contains(position.0));
+
+                        let outside_columns = selected_columns
+                            .as_ref()
+                            .is_some_and(|columns| !columns.contains(&column));
+
+                        let untargeted =
+                            targets.is_some_and(|targets| !targets.contains_key(&position));
+
+                        // Links that show their own URL stay as they are.
+                        let is_link_target = geometry
+                            .hyperlinks()
+                            .get(&(row, column))
+                            .is_some_and(|url| url.strip_prefix("mailto:").unwrap_or(url) == value);
+
+                        let in_target = args.skip_if_target
+                            && language::is_in(
+                                value,
+                                language_name(settings.target(target).language),
+                            );
+
+                        let in_scope = !(header && !args.translate_headers
+                            || outside_window
+                            || outside_selection
+                            || outside_rows
+                            || outside_columns
+                            || untargeted
+                            || !settings.translates());
+
+                        // Numbers and dates are written the way of the target language, not
+                        // translated.
+                        let localized = localizer
+                            .as_ref()
+                            .filter(|_| in_scope)
+                            .zip(Locale::of(settings.target(target).language))
+                            .and_then(|(localizer, locale)| localizer.convert(value, locale));
+
+                        if let Some(localized) = localized {
+                            worksheet.write_string(r, c, &localized, format((row, column)))?;
+                            trace!(
+                                sheet = %sheet.name,
+                                cell = ?(row, column),
+                                %localized,
+                                "Localized"
+                            );
+                            write_label(
+                                worksheet,
+                                layout,
+                                (row, column),
+                                &localized,
+                                label_format,
+                            )?;
+                            written_cells.push((r, c));
+                            cell_counts.borrow_mut().copied += 1;
+                            bar.inc(1);
+                            continue;
+                        }
+
+                        if value.is_empty()
+                            || header && !args.translate_headers
+                            || outside_window
+                            || outside_selection
+                            || outside_rows
+                            || outside_columns
+                            || untargeted
+                            || in_target
+                            || is_link_target
+                            || skip.matches(value)
+                            || !settings.translates()
+                            || pivot.is_some() && (column == 0 || !pivot_rows.contains(&row))
+                            || !header
+                                && column_types
+                                    .get(&column)
+                                    .is_some_and(|t| !t.translates(value))
+                        {
+                            worksheet.write_string(r, c, value, format((row, column)))?;
+
+                            if !value.is_empty() {
+                                trace!(sheet = %sheet.name, cell = ?(row, column), "Copied");
+                                write_label(worksheet, layout, (row, column), value, label_format)?;
+                                cell_counts.borrow_mut().copied += 1;
+                            }
+
+                            bar.inc(1);
+                            continue;
+                        }
+
+                        // Pivoted source rows stay as they are, their translations go in the rows
+                        // below.
+                        if pivot.is_some() {
+                            worksheet.write_string(r, c, value, format((row, column)))?;
+                        }
+
+                        // Cells with runs are translated with the runs marked, to rebuild them
+                        // from.
+                        let marked = runs((row, column)).map(rich_text::marked);
+                        let value = marked.as_deref().unwrap_or(value);
+
+                        let (r, c) = layout.translation_cell(row, column);
+                        let key = args.match_mode.key(value);
+
+                        // Cells with runs can't be taken back from the previous translation, which
+                        // has only its text.
+                        let unchanged =
+                            previous.filter(|_| marked.is_none()).and_then(|previous| {
+                                previous.translation(
+                                    position,
+                                    value,
+                                    args.preserve_whitespace,
+                                    layout,
+                                )
+                            });
+
+                        // Cells whose source changed since the `--previous` one are translated
+                        // anew.
+                        let kept = merged
+                            .filter(|_| {
+                                previous.is_none_or(|previous| {
+                                    !previous.changed(position, value, args.preserve_whitespace)
+                                })
+                            })
+                            .and_then(|merged| {
+                                merge::kept(merged, layout.translation_cell(row, column))
+                            });
+
+                        let translation = match targets.and_then(|targets| targets.get(&position)) {
+                            Some(target) => Some((target.clone(), "xliff")),
+                            None if kept.is_some() => {
+                                kept.map(|translation| (translation, "merged"))
+                            }
+                            None if unchanged.is_some() => {
+                                unchanged.map(|translation| (translation, "previous"))
+                            }
+                            None => {
+                                // Dictionary keys have their line breaks collapsed like any
+                                // whitespace.
+                                let exact = exact(&key).or_else(|| {
+                                    (args.preserve_line_breaks && key.contains('\n'))
+                                        .then(|| exact(&normalize_whitespace(&key)))
+                                        .flatten()
+                                });
+
+                                match exact.filter(|_| !settings.overrides_target_language(target))
+                                {
+                                    Some((translation, method)) => Some((translation, method)),
+                                    None => match (&header_cache, &translation_cache) {
+                                        (Some(cache), _) if header => Some(cache),
+                                        (_, cache) => cache.as_ref(),
+                                    }
+                                    .map(|cache| {
+                                        cache.get(
+                                            &key,
+                                            settings.target(target).language,
+                                            settings.target(target).model,
+                                        )
+                                    })
+                                    .transpose()?
+                                    .flatten()
+                                    .map(|translation| (translation, "cache"))
+                                    .or_else(|| {
+                                        let translation = session
+                                            .borrow()
+                                            .get(&settings.group(&key, target))?
+                                            .clone();
+                                        Some((translation, "session"))
+                                    }),
+                                }
+                            }
+                        };
+
+                        let translation = translation.or_else(|| {
+                            let threshold = args
+                                .fuzzy_threshold
+                                .filter(|_| args.fuzzy_mode == FuzzyMode::Reuse)
+                                .filter(|_| !settings.overrides_target_language(target))?;
+
+                            fuzzy::closest(&key, dictionary.iter().chain(memory), threshold)
+                                .map(|closest| (closest.translation.to_string(), "fuzzy"))
+                        });
+
+                        if let Some((translation, method)) = translation {
+                            // Those of XLIFF files, of the previous translation and of the workbook
+                            // merged into are for this very cell.
+                            let translation = match method {
+                                "xliff" | "previous" | "merged" => translation,
+                                _ => case((row, column)).apply(&translation),
+                            };
+
+                            debug!(
+                                sheet = %sheet.name,
+                                cell = ?(row, column),
+                                method,
+                                source = value,
+                                %translation,
+                                "Translated"
+                            );
+                            hits += 1;
+                            cell_counts.borrow_mut().count(method);
+                            let cell = (row, column);
+                            let text = envelope(cell).restore(&translation);
+                            write_text(worksheet, (r, c), &text, runs(cell), format(cell))?;
+
+                            if !translation.is_empty() {
+                                written_cells.push((r, c));
+                            }
+
+                            // Translations of runs are only compared as a whole.
+                            if let Some(terminology) = terminology
+                                .borrow_mut()
+                                .as_mut()
+                                .filter(|_| marked.is_none())
+                            {
+                                terminology.record(
+                                    index,
+                                    cell,
+                                    (r, c),
+                                    value,
+                                    &translation,
+                                    method,
+                                );
+                            }
+
+                            // The dictionaries, memories and XLIFF files are taken to be reviewed
+                            // already.
+                            if let Some(review) = review.borrow_mut().as_mut().filter(|_| {
+                                marked.is_none() && matches!(method, "cache" | "fuzzy" | "session")
+                            }) {
+                                review.add(&sheet.name, range, cell, value, &translation);
+                            }
+
+                            write_label(
+                                worksheet,
+                                layout,
+                                (row, column),
+                                &translation,
+                                label_format,
+                            )?;
+
+                            if let Some(history) = &mut *history.borrow_mut() {
+                                history.record(
+                                    value,
+                                    &translation,
+                                    target.model,
+                                    method,
+                                    0,
+                                    None,
+                                )?;
+                            }
+
+                            if let Some(provenance) = &mut *provenance.borrow_mut() {
+                                let location = (sheet.name.as_str(), range, cell);
+                                provenance.record(
+                                    location,
+                                    value,
+                                    &translation,
+                                    method,
+                                    target.model,
+                                    0,
+                                )?;
+                            }
+
+                            if let Some(sidecar) = &mut *sidecar.borrow_mut() {
+                                let location = (sheet.name.as_str(), range, cell);
+                                sidecar.record(location, value, &text, method);
+                            }
+
+                            bar.inc(1);
+                            continue;
+                        }
+
+                        let group = settings.group(&key, target);
+
+                        if let Some(cells) = untranslated.get_mut(&group) {
+                            cells.push((row, column));
+                            continue;
+                        }
+
+                        untranslated.insert(group.clone(), vec![(row, column)]);
+                        pending.push((group, value.to_string()));
+                    }
+
+                    passthrough.set(passthrough.get() && pending.is_empty() && hits == 0);
+                    bar.set_message(progress(&cell_counts.borrow()));
+
+                    let mut segments = BTreeMap::new();
+
+                    if let Some(openai) = openai
+                        .as_ref()
+                        .filter(|_| args.ai_segment_classify && !estimating)
+                    {
+                        let mut unclassified = vec![];
+
+                        for (group, value) in &pending {
+                            let journaled = journal.borrow().as_ref().map(|journal| {
+                                let segment = journal.segment(&sheet.name, group);
+                                let translated = journal.completion(&sheet.name, group).is_some();
+                                (segment, translated)
+                            });
+
+                            match journaled {
+                                Some((Some(segment), _)) => {
+                                    segments.insert(group.clone(), segment);
+                                }
+                                // Values translated before need no class anymore.
+                                Some((None, true)) => {}
+                                _ => unclassified.push((group.clone(), value.clone())),
+                            }
+                        }
+
+                        let classified = classify(
+                            &unclassified,
+                            limiter,
+                            bpe.as_ref(),
+                            target.model,
+                            openai,
+                            log,
+                        )
+                        .await;
+
+                        if let Some(journal) = &mut *journal.borrow_mut() {
+                            for (group, segment) in &classified {
+                                journal.record_segment(&sheet.name, group, *segment)?;
+                            }
+                        }
+
+                        segments.extend(classified);
+                    }
+
+                    let mut references = BTreeMap::new();
+
+                    if let (Some(index), Some(openai)) = (&semantic, &openai) {
+                        let values = pending
+                            .iter()
+                            .map(|(_, value)| rich_text::strip_tags(value))
+                            .collect::<Vec<_>>();
+                        let threshold = args.semantic_threshold as f32;
+
+                        match index.references(openai, &values, threshold).await {
+                            Ok(found) => {
+                                for ((group, _), found) in pending.iter().zip(found) {
+                                    let hints = found.iter().map(|reference| reference.hint());
+                                    references.insert(
+                                        group.clone(),
+                                        hints.collect::<Vec<_>>().join("; "),
+                                    );
+                                }
+                            }
+                            Err(e) => {
+                                log.warn(None, format_args!("Semantic lookup failed: {:#}", e))
+                            }
+                        }
+                    }
+
+                    let mut replayed = vec![];
+                    let mut masks = BTreeMap::new();
+                    let mut requests = vec![];
+
+                    for (group, value) in pending {
+                        if let Some(completion) = journal
+                            .borrow()
+                            .as_ref()
+                            .and_then(|journal| journal.completion(&sheet.name, &group))
+                        {
+                            replayed.push((group, value, Ok(completion)));
+                            continue;
+                        }
+
+                        let segment = segments.get(&group).copied();
+                        let cell = untranslated[&group][0];
+                        let settings = schema::column(schema.as_ref(), cell.1);
+                        let cell_target = settings.target(target);
+
+                        if segment.is_some_and(Segment::is_verbatim)
+                            || protected.as_ref().is_some_and(|p| p.covers(&value))
+                        {
+                            for cell in untranslated[&group].iter().copied() {
+                                let (row, column) = layout.translation_cell(cell.0, cell.1);
+                                let text = envelope(cell).restore(&value);
+                                write_text(
+                                    worksheet,
+                                    (row, column),
+                                    &text,
+                                    runs(cell),
+                                    format(cell),
+                                )?;
+                                write_label(worksheet, layout, cell, &value, label_format)?;
+                                written_cells.push((row, column));
+                                cell_counts.borrow_mut().count("verbatim");
+
+                                if let Some(history) = &mut *history.borrow_mut() {
+                                    history.record(
+                                        &value,
+                                        &value,
+                                        cell_target.model,
+                                        "verbatim",
+                                        0,
+                                        None,
+                                    )?;
+                                }
+
+                                if let Some(provenance) = &mut *provenance.borrow_mut() {
+                                    provenance.record(
+                                        (&sheet.name, range, cell),
+                                        &value,
+                                        &value,
+                                        "verbatim",
+                                        cell_target.model,
+                                        0,
+                                    )?;
+                                }
+
+                                if let Some(sidecar) = &mut *sidecar.borrow_mut() {
+                                    sidecar.record(
+                                        (&sheet.name, range, cell),
+                                        &value,
+                                        &text,
+                                        "verbatim",
+                                    );
+                                }
+
+                                bar.inc(1);
+                            }
+
+                            continue;
+                        }
+
+                        if args.offline {
+                            let text = match args.offline_fill {
+                                OfflineFill::Copy => value.as_str(),
+                                OfflineFill::Blank => "",
+                            };
+
+                            for cell in untranslated[&group].iter().copied() {
+                                let (row, column) = layout.translation_cell(cell.0, cell.1);
+                                write_text(
+                                    worksheet,
+                                    (row, column),
+                                    text,
+                                    runs(cell),
+                                    format(cell),
+                                )?;
+                                write_label(worksheet, layout, cell, text, label_format)?;
+                                written_cells.push((row, column));
+                                cell_counts.borrow_mut().untranslated += 1;
+                                bar.inc(1);
+                            }
+
+                            debug!(
+                                sheet = %sheet.name,
+                                cell = ?cell,
+                                source = value.as_str(),
+                                "Left untranslated"
+                            );
+                            offline_untranslated
+                                .borrow_mut()
+                                .insert(rich_text::strip_tags(&value));
+                            continue;
+                        }
+
+                        let source = match protected.as_ref().and_then(|p| p.mask(&value)) {
+                            Some(masked) => {
+                                let text = masked.text.clone();
+                                masks.insert(group.clone(), masked);
+                                text
+                            }
+                            None => value.clone(),
+                        };
+
+                        requests.push((group, value, source, segment));
+                    }
+
+                    // The prompts are only built once there is room for their requests, for the
+                    // values of a large worksheet not to be held all at once.
+                    let mut build = |group: String,
+                                     value: String,
+                                     source: String,
+                                     segment: Option<Segment>|
+                     -> Result<Job> {
+                        let cell = untranslated[&group][0];
+                        let settings = schema::column(schema.as_ref(), cell.1);
+                        let cell_target = settings.target(target);
+
+                        let adjacent = match &bpe {
+                            Some(bpe) => adjacent_context(
+                                range,
+                                cell,
+                                context_columns,
+                                args.context_max_tokens,
+                                bpe,
+                            ),
+                            None => None,
+                        };
+
+                        let position = args
+                            .context
+                            .then(|| position_context(range, cell, layout.label_row))
+                            .flatten();
+
+                        let suggestion = args
+                            .fuzzy_threshold
+                            .filter(|_| args.fuzzy_mode == FuzzyMode::Suggest)
+                            .filter(|_| !settings.overrides_target_language(target))
+                            .and_then(|threshold| {
+                                let entries = dictionary.iter().chain(memory);
+                                fuzzy::closest(&args.match_mode.key(&value), entries, threshold)
+                            })
+                            .map(|closest| closest.hint());
+
+                        // For another language, the references are in the wrong one.
+                        let reference = references
+                            .remove(&group)
+                            .filter(|hints: &String| !hints.is_empty())
+                            .filter(|_| !settings.overrides_target_language(target));
+
+                        let context = [
+                            position,
+                            adjacent
+                                .filter(|adjacent| !adjacent.is_empty())
+                                .map(|adjacent| {
+                                    format!("Adjacent columns in the same row: {}", adjacent)
+                                }),
+                            suggestion,
+                            reference,
+                        ]
+                        .into_iter()
+                        .flatten()
+                        .reduce(|context, part| format!("{}; {}", context, part))
+                        // The neighbours of a value have personal data of their own.
+                        .map(|context| match &protected {
+                            Some(protected) => protected.redact(&context).into_owned(),
+                            None => context,
+                        });
+
+                        let dictionary = if settings.overrides_target_language(target) {
+                            no_dictionary
+                        } else {
+                            &dictionary
+                        };
+
+                        let prompt = build_prompt(
+                            &source,
+                            dictionary,
+                            segment,
+                            context.as_deref(),
+                            source_language(&source),
+                            cell_target.language,
+                            template.as_ref(),
+                        );
+
+                        // The tags of runs could end up in different pieces.
+                        let piece_prompt = |piece: &str, overlap: Option<&str>| {
+                            let overlap = overlap
+                                .map(|overlap| format!("The text goes on from: {}", overlap));
+                            let context = [context.clone(), overlap]
+                                .into_iter()
+                                .flatten()
+                                .reduce(|context, part| format!("{}; {}", context, part));
+
+                            build_prompt(
+                                piece,
+                                dictionary,
+                                segment,
+                                context.as_deref(),
+                                source_language(piece),
+                                cell_target.language,
+                                template.as_ref(),
+                            )
+                        };
+
+                        let (max_tokens, encoding) = (args.max_chunk_tokens, bpe.as_ref());
+                        let mut chunks = if rich_text::has_tags(&source) {
+                            vec![]
+                        } else if args.dedupe_sentences {
+                            chunking::sentence_chunks(&source, max_tokens, encoding, piece_prompt)
+                        } else {
+                            chunking::chunks(&source, max_tokens, encoding, piece_prompt)
+                        };
+
+                        // Sentences known by themselves are not sent again.
+                        if args.dedupe_sentences && !settings.overrides_target_language(target) {
+                            for chunk in &mut chunks {
+                                chunk.translation = match exact(&args.match_mode.key(&chunk.source))
+                                {
+                                    Some((translation, _)) => Some(translation),
+                                    None => translation_cache
+                                        .as_ref()
+                                        .map(|cache| {
+                                            cache.get(
+                                                &chunk.source,
+                                                cell_target.language,
+                                                cell_target.model,
+                                            )
+                                        })
+                                        .transpose()?
+                                        .flatten(),
+                                };
+                            }
+                        }
+
+                        Ok(Job {
+                            // The first chunk is sent when the job is.
+                            estimated_tokens: chunks.first().map_or_else(
+                                || estimate_tokens(&prompt, bpe.as_ref()),
+                                |chunk| chunk.estimated_tokens,
+                            ),
+                            chunks,
+                            max_length: max_length(&untranslated[&group]),
+                            cell,
+                            key: group,
+                            source,
+                            prompt,
+                            temperature: preset_temperature
+                                .or(segment.map(Segment::temperature))
+                                .unwrap_or(0.),
+                            model: cell_target.model.to_string(),
+                            language: cell_target.language.to_string(),
+                            context,
+                        })
+                    };
+
+                    if let (Some(estimate), Some(bpe)) = (&mut *estimate.borrow_mut(), &bpe) {
+                        estimate.hits += hits;
+
+                        for (group, value, source, segment) in requests {
+                            let job = build(group, value, source, segment)?;
+                            estimate.add(&job, untranslated[&job.key].len(), bpe);
+                        }
+
+                        continue;
+                    }
+
+                    let batch = args.use_batch_api.then(|| batch::Options {
+                        poll_interval: Duration::from_secs(args.batch_poll_interval),
+                        timeout: Duration::from_secs(args.batch_timeout * 60 * 60),
+                        request_id_prefix: args.api_request_id_prefix.clone(),
+                        api_base: openai
+                            .as_ref()
+                            .map_or(API_BASE, |openai| openai.api_base())
+                            .to_string(),
+                        sampling: sampling.clone(),
+                        sheet: sheet.name.clone(),
+                        detach: args.batch_detach.then(|| batch::path(destination_path)),
+                    });
+
+                    // Values that fail are sent once more after all the others, by themselves.
+                    let retry_jobs = &RefCell::new(BTreeMap::<String, Job>::new());
+
+                    let mut announced = HashMap::new();
+                    let mut awaited = HashMap::new();
+
+                    // The worksheets translated in batches ask for all of their values.
+                    if batch.is_none() {
+                        let mut requested = requested.borrow_mut();
+
+                        for (group, ..) in &requests {
+                            // Those that failed for another worksheet are asked for again by this
+                            // one.
+                            let pending = requested.get(group).filter(|receiver| {
+                                receiver.borrow().is_some() || receiver.has_changed().is_ok()
+                            });
+
+                            match pending {
+                                Some(receiver) => {
+                                    awaited.insert(group.clone(), receiver.clone());
+                                }
+                                None => {
+                                    let (sender, receiver) = tokio::sync::watch::channel(None);
+                                    requested.insert(group.clone(), receiver);
+                                    announced.insert(group.clone(), sender);
+                                }
+                            }
+                        }
+                    }
+
+                    // The values whose translations came from another worksheet, until they are
+                    // received.
+                    let sessioned = Arc::new(Mutex::new(HashSet::new()));
+
+                    // The values another worksheet asked for are waited for, and only asked for if
+                    // it failed to translate them.
+                    let wait = {
+                        let translator = translator.clone();
+                        let limiter = limiter.clone();
+                        let in_flight = in_flight.clone();
+                        let stopped = stopped.clone();
+                        let budget = budget.clone();
+                        let sessioned = sessioned.clone();
+                        let tx = tx.clone();
+
+                        move |mut receiver: tokio::sync::watch::Receiver<Option<String>>,
+                              job: Job| {
+                            let translator = translator.clone();
+                            let limiter = limiter.clone();
+                            let in_flight = in_flight.clone();
+                            let stopped = stopped.clone();
+                            let budget = budget.clone();
+                            let sessioned = sessioned.clone();
+                            let tx = tx.clone();
+
+                            tokio::spawn(async move {
+                                let translated = receiver.wait_for(Option::is_some).await;
+
+                                let completion = match translated.ok().and_then(|text| text.clone())
+                                {
+                                    Some(text) => {
+                                        sessioned.lock().unwrap().insert(job.key.clone());
+
+                                        Ok(Completion {
+                                            text,
+                                            tokens: 0,
+                                            input_tokens: 0,
+                                            model: job.model.clone(),
+                                            request_id: None,
+                                            confidence: None,
+                                        })
+                                    }
+                                    None => {
+                                        let Ok(_permit) = in_flight.acquire().await else {
+                                            return;
+                                        };
+
+                                        limiter.acquire(job.estimated_tokens).await;
+
+                                        if stopped.load(Ordering::Relaxed) || budget.exhausted() {
+                                            return;
+                                        }
+
+                                        let completion = translator.translate(&job).await;
+                                        budget.record(completion.as_ref().ok());
+                                        completion
+                                    }
+                                };
+
+                                let _ = tx.send((job.key, job.source, completion)).await;
+                            });
+                        }
+                    };
+
+                    let (job_tx, mut jobs) = mpsc::channel::<Job>(args.concurrency);
+                    let mut requests = requests.into_iter();
+
+                    let feed = move || -> Option<Result<Job>> {
+                        loop {
+                            let (group, value, source, segment) = requests.next()?;
+
+                            let job = match build(group, value, source, segment) {
+                                Ok(job) => job,
+                                Err(e) => return Some(Err(e)),
+                            };
+
+                            retry_jobs.borrow_mut().insert(job.key.clone(), job.clone());
+
+                            match awaited.remove(&job.key) {
+                                Some(receiver) => wait(receiver, job),
+                                None => return Some(Ok(job)),
+                            }
+                        }
+                    };
+
+                    let mut feeding = Some((job_tx, feed));
+
+                    let mut retries = vec![];
+                    let mut retrying = false;
+                    // Values rejected in `--review` aren't asked for again.
+                    let mut rejected = HashSet::new();
+                    let retry_translator = translator.clone();
+                    let retry_limiter = limiter.clone();
+
+                    let scheduler_log = log.clone();
+                    let scheduler_stopped = stopped.clone();
+                    let scheduler_budget = budget.clone();
+                    let translator = translator.clone();
+                    let openai = openai.clone();
+                    let limiter = limiter.clone();
+                    let pack_size = args.pack_size.map_or(1, usize::from);
+                    let in_flight = in_flight.clone();
+                    let max_length_retries = args.max_length_retries;
+                    let masks = Arc::new(masks);
+                    let scheduler_masks = masks.clone();
+                    let scheduler_sheet = sheet.name.clone();
+
+                    tokio::spawn(async move {
+                        for translation in replayed {
+                            if tx.send(translation).await.is_err() {
+                                return;
+                            }
+                        }
+
+                        let mut queued = VecDeque::new();
+
+                        if let (Some(options), Some(openai)) = (batch, &openai) {
+                            let client = openai.client();
+                            let mut all = vec![];
+
+                            while let Some(job) = jobs.recv().await {
+                                all.push(job);
+                            }
+
+                            // Long values need a request per chunk, one after the other.
+                            let (chunked, mut batched) = all
+                                .into_iter()
+                                .partition::<Vec<_>, _>(|job| !job.chunks.is_empty());
+
+                            if let Err(e) =
+                                batch::run(&mut batched, &options, client, &tx, &scheduler_log)
+                                    .await
+                            {
+                                scheduler_log.warn(None, format_args!("Batch failed: {:#}", e));
+                            }
+
+                            queued.extend(chunked);
+                            queued.extend(batched);
+
+                            if !queued.is_empty() {
+                                scheduler_log.println(format!(
+                                    "Translating {} remaining values synchronously",
+                                    queued.len()
+                                ));
+                            }
+                        }
+
+                        // In the order the values were found in.
+                        while let Some(job) = next_job(&mut queued, &mut jobs).await {
+                            let mut pack = vec![job];
+
+                            // Only values for the same model and temperature can share a request.
+                            while pack.len() < pack_size {
+                                match next_job(&mut queued, &mut jobs).await {
+                                    Some(next)
+                                        if next.model == pack[0].model
+                                            && next.temperature == pack[0].temperature
+                                            && next.chunks.is_empty()
+                                            && pack[0].chunks.is_empty() =>
+                                    {
+                                        pack.push(next);
+                                    }
+                                    Some(next) => {
+                                        queued.push_front(next);
+                                        break;
+                                    }
+                                    None => break,
+                                }
+                            }
+
+                            // Held until the request is answered.
+                            let Ok(permit) = in_flight.clone().acquire_owned().await else {
+                                return;
+                            };
+
+                            limiter
+                                .acquire(pack.iter().map(|job| job.estimated_tokens).sum())
+                                .await;
+
+                            if scheduler_stopped.load(Ordering::Relaxed)
+                                || scheduler_budget.exhausted()
+                            {
+                                return;
+                            }
+
+                            let translator = translator.clone();
+                            let openai = openai.clone();
+                            let limiter = limiter.clone();
+                            let log = scheduler_log.clone();
+                            let tx = tx.clone();
+
+                            let masks = scheduler_masks.clone();
+                            let budget = scheduler_budget.clone();
+
+                            let span = debug_span!(
+                                "request",
+                                sheet = %scheduler_sheet,
+                                cell = ?pack[0].cell,
+                                values = pack.len(),
+                                model = %pack[0].model,
+                            );
+
+                            let request = async move {
+                                let _permit = permit;
+                                let packed = pack.len() > 1;
+                                let intact = |job: &Job, completion: &Completion| {
+                                    !matches!(
+                                        masks.get(&job.key),
+                                        Some(masked) if masked.unmask(&completion.text).is_err()
+                                    )
+                                };
+                                let too_long = |job: &Job, completion: &Completion| {
+                                    job.max_length.is_some_and(|max| {
+                                        let text = match masks.get(&job.key) {
+                                            Some(masked) => masked.unmask(&completion.text).ok(),
+                                            None => None,
+                                        };
+                                        let text = text.as_deref().unwrap_or(&completion.text);
+                                        text.trim().chars().count() > max
+                                    })
+                                };
+
+                                if let Some(openai) = openai.filter(|_| packed) {
+                                    let completions = openai.complete_packed(&pack).await;
+                                    budget.record(completions.iter().flatten());
+
+                                    match completions {
+                                        Ok(completions) => {
+                                            let mut altered = vec![];
+
+                                            for (job, completion) in
+                                                pack.into_iter().zip(completions)
+                                            {
+                                                if intact(&job, &completion)
+                                                    && !too_long(&job, &completion)
+                                                {
+                                                    tx.send((job.key, job.source, Ok(completion)))
+                                                        .await?;
+                                                } else {
+                                                    altered.push(job);
+                                                }
+                                            }
+
+                                            pack = altered;
+                                        }
+                                        Err(e) => log.warn(
+                                            None,
+                                            format_args!(
+                                                "Translating {} packed values one at a time: {:#}",
+                                                pack.len(),
+                                                e
+                                            ),
+                                        ),
+                                    }
+                                }
+
+                                for job in pack {
+                                    // The pack's share of the rate limit went to the packed
+                                    // request.
+                                    if packed {
+                                        limiter.acquire(job.estimated_tokens).await;
+                                    }
+
+                                    let mut completion = translator.translate(&job).await;
+                                    budget.record(completion.as_ref().ok());
+
+                                    // Placeholders often survive a second attempt, otherwise the
+                                    // cell is reported as failed.
+                                    if completion.as_ref().is_ok_and(|c| !intact(&job, c)) {
+                                        log.warn(
+                                            Some(job.cell),
+                                            "placeholders came back altered, retrying",
+                                        );
+                                        limiter.acquire(job.estimated_tokens).await;
+                                        completion = translator.translate(&job).await;
+                                        budget.record(completion.as_ref().ok());
+                                    }
+
+                                    // Translations too long for their columns are asked for again,
+                                    // shorter.
+                                    for _ in 0..max_length_retries {
+                                        let (Some(max), Ok(long)) = (job.max_length, &completion)
+                                        else {
+                                            break;
+                                        };
+
+                                        if !too_long(&job, long) {
+                                            break;
+                                        }
+
+                                        log.warn(
+                                            Some(job.cell),
+                                            "translation too long, asking for a shorter one",
+                                        );
+
+                                        let shorter = Job {
+                                            prompt: shorten(&job.prompt, long.text.trim(), max),
+                                            chunks: vec![],
+                                            ..job.clone()
+                                        };
+
+                                        limiter.acquire(shorter.estimated_tokens).await;
+                                        let retried = translator.translate(&shorter).await;
+                                        budget.record(retried.as_ref().ok());
+
+                                        match retried {
+                                            Ok(retried) if intact(&job, &retried) => {
+                                                completion = Ok(retried)
+                                            }
+                                            _ => break,
+                                        }
+                                    }
+
+                                    tx.send((job.key, job.source, completion)).await?;
+                                }
+
+                                Ok::<_, mpsc::error::SendError<Translation>>(())
+                            };
+
+                            tokio::spawn(request.instrument(span));
+                        }
+                    });
+
+                    // The protected terms go back in, and translations that lost them count as
+                    // failed.
+                    // Those from another worksheet have their terms back already.
+                    let unmask = |key: &str, source: String, result: Result<Completion>| {
+                        let sessioned = sessioned.lock().unwrap().contains(key);
+
+                        match masks.get(key) {
+                            Some(masked) => (
+                                masked.unmask(&source).unwrap_or(source),
+                                result.and_then(|mut completion| {
+                                    if !sessioned {
+                                        completion.text = masked.unmask(&completion.text)?;
+                                    }
+
+                                    Ok(completion)
+                                }),
+                            ),
+                            None => (source, result),
+                        }
+                    };
+
+                    loop {
+                        // With `--review` every response of the round is in before any of them is
+                        // written, and only the accepted or edited ones are.
+                        let mut reviewed = VecDeque::new();
+
+                        if args.review {
+                            while let Some((key, source, result)) =
+                                receive(&mut rx, &mut feeding).await?
+                            {
+                                let (source, result) = unmask(&key, source, result);
+                                reviewed.push_back((key, source, result));
+                            }
+
+                            let mut items = reviewed
+                                .iter()
+                                .filter_map(|(key, source, result)| {
+                                    let completion = result.as_ref().ok()?;
+                                    let cell = untranslated[key][0];
+
+                                    (!completion.text.trim().is_empty()).then(|| {
+                                        tui::Item::new(
+                                            format!(
+                                                "{}!{}",
+                                                sheet.name,
+                                                cell_reference(range, cell)
+                                            ),
+                                            source.clone(),
+                                            completion.text.clone(),
+                                        )
+                                    })
+                                })
+                                .collect::<Vec<_>>();
+
+                            bar.suspend(|| {
+                                tokio::task::block_in_place(|| tui::review(&mut items))
+                            })?;
+
+                            let mut items = items.into_iter();
+
+                            for (key, _, result) in &mut reviewed {
+                                let Ok(completion) = result else {
+                                    continue;
+                                };
+
+                                if completion.text.trim().is_empty() {
+                                    continue;
+                                }
+
+                                match items.next().map(|item| item.decision) {
+                                    Some(tui::Decision::Accepted) => {}
+                                    Some(tui::Decision::Edited(text)) => completion.text = text,
+                                    _ => {
+                                        *result = Err(eyre!("rejected in review"));
+                                        rejected.insert(key.clone());
+                                    }
+                                }
+                            }
+                        }
+
+                        while let Some((key, source, result)) = match args.review {
+                            true => reviewed.pop_front(),
+                            false => receive(&mut rx, &mut feeding).await?,
+                        } {
+                            bar.set_message(progress(&cell_counts.borrow()));
+                            let cells = &untranslated[&key];
+
+                            let (source, result) = match args.review {
+                                true => (source, result),
+                                false => unmask(&key, source, result),
+                            };
+
+                            // Retries of the values from another worksheet are requests of this
+                            // one.
+                            let method = match sessioned.lock().unwrap().remove(&key) {
+                                true => "session",
+                                false => "api",
+                            };
+
+                            // The other worksheets waiting for the value ask for it themselves
+                            // unless it is translated the first time.
+                            let announcement = announced.remove(&key);
+                            // Kept only until the value is translated.
+                            let retry_job = retry_jobs.borrow_mut().remove(&key);
+
+                            let completion = match result {
+                                Ok(completion) if !completion.text.trim().is_empty() => completion,
+                                result => {
+                                    if !retrying
+                                        && !stopped.load(Ordering::Relaxed)
+                                        && !args.on_error.is_some_and(OnError::skips_retry)
+                                        && !rejected.contains(&key)
+                                    {
+                                        if let Some(job) = retry_job {
+                                            retries.push(job);
+                                            continue;
+                                        }
+                                    }
+
+                                    let (method, tokens, error) = match result {
+                                        Ok(completion) => {
+                                            let error = "empty response".to_string();
+                                            log.warn(Some(cells[0]), &error);
+                                            ("empty-response", completion.tokens, error)
+                                        }
+                                        Err(e) => {
+                                            let error = format!("{:#}", e);
+                                            log.warn(Some(cells[0]), &error);
+                                            ("error", 0, error)
+                                        }
+                                    };
+
+                                    abort(&sheet.name, range, cells[0], &error)?;
+
+                                    for (i, cell) in cells.iter().copied().enumerate() {
+                                        failures.borrow_mut().push(Failure::new(
+                                            &sheet.name,
+                                            range,
+                                            cell,
+                                            &source,
+                                            &error,
+                                        ));
+
+                                        if args.on_error.is_some_and(OnError::copies) {
+                                            let (row, column) =
+                                                layout.translation_cell(cell.0, cell.1);
+                                            write_text(
+                                                worksheet,
+                                                (row, column),
+                                                &source,
+                                                runs(cell),
+                                                format(cell),
+                                            )?;
+                                            written_cells.push((row, column));
+                                        } else if args.output_empty_for_untranslatable {
+                                            let (row, column) =
+                                                layout.translation_cell(cell.0, cell.1);
+                                            worksheet.write_string(
+                                                row,
+                                                column,
+                                                "",
+                                                Some(error_format),
+                                            )?;
+                                        }
+
+                                        let tokens = if i == 0 { tokens } else { 0 };
+
+                                        if let Some(history) = &mut *history.borrow_mut() {
+                                            history.record(
+                                                &source,
+                                                "",
+                                                target.model,
+                                                method,
+                                                tokens,
+                                                None,
+                                            )?;
+                                        }
+
+                                        if let Some(provenance) = &mut *provenance.borrow_mut() {
+                                            provenance.record(
+                                                (&sheet.name, range, cell),
+                                                &source,
+                                                "",
+                                                method,
+                                                target.model,
+                                                tokens,
+                                            )?;
+                                        }
+
+                                        if let Some(sidecar) = &mut *sidecar.borrow_mut() {
+                                            sidecar.record(
+                                                (&sheet.name, range, cell),
+                                                &source,
+                                                "",
+                                                method,
+                                            );
+                                        }
+
+                                        bar.inc(1);
+                                    }
+
+                                    continue;
+                                }
+                            };
+
+                            let settings = schema::column(schema.as_ref(), cells[0].1);
+                            let cell_target = settings.target(target);
+
+                            if let Some(max) = max_length(cells) {
+                                let length = completion.text.trim().chars().count();
+
+                                if length > max {
+                                    let error = format!(
+                                        "translation of {} characters, more than the {} allowed",
+                                        length, max
+                                    );
+                                    log.warn(Some(cells[0]), &error);
+
+                                    for cell in cells.iter().copied() {
+                                        failures.borrow_mut().push(Failure::new(
+                                            &sheet.name,
+                                            range,
+                                            cell,
+                                            &source,
+                                            &error,
+                                        ));
+                                    }
+                                }
+                            }
+
+                            if args.enforce_glossary && !settings.overrides_target_language(target)
+                            {
+                                let missing = consistency::missing_terms(
+                                    &dictionary,
+                                    &args.match_mode.key(&source),
+                                    &completion.text,
+                                );
+
+                                if !missing.is_empty() {
+                                    match retry_job {
+                                        Some(mut job)
+                                            if !retrying && !stopped.load(Ordering::Relaxed) =>
+                                        {
+                                            job.prompt = enforce_terms(&job.prompt, &missing);
+
+                                            for chunk in &mut job.chunks {
+                                                chunk.prompt =
+                                                    enforce_terms(&chunk.prompt, &missing);
+                                            }
+
+                                            retries.push(job);
+                                            continue;
+                                        }
+                                        _ => {
+                                            let terms = missing
+                                                .iter()
+                                                .map(|(key, value)| format!("{} – {}", key, value))
+                                                .collect::<Vec<_>>()
+                                                .join(", ");
+                                            let error =
+                                                format!("dictionary terms not used: {}", terms);
+                                            log.warn(Some(cells[0]), &error);
+
+                                            for cell in cells.iter().copied() {
+                                                failures.borrow_mut().push(Failure::new(
+                                                    &sheet.name,
+                                                    range,
+                                                    cell,
+                                                    &source,
+                                                    &error,
+                                                ));
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+
+                            session
+                                .borrow_mut()
+                                .insert(key.clone(), completion.text.clone());
+
+                            if let Some(sender) = announcement {
+                                sender.send_replace(Some(completion.text.clone()));
+                            }
+
+                            // Headers go in the header cache if there is one, everything else in
+                            // the translation memory.
+                            if let Some(cache) = &header_cache {
+                                if cells.iter().any(|&(row, _)| row < args.header_rows) {
+                                    let (language, model) =
+                                        (cell_target.language, cell_target.model);
+                                    cache.put(
+                                        &args.match_mode.key(&source),
+                                        language,
+                                        model,
+                                        &completion.text,
+                                    )?;
+                                }
+                            }
+
+                            if let Some(cache) = &translation_cache {
+                                if cells.iter().any(|&(row, _)| {
+                                    row >= args.header_rows || header_cache.is_none()
+                                }) {
+                                    let (language, model) =
+                                        (cell_target.language, cell_target.model);
+                                    cache.put(
+                                        &args.match_mode.key(&source),
+                                        language,
+                                        model,
+                                        &completion.text,
+                                    )?;
+                                }
+                            }
+
+                            if let Some(journal) = &mut *journal.borrow_mut() {
+                                journal.record(&sheet.name, &key, &completion)?;
+                            }
+
+                            if score_consistency {
+                                translated.borrow_mut().push((
+                                    source.to_lowercase(),
+                                    completion.text.clone(),
+                                    cells.len(),
+                                ));
+                            }
+
+                            // The dictionary is for the target language, not the one of the column.
+                            if let Some(learned) = learned
+                                .borrow_mut()
+                                .as_mut()
+                                .filter(|_| !settings.overrides_target_language(target))
+                                .filter(|_| !rich_text::has_tags(&source))
+                            {
+                                learned.insert(&source, &completion.text);
+                            }
+
+                            debug!(
+                                sheet = %sheet.name,
+                                cells = ?cells,
+                                method,
+                                source,
+                                translation = completion.text,
+                                model = completion.model,
+                                tokens = completion.tokens,
+                                request_id = completion.request_id,
+                                "Translated"
+                            );
+
+                            // The tokens are only attributed to the first of the cells sharing a
+                            // request.
+                            let mut tokens = completion.tokens;
+
+                            let unsure = args
+                                .confidence_threshold
+                                .zip(completion.confidence)
+                                .filter(|(threshold, confidence)| confidence < threshold)
+                                .map(|(_, confidence)| confidence);
+
+                            for cell in cells.iter().copied() {
+                                let (row, column) = layout.translation_cell(cell.0, cell.1);
+                                let cased = case(cell).apply(&completion.text);
+                                let restored = envelope(cell).restore(&cased);
+                                let (text, runs) = (&restored, runs(cell));
+
+                                let highlighted = match unsure {
+                                    Some(_) => Some(filled(cell, FormatColor::Yellow)),
+                                    None => (args.mark_translated && method == "api")
+                                        .then(|| filled(cell, mark)),
+                                };
+
+                                let cell_format = highlighted.as_ref().or(format(cell));
+                                write_text(worksheet, (row, column), text, runs, cell_format)?;
+
+                                if let Some(confidence) = unsure {
+                                    let comment = format!(
+                                        "{}\n\nConfidence: {:.0}%",
+                                        source,
+                                        confidence * 100.
+                                    );
+                                    worksheet.write_comment(row, column, &comment)?;
+                                }
+
+                                write_label(
+                                    worksheet,
+                                    layout,
+                                    cell,
+                                    &completion.text,
+                                    label_format,
+                                )?;
+                                written_cells.push((row, column));
+                                cell_counts.borrow_mut().count(method);
+
+                                if let Some(terminology) =
+                                    terminology.borrow_mut().as_mut().filter(|_| runs.is_none())
+                                {
+                                    terminology.record(
+                                        index,
+                                        cell,
+                                        (row, column),
+                                        &source,
+                                        text,
+                                        method,
+                                    );
+                                }
+
+                                if let Some(review) =
+                                    review.borrow_mut().as_mut().filter(|_| runs.is_none())
+                                {
+                                    review.add(&sheet.name, range, cell, &source, text);
+                                }
+
+                                if let Some(history) = &mut *history.borrow_mut() {
+                                    history.record(
+                                        &source,
+                                        &completion.text,
+                                        &completion.model,
+                                        method,
+                                        tokens,
+                                        completion.request_id.as_deref(),
+                                    )?;
+                                }
+
+                                if let Some(provenance) = &mut *provenance.borrow_mut() {
+                                    provenance.record(
+                                        (&sheet.name, range, cell),
+                                        &source,
+                                        &completion.text,
+                                        method,
+                                        &completion.model,
+                                        tokens,
+                                    )?;
+                                }
+
+                                if let Some(sidecar) = &mut *sidecar.borrow_mut() {
+                                    let location = (sheet.name.as_str(), range, cell);
+                                    sidecar.record(location, &source, text, method);
+                                }
+
+                                tokens = 0;
+                                bar.inc(1);
+                            }
+                        }
+
+                        if budget.exhausted() && !stopped.swap(true, Ordering::Relaxed) {
+                            log.println("Budget reached, sending no more requests");
+                        }
+
+                        if retrying || retries.is_empty() || stopped.load(Ordering::Relaxed) {
+                            break;
+                        }
+
+                        retrying = true;
+                        log.println(format!("Retrying {} values that failed", retries.len()));
+
+                        let (tx, retry_rx) = mpsc::channel(rate_limit.requests);
+                        rx = retry_rx;
+
+                        let translator = retry_translator.clone();
+                        let limiter = retry_limiter.clone();
+                        let stopped = stopped.clone();
+                        let budget = budget.clone();
+                        let retries = mem::take(&mut retries);
+
+                        tokio::spawn(async move {
+                            for job in retries {
+                                limiter.acquire(job.estimated_tokens).await;
+
+                                if stopped.load(Ordering::Relaxed) || budget.exhausted() {
+                                    return;
+                                }
+
+                                let span =
+                                    debug_span!("retry", cell = ?job.cell, model = %job.model);
+                                let completion = translator.translate(&job).instrument(span).await;
+                                budget.record(completion.as_ref().ok());
+
+                                if tx.send((job.key, job.source, completion)).await.is_err() {
+                                    return;
+                                }
+                            }
+                        });
+                    }
+
+                    // The rows of the block are done with, the later ones only start with those of
+                    // the next block.
+                    let next = (block + block_rows) as u32;
+                    worksheet.flush(layout.cell(next, 0).0)?;
+                }
+
+                if estimating {
+                    return Ok(());
+                }
+
+                for (validation, translations) in lists {
+                    // The cells holding one of the values get the same translation.
+                    if matches!(validation.source, ListSource::Values(_)) {
+                        for &((r1, c1), (r2, c2)) in &validation.ranges {
+                            let r2 = r2.min((range.height() as u32).saturating_sub(1));
+                            let c2 = c2.min((range.width() as u16).saturating_sub(1));
+
+                            for cell in (r1..=r2).flat_map(|row| (c1..=c2).map(move |c| (row, c))) {
+                                let Some(DataType::String(value)) =
+                                    range.get((cell.0 as usize, cell.1 as usize))
+                                else {
+                                    continue;
+                                };
+
+                                let key = args.match_mode.key(&normalize_whitespace(value));
+
+                                if let Some(translation) = translations.get(&key) {
+                                    let (row, column) = layout.translation_cell(cell.0, cell.1);
+                                    worksheet.write_string(
+                                        row,
+                                        column,
+                                        translation,
+                                        format(cell),
+                                    )?;
+                                    written_cells.push((row, column));
+
+                                    if let Some(provenance) = &mut *provenance.borrow_mut() {
+                                        provenance.record(
+                                            (&sheet.name, range, cell),
+                                            value,
+                                            translation,
+                                            "list",
+                                            target.model,
+                                            0,
+                                        )?;
+                                    }
+
+                                    if let Some(sidecar) = &mut *sidecar.borrow_mut() {
+                                        let location = (sheet.name.as_str(), range, cell);
+                                        sidecar.record(location, value, translation, "list");
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    let list = data_validation(validation, &translations, args.match_mode);
+
+                    for &((r1, c1), (r2, c2)) in &validation.ranges {
+                        let (r1, c1) = layout.translation_cell(r1, c1);
+                        let (r2, c2) = layout.translation_cell(r2, c2);
+
+                        if let Err(e) = worksheet.data_validation_range(r1, c1, r2, c2, &list) {
+                            let error = format!("Failed to keep the list validation: {}", e);
+                            log.warn(Some((r1, c1)), &error);
+                        }
+                    }
+                }
+
+                worksheet.finish()?;
+
+                written.borrow_mut().push((
+                    index,
+                    Written {
+                        name: sheet.name.clone(),
+                        cells: written_cells,
+                    },
+                ));
+
+                Ok::<_, color_eyre::Report>(())
+            };
+
+            // Reviewing the translations of more than one worksheet at a time isn't possible, and
+            // with `--stream-rows` they are read one at a time.
+            match args.review || streamed {
+                true => run.await?,
+                false => sheet_runs.push(run),
+            }
+        }
+
+        while let Some(result) = sheet_runs.next().await {
+            result?;
+        }
+    }
+
+    let journal = journal.take();
+    let mut history = history.take();
+    let sidecar = sidecar.take();
+    let learned = learned.take();
+    let translated = translated.take();
+    let column_types_report = column_types_report.take();
+    let passthrough = passthrough.get();
+    let failures = failures.take();
+    let mut cell_counts = cell_counts.take();
+    let offline_untranslated = offline_untranslated.take();
+    let terminology = terminology.take();
+    let mut review = review.take();
+    let dictionary = live.current();
+
+    // In the order of the worksheets rather than the one they were done in.
+    let mut written = written.take();
+    written.sort_by_key(|(index, _)| *index);
+    let written = written
+        .into_iter()
+        .map(|(_, written)| written)
+        .collect::<Vec<_>>();
+
+    if let Some(terminology) = terminology.as_ref().filter(|_| !estimating) {
+        if args.harmonize {
+            let rewrites = terminology.harmonize();
+
+            // Without `--stream-rows` nothing is held back, so the rewrites are written right away
+            // into the finished worksheets.
+            for rewrite in &rewrites {
+                let (row, column) = rewrite.output;
+                let format = geometries[rewrite.sheet].format(&styles, rewrite.cell);
+                Output::new(&mut worksheets[rewrite.sheet], false).write_string(
+                    row,
+                    column,
+                    rewrite.translation,
+                    format,
+                )?;
+            }
+
+            if !rewrites.is_empty() {
+                eprintln!("Harmonized the translations of {} cells", rewrites.len());
+            }
+        }
+
+        if let Some(path) = &args.terminology_report {
+            let groups = terminology.write(path)?;
+            eprintln!(
+                "{} sources are translated in more than one way, listed in {}",
+                groups,
+                path.display()
+            );
+        }
+    }
+
+    if let Some(review) = review.as_mut().filter(|_| !estimating) {
+        let pending = review.pending();
+        let language = language_name(&args.source_lang);
+
+        if !pending.is_empty() {
+            eprintln!("Back-translating {} translations", pending.len());
+        }
+
+        for (source, translation) in pending {
+            if stopped.load(Ordering::Relaxed) || budget.exhausted() {
+                break;
+            }
+
+            let prompt = |text: &str, overlap: Option<&str>| {
+                let context = overlap.map(|overlap| format!("The text goes on from: {}", overlap));
+                build_prompt(
+                    text,
+                    &no_dictionary,
+                    None,
+                    context.as_deref(),
+                    language_name(args.target_language()),
+                    language,
+                    None,
+                )
+            };
+
+            // The translations have the personal data of their sources back.
+            let masked = protected
+                .as_ref()
+                .filter(|_| args.redact_pii)
+                .and_then(|protected| protected.mask(&translation));
+            let text = masked.as_ref().map_or(&translation, |masked| &masked.text);
+
+            let chunks = chunking::chunks(text, args.max_chunk_tokens, bpe.as_ref(), prompt);
+            let prompt = prompt(text, None);
+
+            let job = Job {
+                estimated_tokens: chunks.first().map_or_else(
+                    || estimate_tokens(&prompt, bpe.as_ref()),
+                    |chunk| chunk.estimated_tokens,
+                ),
+                prompt,
+                key: translation.to_lowercase(),
+                source: text.clone(),
+                temperature,
+                model: target.model.to_string(),
+                language: language.to_string(),
+                context: None,
+                // The cells are named in the warnings about them instead.
+                cell: (0, 0),
+                chunks,
+                max_length: None,
+            };
+
+            limiter.acquire(job.estimated_tokens).await;
+
+            let completion = translator.translate(&job).await;
+            budget.record(completion.as_ref().ok());
+
+            let completion = completion.map(|mut completion| {
+                if let Some(masked) = &masked {
+                    completion.text = masked.unmask(&completion.text).unwrap_or(completion.text);
+                }
+
+                completion
+            });
+
+            match completion {
+                Ok(completion) => {
+                    if let Some(history) = &mut history {
+                        history.record(
+                            &translation,
+                            &completion.text,
+                            &completion.model,
+                            "back-translation",
+                            completion.tokens,
+                            completion.request_id.as_deref(),
+                        )?;
+                    }
+
+                    review.back_translated((source, translation), &completion.text);
+                }
+                Err(e) => log.warn(
+                    None,
+                    format_args!("Failed to back-translate '{}': {:#}", translation, e),
+                ),
+            }
+        }
+
+        let flagged = review.flagged(args.qa_threshold);
+        cell_counts.flagged = flagged.len();
+
+        if let Some(path) = &args.qa_report {
+            qa::write(path, &flagged)?;
+        }
+
+        if !flagged.is_empty() {
+            match &args.qa_report {
+                Some(path) => eprintln!(
+                    "{} cells need a review of their translation, listed in {}",
+                    flagged.len(),
+                    path.display()
+                ),
+                None => eprintln!("{} cells need a review of their translation", flagged.len()),
+            }
+        }
+    }
+
+    // Shapes and charts don't survive the rebuilt workbook, they are only translated in the copy
+    // of the source package.
+    let mut drawing_translations = HashMap::new();
+
+    if args.translate_drawings {
+        for text in package::drawing_texts(source_path)? {
+            let value = text.trim();
+
+            if drawing_translations.contains_key(&text)
+                || !value.chars().any(char::is_alphabetic)
+                || protected.as_ref().is_some_and(|p| p.covers(value))
+            {
+                continue;
+            }
+
+            // Left as they are in the copy of the package, without the API or the budget for it.
+            let (translation, method, tokens) =
+                translate_text(value, &dictionary, (0, 0), args.offline, "drawing").await?;
+
+            if matches!(method, "verbatim" | "estimate") {
+                continue;
+            }
+
+            debug!(source = value, %translation, method, "Translated drawing text");
+
+            if let Some(history) = &mut history {
+                history.record(value, &translation, target.model, method, tokens, None)?;
+            }
+
+            drawing_translations.insert(text, translation);
+        }
+    }
+
+    // The drawing texts are counted in the estimate as well.
+    let estimate = estimate.take();
+
+    if let Some(path) = &args.infer_column_types_report {
+        fs::write(path, serde_json::to_string_pretty(&column_types_report)?)?;
+    }
+
+    bar.finish_and_clear();
+    log.summarize();
+
+    let spent = budget.spent();
+
+    if let Some(shared) = &args.spent {
+        *shared.lock().unwrap() = spent;
+    }
+
+    if spent.requests > 0 && !args.pseudo {
+        match args.backend {
+            Backend::DeepL => eprintln!(
+                "Sent {} requests for {} characters",
+                spent.requests, spent.input_tokens
+            ),
+            Backend::Google => eprintln!(
+                "Sent {} requests for ${:.2} ({} characters)",
+                spent.requests, spent.cost, spent.input_tokens
+            ),
+            Backend::LibreTranslate => eprintln!(
+                "Sent {} requests for {} characters",
+                spent.requests, spent.input_tokens
+            ),
+            _ if price.is_none() => eprintln!(
+                "Sent {} requests for {} input and {} output tokens",
+                spent.requests, spent.input_tokens, spent.output_tokens
+            ),
+            _ => eprintln!(
+                "Sent {} requests for ${:.2} ({} input and {} output tokens)",
+                spent.requests, spent.cost, spent.input_tokens, spent.output_tokens
+            ),
+        }
+    }
+
+    if !offline_untranslated.is_empty() {
+        eprintln!(
+            "{} values have no translation without the API",
+            offline_untranslated.len()
+        );
+    }
+
+    if let (
+        Some(estimate),
+        Some(Command::Estimate {
+            input_price,
+            output_price,
+        }),
+    ) = (&estimate, &args.command)
+    {
+        let list_price = match args.backend {
+            Backend::OpenAi | Backend::Azure | Backend::Claude => estimate::price(target.model),
+            Backend::DeepL => None,
+            Backend::Google => Some(google::PRICE),
+            Backend::LibreTranslate => Some(libretranslate::PRICE),
+        };
+
+        let price = match list_price {
+            Some(list) => Some(Price {
+                input: input_price.unwrap_or(list.input),
+                output: output_price.unwrap_or(list.output),
+            }),
+            None => input_price.map(|input| Price {
+                input,
+                output: output_price.unwrap_or(input),
+            }),
+        };
+
+        // The batch API costs half as much.
+        let discount = if args.use_batch_api { 0.5 } else { 1. };
+        let cost =
+            price.map(|price| estimate.cost(price, args.backend.bills_characters()) * discount);
+
+        estimate.print(target.model, cost);
+
+        workbook.close()?;
+        fs::remove_file(&destination_path)?;
+
+        return Ok(());
+    }
+
+    if let Some(learned) = learned.filter(|learned| !learned.is_empty()) {
+        let written = learned.write()?;
+        eprintln!(
+            "Learned {} of {} translations into {}",
+            written,
+            learned.len(),
+            learned.path().display()
+        );
+    }
+
+    if score_consistency {
+        let report = consistency::score(&dictionary, &translated);
+
+        eprintln!("Overall glossary adherence score: {:.1}%", report.overall);
+
+        if let Some(path) = &args.glossary_consistency_report {
+            fs::write(path, serde_json::to_string_pretty(&report)?)?;
+        }
+    }
+
+    if let Some(path) = &args.failures {
+        failures::write(path, &failures)?;
+    }
+
+    if let (Some(path), Some(sidecar)) = (&args.sidecar, sidecar) {
+        sidecar.write(path)?;
+    }
+
+    cell_counts.failed = failures.len();
+
+    let redacted = protected
+        .as_ref()
+        .and_then(|protected| protected.redacted())
+        .map(|mut redacted| mem::take(&mut *redacted));
+
+    if let Some(summary) = redacted.as_ref().and_then(Redacted::summary) {
+        eprintln!("{}", summary);
+    }
+
+    if let Some(path) = &args.report {
+        let stopped = stopped.load(Ordering::Relaxed);
+        let report = Report {
+            redacted: redacted.as_ref().map(Redacted::counts).unwrap_or_default(),
+            keys: openai
+                .as_ref()
+                .map(|openai| openai.key_usage())
+                .unwrap_or_default(),
+            ..Report::new(
+                &cell_counts,
+                spent,
+                price.is_some(),
+                started.elapsed(),
+                stopped,
+                &offline_untranslated,
+            )
+        };
+        report.write(path)?;
+    }
+
+    if !failures.is_empty() {
+        match &args.failures {
+            Some(path) => eprintln!(
+                "{} cells could not be translated, listed in {}",
+                failures.len(),
+                path.display()
+            ),
+            None => eprintln!("{} cells could not be translated", failures.len()),
+        }
+    }
+
+    workbook.close()?;
+
+    // The rebuilt workbook only serves to read the translations from.
+    if args.preserve_structure {
+        let translations = read_translations(&destination_path, &written)?;
+        let sheets = written
+            .iter()
+            .map(|written| written.name.as_str())
+            .zip(translations)
+            .collect::<Vec<_>>();
+
+        package::rewrite_strings(
+            source_path,
+            &sheets,
+            &drawing_translations,
+            &destination_path,
+        )?;
+    }
+
+    let names = sheets
+        .iter()
+        .map(|sheet| sheet.name.as_str())
+        .collect::<Vec<_>>();
+
+    if passthrough && package::pass_through_worksheets(source_path, &names, &destination_path)? {
+        for name in names {
+            eprintln!("Sheet {}: zero-copy passthrough", name);
+        }
+    }
+
+    verify(&destination_path, &written)?;
+
+    let stopped = stopped.load(Ordering::Relaxed);
+
+    // The translations of detached batches are still to be fetched into the journal, and a stopped
+    // run is resumed from it.
+    if args.batch_detach {
+        eprintln!(
+            "Collect the batches with `batch fetch {0}`, then run again to write {0}",
+            destination_path.display()
+        );
+    } else if let Some(journal) = journal.filter(|_| !stopped) {
+        journal.remove()?;
+    }
+
+    if args.in_place && args.in_place_backup {
+        let mut backup = source_path.as_os_str().to_owned();
+        backup.push(".bak");
+        fs::copy(source_path, backup)?;
+    }
+
+    let path = if destination_format.is_converted() {
+        let path = args.destination_path.as_deref().unwrap_or(source_path);
+
+        if sheets.len() > 1 {
+            eprintln!(
+                "Warning: only the first of {} worksheets is written to {}",
+                sheets.len(),
+                path.display()
+            );
+        }
+
+        match destination_format {
+            FileFormat::Po => gettext::write(&destination_path, source_path, path)?,
+            FileFormat::Docx | FileFormat::Pptx => {
+                office::write(&destination_path, source_path, path, destination_format)?
+            }
+            _ => delimited::write(&destination_path, path, destination_format)?,
+        }
+
+        fs::remove_file(&destination_path)?;
+        path
+    } else if args.in_place {
+        fs::rename(&destination_path, source_path)?;
+        source_path
+    } else {
+        &destination_path
+    };
+
+    if stopped {
+        eprintln!(
+            "Stopped early with {} cells left untranslated, wrote {}",
+            bar.length().unwrap_or_default() - bar.position(),
+            path.display()
+        );
+        return Err(Stopped.into());
+    }
+
+    if let Some(max) = args.max_failures.filter(|&max| failures.len() > max) {
+        bail!(
+            "{} cells could not be translated, more than --max-failures {}",
+            failures.len(),
+            max
+        );
+    }
+
+    let violations = args
+        .fail_on
+        .iter()
+        .filter_map(|gate| Some((gate, gate.violation(&cell_counts, spent)?)))
+        .collect::<Vec<_>>();
+
+    for (gate, violation) in &violations {
+        eprintln!("Quality gate {} failed: {}", gate, violation);
+    }
+
+    if !violations.is_empty() {
+        bail!("{} of the --fail-on quality gates failed", violations.len());
+    }
+
+    Ok(())
+}
+
+/// Reads the geometry of the worksheet from the package, along with its tab color and, for
+/// `--translate-comments`, its comments.
+fn read_geometry(
+    archive: &mut package::Archive,
+    sheet: &mut Sheet,
+    rich_text: &RichText,
+    comments: bool,
+) -> Result<Geometry> {
+    let part = package::worksheet_part(archive, &sheet.name)?;
+
+    sheet.tab_color = match &part {
+        Some(part) => package::tab_color(archive, part)?,
+        None => None,
+    };
+
+    let mut geometry = match &part {
+        Some(part) => Geometry::read(archive, part, &sheet.range)?,
+        None => Geometry::default(),
+    };
+
+    if let (Some(part), false) = (&part, rich_text.is_empty()) {
+        geometry.read_runs(archive, part, &sheet.range, rich_text)?;
+    }
+
+    if let Some(part) = &part {
+        geometry.read_hyperlinks(archive, part, &sheet.range)?;
+        geometry.read_validations(archive, part, &sheet.range)?;
+    }
+
+    if let (Some(part), true) = (&part, comments) {
+        sheet.comments = package::comments(archive, part)?;
+    }
+
+    Ok(geometry)
+}
+
+/// The columns of the range getting one of their own for their translations with
+/// `--bilingual`: the selected ones the schema translates, with text below `first_row`.
+fn bilingual_columns(
+    range: &Range<DataType>,
+    selected: Option<&BTreeSet<u16>>,
+    schema: Option<&Schema>,
+    first_row: usize,
+) -> BTreeSet<u16> {
+    (0..range.width() as u16)
+        .filter(|column| selected.is_none_or(|columns| columns.contains(column)))
+        .filter(|&column| schema::column(schema, column).translates())
+        .filter(|&column| {
+            range
+                .rows()
+                .skip(first_row)
+                .any(|row| matches!(row.get(usize::from(column)), Some(DataType::String(_))))
+        })
+        .collect()
+}
+
+/// Labels every column with its letter, which the headers overwrite as they arrive.
+fn write_column_labels(
+    worksheet: &mut Output,
+    layout: Layout<'_>,
+    width: u16,
+    source_lang: &str,
+    label_format: &Format,
+) -> Result<()> {
+    for column in 0..width {
+        let (r, c) = layout.label_cell(column).unwrap();
+        worksheet.write_string(r, c, &column_letter(column), Some(label_format))?;
+
+        if let Some((_, c)) = layout.source_cell(0, column) {
+            let label = column_label(source_lang);
+            worksheet.write_string(r, c, &label, Some(label_format))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The rows of the range whose first cell the `--pivot-translate` selector matches.
+fn pivot_rows(range: &Range<DataType>, selector: &Regex) -> BTreeSet<u32> {
+    range
+        .rows()
+        .enumerate()
+        .filter(|(_, row)| match row.first() {
+            Some(DataType::String(label)) => selector.is_match(label.trim()),
+            _ => false,
+        })
+        .map(|(i, _)| i as u32)
+        .collect()
+}
+
+/// Keeps the hyperlinks of the worksheet on the cells their translations go in.
+fn write_hyperlinks(
+    worksheet: &mut Output,
+    layout: Layout<'_>,
+    geometry: &Geometry,
+    styles: &Styles,
+    log: &DedupLogger,
+) {
+    for (&cell, url) in geometry.hyperlinks() {
+        let (row, column) = layout.translation_cell(cell.0, cell.1);
+
+        if let Err(e) = worksheet.write_url(row, column, url, geometry.format(styles, cell)) {
+            log.warn(
+                Some(cell),
+                format!("Failed to keep the hyperlink {}: {}", url, e),
+            );
+        }
+    }
+}
+
+/// The list validation of the destination, with the `translations` of its values by their keys.
+fn data_validation(
+    validation: &ListValidation,
+    translations: &BTreeMap<String, String>,
+    match_mode: MatchMode,
+) -> DataValidation {
+    let list = match &validation.source {
+        ListSource::Formula(formula) => DataValidationType::ListFormula {
+            ignore_blank: validation.allow_blank,
+            formula: formula.clone(),
+        },
+        ListSource::Values(values) => DataValidationType::List {
+            ignore_blank: validation.allow_blank,
+            dropdown: validation.dropdown,
+            values: values
+                .iter()
+                .map(|value| translations[&match_mode.key(value.trim())].clone())
+                .collect(),
+        },
+    };
+
+    let prompt = validation
+        .prompt
+        .clone()
+        .map(|(title, message)| InputMessageOptions { title, message });
+
+    let error = validation.error.clone().map(|(style, title, message)| {
+        let style = match style.as_str() {
+            "warning" => DataValidationErrorType::Warning,
+            "information" => DataValidationErrorType::Information,
+            _ => DataValidationErrorType::Stop,
+        };
+        ErrorAlertOptions {
+            style,
+            title,
+            message,
+        }
+    });
+
+    DataValidation::new(list, prompt, error)
+}
@@ -0,0 +1,125 @@
+//! A LibreTranslate server (<https://docs.libretranslate.com>), or any other machine translation
+//! server with its API, like those in front of Argos, Marian or CTranslate2 models. It runs
+//! where no external service is allowed and gets the plain values like DeepL.
+
+use color_eyre::{
+    eyre::{bail, ContextCompat},
+    Result,
+};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    estimate::Price,
+    prompt::{language_code, LANGUAGES},
+    retry,
+    scheduler::Job,
+    translator::{self, Completion, Connection},
+};
+
+/// What the history records as the model of LibreTranslate translations.
+pub const MODEL: &str = "libretranslate";
+
+/// Where LibreTranslate listens unless `--api-base` says otherwise.
+pub const API_BASE: &str = "http://localhost:5000";
+
+/// Nothing is paid for a server of one's own.
+pub const PRICE: Price = Price {
+    input: 0.,
+    output: 0.,
+};
+
+#[derive(Debug, Serialize)]
+struct Request<'a> {
+    q: &'a str,
+    source: &'a str,
+    target: &'a str,
+    format: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_key: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    translated_text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Error {
+    error: String,
+}
+
+pub struct LibreTranslate {
+    client: Client,
+    url: String,
+    /// `auto` for the server to detect the language of every value.
+    source: String,
+    api_key: Option<String>,
+    retry: retry::Policy,
+}
+
+/// The ISO 639-1 code of a known language name or code.
+fn code(language: &str) -> Option<&'static str> {
+    let code = language_code(language);
+
+    LANGUAGES
+        .iter()
+        .map(|(known, _)| *known)
+        .find(|known| known.eq_ignore_ascii_case(code))
+}
+
+impl LibreTranslate {
+    pub fn new(
+        api_base: Option<&str>,
+        api_key: Option<String>,
+        source_language: &str,
+        connection: &Connection,
+        retry: retry::Policy,
+    ) -> Result<Self> {
+        let api_base = api_base.unwrap_or(API_BASE).trim_end_matches('/');
+
+        Ok(Self {
+            client: translator::client(None, connection)?,
+            url: format!("{}/translate", api_base),
+            source: code(source_language).unwrap_or("auto").to_string(),
+            api_key,
+            retry,
+        })
+    }
+
+    pub async fn translate(&self, job: &Job) -> Result<Completion> {
+        let target = code(&job.language)
+            .wrap_err_with(|| format!("No language code for {}", job.language))?;
+
+        let request = self.client.post(&self.url).json(&Request {
+            q: &job.source,
+            source: &self.source,
+            target,
+            format: "text",
+            api_key: self.api_key.as_deref(),
+        });
+
+        let response = retry::send(request, &self.retry).await?;
+        let status = response.status();
+
+        if !status.is_success() {
+            let message = response
+                .json::<Error>()
+                .await
+                .map_or_else(|_| status.to_string(), |error| error.error);
+            bail!("LibreTranslate: {}", message);
+        }
+
+        let characters = job.source.chars().count();
+
+        Ok(Completion {
+            text: response.json::<Response>().await?.translated_text,
+            tokens: characters,
+            input_tokens: characters,
+            model: MODEL.to_string(),
+            request_id: None,
+            confidence: None,
+        })
+    }
+}
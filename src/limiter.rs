@@ -0,0 +1,219 @@
+//! A token bucket rate limiter for API requests and tokens, which also backs off when the
+//! rate limit headers of OpenAI or Anthropic responses say the account is running out of
+//! headroom.
+
+use std::{sync::Mutex, time::Duration};
+
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use tokio::time::{self, Instant};
+
+use crate::scheduler::RateLimit;
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    per_second: f64,
+    level: f64,
+}
+
+impl Bucket {
+    fn new(capacity: usize, window: Duration) -> Self {
+        Self {
+            capacity: capacity as f64,
+            per_second: capacity as f64 / window.as_secs_f64(),
+            level: capacity as f64,
+        }
+    }
+
+    fn refill(&mut self, elapsed: Duration) {
+        self.level = (self.level + self.per_second * elapsed.as_secs_f64()).min(self.capacity);
+    }
+
+    /// How long until `amount` is available. More than the capacity is never available, so
+    /// that much only waits for a full bucket.
+    fn wait(&self, amount: f64) -> Duration {
+        let missing = amount.min(self.capacity) - self.level;
+
+        if missing <= 0. {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing / self.per_second)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    requests: Bucket,
+    tokens: Option<Bucket>,
+    refilled: Instant,
+    /// Set when the API reported no headroom left, until it said it would reset.
+    paused_until: Option<Instant>,
+}
+
+impl State {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.refilled;
+
+        self.requests.refill(elapsed);
+
+        if let Some(tokens) = &mut self.tokens {
+            tokens.refill(elapsed);
+        }
+
+        self.refilled = now;
+    }
+
+    fn pause(&mut self, duration: Duration) {
+        let until = Instant::now() + duration;
+        self.paused_until = Some(self.paused_until.map_or(until, |paused| paused.max(until)));
+    }
+}
+
+#[derive(Debug)]
+pub struct Limiter {
+    state: Mutex<State>,
+}
+
+/// Parses the durations of the reset headers, such as `20ms`, `1s` or `6m0s`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let mut total = 0.;
+    let mut rest = s.trim();
+
+    while !rest.is_empty() {
+        let number = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let value: f64 = rest[..number].parse().ok()?;
+        rest = &rest[number..];
+
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let seconds = match &rest[..unit] {
+            "h" => 3600.,
+            "m" => 60.,
+            "s" => 1.,
+            "ms" => 0.001,
+            _ => return None,
+        };
+        rest = &rest[unit..];
+
+        total += value * seconds;
+    }
+
+    Some(Duration::from_secs_f64(total))
+}
+
+impl Limiter {
+    /// Allows `rate_limit.requests` requests, and `tokens_per_minute` tokens if given, with the
+    /// full amount available right away and refilling evenly over the window.
+    pub fn new(rate_limit: RateLimit, tokens_per_minute: Option<usize>) -> Self {
+        Self {
+            state: Mutex::new(State {
+                requests: Bucket::new(rate_limit.requests, rate_limit.window),
+                tokens: tokens_per_minute.map(|tpm| Bucket::new(tpm, Duration::from_secs(60))),
+                refilled: Instant::now(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Waits until a request estimated to use the given number of tokens can be sent.
+    pub async fn acquire(&self, tokens: usize) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                state.refill();
+
+                let mut wait = state
+                    .paused_until
+                    .map_or(Duration::ZERO, |until| {
+                        until.saturating_duration_since(Instant::now())
+                    })
+                    .max(state.requests.wait(1.));
+
+                if let Some(bucket) = &state.tokens {
+                    wait = wait.max(bucket.wait(tokens as f64));
+                }
+
+                if wait.is_zero() {
+                    state.paused_until = None;
+                    state.requests.level -= 1.;
+
+                    if let Some(bucket) = &mut state.tokens {
+                        bucket.level -= (tokens as f64).min(bucket.capacity);
+                    }
+
+                    return;
+                }
+
+                wait
+            };
+
+            time::sleep(wait).await;
+        }
+    }
+
+    /// Takes the headroom the API reported into account, so that the limiter never lets more
+    /// through than the account has left, and waits for the reset once it has nothing left.
+    pub fn observe(&self, headers: &HeaderMap) {
+        let header = |name: &str| headers.get(name)?.to_str().ok();
+        let reported = |kind: &str| {
+            let remaining = header(&format!("x-ratelimit-remaining-{}", kind))?
+                .parse::<f64>()
+                .ok()?;
+            let reset = header(&format!("x-ratelimit-reset-{}", kind)).and_then(parse_duration);
+            Some((remaining, reset))
+        };
+
+        self.headroom(reported("requests"), reported("tokens"));
+    }
+
+    /// Like [`Limiter::observe`] for the `anthropic-ratelimit-*` headers of the Anthropic API,
+    /// which give the resets as points in time.
+    pub fn observe_anthropic(&self, headers: &HeaderMap) {
+        let header = |name: &str| headers.get(name)?.to_str().ok();
+        let reported = |kind: &str| {
+            let remaining = header(&format!("anthropic-ratelimit-{}-remaining", kind))?
+                .parse::<f64>()
+                .ok()?;
+            let reset = header(&format!("anthropic-ratelimit-{}-reset", kind))
+                .and_then(|reset| DateTime::parse_from_rfc3339(reset).ok())
+                .and_then(|reset| (reset.with_timezone(&Utc) - Utc::now()).to_std().ok());
+            Some((remaining, reset))
+        };
+
+        self.headroom(reported("requests"), reported("tokens"));
+    }
+
+    /// Applies the remaining requests and tokens, with the time until they reset.
+    fn headroom(
+        &self,
+        requests: Option<(f64, Option<Duration>)>,
+        tokens: Option<(f64, Option<Duration>)>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        state.refill();
+
+        if let Some((remaining, reset)) = requests {
+            state.requests.level = state.requests.level.min(remaining);
+
+            if remaining < 1. {
+                state.pause(reset.unwrap_or(Duration::from_secs(1)));
+            }
+        }
+
+        if let Some((remaining, reset)) = tokens {
+            if let Some(bucket) = &mut state.tokens {
+                bucket.level = bucket.level.min(remaining);
+            }
+
+            if remaining < 1. {
+                state.pause(reset.unwrap_or(Duration::from_secs(1)));
+            }
+        }
+    }
+}
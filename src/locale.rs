@@ -0,0 +1,345 @@
+//! How the languages write numbers, dates and amounts, for `--localize-numbers` to write
+//! `1,234.56` and `03/14/2025` as `1.234,56` and `14.03.2025` for Romanian. Text values are
+//! converted from the way of the source language, and date cells get the date format of the
+//! target one. Excel itself shows the separators of the numbers in cells the way of the system.
+
+use regex::Regex;
+
+use crate::prompt::language_code;
+
+/// The currency symbols and abbreviations, in the order they are tried.
+const CURRENCIES: &str = r"[$€£¥₹₽₴]|lei|zł|Kč|kr|Ft|[A-Z]{3}";
+
+/// The spaces numbers are grouped with, all taken for the same.
+const SPACES: [char; 3] = [' ', '\u{a0}', '\u{202f}'];
+
+#[derive(Debug)]
+pub(crate) struct Locale {
+    decimal: char,
+    /// Written as a no-break space where it is a space.
+    group: char,
+    /// An ICU-style pattern of `yyyy`, `MM`, `M`, `dd` and `d` with the separators between.
+    date: &'static str,
+    /// Whether amounts are written with the currency after the number, as in `12,50 €`.
+    currency_after: bool,
+}
+
+const fn locale(decimal: char, group: char, date: &'static str, after: bool) -> Locale {
+    Locale {
+        decimal,
+        group,
+        date,
+        currency_after: after,
+    }
+}
+
+/// By ISO 639-1 code, or language and region for the regions writing differently.
+const LOCALES: [(&str, Locale); 25] = [
+    ("bg", locale(',', ' ', "dd.MM.yyyy", true)),
+    ("cs", locale(',', ' ', "dd.MM.yyyy", true)),
+    ("da", locale(',', '.', "dd.MM.yyyy", true)),
+    ("de", locale(',', '.', "dd.MM.yyyy", true)),
+    ("el", locale(',', '.', "dd/MM/yyyy", true)),
+    ("en", locale('.', ',', "MM/dd/yyyy", false)),
+    ("en-gb", locale('.', ',', "dd/MM/yyyy", false)),
+    ("es", locale(',', '.', "dd/MM/yyyy", true)),
+    ("fi", locale(',', ' ', "d.M.yyyy", true)),
+    ("fr", locale(',', ' ', "dd/MM/yyyy", true)),
+    ("hu", locale(',', ' ', "yyyy.MM.dd.", true)),
+    ("it", locale(',', '.', "dd/MM/yyyy", true)),
+    ("ja", locale('.', ',', "yyyy/MM/dd", false)),
+    ("ko", locale('.', ',', "yyyy. MM. dd.", false)),
+    ("nl", locale(',', '.', "dd-MM-yyyy", false)),
+    ("no", locale(',', ' ', "dd.MM.yyyy", true)),
+    ("pl", locale(',', ' ', "dd.MM.yyyy", true)),
+    ("pt", locale(',', '.', "dd/MM/yyyy", true)),
+    ("ro", locale(',', '.', "dd.MM.yyyy", true)),
+    ("ru", locale(',', ' ', "dd.MM.yyyy", true)),
+    ("sk", locale(',', ' ', "dd.MM.yyyy", true)),
+    ("sv", locale(',', ' ', "yyyy-MM-dd", true)),
+    ("tr", locale(',', '.', "dd.MM.yyyy", false)),
+    ("uk", locale(',', ' ', "dd.MM.yyyy", true)),
+    ("zh", locale('.', ',', "yyyy/MM/dd", false)),
+];
+
+/// The fields of a date pattern and the literal text between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Year,
+    Month { padded: bool },
+    Day { padded: bool },
+    Literal(&'a str),
+}
+
+fn tokens(pattern: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut rest = pattern;
+
+    while !rest.is_empty() {
+        let field = [
+            ("yyyy", Token::Year),
+            ("MM", Token::Month { padded: true }),
+            ("M", Token::Month { padded: false }),
+            ("dd", Token::Day { padded: true }),
+            ("d", Token::Day { padded: false }),
+        ]
+        .into_iter()
+        .find(|(field, _)| rest.starts_with(field));
+
+        match field {
+            Some((field, token)) => {
+                tokens.push(token);
+                rest = &rest[field.len()..];
+            }
+            None => {
+                let end = rest.find(['y', 'M', 'd']).unwrap_or(rest.len());
+                tokens.push(Token::Literal(&rest[..end]));
+                rest = &rest[end..];
+            }
+        }
+    }
+
+    tokens
+}
+
+fn separator(c: char) -> String {
+    match c {
+        ' ' => format!("[{}]", SPACES.iter().collect::<String>()),
+        c => regex::escape(&c.to_string()),
+    }
+}
+
+impl Locale {
+    /// The locale of a language given by name or code, like `Romanian`, `ro` or `en-GB`.
+    pub(crate) fn of(language: &str) -> Option<&'static Self> {
+        let code = language_code(language).to_lowercase().replace('_', "-");
+        let find = |code: &str| {
+            LOCALES
+                .iter()
+                .find(|(known, _)| *known == code)
+                .map(|(_, locale)| locale)
+        };
+
+        find(&code).or_else(|| find(code.split('-').next()?))
+    }
+
+    /// The Excel number format of the dates, and their times if they have one.
+    pub(crate) fn date_format(&self, time: bool) -> String {
+        let mut format = tokens(self.date)
+            .into_iter()
+            .map(|token| match token {
+                Token::Year => "yyyy".to_string(),
+                Token::Month { padded: true } => "mm".to_string(),
+                Token::Month { padded: false } => "m".to_string(),
+                Token::Day { padded: true } => "dd".to_string(),
+                Token::Day { padded: false } => "d".to_string(),
+                Token::Literal(literal) => literal.replace('/', "\\/"),
+            })
+            .collect::<String>();
+
+        if time {
+            format.push_str(" hh:mm:ss");
+        }
+
+        format
+    }
+
+    fn number(&self) -> Regex {
+        let (group, decimal) = (separator(self.group), separator(self.decimal));
+
+        Regex::new(&format!(
+            r"^(?P<sign>[-+−]?)(?:(?P<prefix>{currencies})[ \u{{a0}}]?)?(?P<int>\d{{1,3}}(?:{group}\d{{3}})+|\d+)(?:{decimal}(?P<frac>\d+))?(?P<suffix>[ \u{{a0}}]?(?:%|{currencies}))?$",
+            currencies = CURRENCIES,
+        ))
+        .unwrap()
+    }
+
+    fn date(&self) -> Regex {
+        let pattern = tokens(self.date)
+            .into_iter()
+            .map(|token| match token {
+                Token::Year => r"(?P<year>\d{4})".to_string(),
+                Token::Month { .. } => r"(?P<month>\d{1,2})".to_string(),
+                Token::Day { .. } => r"(?P<day>\d{1,2})".to_string(),
+                Token::Literal(literal) => literal.chars().map(separator).collect(),
+            })
+            .collect::<String>();
+
+        Regex::new(&format!("^{}$", pattern)).unwrap()
+    }
+
+    fn format_date(&self, year: &str, month: u32, day: u32) -> String {
+        tokens(self.date)
+            .into_iter()
+            .map(|token| match token {
+                Token::Year => year.to_string(),
+                Token::Month { padded: true } => format!("{:02}", month),
+                Token::Month { padded: false } => month.to_string(),
+                Token::Day { padded: true } => format!("{:02}", day),
+                Token::Day { padded: false } => day.to_string(),
+                Token::Literal(literal) => literal.to_string(),
+            })
+            .collect()
+    }
+
+    fn group(&self, digits: &str) -> String {
+        let group = match self.group {
+            ' ' => '\u{a0}',
+            group => group,
+        };
+
+        let mut grouped = String::new();
+
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped.push(group);
+            }
+
+            grouped.push(digit);
+        }
+
+        grouped
+    }
+}
+
+/// Reads the numbers and dates of the values the way of the source language.
+pub(crate) struct Localizer {
+    number: Regex,
+    date: Regex,
+}
+
+impl Localizer {
+    pub(crate) fn new(source: &Locale) -> Self {
+        Self {
+            number: source.number(),
+            date: source.date(),
+        }
+    }
+
+    /// The value written the way of the `target` locale, if it is a number with separators, an
+    /// amount, a percentage or a date. Whole numbers without separators, like years and IDs,
+    /// are left alone.
+    pub(crate) fn convert(&self, value: &str, target: &Locale) -> Option<String> {
+        if let Some(captures) = self.date.captures(value) {
+            let month = captures["month"]
+                .parse()
+                .ok()
+                .filter(|m| (1..=12).contains(m))?;
+            let day = captures["day"]
+                .parse()
+                .ok()
+                .filter(|d| (1..=31).contains(d))?;
+            return Some(target.format_date(&captures["year"], month, day));
+        }
+
+        let captures = self.number.captures(value)?;
+        let int = captures["int"]
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect::<String>();
+        let grouped = int.len() < captures["int"].len();
+        let frac = captures.name("frac").map(|frac| frac.as_str());
+        let prefix = captures.name("prefix").map(|prefix| prefix.as_str());
+        let suffix = captures.name("suffix").map(|suffix| suffix.as_str());
+
+        if !grouped && frac.is_none() && prefix.is_none() && suffix.is_none() {
+            return None;
+        }
+
+        let mut number = match grouped {
+            true => target.group(&int),
+            false => int,
+        };
+
+        if let Some(frac) = frac {
+            number.push(target.decimal);
+            number.push_str(frac);
+        }
+
+        let sign = &captures["sign"];
+        let currency = prefix.or(suffix.filter(|suffix| !suffix.ends_with('%')));
+        let percent = suffix.filter(|suffix| suffix.ends_with('%')).unwrap_or("");
+
+        Some(match currency.map(str::trim) {
+            Some(currency) if target.currency_after => {
+                format!("{}{}\u{a0}{}", sign, number, currency)
+            }
+            Some(currency) if currency.chars().all(char::is_alphabetic) => {
+                format!("{}{}\u{a0}{}", sign, currency, number)
+            }
+            Some(currency) => format!("{}{}{}", sign, currency, number),
+            None => format!("{}{}{}", sign, number, percent),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(source: &str, value: &str, target: &str) -> Option<String> {
+        let (source, target) = (Locale::of(source).unwrap(), Locale::of(target).unwrap());
+        Localizer::new(source).convert(value, target)
+    }
+
+    #[test]
+    fn finds_locales_by_name_code_and_region() {
+        assert_eq!(
+            Locale::of("Romanian").unwrap().date_format(false),
+            "dd.mm.yyyy"
+        );
+        assert_eq!(
+            Locale::of("en-GB").unwrap().date_format(false),
+            "dd\\/mm\\/yyyy"
+        );
+        assert_eq!(
+            Locale::of("en_US").unwrap().date_format(false),
+            "mm\\/dd\\/yyyy"
+        );
+        assert!(Locale::of("Klingon").is_none());
+    }
+
+    #[test]
+    fn date_formats_have_the_time_when_asked() {
+        assert_eq!(
+            Locale::of("sv").unwrap().date_format(true),
+            "yyyy-mm-dd hh:mm:ss"
+        );
+    }
+
+    #[test]
+    fn converts_numbers_with_separators() {
+        assert_eq!(convert("en", "1,234.56", "ro").as_deref(), Some("1.234,56"));
+        assert_eq!(convert("fr", "1 234,5", "en").as_deref(), Some("1,234.5"));
+        assert_eq!(convert("en", "1234567", "de"), None);
+    }
+
+    #[test]
+    fn converts_amounts_and_percentages() {
+        assert_eq!(
+            convert("en", "$12.50", "de").as_deref(),
+            Some("12,50\u{a0}$")
+        );
+        assert_eq!(convert("de", "12,50 €", "en").as_deref(), Some("€12.50"));
+        assert_eq!(
+            convert("en", "-EUR 3.5", "en-gb").as_deref(),
+            Some("-EUR\u{a0}3.5")
+        );
+        assert_eq!(convert("en", "12.5%", "fr").as_deref(), Some("12,5%"));
+    }
+
+    #[test]
+    fn converts_dates() {
+        assert_eq!(
+            convert("en", "03/14/2025", "ro").as_deref(),
+            Some("14.03.2025")
+        );
+        assert_eq!(convert("de", "1.2.2025", "fi").as_deref(), Some("1.2.2025"));
+        assert_eq!(convert("en", "14/03/2025", "ro"), None);
+    }
+
+    #[test]
+    fn leaves_text_alone() {
+        assert_eq!(convert("en", "Total", "de"), None);
+    }
+}
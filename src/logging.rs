@@ -0,0 +1,75 @@
+//! Diagnostics of what happens to every cell and request, on stderr with `--verbose` and in full
+//! with `--log-file`. Warnings are left to the [`DedupLogger`](crate::warnings::DedupLogger) on
+//! stderr, which prints them above the progress bar.
+
+use std::{fs::File, io, path::Path, sync::Mutex};
+
+use color_eyre::{eyre::Context, Result};
+use reqwest::header::HeaderMap;
+use tracing::{level_filters::LevelFilter, Level};
+use tracing_subscriber::{
+    filter::{filter_fn, Targets},
+    fmt,
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    Layer,
+};
+
+/// The headers carrying the API keys of the different backends.
+const SECRET_HEADERS: [&str; 3] = ["authorization", "api-key", "x-api-key"];
+
+/// Sets up the logging for the run, `-v` for the cells and requests, `-vv` for the request and
+/// response bodies as well. The log file always gets everything.
+pub(crate) fn init(verbose: u8, log_file: Option<&Path>) -> Result<()> {
+    let own = |level| Targets::new().with_target(env!("CARGO_CRATE_NAME"), level);
+
+    let stderr = (verbose > 0).then(|| {
+        let level = if verbose > 1 {
+            LevelFilter::TRACE
+        } else {
+            LevelFilter::DEBUG
+        };
+
+        fmt::layer()
+            .with_writer(io::stderr)
+            .with_filter(own(level))
+            .with_filter(filter_fn(|metadata| *metadata.level() > Level::WARN))
+    });
+
+    let file = log_file
+        .map(|path| {
+            File::create(path)
+                .wrap_err_with(|| format!("Failed to create the log file {}", path.display()))
+        })
+        .transpose()?
+        .map(|file| {
+            fmt::layer()
+                .with_writer(Mutex::new(file))
+                .with_filter(own(LevelFilter::TRACE))
+        });
+
+    // A subscriber set up by a program embedding the library takes precedence.
+    let _ = tracing_subscriber::registry()
+        .with(stderr)
+        .with(file)
+        .try_init();
+
+    Ok(())
+}
+
+/// The headers as they can go in the log, with the API keys blanked out.
+pub(crate) fn redacted(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SECRET_HEADERS.contains(&name.as_str()) {
+                "[redacted]"
+            } else {
+                value.to_str().unwrap_or("[binary]")
+            };
+
+            format!("{}: {}", name, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,33 @@
+//! The translated workbook of `--merge-into`, whose cells are kept as they are, edits of the
+//! reviewers included. Only the cells it has no translation for are translated, like the ones of
+//! the rows added to the source since, and with `--previous` also those whose source changed.
+
+use std::{collections::BTreeMap, path::Path};
+
+use calamine::{open_workbook, DataType, Range, Xlsx};
+use color_eyre::{eyre::Context, Result};
+
+use crate::workbook::read_worksheets;
+
+/// The worksheets of the translated workbook by name.
+pub(crate) fn load(path: &Path) -> Result<BTreeMap<String, Range<DataType>>> {
+    let workbook = open_workbook::<Xlsx<_>, _>(path).wrap_err_with(|| {
+        format!(
+            "Failed to open the workbook to merge into {}",
+            path.display()
+        )
+    })?;
+
+    Ok(read_worksheets(workbook, &[], true)?
+        .into_iter()
+        .map(|sheet| (sheet.name, sheet.range))
+        .collect())
+}
+
+/// The text the translated worksheet has at `cell`, if it isn't empty.
+pub(crate) fn kept(output: &Range<DataType>, cell: (u32, u16)) -> Option<String> {
+    match output.get_value((cell.0, u32::from(cell.1))) {
+        Some(DataType::String(text)) if !text.trim().is_empty() => Some(text.clone()),
+        _ => None,
+    }
+}
@@ -0,0 +1,427 @@
+//! Word documents and PowerPoint presentations, read into a single worksheet of the texts of
+//! their paragraphs and written by replacing the runs of text of the source's paragraphs with
+//! runs of their translations. Paragraphs whose runs differ in formatting are translated with the
+//! runs marked like rich text cells, and everything else, tables, images, fields, hyperlinks,
+//! layouts and the rest, is copied as it was.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufWriter, Read, Seek, Write},
+    ops,
+    path::Path,
+};
+
+use calamine::{open_workbook, DataType, Range, Reader, Xlsx};
+use color_eyre::{eyre::ContextCompat, Result};
+use quick_xml::{escape::escape, events::Event};
+use zip::{write::FileOptions, ZipArchive, ZipWriter};
+
+use crate::{
+    package, rich_text,
+    workbook::{FileFormat, Sheet, WORKSHEET},
+};
+
+/// The first row of the worksheet, followed by a row for every paragraph with text.
+const HEADER: &str = "paragraph";
+
+/// The elements the paragraphs of a format are made of.
+struct Markup {
+    paragraph: &'static [u8],
+    run: &'static [u8],
+    properties: &'static [u8],
+    text: &'static [u8],
+    /// The line breaks of PowerPoint, which go between the runs and are read as runs of a line
+    /// break.
+    line_break: Option<&'static [u8]>,
+}
+
+const WORD: Markup = Markup {
+    paragraph: b"w:p",
+    run: b"w:r",
+    properties: b"w:rPr",
+    text: b"w:t",
+    line_break: None,
+};
+
+const DRAWING: Markup = Markup {
+    paragraph: b"a:p",
+    run: b"a:r",
+    properties: b"a:rPr",
+    text: b"a:t",
+    line_break: Some(b"a:br"),
+};
+
+fn markup(format: FileFormat) -> &'static Markup {
+    match format {
+        FileFormat::Pptx => &DRAWING,
+        _ => &WORD,
+    }
+}
+
+/// A run of text, a run element of nothing but text, tabs and line breaks.
+#[derive(Debug)]
+struct Run {
+    /// Where the element is in the part.
+    span: ops::Range<usize>,
+    /// The run properties element, as it is in the part.
+    properties: String,
+    text: String,
+}
+
+/// A run being read, which turns out to be one of text or not.
+#[derive(Debug)]
+struct Open {
+    start: usize,
+    /// The depth of the elements in the run.
+    depth: usize,
+    properties_start: usize,
+    properties: String,
+    text: String,
+    inside_text: bool,
+    /// Whether the run has something other than text, like a drawing or a field, and is kept.
+    other: bool,
+}
+
+/// Whether a part holds paragraphs: the body, the headers and footers, the footnotes and the
+/// endnotes of a document, and the slides and the notes of a presentation.
+fn is_text(name: &str, format: FileFormat) -> bool {
+    match format {
+        FileFormat::Pptx => {
+            (name.starts_with("ppt/slides/slide") || name.starts_with("ppt/notesSlides/notesSlide"))
+                && name.ends_with(".xml")
+        }
+        _ => {
+            name == "word/document.xml"
+                || ((name.starts_with("word/header") || name.starts_with("word/footer"))
+                    && name.ends_with(".xml"))
+                || name == "word/footnotes.xml"
+                || name == "word/endnotes.xml"
+        }
+    }
+}
+
+/// The parts with paragraphs, in the same order every time.
+fn text_parts<R: Read + Seek>(archive: &ZipArchive<R>, format: FileFormat) -> Vec<String> {
+    let mut parts = archive
+        .file_names()
+        .filter(|name| is_text(name, format))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    // The slides in their numbers' order.
+    parts.sort_by_key(|name| (name.len(), name.clone()));
+    parts
+}
+
+/// The runs of text of every paragraph that has some. Runs inside other elements of the
+/// paragraph, like hyperlinks, are left out, and so are the paragraphs of Word text boxes.
+fn paragraphs(xml: &str, markup: &Markup) -> Result<Vec<Vec<Run>>> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut paragraphs = vec![];
+    let mut open: Vec<Vec<Run>> = vec![];
+    let mut path = vec![];
+    let mut run = None::<Open>;
+
+    loop {
+        let start = reader.buffer_position();
+        let event = reader.read_event()?;
+        let end = reader.buffer_position();
+
+        // Only the elements right inside the run make runs of text.
+        let inside = |path: &[Vec<u8>], run: &Option<Open>| {
+            run.as_ref().is_some_and(|run| path.len() == run.depth)
+        };
+
+        match &event {
+            Event::Start(e) | Event::Empty(e) => {
+                let name = e.name().as_ref().to_vec();
+                let empty = matches!(event, Event::Empty(_));
+                let inside = inside(&path, &run);
+
+                if let Some(run) = run.as_mut().filter(|_| inside) {
+                    match name.as_slice() {
+                        name if name == markup.properties && empty => {
+                            run.properties = xml[start..end].to_string();
+                        }
+                        name if name == markup.properties => run.properties_start = start,
+                        name if name == markup.text => run.inside_text = !empty,
+                        b"w:tab" => run.text.push('\t'),
+                        // Page and column breaks are kept, along with their runs.
+                        b"w:br" if e.try_get_attribute("w:type")?.is_none() => run.text.push('\n'),
+                        b"w:cr" => run.text.push('\n'),
+                        b"w:lastRenderedPageBreak" => {}
+                        _ => run.other = true,
+                    }
+                }
+
+                let in_paragraph =
+                    run.is_none() && path.last().is_some_and(|parent| parent == markup.paragraph);
+
+                let line_break = markup.line_break == Some(name.as_slice());
+
+                if in_paragraph && empty && line_break {
+                    if let Some(runs) = open.last_mut() {
+                        runs.push(Run {
+                            span: start..end,
+                            properties: String::new(),
+                            text: "\n".to_string(),
+                        });
+                    }
+                } else if in_paragraph && !empty && (name == markup.run || line_break) {
+                    run = Some(Open {
+                        start,
+                        depth: path.len() + 1,
+                        properties_start: start,
+                        properties: String::new(),
+                        text: if line_break { "\n" } else { "" }.to_string(),
+                        inside_text: false,
+                        other: false,
+                    });
+                }
+
+                if !empty {
+                    if name == markup.paragraph {
+                        open.push(vec![]);
+                    }
+
+                    path.push(name);
+                }
+            }
+            Event::Text(t) => {
+                if let Some(run) = run.as_mut().filter(|run| run.inside_text) {
+                    run.text.push_str(&t.unescape()?);
+                }
+            }
+            Event::End(e) => {
+                path.pop();
+
+                let inside = inside(&path, &run);
+                let closes_run = run.as_ref().is_some_and(|run| path.len() + 1 == run.depth);
+                let name = e.name();
+
+                if closes_run {
+                    let run = run.take().unwrap();
+
+                    if let (false, Some(runs)) = (run.other, open.last_mut()) {
+                        runs.push(Run {
+                            span: run.start..end,
+                            properties: run.properties,
+                            text: run.text,
+                        });
+                    }
+                } else if inside && name.as_ref() == markup.properties {
+                    if let Some(run) = &mut run {
+                        run.properties = xml[run.properties_start..end].to_string();
+                    }
+                } else if inside && name.as_ref() == markup.text {
+                    if let Some(run) = &mut run {
+                        run.inside_text = false;
+                    }
+                } else if name.as_ref() == markup.paragraph {
+                    // The paragraphs of Word text boxes have no runs of text, their runs being
+                    // inside one.
+                    let runs = open.pop().unwrap_or_default();
+
+                    if runs.iter().any(|run| !run.text.trim().is_empty()) {
+                        paragraphs.push(runs);
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(paragraphs)
+}
+
+/// The runs of a paragraph with the neighbours in the same formatting put together, as the texts
+/// with the properties of their runs.
+fn pieces(runs: &[Run]) -> Vec<(String, &str)> {
+    let mut pieces: Vec<(String, &str)> = vec![];
+
+    for run in runs {
+        match pieces.last_mut() {
+            Some((text, properties)) if *properties == run.properties => {
+                text.push_str(&run.text);
+            }
+            _ => pieces.push((run.text.clone(), &run.properties)),
+        }
+    }
+
+    pieces
+}
+
+/// The text of a paragraph, with the runs marked if they aren't all in the same formatting.
+fn text(runs: &[Run]) -> String {
+    match pieces(runs).as_slice() {
+        [(text, _)] => text.clone(),
+        pieces => rich_text::mark(pieces.iter().map(|(text, _)| text.as_str())),
+    }
+}
+
+/// Runs of the text with the properties, its tabs and line breaks made into their elements.
+fn runs_xml(properties: &str, text: &str, format: FileFormat) -> String {
+    let mut xml = String::new();
+
+    for (i, line) in text.split('\n').enumerate() {
+        match format {
+            FileFormat::Pptx => {
+                if i > 0 {
+                    xml.push_str(&format!("<a:br>{}</a:br>", properties));
+                }
+
+                if !line.is_empty() {
+                    xml.push_str(&format!(
+                        "<a:r>{}<a:t>{}</a:t></a:r>",
+                        properties,
+                        escape(line)
+                    ));
+                }
+            }
+            _ => {
+                if i == 0 {
+                    xml.push_str(&format!("<w:r>{}", properties));
+                } else {
+                    xml.push_str("<w:br/>");
+                }
+
+                for (j, part) in line.split('\t').enumerate() {
+                    if j > 0 {
+                        xml.push_str("<w:tab/>");
+                    }
+
+                    if !part.is_empty() {
+                        xml.push_str(&format!(
+                            "<w:t xml:space=\"preserve\">{}</w:t>",
+                            escape(part)
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    if format != FileFormat::Pptx {
+        xml.push_str("</w:r>");
+    }
+
+    xml
+}
+
+pub(crate) fn read(path: &Path, format: FileFormat) -> Result<Sheet> {
+    let mut archive = package::open(path)?;
+    let mut texts = vec![HEADER.to_string()];
+
+    for part in text_parts(&archive, format) {
+        let xml =
+            package::read(&mut archive, &part)?.wrap_err_with(|| format!("Missing {}", part))?;
+        let paragraphs = paragraphs(&String::from_utf8(xml)?, markup(format))?;
+        texts.extend(paragraphs.iter().map(|runs| text(runs)));
+    }
+
+    let mut range = Range::new((0, 0), (texts.len() as u32 - 1, 0));
+
+    for (row, text) in texts.into_iter().enumerate() {
+        range.set_value((row as u32, 0), DataType::String(text));
+    }
+
+    Ok(Sheet {
+        name: WORKSHEET.to_string(),
+        range,
+        formulas: Range::empty(),
+        tab_color: None,
+        comments: BTreeMap::new(),
+    })
+}
+
+/// Writes the document or presentation at `source` to `destination` with the paragraphs
+/// translated by the first worksheet of the translated workbook at `translated`. The runs of a
+/// translation are in the formatting of the runs their tags stand for, or all in that of the
+/// first run of the paragraph if the tags didn't make it through. Parts without paragraphs are
+/// copied byte for byte.
+pub(crate) fn write(
+    translated: &Path,
+    source: &Path,
+    destination: &Path,
+    format: FileFormat,
+) -> Result<()> {
+    let mut workbook: Xlsx<_> = open_workbook(translated)?;
+
+    let range = workbook
+        .worksheet_range_at(0)
+        .wrap_err("The translated workbook has no worksheets")??;
+
+    let mut archive = package::open(source)?;
+    let mut replacements = HashMap::new();
+    let mut row = 0;
+
+    for part in text_parts(&archive, format) {
+        let xml =
+            package::read(&mut archive, &part)?.wrap_err_with(|| format!("Missing {}", part))?;
+        let xml = String::from_utf8(xml)?;
+        let mut rewritten = String::with_capacity(xml.len());
+        let mut position = 0;
+
+        for runs in paragraphs(&xml, markup(format))? {
+            row += 1;
+
+            let Some(translation) = range
+                .get_value((row, 0))
+                .map(DataType::to_string)
+                .filter(|translation| !translation.is_empty())
+            else {
+                continue;
+            };
+
+            let pieces = pieces(&runs);
+
+            let fragments = match pieces.as_slice() {
+                [(_, properties)] => vec![(translation, *properties)],
+                _ => rich_text::split(pieces.len(), &translation)
+                    .map(|fragments| {
+                        fragments
+                            .into_iter()
+                            .map(|(text, run)| (text, pieces[run].1))
+                            .collect()
+                    })
+                    .unwrap_or_else(|| vec![(rich_text::strip_tags(&translation), pieces[0].1)]),
+            };
+
+            // The translation takes the place of the first run, the others are left out.
+            for (i, run) in runs.iter().enumerate() {
+                rewritten.push_str(&xml[position..run.span.start]);
+
+                if i == 0 {
+                    for (text, properties) in &fragments {
+                        rewritten.push_str(&runs_xml(properties, text, format));
+                    }
+                }
+
+                position = run.span.end;
+            }
+        }
+
+        rewritten.push_str(&xml[position..]);
+        replacements.insert(part, rewritten);
+    }
+
+    let mut writer = ZipWriter::new(BufWriter::new(File::create(destination)?));
+
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+
+        match replacements.get(file.name()) {
+            Some(xml) => {
+                writer.start_file(file.name(), FileOptions::default())?;
+                writer.write_all(xml.as_bytes())?;
+            }
+            None => writer.raw_copy_file(file)?,
+        }
+    }
+
+    writer.finish()?.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,1151 @@
+//! Direct access to the zip package behind an xlsx file, for the cases where going through
+//! calamine and xlsxwriter would lose information or do unnecessary work.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{BufReader, BufWriter, Cursor, Read, Seek, Write},
+    path::Path,
+};
+
+use color_eyre::{eyre::ContextCompat, Result};
+use encoding_rs::{Encoding, UTF_8};
+use quick_xml::{
+    events::{BytesEnd, BytesStart, BytesText, Event},
+    Reader, Writer,
+};
+use zip::{write::FileOptions, ZipArchive, ZipWriter};
+
+use crate::columns::parse_reference;
+
+pub const STYLES: &str = "xl/styles.xml";
+
+pub const SHARED_STRINGS: &str = "xl/sharedStrings.xml";
+
+pub type Archive = ZipArchive<BufReader<File>>;
+
+/// The texts of the cells of a worksheet, by their zero-based positions.
+pub type CellTexts = HashMap<(u32, u16), String>;
+
+pub fn open(path: &Path) -> Result<Archive> {
+    Ok(ZipArchive::new(BufReader::new(File::open(path)?))?)
+}
+
+pub fn read<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<Option<Vec<u8>>> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes)?;
+
+    Ok(Some(bytes))
+}
+
+/// The attributes of an element, keyed by their qualified names.
+pub fn attribute_map(e: &BytesStart, reader: &Reader<&[u8]>) -> Result<HashMap<String, String>> {
+    let mut attributes = HashMap::new();
+
+    for attribute in e.attributes() {
+        let attribute = attribute?;
+        let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+        let value = attribute.decode_and_unescape_value(reader)?.into_owned();
+        attributes.insert(key, value);
+    }
+
+    Ok(attributes)
+}
+
+/// Collects the attributes of every element with the given local name.
+pub fn attributes(xml: &[u8], element: &[u8]) -> Result<Vec<HashMap<String, String>>> {
+    let mut reader = Reader::from_reader(xml);
+    let mut buf = vec![];
+    let mut elements = vec![];
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == element => {
+                elements.push(attribute_map(&e, &reader)?);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+
+        buf.clear();
+    }
+
+    Ok(elements)
+}
+
+/// Resolves the name of the part holding the worksheet with the given name.
+pub fn worksheet_part<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    sheet: &str,
+) -> Result<Option<String>> {
+    let workbook = read(archive, "xl/workbook.xml")?.wrap_err("Missing xl/workbook.xml")?;
+
+    let Some(id) = attributes(&workbook, b"sheet")?
+        .into_iter()
+        .find(|a| a.get("name").map(String::as_str) == Some(sheet))
+        .and_then(|mut a| a.remove("r:id"))
+    else {
+        return Ok(None);
+    };
+
+    let relationships = read(archive, "xl/_rels/workbook.xml.rels")?
+        .wrap_err("Missing xl/_rels/workbook.xml.rels")?;
+
+    let target = attributes(&relationships, b"Relationship")?
+        .into_iter()
+        .find(|a| a.get("Id") == Some(&id))
+        .and_then(|mut a| a.remove("Target"));
+
+    Ok(target.map(|target| match target.strip_prefix('/') {
+        Some(absolute) => absolute.to_string(),
+        None => format!("xl/{}", target),
+    }))
+}
+
+/// The signature of OLE compound files, which password-protected workbooks are saved as
+/// instead of zip packages.
+const COMPOUND_FILE: [u8; 8] = [0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1];
+
+/// Whether the file is an encrypted workbook, which can't be read without its password.
+pub fn is_encrypted(path: &Path) -> Result<bool> {
+    let mut signature = [0; 8];
+    let read = File::open(path)?.read(&mut signature)?;
+
+    Ok(read == signature.len() && signature == COMPOUND_FILE)
+}
+
+/// Whether the structure of the workbook is locked against adding, renaming or moving sheets.
+pub fn workbook_protected<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<bool> {
+    let workbook = read(archive, "xl/workbook.xml")?.wrap_err("Missing xl/workbook.xml")?;
+
+    Ok(attributes(&workbook, b"workbookProtection")?
+        .iter()
+        .any(|a| {
+            a.get("lockStructure")
+                .is_some_and(|lock| lock == "1" || lock == "true")
+        }))
+}
+
+/// Whether the worksheet part has its cells locked against editing.
+pub fn sheet_protected<R: Read + Seek>(archive: &mut ZipArchive<R>, part: &str) -> Result<bool> {
+    let xml = read(archive, part)?.wrap_err_with(|| format!("Missing {}", part))?;
+
+    Ok(!attributes(&xml, b"sheetProtection")?.is_empty())
+}
+
+/// Parses an RGB or ARGB hex color such as `FF0000`, `#FF0000` or `FFFF0000` into `0xRRGGBB`.
+pub fn parse_rgb(color: &str) -> Option<u32> {
+    let color = color.trim().trim_start_matches('#');
+
+    if !matches!(color.len(), 6 | 8) {
+        return None;
+    }
+
+    u32::from_str_radix(&color[color.len() - 6..], 16).ok()
+}
+
+/// Reads the RGB tab color of a worksheet part, if it has one.
+pub fn tab_color<R: Read + Seek>(archive: &mut ZipArchive<R>, part: &str) -> Result<Option<u32>> {
+    let worksheet = read(archive, part)?.wrap_err_with(|| format!("Missing {}", part))?;
+
+    Ok(attributes(&worksheet, b"tabColor")?
+        .into_iter()
+        .next()
+        .and_then(|a| parse_rgb(a.get("rgb")?)))
+}
+
+/// The relationships part of a package part, e.g. `xl/worksheets/_rels/sheet1.xml.rels` for
+/// `xl/worksheets/sheet1.xml`.
+pub fn relationships_part(part: &str) -> String {
+    match part.rsplit_once('/') {
+        Some((directory, file)) => format!("{}/_rels/{}.rels", directory, file),
+        None => format!("_rels/{}.rels", part),
+    }
+}
+
+/// Resolves the target of a relationship of `part`, relative to the directory of `part` unless it
+/// is absolute.
+fn resolve(part: &str, target: &str) -> String {
+    if let Some(absolute) = target.strip_prefix('/') {
+        return absolute.to_string();
+    }
+
+    let mut path = part.split('/').collect::<Vec<_>>();
+    path.pop();
+
+    for segment in target.split('/') {
+        match segment {
+            ".." => {
+                path.pop();
+            }
+            "." => {}
+            segment => path.push(segment),
+        }
+    }
+
+    path.join("/")
+}
+
+/// The parts within the package that `part` has relationships of the given type to, like
+/// `comments` for `http://schemas.openxmlformats.org/officeDocument/2006/relationships/comments`.
+fn related_parts<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    part: &str,
+    kind: &str,
+) -> Result<Vec<String>> {
+    let Some(relationships) = read(archive, &relationships_part(part))? else {
+        return Ok(vec![]);
+    };
+
+    let suffix = format!("/{}", kind);
+
+    Ok(attributes(&relationships, b"Relationship")?
+        .into_iter()
+        .filter(|a| a.get("Type").is_some_and(|t| t.ends_with(&suffix)))
+        .filter(|a| a.get("TargetMode").map(String::as_str) != Some("External"))
+        .filter_map(|a| Some(resolve(part, a.get("Target")?)))
+        .collect())
+}
+
+/// The texts of the `element`s with a cell reference in a part, each put together from its
+/// `text` elements, with the phonetic guides of rich text left out.
+fn cell_texts(xml: &[u8], element: &[u8], text: &[u8]) -> Result<Vec<((u32, u16), String)>> {
+    let mut reader = Reader::from_reader(xml);
+    let mut texts = vec![];
+    let mut current: Option<((u32, u16), String)> = None;
+    let mut inside_text = false;
+    let mut phonetic = false;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.local_name().as_ref() == element => {
+                current = attribute_map(&e, &reader)?
+                    .get("ref")
+                    .and_then(|r| parse_reference(r))
+                    .map(|cell| (cell, String::new()));
+            }
+            Event::Start(e) if e.local_name().as_ref() == text => inside_text = !phonetic,
+            Event::Start(e) if e.local_name().as_ref() == b"rPh" => phonetic = true,
+            Event::Text(t) if inside_text => {
+                if let Some((_, s)) = &mut current {
+                    s.push_str(&t.unescape()?);
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                name if name == element => texts.extend(current.take()),
+                name if name == text => inside_text = false,
+                b"rPh" => phonetic = false,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(texts)
+}
+
+/// The notes and comments of a worksheet part by the positions of their cells. A threaded
+/// comment is taken with its replies, one per line, instead of the note Excel keeps alongside
+/// it for older versions.
+pub fn comments<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    part: &str,
+) -> Result<BTreeMap<(u32, u16), String>> {
+    let mut comments = BTreeMap::new();
+
+    for notes in related_parts(archive, part, "comments")? {
+        let xml = read(archive, &notes)?.wrap_err_with(|| format!("Missing {}", notes))?;
+
+        for (cell, text) in cell_texts(&xml, b"comment", b"t")? {
+            comments.insert(cell, text);
+        }
+    }
+
+    let mut threads = BTreeMap::<_, Vec<_>>::new();
+
+    for threaded in related_parts(archive, part, "threadedComment")? {
+        let xml = read(archive, &threaded)?.wrap_err_with(|| format!("Missing {}", threaded))?;
+
+        for (cell, text) in cell_texts(&xml, b"threadedComment", b"text")? {
+            threads.entry(cell).or_default().push(text);
+        }
+    }
+
+    for (cell, thread) in threads {
+        comments.insert(cell, thread.join("\n"));
+    }
+
+    Ok(comments)
+}
+
+/// The hyperlinks of a worksheet part by the positions of their cells, in the form xlsxwriter
+/// takes them: URLs as they are, links to local files with `external:` and links to places
+/// within the workbook with `internal:`. A link over a range of cells is taken for the first.
+pub fn hyperlinks<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    part: &str,
+) -> Result<Vec<((u32, u16), String)>> {
+    let worksheet = read(archive, part)?.wrap_err_with(|| format!("Missing {}", part))?;
+    let hyperlinks = attributes(&worksheet, b"hyperlink")?;
+
+    if hyperlinks.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let targets = match read(archive, &relationships_part(part))? {
+        Some(relationships) => attributes(&relationships, b"Relationship")?
+            .into_iter()
+            .filter_map(|mut a| Some((a.remove("Id")?, a.remove("Target")?)))
+            .collect(),
+        None => HashMap::new(),
+    };
+
+    Ok(hyperlinks
+        .into_iter()
+        .filter_map(|a| {
+            let first = a.get("ref")?.split(':').next()?;
+            let cell = parse_reference(first)?;
+            let location = a.get("location");
+
+            let url = match a.get("r:id").and_then(|id| targets.get(id)) {
+                Some(target) => {
+                    let target = match location {
+                        Some(location) => format!("{}#{}", target, location),
+                        None => target.clone(),
+                    };
+
+                    if target.contains("://") || target.starts_with("mailto:") {
+                        target
+                    } else {
+                        format!("external:{}", target)
+                    }
+                }
+                None => format!("internal:{}", location?),
+            };
+
+            Some((cell, url))
+        })
+        .collect())
+}
+
+/// Where the values of a list validation come from.
+#[derive(Debug, Clone)]
+pub enum ListSource {
+    Values(Vec<String>),
+    /// A formula, usually a reference to the cells holding them.
+    Formula(String),
+}
+
+/// A list validation of a worksheet, which gives its cells a dropdown of the allowed values.
+#[derive(Debug, Clone)]
+pub struct ListValidation {
+    /// The first and last cells of the ranges it applies to.
+    pub ranges: Vec<((u32, u16), (u32, u16))>,
+    pub source: ListSource,
+    pub allow_blank: bool,
+    pub dropdown: bool,
+    /// The title and text of the message shown on selecting a cell.
+    pub prompt: Option<(String, String)>,
+    /// The style, title and text of the message shown on entering another value.
+    pub error: Option<(String, String, String)>,
+}
+
+/// The list validations of a worksheet part. Validations of other types are left out.
+pub fn list_validations<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    part: &str,
+) -> Result<Vec<ListValidation>> {
+    let worksheet = read(archive, part)?.wrap_err_with(|| format!("Missing {}", part))?;
+    let mut reader = Reader::from_reader(worksheet.as_slice());
+    let mut validations = vec![];
+    let mut current = None;
+    let mut formula = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.local_name().as_ref() == b"dataValidation" => {
+                let a = attribute_map(&e, &reader)?;
+
+                if a.get("type").map(String::as_str) != Some("list") {
+                    continue;
+                }
+
+                let flag =
+                    |name: &str| matches!(a.get(name).map(String::as_str), Some("1" | "true"));
+                let text = |name: &str| a.get(name).cloned().unwrap_or_default();
+
+                let ranges = a
+                    .get("sqref")
+                    .map(|sqref| {
+                        sqref
+                            .split_whitespace()
+                            .filter_map(|range| match range.split_once(':') {
+                                Some((first, last)) => {
+                                    parse_reference(first).zip(parse_reference(last))
+                                }
+                                None => parse_reference(range).map(|cell| (cell, cell)),
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                current = Some(ListValidation {
+                    ranges,
+                    source: ListSource::Values(vec![]),
+                    allow_blank: flag("allowBlank"),
+                    // Excel sets it to hide the dropdown, despite the name.
+                    dropdown: !flag("showDropDown"),
+                    prompt: flag("showInputMessage").then(|| (text("promptTitle"), text("prompt"))),
+                    error: flag("showErrorMessage").then(|| {
+                        let style = a.get("errorStyle").map_or("stop", String::as_str);
+                        (style.to_string(), text("errorTitle"), text("error"))
+                    }),
+                });
+            }
+            Event::Start(e) if e.local_name().as_ref() == b"formula1" && current.is_some() => {
+                formula = Some(String::new());
+            }
+            Event::Text(t) => {
+                if let Some(formula) = &mut formula {
+                    formula.push_str(&t.unescape()?);
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"formula1" => {
+                    if let (Some(validation), Some(formula)) = (&mut current, formula.take()) {
+                        validation.source = match formula
+                            .strip_prefix('"')
+                            .and_then(|values| values.strip_suffix('"'))
+                        {
+                            Some(values) => ListSource::Values(
+                                values
+                                    .split(',')
+                                    .map(|value| value.replace("\"\"", "\""))
+                                    .collect(),
+                            ),
+                            None => ListSource::Formula(formula),
+                        };
+                    }
+                }
+                b"dataValidation" => validations.extend(current.take()),
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(validations)
+}
+
+/// xlsxwriter names worksheet parts after their position, starting with `sheet1.xml`.
+fn written_worksheet_part(index: usize) -> String {
+    format!("xl/worksheets/sheet{}.xml", index + 1)
+}
+
+/// Replaces the first worksheets of the xlsxwriter-generated `destination` with the untouched
+/// worksheet parts of the given `sheets` of `source`, carrying over the styles and shared
+/// strings they refer to. Since those are shared by all worksheets, this only works if every
+/// worksheet of the destination is passed through.
+///
+/// Worksheets that point to other parts (comments, drawings, hyperlinks, ...) can't be passed
+/// through either, since those would not be copied along. Returns `false` without touching the
+/// destination in these cases.
+pub fn pass_through_worksheets(source: &Path, sheets: &[&str], destination: &Path) -> Result<bool> {
+    let mut source = open(source)?;
+    let mut replacements = HashMap::new();
+
+    for (i, sheet) in sheets.iter().enumerate() {
+        let Some(part) = worksheet_part(&mut source, sheet)? else {
+            return Ok(false);
+        };
+
+        if source.by_name(&relationships_part(&part)).is_ok() {
+            return Ok(false);
+        }
+
+        let worksheet = read(&mut source, &part)?.wrap_err_with(|| format!("Missing {}", part))?;
+        replacements.insert(written_worksheet_part(i), Some(worksheet));
+    }
+
+    let styles = read(&mut source, STYLES)?;
+    let shared_strings = read(&mut source, SHARED_STRINGS)?;
+
+    let mut output = open(destination)?;
+
+    // The destination needs to have a shared strings part already, otherwise the content
+    // types and workbook relationships would have to be amended as well.
+    if shared_strings.is_some() && output.by_name(SHARED_STRINGS).is_err() {
+        return Ok(false);
+    }
+
+    replacements.insert(STYLES.to_string(), styles);
+    replacements.insert(SHARED_STRINGS.to_string(), shared_strings);
+
+    let mut temporary = destination.as_os_str().to_owned();
+    temporary.push(".tmp");
+
+    let mut writer = ZipWriter::new(BufWriter::new(File::create(&temporary)?));
+
+    for i in 0..output.len() {
+        let file = output.by_index_raw(i)?;
+
+        match replacements.get(file.name()) {
+            Some(Some(bytes)) => {
+                writer.start_file(file.name(), FileOptions::default())?;
+                writer.write_all(bytes)?;
+            }
+            _ => writer.raw_copy_file(file)?,
+        }
+    }
+
+    writer.finish()?.flush()?;
+    fs::rename(temporary, destination)?;
+
+    Ok(true)
+}
+
+/// Keeps track of the position in a worksheet part, whose rows and cells can leave out their
+/// references when they come right after the previous ones.
+#[derive(Debug, Default)]
+struct Position {
+    row: Option<u32>,
+    column: Option<u16>,
+}
+
+impl Position {
+    fn row(&mut self, attributes: &HashMap<String, String>) {
+        let row = attributes.get("r").and_then(|r| r.parse::<u32>().ok());
+        self.row = Some(match row {
+            Some(row) => row.saturating_sub(1),
+            None => self.row.map_or(0, |row| row + 1),
+        });
+        self.column = None;
+    }
+
+    fn cell(&mut self, attributes: &HashMap<String, String>) -> (u32, u16) {
+        let (row, column) = match attributes.get("r").and_then(|r| parse_reference(r)) {
+            Some(reference) => reference,
+            None => (
+                self.row.unwrap_or(0),
+                self.column.map_or(0, |column| column + 1),
+            ),
+        };
+
+        self.column = Some(column);
+
+        (row, column)
+    }
+}
+
+/// The positions of the cells of a worksheet part that hold shared strings, with the indices of
+/// their strings.
+pub fn shared_string_cells(xml: &[u8]) -> Result<Vec<((u32, u16), usize)>> {
+    let mut reader = Reader::from_reader(xml);
+    let mut position = Position::default();
+    let mut cell = None;
+    let mut value = false;
+    let mut cells = vec![];
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"row" => {
+                position.row(&attribute_map(&e, &reader)?);
+            }
+            Event::Start(e) if e.local_name().as_ref() == b"c" => {
+                let attributes = attribute_map(&e, &reader)?;
+                let at = position.cell(&attributes);
+                cell = (attributes.get("t").map(String::as_str) == Some("s")).then_some(at);
+            }
+            Event::Empty(e) if e.local_name().as_ref() == b"c" => {
+                position.cell(&attribute_map(&e, &reader)?);
+            }
+            Event::Start(e) if e.local_name().as_ref() == b"v" => value = cell.is_some(),
+            Event::Text(text) if value => {
+                if let (Some(at), Ok(index)) = (cell, text.unescape()?.trim().parse()) {
+                    cells.push((at, index));
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"v" => value = false,
+                b"c" => cell = None,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(cells)
+}
+
+/// The index of the shared string in the events of a cell.
+fn shared_index(events: &[Event]) -> Option<usize> {
+    events
+        .iter()
+        .skip_while(|event| !matches!(event, Event::Start(e) if e.local_name().as_ref() == b"v"))
+        .find_map(|event| match event {
+            Event::Text(text) => text.unescape().ok()?.trim().parse().ok(),
+            _ => None,
+        })
+}
+
+/// Writes a `<t>` element with the text, keeping its surrounding whitespace.
+fn write_text(writer: &mut Writer<Vec<u8>>, text: &str) -> Result<()> {
+    writer.write_event(Event::Start(
+        BytesStart::new("t").with_attributes([("xml:space", "preserve")]),
+    ))?;
+    writer.write_event(Event::Text(BytesText::new(text)))?;
+    writer.write_event(Event::End(BytesEnd::new("t")))?;
+
+    Ok(())
+}
+
+/// The translations given shared strings of their own, for the cells whose strings can't be
+/// replaced where they are to still share every distinct text.
+struct Appended {
+    /// The index of the first of them, after the strings there were.
+    first: usize,
+    texts: Vec<String>,
+    indices: HashMap<String, usize>,
+}
+
+impl Appended {
+    fn new(first: usize) -> Self {
+        Self {
+            first,
+            texts: vec![],
+            indices: HashMap::new(),
+        }
+    }
+
+    fn index(&mut self, text: &str) -> usize {
+        if let Some(&index) = self.indices.get(text) {
+            return index;
+        }
+
+        let index = self.first + self.texts.len();
+        self.texts.push(text.to_string());
+        self.indices.insert(text.to_string(), index);
+        index
+    }
+}
+
+/// The number of strings of the shared strings part.
+fn shared_string_count(xml: &[u8]) -> Result<usize> {
+    let mut reader = Reader::from_reader(xml);
+    let mut count = 0;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"si" => count += 1,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(count)
+}
+
+/// A cell of a worksheet part whose text is replaced, read up to its end.
+struct Replaced<'a> {
+    start: BytesStart<'a>,
+    text: &'a str,
+    shared: bool,
+    events: Vec<Event<'a>>,
+}
+
+/// Replaces the texts of the given cells of a worksheet part. The cells whose shared strings are
+/// translated in place keep referring to them, the others refer to `appended` strings or, without
+/// a shared strings part, are turned into inline strings.
+fn rewrite_worksheet<'a>(
+    xml: &'a [u8],
+    cells: &'a CellTexts,
+    shared: &HashMap<usize, Option<&str>>,
+    mut appended: Option<&mut Appended>,
+) -> Result<Vec<u8>> {
+    let mut reader = Reader::from_reader(xml);
+    let mut writer = Writer::new(Vec::with_capacity(xml.len()));
+    let mut position = Position::default();
+    let mut replaced: Option<Replaced> = None;
+
+    loop {
+        let event = reader.read_event()?;
+
+        match &event {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"row" => {
+                position.row(&attribute_map(e, &reader)?);
+            }
+            Event::Start(e) if e.local_name().as_ref() == b"c" => {
+                let attributes = attribute_map(e, &reader)?;
+                let at = position.cell(&attributes);
+                let kind = attributes.get("t").map(String::as_str);
+
+                if let (Some(text), Some("s" | "inlineStr")) = (cells.get(&at), kind) {
+                    replaced = Some(Replaced {
+                        start: e.clone(),
+                        text,
+                        shared: kind == Some("s"),
+                        events: vec![],
+                    });
+                    continue;
+                }
+            }
+            Event::Empty(e) if e.local_name().as_ref() == b"c" => {
+                position.cell(&attribute_map(e, &reader)?);
+            }
+            Event::End(e) if e.local_name().as_ref() == b"c" => {
+                if let Some(cell) = replaced.take() {
+                    let translated_in_place = cell.shared
+                        && shared_index(&cell.events)
+                            .and_then(|index| shared.get(&index).copied().flatten())
+                            == Some(cell.text);
+
+                    if translated_in_place {
+                        writer.write_event(Event::Start(cell.start))?;
+
+                        for event in cell.events {
+                            writer.write_event(event)?;
+                        }
+                    } else if let Some(appended) = appended.as_mut().filter(|_| cell.shared) {
+                        let index = appended.index(cell.text).to_string();
+
+                        writer.write_event(Event::Start(cell.start))?;
+                        writer.write_event(Event::Start(BytesStart::new("v")))?;
+                        writer.write_event(Event::Text(BytesText::new(&index)))?;
+                        writer.write_event(Event::End(BytesEnd::new("v")))?;
+                    } else {
+                        let attributes = cell
+                            .start
+                            .attributes()
+                            .filter(|a| !matches!(a, Ok(a) if a.key.as_ref() == b"t"))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let start = BytesStart::new("c")
+                            .with_attributes(attributes)
+                            .with_attributes([("t", "inlineStr")]);
+
+                        writer.write_event(Event::Start(start))?;
+                        writer.write_event(Event::Start(BytesStart::new("is")))?;
+                        write_text(&mut writer, cell.text)?;
+                        writer.write_event(Event::End(BytesEnd::new("is")))?;
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+
+        match &mut replaced {
+            Some(cell) => cell.events.push(event),
+            None => writer.write_event(event)?,
+        }
+    }
+
+    Ok(writer.into_inner())
+}
+
+/// Replaces the shared strings that have a translation, rich text by plain text, and adds the
+/// `appended` ones at the end.
+fn rewrite_shared_strings(
+    xml: &[u8],
+    shared: &HashMap<usize, Option<&str>>,
+    appended: &[String],
+) -> Result<Vec<u8>> {
+    let mut reader = Reader::from_reader(xml);
+    let mut writer = Writer::new(Vec::with_capacity(xml.len()));
+    let mut index = 0;
+    let mut replacing = false;
+
+    loop {
+        let event = reader.read_event()?;
+
+        match &event {
+            Event::Start(e) if e.local_name().as_ref() == b"sst" && !appended.is_empty() => {
+                let attributes = attribute_map(e, &reader)?;
+                let mut start = e.to_owned();
+                start.clear_attributes();
+
+                for attribute in e.attributes() {
+                    let attribute = attribute?;
+
+                    if attribute.key.as_ref() != b"uniqueCount" {
+                        start.push_attribute(attribute);
+                    }
+                }
+
+                // The total count stays the same, the cells referring to other strings.
+                if let Some(count) = attributes
+                    .get("uniqueCount")
+                    .and_then(|count| count.parse::<usize>().ok())
+                {
+                    let count = (count + appended.len()).to_string();
+                    start.push_attribute(("uniqueCount", count.as_str()));
+                }
+
+                writer.write_event(Event::Start(start))?;
+                continue;
+            }
+            Event::End(e) if e.local_name().as_ref() == b"sst" => {
+                for text in appended {
+                    writer.write_event(Event::Start(BytesStart::new("si")))?;
+                    write_text(&mut writer, text)?;
+                    writer.write_event(Event::End(BytesEnd::new("si")))?;
+                }
+            }
+            Event::Start(e) if e.local_name().as_ref() == b"si" => {
+                if let Some(Some(text)) = shared.get(&index) {
+                    writer.write_event(&event)?;
+                    write_text(&mut writer, text)?;
+                    replacing = true;
+                }
+
+                index += 1;
+
+                if replacing {
+                    continue;
+                }
+            }
+            Event::Empty(e) if e.local_name().as_ref() == b"si" => index += 1,
+            Event::End(e) if e.local_name().as_ref() == b"si" => replacing = false,
+            Event::Eof => break,
+            _ if replacing => continue,
+            _ => {}
+        }
+
+        writer.write_event(event)?;
+    }
+
+    Ok(writer.into_inner())
+}
+
+/// Whether a part holds DrawingML text, that of the shapes and text boxes of drawings or of the
+/// titles and labels of charts.
+fn is_drawing(name: &str) -> bool {
+    (name.starts_with("xl/drawings/") || name.starts_with("xl/charts/")) && name.ends_with(".xml")
+}
+
+/// The text of every paragraph of the shapes, text boxes and charts of a package, with the runs
+/// of each put together.
+pub fn drawing_texts(path: &Path) -> Result<Vec<String>> {
+    let mut archive = open(path)?;
+
+    let parts = archive
+        .file_names()
+        .filter(|name| is_drawing(name))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    let mut texts = vec![];
+
+    for part in parts {
+        let xml = read(&mut archive, &part)?.wrap_err_with(|| format!("Missing {}", part))?;
+        let mut reader = Reader::from_reader(xml.as_slice());
+        let mut paragraph = None;
+        let mut inside_text = false;
+
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) if e.local_name().as_ref() == b"p" => {
+                    paragraph = Some(String::new());
+                }
+                Event::Start(e) if e.local_name().as_ref() == b"t" => inside_text = true,
+                Event::Text(t) if inside_text => {
+                    if let Some(paragraph) = &mut paragraph {
+                        paragraph.push_str(&t.unescape()?);
+                    }
+                }
+                Event::End(e) => match e.local_name().as_ref() {
+                    b"p" => texts.extend(paragraph.take().filter(|p| !p.trim().is_empty())),
+                    b"t" => inside_text = false,
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+    }
+
+    Ok(texts)
+}
+
+/// Replaces the paragraphs of a drawing or chart part that have a translation. The translation
+/// goes in the first run, which keeps its formatting, and the other runs are emptied.
+fn rewrite_drawing(xml: &[u8], translations: &HashMap<String, String>) -> Result<Vec<u8>> {
+    let mut reader = Reader::from_reader(xml);
+    let mut writer = Writer::new(Vec::with_capacity(xml.len()));
+    let mut paragraph: Option<Vec<Event>> = None;
+
+    loop {
+        let event = reader.read_event()?;
+
+        match &event {
+            Event::Start(e) if e.local_name().as_ref() == b"p" => paragraph = Some(vec![]),
+            Event::End(e) if e.local_name().as_ref() == b"p" => {
+                if let Some(events) = paragraph.take() {
+                    write_paragraph(&mut writer, events, translations)?;
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+
+        match &mut paragraph {
+            Some(events) => events.push(event),
+            None => writer.write_event(event)?,
+        }
+    }
+
+    Ok(writer.into_inner())
+}
+
+fn write_paragraph(
+    writer: &mut Writer<Vec<u8>>,
+    events: Vec<Event>,
+    translations: &HashMap<String, String>,
+) -> Result<()> {
+    let mut text = String::new();
+    let mut inside_text = false;
+
+    for event in &events {
+        match event {
+            Event::Start(e) if e.local_name().as_ref() == b"t" => inside_text = true,
+            Event::End(e) if e.local_name().as_ref() == b"t" => inside_text = false,
+            Event::Text(t) if inside_text => text.push_str(&t.unescape()?),
+            _ => {}
+        }
+    }
+
+    let Some(translation) = translations.get(&text) else {
+        for event in events {
+            writer.write_event(event)?;
+        }
+
+        return Ok(());
+    };
+
+    let mut translation = Some(translation.as_str());
+    inside_text = false;
+
+    for event in events {
+        match &event {
+            Event::Start(e) if e.local_name().as_ref() == b"t" => {
+                inside_text = true;
+                writer.write_event(&event)?;
+
+                if let Some(translation) = translation.take() {
+                    writer.write_event(Event::Text(BytesText::new(translation)))?;
+                }
+
+                continue;
+            }
+            Event::End(e) if e.local_name().as_ref() == b"t" => inside_text = false,
+            Event::Text(_) if inside_text => continue,
+            _ => {}
+        }
+
+        writer.write_event(event)?;
+    }
+
+    Ok(())
+}
+
+/// Copies `source` to `destination` with the texts of the given cells of its worksheets
+/// replaced, for `--preserve-structure`. Only the shared strings and the worksheets with
+/// replaced cells are rewritten, every other part, charts, pivot tables, images and macros
+/// included, is copied byte for byte.
+///
+/// A shared string is replaced where it is if all the cells referring to it get the same text,
+/// otherwise the cells that get another one refer to a new shared string of it, added once for
+/// all of them. The paragraphs of the drawings and charts are replaced by their `drawings`
+/// translations, if there are any.
+pub fn rewrite_strings(
+    source: &Path,
+    sheets: &[(&str, CellTexts)],
+    drawings: &HashMap<String, String>,
+    destination: &Path,
+) -> Result<()> {
+    let mut archive = open(source)?;
+    let mut texts = HashMap::new();
+
+    for (sheet, cells) in sheets {
+        let part = worksheet_part(&mut archive, sheet)?
+            .wrap_err_with(|| format!("No part for the worksheet '{}'", sheet))?;
+        texts.insert(part, cells);
+    }
+
+    let worksheets = archive
+        .file_names()
+        .filter(|name| name.starts_with("xl/worksheets/") && name.ends_with(".xml"))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    // The text of every shared string, or `None` where it has to stay as it is for a cell.
+    let mut shared = HashMap::new();
+    let mut rewritten = vec![];
+
+    for part in worksheets {
+        let xml = read(&mut archive, &part)?.wrap_err_with(|| format!("Missing {}", part))?;
+        let cells = texts.get(&part).copied();
+
+        for (position, index) in shared_string_cells(&xml)? {
+            let text = cells
+                .and_then(|cells| cells.get(&position))
+                .map(String::as_str);
+
+            shared
+                .entry(index)
+                .and_modify(|shared| {
+                    if *shared != text {
+                        *shared = None;
+                    }
+                })
+                .or_insert(text);
+        }
+
+        if let Some(cells) = cells {
+            rewritten.push((part, xml, cells));
+        }
+    }
+
+    let strings = read(&mut archive, SHARED_STRINGS)?;
+    let mut appended = strings
+        .as_deref()
+        .map(shared_string_count)
+        .transpose()?
+        .map(Appended::new);
+    let mut replacements = HashMap::new();
+
+    for (part, xml, cells) in rewritten {
+        let xml = rewrite_worksheet(&xml, cells, &shared, appended.as_mut())?;
+        replacements.insert(part, xml);
+    }
+
+    if !drawings.is_empty() {
+        let parts = archive
+            .file_names()
+            .filter(|name| is_drawing(name))
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        for part in parts {
+            let xml = read(&mut archive, &part)?.wrap_err_with(|| format!("Missing {}", part))?;
+            replacements.insert(part, rewrite_drawing(&xml, drawings)?);
+        }
+    }
+
+    if let (Some(xml), Some(appended)) = (strings, appended) {
+        let xml = rewrite_shared_strings(&xml, &shared, &appended.texts)?;
+        replacements.insert(SHARED_STRINGS.to_string(), xml);
+    }
+
+    let mut writer = ZipWriter::new(BufWriter::new(File::create(destination)?));
+
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+
+        match replacements.get(file.name()) {
+            Some(bytes) => {
+                writer.start_file(file.name(), FileOptions::default())?;
+                writer.write_all(bytes)?;
+            }
+            None => writer.raw_copy_file(file)?,
+        }
+    }
+
+    writer.finish()?.flush()?;
+
+    Ok(())
+}
+
+/// The encoding named by the XML declaration at the start of a part, if there is one.
+fn declared_encoding(xml: &[u8]) -> Option<(&'static Encoding, std::ops::Range<usize>)> {
+    if !xml.starts_with(b"<?xml") {
+        return None;
+    }
+
+    let end = xml.windows(2).position(|w| w == b"?>")?;
+    let declaration = &xml[..end];
+    let start = declaration.windows(9).position(|w| w == b"encoding=")? + 9;
+    let quote = *declaration.get(start)?;
+
+    if quote != b'"' && quote != b'\'' {
+        return None;
+    }
+
+    let length = declaration[start + 1..].iter().position(|&b| b == quote)?;
+    let label = start + 1..start + 1 + length;
+
+    Some((Encoding::for_label(&xml[label.clone()])?, label))
+}
+
+/// A package whose XML parts have been converted to UTF-8.
+pub struct Transcoded {
+    pub package: Vec<u8>,
+    /// The names and originally declared encodings of the converted parts.
+    pub parts: Vec<(String, String)>,
+}
+
+/// Rewrites the XML parts of the package that declare an encoding other than UTF-8 to UTF-8,
+/// since that's all calamine can read. Returns `None` if all parts are UTF-8 already.
+pub fn transcode_to_utf8(path: &Path) -> Result<Option<Transcoded>> {
+    let mut archive = open(path)?;
+    let mut transcoded = HashMap::new();
+    let mut affected = vec![];
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+
+        if !file.name().ends_with(".xml") && !file.name().ends_with(".rels") {
+            continue;
+        }
+
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)?;
+
+        let Some((encoding, label)) = declared_encoding(&bytes) else {
+            continue;
+        };
+
+        if encoding == UTF_8 {
+            continue;
+        }
+
+        let (decoded, _) = encoding.decode_without_bom_handling(&bytes[label.end..]);
+        let mut xml = bytes[..label.start].to_vec();
+        xml.extend_from_slice(b"UTF-8");
+        xml.extend_from_slice(decoded.as_bytes());
+
+        affected.push((file.name().to_string(), encoding.name().to_string()));
+        transcoded.insert(file.name().to_string(), xml);
+    }
+
+    if transcoded.is_empty() {
+        return Ok(None);
+    }
+
+    let mut writer = ZipWriter::new(Cursor::new(vec![]));
+
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+
+        match transcoded.get(file.name()) {
+            Some(bytes) => {
+                writer.start_file(file.name(), FileOptions::default())?;
+                writer.write_all(bytes)?;
+            }
+            None => writer.raw_copy_file(file)?,
+        }
+    }
+
+    Ok(Some(Transcoded {
+        package: writer.finish()?.into_inner(),
+        parts: affected,
+    }))
+}
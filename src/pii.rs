@@ -0,0 +1,94 @@
+//! The personal data `--redact-pii` keeps from the API: email addresses, phone numbers, IBANs and
+//! the names that follow a title like `Mr.` or `Dr.`. They are masked like protected terms, so
+//! that only placeholders are sent and the data is put back into the translations.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use regex::Captures;
+
+/// The kinds of personal data by the names of their groups in the pattern.
+pub(crate) const KINDS: [(&str, &str); 4] = [
+    ("email", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}"),
+    (
+        "iban",
+        r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]{4}){2,7}(?: ?[A-Z0-9]{1,4})?\b",
+    ),
+    (
+        "phone",
+        r"(?:\+|\b)(?:\d{1,4}[ .-]?)?(?:\(\d{1,4}\)[ .-]?)?\d{2,4}(?:[ .-]?\d{2,4}){1,4}\b",
+    ),
+    (
+        "name",
+        r"\b(?:Mr|Mrs|Ms|Miss|Dr|Prof)\.? [A-Z][\p{Ll}'-]+(?: [A-Z][\p{Ll}'-]+)?",
+    ),
+];
+
+/// The fewest digits of a phone number, for dates, amounts and codes not to be taken for one.
+const PHONE_DIGITS: usize = 9;
+
+/// The patterns of the kinds, each in a group of its name.
+pub(crate) fn patterns() -> impl Iterator<Item = String> {
+    KINDS
+        .iter()
+        .map(|(kind, pattern)| format!("(?P<{}>{})", kind, pattern))
+}
+
+/// Whether the check digits of the IBAN are right, as computed modulo 97.
+fn valid_iban(iban: &str) -> bool {
+    let iban = iban.replace(' ', "");
+    let (country, rest) = iban.split_at(4);
+
+    rest.chars()
+        .chain(country.chars())
+        .try_fold(0u32, |remainder, c| {
+            let digits = c.to_digit(36)?;
+            let shift = if digits < 10 { 10 } else { 100 };
+            Some((remainder * shift + digits) % 97)
+        })
+        == Some(1)
+}
+
+/// The kind of personal data the match is, if it is one. Phone numbers with too few digits and
+/// IBANs with the wrong check digits are other numbers.
+pub(crate) fn kind(captures: &Captures) -> Option<&'static str> {
+    let (kind, _) = KINDS
+        .iter()
+        .find(|(kind, _)| captures.name(kind).is_some())?;
+
+    let valid = match *kind {
+        "phone" => captures[0].chars().filter(char::is_ascii_digit).count() >= PHONE_DIGITS,
+        "iban" => valid_iban(&captures[0]),
+        _ => true,
+    };
+
+    valid.then_some(kind)
+}
+
+/// What was redacted, the distinct values of every kind.
+#[derive(Debug, Default)]
+pub(crate) struct Redacted(BTreeMap<&'static str, BTreeSet<String>>);
+
+impl Redacted {
+    pub(crate) fn add(&mut self, kind: &'static str, value: &str) {
+        self.0.entry(kind).or_default().insert(value.to_string());
+    }
+
+    /// How many distinct values of every kind were redacted.
+    pub(crate) fn counts(&self) -> BTreeMap<&'static str, usize> {
+        self.0
+            .iter()
+            .map(|(kind, values)| (*kind, values.len()))
+            .collect()
+    }
+
+    /// A line for the end of the run, like `Redacted personal data: 3 email, 1 phone`.
+    pub(crate) fn summary(&self) -> Option<String> {
+        let counts = self
+            .counts()
+            .into_iter()
+            .map(|(kind, count)| format!("{} {}", count, kind))
+            .collect::<Vec<_>>();
+
+        (!counts.is_empty()).then(|| format!("Redacted personal data: {}", counts.join(", ")))
+    }
+}
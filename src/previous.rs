@@ -0,0 +1,102 @@
+//! The previous revision of the source and its translation, for `--previous`. The cells whose
+//! value hasn't changed since get their translation from the previous output instead of the
+//! API, so that only the new and changed ones are sent.
+
+use std::{borrow::Cow, collections::BTreeMap, path::Path};
+
+use calamine::{open_workbook, DataType, Range, Xlsx};
+use color_eyre::{eyre::Context, Result};
+
+use crate::{
+    dictionary::normalize_whitespace,
+    workbook::{read_worksheets, Layout, Sheet},
+};
+
+/// A worksheet in both previous workbooks.
+pub(crate) struct Previous {
+    source: Range<DataType>,
+    output: Range<DataType>,
+}
+
+fn read(path: &Path) -> Result<Vec<Sheet>> {
+    let workbook = open_workbook::<Xlsx<_>, _>(path)
+        .wrap_err_with(|| format!("Failed to open the previous workbook {}", path.display()))?;
+
+    read_worksheets(workbook, &[], true)
+}
+
+/// The worksheets of the previous source by name, with those of its translation. Worksheets the
+/// translation doesn't have are left out.
+pub(crate) fn load(source: &Path, output: &Path) -> Result<BTreeMap<String, Previous>> {
+    let mut outputs = read(output)?
+        .into_iter()
+        .map(|sheet| (sheet.name, sheet.range))
+        .collect::<BTreeMap<_, _>>();
+
+    Ok(read(source)?
+        .into_iter()
+        .filter_map(|sheet| {
+            let output = outputs.remove(&sheet.name)?;
+            let previous = Previous {
+                source: sheet.range,
+                output,
+            };
+            Some((sheet.name, previous))
+        })
+        .collect())
+}
+
+impl Previous {
+    /// Whether the cell at `position` in the worksheet had another value in the previous source,
+    /// or none at all.
+    pub(crate) fn changed(
+        &self,
+        position: (u32, u16),
+        value: &str,
+        preserve_whitespace: bool,
+    ) -> bool {
+        let Some(DataType::String(previous)) =
+            self.source.get_value((position.0, u32::from(position.1)))
+        else {
+            return true;
+        };
+
+        let previous = if preserve_whitespace {
+            Cow::Borrowed(previous.trim())
+        } else {
+            Cow::Owned(normalize_whitespace(previous))
+        };
+
+        previous != value
+    }
+
+    /// The previous translation of the cell at `position` in the worksheet, if it had the same
+    /// value in the previous source. `layout` is the one the translations went by, from where the
+    /// range of the previous source starts.
+    pub(crate) fn translation(
+        &self,
+        position: (u32, u16),
+        value: &str,
+        preserve_whitespace: bool,
+        layout: Layout<'_>,
+    ) -> Option<String> {
+        if self.changed(position, value, preserve_whitespace) {
+            return None;
+        }
+
+        let (first_row, first_column) = self.source.start().unwrap_or((0, 0));
+        let layout = Layout {
+            origin: (first_row, first_column as u16),
+            ..layout
+        };
+        let cell =
+            layout.translation_cell(position.0 - first_row, position.1 - first_column as u16);
+
+        match self.output.get_value((cell.0, u32::from(cell.1))) {
+            Some(DataType::String(translation)) if !translation.trim().is_empty() => {
+                Some(translation.trim().to_string())
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,376 @@
+//! The prompts cells are translated with, and the segment classes that shape them.
+
+use std::{collections::BTreeMap, fs, path::Path, str::FromStr};
+
+use calamine::{DataType, Range};
+use color_eyre::{
+    eyre::{bail, Context},
+    Result,
+};
+use serde::{Deserialize, Serialize};
+use tiktoken_rs::CoreBPE;
+
+use crate::{dictionary::normalize_whitespace, protect, rich_text};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Segment {
+    Technical,
+    Prose,
+    Ui,
+    Number,
+    Code,
+    ProperNoun,
+}
+
+impl FromStr for Segment {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "technical" => Ok(Self::Technical),
+            "prose" => Ok(Self::Prose),
+            "ui" => Ok(Self::Ui),
+            "number" => Ok(Self::Number),
+            "code" => Ok(Self::Code),
+            "proper_noun" => Ok(Self::ProperNoun),
+            _ => bail!("Unknown segment class '{}'", s.trim()),
+        }
+    }
+}
+
+impl Segment {
+    /// Whether cells of this class are copied verbatim instead of being translated.
+    pub(crate) fn is_verbatim(self) -> bool {
+        matches!(self, Self::Number | Self::Code)
+    }
+
+    pub(crate) fn instruction(self, source_language: &str, language: &str) -> String {
+        match self {
+            Self::Technical => format!(
+                "Translate this technical text from {} into {}:\n",
+                source_language, language
+            ),
+            Self::Prose => format!(
+                "Translate this text from {} into fluent, natural {}:\n",
+                source_language, language
+            ),
+            Self::Ui => format!(
+                "Translate this user interface string from {} into concise {}:\n",
+                source_language, language
+            ),
+            Self::ProperNoun => format!(
+                "Translate this {1} proper noun into {0} only if it has an established {0} form, \
+                 otherwise repeat it unchanged:\n",
+                language, source_language
+            ),
+            Self::Number | Self::Code => unreachable!(),
+        }
+    }
+
+    pub(crate) fn temperature(self) -> f32 {
+        match self {
+            Self::Prose => 0.3,
+            _ => 0.,
+        }
+    }
+
+    /// Technical text must use the glossary terms as given, everything else only takes them
+    /// into account.
+    pub(crate) fn glossary_preamble(self) -> &'static str {
+        match self {
+            Self::Technical => "Use exactly the following translations:\n",
+            _ => "Considering the following translations:\n",
+        }
+    }
+}
+
+/// The names of the languages that can also be given by their ISO 639-1 code.
+pub(crate) const LANGUAGES: [(&str, &str); 24] = [
+    ("bg", "Bulgarian"),
+    ("cs", "Czech"),
+    ("da", "Danish"),
+    ("de", "German"),
+    ("el", "Greek"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fi", "Finnish"),
+    ("fr", "French"),
+    ("hu", "Hungarian"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nl", "Dutch"),
+    ("no", "Norwegian"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("ro", "Romanian"),
+    ("ru", "Russian"),
+    ("sk", "Slovak"),
+    ("sv", "Swedish"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("zh", "Chinese"),
+];
+
+/// Resolves an ISO 639-1 code to the name of its language, taking anything else as a name.
+pub(crate) fn language_name(language: &str) -> &str {
+    let language = language.trim();
+
+    LANGUAGES
+        .iter()
+        .find(|(code, _)| code.eq_ignore_ascii_case(language))
+        .map_or(language, |(_, name)| name)
+}
+
+/// The ISO 639-1 code of a language given by name or code, or the name itself if it is not a
+/// known one.
+pub(crate) fn language_code(language: &str) -> &str {
+    let language = language.trim();
+
+    LANGUAGES
+        .iter()
+        .find(|(code, name)| {
+            code.eq_ignore_ascii_case(language) || name.eq_ignore_ascii_case(language)
+        })
+        .map_or(language, |(code, _)| code)
+}
+
+/// A prompt given with `--prompt-template`, replacing the built-in one.
+#[derive(Debug)]
+pub(crate) struct Template(String);
+
+impl Template {
+    const PLACEHOLDERS: [&'static str; 5] = [
+        "{source}",
+        "{glossary}",
+        "{target_lang}",
+        "{context}",
+        "{source_lang}",
+    ];
+
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read the prompt template {}", path.display()))?;
+
+        if !text.contains("{source}") {
+            bail!(
+                "The prompt template {} has no {{source}} placeholder",
+                path.display()
+            );
+        }
+
+        Ok(Self(text))
+    }
+
+    /// Fills in the placeholders in a single pass, so that braces in the values stay as they
+    /// are.
+    fn render(&self, values: [&str; 5]) -> String {
+        let mut prompt = String::new();
+        let mut rest = self.0.as_str();
+
+        while let Some(start) = rest.find('{') {
+            prompt.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            match Self::PLACEHOLDERS
+                .iter()
+                .position(|placeholder| rest.starts_with(placeholder))
+            {
+                Some(i) => {
+                    prompt.push_str(values[i]);
+                    rest = &rest[Self::PLACEHOLDERS[i].len()..];
+                }
+                None => {
+                    prompt.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        prompt.push_str(rest);
+        prompt
+    }
+}
+
+/// Builds the translation prompt for a cell value in `source_language`, suggesting the
+/// dictionary entries whose keys occur in it.
+pub(crate) fn build_prompt(
+    value: &str,
+    dictionary: &BTreeMap<String, String>,
+    segment: Option<Segment>,
+    context: Option<&str>,
+    source_language: &str,
+    language: &str,
+    template: Option<&Template>,
+) -> String {
+    let key = value.to_lowercase();
+    let mut prompt = String::new();
+    let mut translations = String::new();
+
+    // Keys of `--match-mode exact` can have capitals.
+    for (k, v) in dictionary {
+        if key.contains(&k.to_lowercase()) {
+            translations.push_str(k);
+            translations.push_str(" – ");
+            translations.push_str(v);
+            translations.push('\n');
+        }
+    }
+
+    if let Some(template) = template {
+        return template.render([
+            value,
+            &translations,
+            language,
+            context.unwrap_or_default(),
+            source_language,
+        ]);
+    }
+
+    if !translations.is_empty() {
+        prompt.push_str(segment.map_or(
+            "Considering the following translations:\n",
+            Segment::glossary_preamble,
+        ));
+        prompt.push_str(&translations);
+        prompt.push('\n');
+    }
+
+    if let Some(context) = context {
+        prompt.push_str("Context for this cell: ");
+        prompt.push_str(context);
+        prompt.push_str("\n\n");
+    }
+
+    if protect::has_placeholders(value) {
+        prompt.push_str(protect::INSTRUCTION);
+    }
+
+    if rich_text::has_tags(value) {
+        prompt.push_str(rich_text::INSTRUCTION);
+    }
+
+    match segment {
+        Some(segment) => prompt.push_str(&segment.instruction(source_language, language)),
+        None => prompt.push_str(&format!(
+            "Translate this from {} into {}:\n",
+            source_language, language
+        )),
+    }
+
+    // Delimited for the instructions in the value to be taken for text to translate, with a tag
+    // the value doesn't close itself.
+    let tag = delimiter(value);
+    prompt.push_str(&format!("<{}>\n{}\n</{}>", tag, value, tag));
+    prompt.push_str(&format!("\n\n{}:\n", language));
+
+    prompt
+}
+
+/// The name of the tags around the value in the prompt, `source` unless the value has a closing
+/// tag of that name, and `source` with the first number it hasn't otherwise.
+fn delimiter(value: &str) -> String {
+    (0..)
+        .map(|i| match i {
+            0 => "source".to_string(),
+            i => format!("source{}", i),
+        })
+        .find(|tag| !value.contains(&format!("</{}>", tag)))
+        .unwrap()
+}
+
+/// The prompt once more, demanding the dictionary terms a translation didn't use, for
+/// `--enforce-glossary`.
+pub(crate) fn enforce_terms(prompt: &str, terms: &[(&str, &str)]) -> String {
+    let mut strict = String::from(
+        "The translation must use exactly these translations of the terms, whatever else the \
+         instructions below suggest:\n",
+    );
+
+    for (key, value) in terms {
+        strict.push_str(&format!("{} – {}\n", key, value));
+    }
+
+    strict.push('\n');
+    strict.push_str(prompt);
+    strict
+}
+
+/// The prompt once more, asking for a translation shorter than the one it got, for
+/// `--max-length`.
+pub(crate) fn shorten(prompt: &str, translation: &str, max_length: usize) -> String {
+    format!(
+        "The translation must be at most {} characters long, \"{}\" is too long. Shorten or \
+         abbreviate it if need be.\n\n{}",
+        max_length, translation, prompt
+    )
+}
+
+/// Joins the values around a cell in its row, widening one column to each side at a time for up
+/// to `columns` columns and for as long as the result fits in `max_tokens`.
+pub(crate) fn adjacent_context(
+    range: &Range<DataType>,
+    (row, column): (u32, u16),
+    columns: usize,
+    max_tokens: usize,
+    bpe: &CoreBPE,
+) -> Option<String> {
+    let (row, column) = (row as usize, column as usize);
+
+    let value = |column: usize| match range.get((row, column)) {
+        Some(DataType::Empty) | None => None,
+        Some(data) => Some(normalize_whitespace(&data.to_string())),
+    };
+
+    let mut context = None;
+
+    for distance in 1..=columns {
+        let first = column.saturating_sub(distance);
+        let last = (column + distance).min(range.width().saturating_sub(1));
+
+        let candidate = (first..=last)
+            .filter_map(value)
+            .filter(|v| !v.is_empty())
+            .collect::<Vec<_>>()
+            .join(" | ");
+
+        if bpe.encode_with_special_tokens(&candidate).len() > max_tokens {
+            break;
+        }
+
+        context = Some(candidate);
+
+        if first == 0 && last + 1 >= range.width() {
+            break;
+        }
+    }
+
+    context
+}
+
+/// Where a cell sits, by the header of its column and the label in the first column of its row,
+/// e.g. `Column: Status, Row: Order #123`.
+pub(crate) fn position_context(
+    range: &Range<DataType>,
+    (row, column): (u32, u16),
+    header_row: Option<u32>,
+) -> Option<String> {
+    let text = |row: u32, column: u16| match range.get((row as usize, column as usize)) {
+        Some(DataType::Empty) | None => None,
+        Some(data) => Some(normalize_whitespace(&data.to_string())).filter(|v| !v.is_empty()),
+    };
+
+    let header = header_row
+        .filter(|&header_row| header_row < row)
+        .and_then(|header_row| text(header_row, column))
+        .map(|header| format!("Column: {}", header));
+
+    let label = (column > 0)
+        .then(|| text(row, 0))
+        .flatten()
+        .map(|label| format!("Row: {}", label));
+
+    let parts = [header, label].into_iter().flatten().collect::<Vec<_>>();
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
@@ -0,0 +1,299 @@
+//! Text that must never be translated, like the product names and trademarks given with
+//! `--protect` or the placeholders of format strings. It is swapped for numbered placeholders
+//! before a value is sent and put back into its translation.
+
+use std::{
+    borrow::Cow,
+    fs,
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+use color_eyre::{
+    eyre::{bail, Context},
+    Result,
+};
+use regex::{Captures, Regex};
+
+use crate::pii::{self, Redacted};
+
+#[derive(Debug)]
+pub(crate) struct Protected {
+    pattern: Regex,
+    /// The personal data masked so far, with `--redact-pii`.
+    redacted: Option<Mutex<Redacted>>,
+}
+
+/// A value with its protected terms replaced by placeholders.
+#[derive(Debug, Clone)]
+pub(crate) struct Masked {
+    pub(crate) text: String,
+    /// The terms, in the order of their placeholders.
+    pub(crate) terms: Vec<String>,
+}
+
+/// Asks the model to leave the placeholders alone, for prompts of masked values.
+pub(crate) const INSTRUCTION: &str = "Keep the placeholders like ⟦0⟧ exactly as they are.\n\n";
+
+fn placeholder(i: usize) -> String {
+    format!("⟦{}⟧", i)
+}
+
+pub(crate) fn has_placeholders(text: &str) -> bool {
+    text.contains('⟦')
+}
+
+/// Format string placeholders and markup, which have to come back exactly as they are: `{0}`,
+/// `{name}`, `{{variable}}`, printf-style `%s` or `%1$d`, and tags like `<b>`, `</b>` or `<br/>`.
+pub(crate) const FORMAT_TOKENS: &str = r"\{\{[^{}]*\}\}|\{[^{}\s]*\}|%(?:\d+\$)?[-+#0]*\d*(?:\.\d+)?[sdifuxXeEgGc@]|</?[A-Za-z][^<>]*>";
+
+/// Reads one term per line, taken literally and matched as a whole word, or a regular expression
+/// if it is enclosed in slashes like `/iPhone \d+/`.
+fn load(path: &Path) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read the protected terms {}", path.display()))?;
+
+    let mut patterns = vec![];
+    let mut terms = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        match line
+            .strip_prefix('/')
+            .and_then(|line| line.strip_suffix('/'))
+        {
+            Some(pattern) if !pattern.is_empty() => {
+                Regex::new(pattern).wrap_err_with(|| {
+                    format!("Invalid protected term pattern at line #{}", i + 1)
+                })?;
+                patterns.push(format!("(?:{})", pattern));
+            }
+            _ => terms.push(line),
+        }
+    }
+
+    // Longer terms first, so that they win over the shorter ones they contain.
+    terms.sort_by_key(|term| std::cmp::Reverse(term.chars().count()));
+
+    for term in terms {
+        let word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+        let start = if word(term.chars().next()) { r"\b" } else { "" };
+        let end = if word(term.chars().last()) { r"\b" } else { "" };
+        patterns.push(format!("{}{}{}", start, regex::escape(term), end));
+    }
+
+    if patterns.is_empty() {
+        bail!("No protected terms in {}", path.display());
+    }
+
+    Ok(patterns)
+}
+
+impl Protected {
+    /// Protects the terms of the `--protect` file, with `format_tokens` the placeholders and tags
+    /// of format strings and with `pii` the personal data. `None` if there is nothing to protect.
+    pub(crate) fn new(path: Option<&Path>, format_tokens: bool, pii: bool) -> Result<Option<Self>> {
+        let mut patterns = path.map(load).transpose()?.unwrap_or_default();
+
+        if format_tokens {
+            patterns.push(FORMAT_TOKENS.to_string());
+        }
+
+        if pii {
+            patterns.extend(pii::patterns());
+        }
+
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            pattern: Regex::new(&patterns.join("|"))?,
+            redacted: pii.then(Mutex::default),
+        }))
+    }
+
+    /// Masks the protected terms of the text, if it has any.
+    pub(crate) fn mask(&self, text: &str) -> Option<Masked> {
+        let mut terms = vec![];
+
+        let masked = self.pattern.replace_all(text, |captures: &Captures| {
+            if let Some(redacted) = &self.redacted {
+                match pii::kind(captures) {
+                    Some(kind) => redacted.lock().unwrap().add(kind, &captures[0]),
+                    // Numbers that only look like phone numbers or IBANs are sent.
+                    None if pii::KINDS
+                        .iter()
+                        .any(|(kind, _)| captures.name(kind).is_some()) =>
+                    {
+                        return captures[0].to_string();
+                    }
+                    None => {}
+                }
+            }
+
+            terms.push(captures[0].to_string());
+            placeholder(terms.len() - 1)
+        });
+
+        (!terms.is_empty()).then(|| Masked {
+            text: masked.into_owned(),
+            terms,
+        })
+    }
+
+    /// Whether nothing worth translating is left of the text once its protected terms are taken
+    /// out.
+    pub(crate) fn covers(&self, text: &str) -> bool {
+        self.pattern.is_match(text)
+            && !self
+                .pattern
+                .replace_all(text, "")
+                .chars()
+                .any(char::is_alphabetic)
+    }
+
+    /// The personal data masked so far, with `--redact-pii`.
+    pub(crate) fn redacted(&self) -> Option<MutexGuard<'_, Redacted>> {
+        Some(self.redacted.as_ref()?.lock().unwrap())
+    }
+
+    /// The text with its personal data replaced by its kind, like `[email]`, for what is sent
+    /// along with a value rather than translated, like its context.
+    pub(crate) fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.redacted.is_none() {
+            return Cow::Borrowed(text);
+        }
+
+        self.pattern
+            .replace_all(text, |captures: &Captures| match pii::kind(captures) {
+                Some(kind) => format!("[{}]", kind),
+                None => captures[0].to_string(),
+            })
+    }
+}
+
+impl Masked {
+    /// Puts the terms back into the translation of the masked text, which must have kept every
+    /// placeholder exactly once.
+    pub(crate) fn unmask(&self, translation: &str) -> Result<String> {
+        let placeholders = Regex::new(r"⟦(\d+)⟧").unwrap();
+        let mut seen = vec![false; self.terms.len()];
+        let mut invalid = None;
+
+        let unmasked =
+            placeholders.replace_all(translation, |captures: &Captures| {
+                match captures[1]
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&i| i < seen.len())
+                {
+                    Some(i) if !seen[i] => {
+                        seen[i] = true;
+                        self.terms[i].clone()
+                    }
+                    _ => {
+                        invalid.get_or_insert_with(|| captures[0].to_string());
+                        captures[0].to_string()
+                    }
+                }
+            });
+
+        if let Some(placeholder) = invalid {
+            bail!(
+                "The translation has an unexpected placeholder {}",
+                placeholder
+            );
+        }
+
+        if let Some(i) = seen.iter().position(|seen| !seen) {
+            bail!(
+                "The translation lost the placeholder {} of the protected term '{}'",
+                placeholder(i),
+                self.terms[i]
+            );
+        }
+
+        Ok(unmasked.into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn terms(name: &str, text: &str) -> Protected {
+        let path = env::temp_dir().join(format!("protect-{}-{}.txt", process::id(), name));
+        fs::write(&path, text).unwrap();
+        let protected = Protected::new(Some(&path), false, false).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        protected
+    }
+
+    #[test]
+    fn nothing_to_protect_is_none() {
+        assert!(Protected::new(None, false, false).unwrap().is_none());
+    }
+
+    #[test]
+    fn masks_and_unmasks_format_tokens() {
+        let protected = Protected::new(None, true, false).unwrap().unwrap();
+        let masked = protected
+            .mask("Hello {name}, you have %d <b>new</b> messages")
+            .unwrap();
+
+        assert_eq!(masked.text, "Hello ⟦0⟧, you have ⟦1⟧ ⟦2⟧new⟦3⟧ messages");
+        assert_eq!(masked.terms, ["{name}", "%d", "<b>", "</b>"]);
+        assert_eq!(
+            masked
+                .unmask("Hallo ⟦0⟧, du hast ⟦1⟧ ⟦2⟧neue⟦3⟧ Nachrichten")
+                .unwrap(),
+            "Hallo {name}, du hast %d <b>neue</b> Nachrichten"
+        );
+    }
+
+    #[test]
+    fn text_without_terms_is_not_masked() {
+        let protected = Protected::new(None, true, false).unwrap().unwrap();
+
+        assert!(protected.mask("Total").is_none());
+    }
+
+    #[test]
+    fn terms_are_matched_as_whole_words_longest_first() {
+        let protected = terms("words", "Acme\nAcme Cloud\n/iPhone \\d+/\n");
+        let masked = protected
+            .mask("Acme Cloud and Acmes on the iPhone 15")
+            .unwrap();
+
+        assert_eq!(masked.text, "⟦0⟧ and Acmes on the ⟦1⟧");
+        assert_eq!(masked.terms, ["Acme Cloud", "iPhone 15"]);
+    }
+
+    #[test]
+    fn unmask_rejects_lost_and_repeated_placeholders() {
+        let protected = Protected::new(None, true, false).unwrap().unwrap();
+        let masked = protected.mask("{0} of {1}").unwrap();
+
+        assert!(masked.unmask("⟦0⟧ von").is_err());
+        assert!(masked.unmask("⟦0⟧ von ⟦0⟧ ⟦1⟧").is_err());
+        assert!(masked.unmask("⟦0⟧ von ⟦2⟧").is_err());
+        assert_eq!(masked.unmask("⟦1⟧ von ⟦0⟧").unwrap(), "{1} von {0}");
+    }
+
+    #[test]
+    fn covers_text_made_of_terms_alone() {
+        let protected = Protected::new(None, true, false).unwrap().unwrap();
+
+        assert!(protected.covers("{0} - %s"));
+        assert!(!protected.covers("{0} items"));
+        assert!(!protected.covers("12"));
+    }
+}
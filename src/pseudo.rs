@@ -0,0 +1,106 @@
+//! Pseudo-translations for `--pseudo`, to find the strings an application doesn't localize and
+//! the layouts too tight for longer text before paying for real translations. Letters get
+//! accents, the text is padded by the expansion factor and enclosed in brackets, so that a
+//! missing bracket shows a truncation. Placeholders, protected terms and tags stay as they are.
+
+use regex::Regex;
+
+use crate::{
+    protect,
+    scheduler::{Job, RateLimit},
+    translator::{Completion, Translating, Translator},
+};
+
+/// What the translations are recorded as made by.
+pub(crate) const MODEL: &str = "pseudo";
+
+/// Nothing is sent, so nothing has to wait.
+pub(crate) const RATE_LIMIT: RateLimit = RateLimit {
+    requests: 1000,
+    window: std::time::Duration::from_secs(1),
+};
+
+fn accented(c: char) -> char {
+    match c {
+        'a' => 'á',
+        'c' => 'ç',
+        'e' => 'é',
+        'i' => 'í',
+        'n' => 'ñ',
+        'o' => 'ö',
+        's' => 'š',
+        'u' => 'ü',
+        'y' => 'ý',
+        'z' => 'ž',
+        'A' => 'Å',
+        'C' => 'Ç',
+        'E' => 'É',
+        'I' => 'Î',
+        'N' => 'Ñ',
+        'O' => 'Ö',
+        'S' => 'Š',
+        'U' => 'Ü',
+        'Y' => 'Ý',
+        'Z' => 'Ž',
+        c => c,
+    }
+}
+
+/// The pseudo-translation of a text, longer by `expansion` times its characters.
+pub(crate) fn pseudo(text: &str, expansion: f64) -> String {
+    let kept = Regex::new(&format!(r"⟦\d+⟧|{}", protect::FORMAT_TOKENS)).unwrap();
+    let mut translation = String::from("[");
+    let mut translated = 0;
+    let mut position = 0;
+
+    let mut accent = |translation: &mut String, part: &str| {
+        translated += part.chars().count();
+        translation.extend(part.chars().map(accented));
+    };
+
+    for token in kept.find_iter(text) {
+        accent(&mut translation, &text[position..token.start()]);
+        translation.push_str(token.as_str());
+        position = token.end();
+    }
+
+    accent(&mut translation, &text[position..]);
+
+    let padding = (translated as f64 * expansion).round() as usize;
+
+    if padding > 0 {
+        translation.push(' ');
+        translation.push_str(&"~".repeat(padding));
+    }
+
+    translation.push(']');
+    translation
+}
+
+/// Pseudo-translates the jobs instead of sending them anywhere.
+pub(crate) struct Pseudo {
+    expansion: f64,
+}
+
+impl Pseudo {
+    pub(crate) fn new(expansion: f64) -> Self {
+        Self { expansion }
+    }
+}
+
+impl Translator for Pseudo {
+    fn translate<'a>(&'a self, job: &'a Job) -> Translating<'a> {
+        let text = pseudo(&job.source, self.expansion);
+
+        Box::pin(async move {
+            Ok(Completion {
+                text,
+                tokens: 0,
+                input_tokens: 0,
+                model: MODEL.to_string(),
+                request_id: None,
+                confidence: None,
+            })
+        })
+    }
+}
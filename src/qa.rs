@@ -0,0 +1,148 @@
+//! Translating the translations back into the source language, for `--qa back-translate`, and
+//! flagging the cells whose back-translation strays too far from their source for a human to
+//! review.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use calamine::{DataType, Range};
+use color_eyre::{eyre::Context, Result};
+
+use crate::{
+    dictionary::{csv_field, normalize_whitespace},
+    workbook::cell_reference,
+};
+
+/// A translated cell.
+#[derive(Debug)]
+struct Cell {
+    sheet: String,
+    /// Where the cell is in its worksheet, like `B7`.
+    reference: String,
+    source: String,
+    translation: String,
+}
+
+/// A cell whose back-translation scored below the threshold.
+pub(crate) struct Flagged<'a> {
+    cell: &'a Cell,
+    back_translation: &'a str,
+    similarity: f64,
+}
+
+/// How much two texts have in common, from 0 to 1: the share of their words they have in
+/// common, in any order and regardless of case and punctuation.
+fn similarity(a: &str, b: &str) -> f64 {
+    let words = |text: &str| {
+        let mut words = BTreeMap::<String, usize>::new();
+
+        for word in normalize_whitespace(&text.to_lowercase())
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            *words.entry(word.to_string()).or_default() += 1;
+        }
+
+        words
+    };
+
+    let (a, b) = (words(a), words(b));
+    let total = a.values().sum::<usize>() + b.values().sum::<usize>();
+
+    if total == 0 {
+        return 1.;
+    }
+
+    let common = a
+        .iter()
+        .map(|(word, count)| b.get(word).map_or(0, |other| *count.min(other)))
+        .sum::<usize>();
+
+    2. * common as f64 / total as f64
+}
+
+/// The cells to check and the back-translations they got.
+#[derive(Debug, Default)]
+pub(crate) struct Review {
+    cells: Vec<Cell>,
+    back_translations: BTreeMap<(String, String), String>,
+}
+
+impl Review {
+    pub(crate) fn add(
+        &mut self,
+        sheet: &str,
+        range: &Range<DataType>,
+        cell: (u32, u16),
+        source: &str,
+        translation: &str,
+    ) {
+        self.cells.push(Cell {
+            sheet: sheet.to_string(),
+            reference: cell_reference(range, cell),
+            source: source.trim().to_string(),
+            translation: translation.trim().to_string(),
+        });
+    }
+
+    /// The distinct pairs of source and translation to translate back.
+    pub(crate) fn pending(&self) -> BTreeSet<(String, String)> {
+        self.cells
+            .iter()
+            .map(|cell| (cell.source.clone(), cell.translation.clone()))
+            .collect()
+    }
+
+    pub(crate) fn back_translated(
+        &mut self,
+        (source, translation): (String, String),
+        back_translation: &str,
+    ) {
+        self.back_translations
+            .insert((source, translation), back_translation.trim().to_string());
+    }
+
+    /// The cells whose back-translation is less similar to their source than `threshold`.
+    /// Those whose back-translation failed are left out.
+    pub(crate) fn flagged(&self, threshold: f64) -> Vec<Flagged<'_>> {
+        self.cells
+            .iter()
+            .filter_map(|cell| {
+                let key = (cell.source.clone(), cell.translation.clone());
+                let back_translation = self.back_translations.get(&key)?;
+                let similarity = similarity(&cell.source, back_translation);
+
+                (similarity < threshold).then_some(Flagged {
+                    cell,
+                    back_translation,
+                    similarity,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Writes the flagged cells as CSV, one line per cell.
+pub(crate) fn write(path: &Path, flagged: &[Flagged]) -> Result<()> {
+    let mut text = String::from("sheet,cell,source,translation,back_translation,similarity\n");
+
+    for flagged in flagged {
+        let similarity = format!("{:.2}", flagged.similarity);
+        let fields = [
+            flagged.cell.sheet.as_str(),
+            &flagged.cell.reference,
+            &flagged.cell.source,
+            &flagged.cell.translation,
+            flagged.back_translation,
+            &similarity,
+        ];
+
+        text.push_str(&fields.map(csv_field).join(","));
+        text.push('\n');
+    }
+
+    fs::write(path, text).wrap_err_with(|| format!("Failed to write {}", path.display()))
+}
@@ -0,0 +1,161 @@
+//! The jobs of `serve` in SQLite, so that a restarted server takes up the ones it hadn't
+//! finished where their journals left off.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use chrono::Utc;
+use color_eyre::{eyre::Context, Result};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Status {
+    /// Waiting for one of the jobs before it to finish.
+    Queued,
+    Running,
+    Finished,
+    Failed,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Finished => "finished",
+            Self::Failed => "failed",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "queued" => Self::Queued,
+            "running" => Self::Running,
+            "finished" => Self::Finished,
+            _ => Self::Failed,
+        }
+    }
+}
+
+/// What a job translates, with the files resolved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Options {
+    pub(crate) source: PathBuf,
+    pub(crate) dictionary: PathBuf,
+    pub(crate) destination: PathBuf,
+    pub(crate) target_lang: Option<String>,
+    pub(crate) model: Option<String>,
+}
+
+/// A job as it was last recorded.
+#[derive(Debug)]
+pub(crate) struct Record {
+    pub(crate) id: String,
+    pub(crate) status: Status,
+    pub(crate) options: Options,
+    pub(crate) error: Option<String>,
+    /// In USD, once the job has ended.
+    pub(crate) cost: f64,
+}
+
+#[derive(Debug)]
+pub(crate) struct Queue {
+    connection: Mutex<Connection>,
+}
+
+impl Queue {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)
+            .wrap_err_with(|| format!("Failed to open the job queue {}", path.display()))?;
+
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS jobs (
+                id TEXT PRIMARY KEY,
+                status TEXT NOT NULL,
+                options TEXT NOT NULL,
+                error TEXT,
+                cost REAL NOT NULL DEFAULT 0,
+                created_at INTEGER NOT NULL
+            )",
+        )?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub(crate) fn add(&self, id: &str, options: &Options) -> Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO jobs (id, status, options, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                id,
+                Status::Queued.as_str(),
+                serde_json::to_string(options)?,
+                Utc::now().timestamp()
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub(crate) fn set_status(&self, id: &str, status: Status) -> Result<()> {
+        self.connection.lock().unwrap().execute(
+            "UPDATE jobs SET status = ?2 WHERE id = ?1",
+            params![id, status.as_str()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Records how a job ended, with what it cost.
+    pub(crate) fn end(&self, id: &str, error: Option<&str>, cost: f64) -> Result<()> {
+        let status = match error {
+            Some(_) => Status::Failed,
+            None => Status::Finished,
+        };
+
+        self.connection.lock().unwrap().execute(
+            "UPDATE jobs SET status = ?2, error = ?3, cost = ?4 WHERE id = ?1",
+            params![id, status.as_str(), error, cost],
+        )?;
+
+        Ok(())
+    }
+
+    /// Every job, the oldest first.
+    pub(crate) fn all(&self) -> Result<Vec<Record>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT id, status, options, error, cost FROM jobs ORDER BY created_at")?;
+
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })?;
+
+        let mut records = vec![];
+
+        for row in rows {
+            let (id, status, options, error, cost): (String, String, String, _, _) = row?;
+
+            records.push(Record {
+                id,
+                status: Status::parse(&status),
+                options: serde_json::from_str(&options)?,
+                error,
+                cost,
+            });
+        }
+
+        Ok(records)
+    }
+}
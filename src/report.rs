@@ -0,0 +1,155 @@
+//! A summary of the run for `--report`, for pipelines that need more than the exit code.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+    time::Duration,
+};
+
+use color_eyre::{eyre::Context, Result};
+use serde::Serialize;
+
+use crate::{budget::Spent, translator::KeyUsage};
+
+/// How many cells were translated in which way.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct Cells {
+    /// Written as they were, because they were left out or not worth translating.
+    pub(crate) copied: usize,
+    pub(crate) dictionary: usize,
+    /// From the `--tmx` translation memories.
+    pub(crate) memory: usize,
+    pub(crate) cache: usize,
+    pub(crate) fuzzy: usize,
+    pub(crate) xliff: usize,
+    /// From the translation of the `--previous` source.
+    pub(crate) previous: usize,
+    /// Kept from the workbook of `--merge-into`.
+    pub(crate) merged: usize,
+    /// From the strings file of `apply --translations`.
+    pub(crate) reviewed: usize,
+    /// Translated through the API for another cell of the run with the same value.
+    pub(crate) session: usize,
+    pub(crate) api: usize,
+    /// Formulas with translated string literals.
+    pub(crate) formulas: usize,
+    pub(crate) failed: usize,
+    /// Flagged by `--qa` for a human to review.
+    pub(crate) flagged: usize,
+    /// Found in none of the dictionaries, memories and caches by `--offline`.
+    pub(crate) untranslated: usize,
+}
+
+impl Cells {
+    /// Counts a cell by the method its translation is recorded with in the history.
+    pub(crate) fn count(&mut self, method: &str) {
+        match method {
+            "dictionary" => self.dictionary += 1,
+            "tmx" => self.memory += 1,
+            "cache" => self.cache += 1,
+            "fuzzy" => self.fuzzy += 1,
+            "xliff" => self.xliff += 1,
+            "previous" => self.previous += 1,
+            "merged" => self.merged += 1,
+            "reviewed" => self.reviewed += 1,
+            "verbatim" => self.copied += 1,
+            "session" => self.session += 1,
+            "api" => self.api += 1,
+            _ => {}
+        }
+    }
+
+    /// The cells that were to be translated, however they were or weren't.
+    pub(crate) fn translatable(&self) -> usize {
+        self.dictionary
+            + self.memory
+            + self.cache
+            + self.fuzzy
+            + self.xliff
+            + self.previous
+            + self.merged
+            + self.reviewed
+            + self.session
+            + self.api
+            + self.failed
+            + self.untranslated
+    }
+
+    /// The run so far in a line for the progress bar.
+    pub(crate) fn progress(
+        &self,
+        spent: Spent,
+        priced: bool,
+        rpm: usize,
+        in_flight: usize,
+    ) -> String {
+        let tokens = spent.input_tokens + spent.output_tokens;
+        let mut message = format!(
+            "{} in flight · {} dictionary, {} cache, {} API · {} tokens",
+            in_flight,
+            self.dictionary + self.memory,
+            self.cache + self.fuzzy + self.session,
+            self.api,
+            tokens
+        );
+
+        if priced {
+            message.push_str(&format!(" · ${:.2}", spent.cost));
+        }
+
+        message.push_str(&format!(" · {} RPM", rpm));
+        message
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Report<'a> {
+    pub(crate) cells: &'a Cells,
+    pub(crate) requests: usize,
+    pub(crate) input_tokens: usize,
+    pub(crate) output_tokens: usize,
+    /// In USD, unless the price of the model is unknown.
+    pub(crate) estimated_cost: Option<f64>,
+    pub(crate) wall_time_secs: f64,
+    /// Whether the run ended early, by Ctrl-C, the stop file or the budget.
+    pub(crate) stopped: bool,
+    /// The values `--offline` had no translation for.
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) untranslated: &'a BTreeSet<String>,
+    /// How many distinct emails, phone numbers, IBANs and names `--redact-pii` kept from the API.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) redacted: BTreeMap<&'static str, usize>,
+    /// The share of every API key, when `--api-key` is given several times.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) keys: Vec<KeyUsage>,
+}
+
+impl<'a> Report<'a> {
+    pub(crate) fn new(
+        cells: &'a Cells,
+        spent: Spent,
+        priced: bool,
+        elapsed: Duration,
+        stopped: bool,
+        untranslated: &'a BTreeSet<String>,
+    ) -> Self {
+        Self {
+            cells,
+            requests: spent.requests,
+            input_tokens: spent.input_tokens,
+            output_tokens: spent.output_tokens,
+            estimated_cost: priced.then_some(spent.cost),
+            wall_time_secs: elapsed.as_secs_f64(),
+            stopped,
+            untranslated,
+            redacted: BTreeMap::new(),
+            keys: vec![],
+        }
+    }
+
+    pub(crate) fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .wrap_err_with(|| format!("Failed to write the report {}", path.display()))
+    }
+}
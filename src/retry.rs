@@ -0,0 +1,154 @@
+//! Retrying API requests that failed for reasons likely to go away by themselves, such as rate
+//! limits, overloaded servers and dropped connections.
+
+use std::{
+    borrow::Cow,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use color_eyre::{eyre::ContextCompat, Result};
+use reqwest::{header::RETRY_AFTER, Request, RequestBuilder, Response, StatusCode};
+use tokio::time;
+use tracing::{debug, trace, Level};
+
+use crate::{fixtures::Fixtures, logging};
+
+/// The longest to back off for between two attempts, unless the server asks for more.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct Policy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    /// Where the responses are recorded to or replayed from, with `--record` and `--replay`.
+    pub fixtures: Option<Arc<Fixtures>>,
+}
+
+fn retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// The delay asked for by the `Retry-After` header, given either in seconds or as a date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+/// Doubles with every attempt, half of it randomized so that requests that failed together
+/// don't all come back at the same time.
+fn backoff(policy: &Policy, attempt: u32) -> Duration {
+    let delay = policy
+        .initial_backoff
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_BACKOFF);
+
+    // The standard library has no random numbers, but it seeds its hashers randomly.
+    let random = RandomState::new().build_hasher().finish();
+    let jitter = (random % 1000) as f64 / 1000.;
+
+    delay / 2 + delay.mul_f64(jitter / 2.)
+}
+
+/// Logs the request about to be sent, with its headers and body at the trace level.
+fn log_request(request: &Request, attempt: u32) {
+    debug!(attempt, method = %request.method(), url = %request.url(), "Sending request");
+
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map_or(Cow::Borrowed("[stream]"), String::from_utf8_lossy);
+
+    trace!(headers = %logging::redacted(request.headers()), %body, "Request body");
+}
+
+/// Logs the response, and at the trace level its headers and body, which is read for that and
+/// put back for the caller.
+async fn log_response(response: Response) -> reqwest::Result<Response> {
+    debug!(status = %response.status(), "Received response");
+
+    if !tracing::enabled!(Level::TRACE) {
+        return Ok(response);
+    }
+
+    let mut builder = http::Response::builder()
+        .status(response.status())
+        .version(response.version());
+
+    if let Some(headers) = builder.headers_mut() {
+        *headers = response.headers().clone();
+    }
+
+    let headers = logging::redacted(response.headers());
+    let body = response.bytes().await?;
+    trace!(%headers, body = %String::from_utf8_lossy(&body), "Response body");
+
+    Ok(builder.body(body).unwrap().into())
+}
+
+/// Sends the request until it gets a response that isn't worth retrying or runs out of attempts,
+/// in which case the last response or error is returned. With fixtures, the response is the
+/// recorded one instead, or the one sent for is recorded.
+pub async fn send(request: RequestBuilder, policy: &Policy) -> Result<Response> {
+    let Some(fixtures) = &policy.fixtures else {
+        return transmit(request, policy).await;
+    };
+
+    let built = request
+        .try_clone()
+        .wrap_err("Request can't be retried")?
+        .build()?;
+
+    if fixtures.replaying() {
+        debug!(method = %built.method(), url = %built.url(), "Replaying request");
+        return fixtures.respond(&built);
+    }
+
+    let response = transmit(request, policy).await?;
+    fixtures.save(&built, response).await
+}
+
+async fn transmit(request: RequestBuilder, policy: &Policy) -> Result<Response> {
+    let mut attempt = 1;
+
+    loop {
+        let (client, attempted) = request
+            .try_clone()
+            .wrap_err("Request can't be retried")?
+            .build_split();
+        let attempted = attempted?;
+
+        log_request(&attempted, attempt);
+
+        let result = match client.execute(attempted).await {
+            Ok(response) => log_response(response).await,
+            Err(e) => Err(e),
+        };
+
+        let delay = match &result {
+            Ok(response) if retryable(response.status()) => {
+                retry_after(response).unwrap_or_else(|| backoff(policy, attempt))
+            }
+            Err(e) if e.is_connect() || e.is_timeout() => backoff(policy, attempt),
+            _ => return Ok(result?),
+        };
+
+        if attempt >= policy.max_attempts {
+            return Ok(result?);
+        }
+
+        debug!(attempt, ?delay, "Retrying");
+
+        time::sleep(delay).await;
+        attempt += 1;
+    }
+}
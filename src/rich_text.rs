@@ -0,0 +1,174 @@
+//! Cells made of runs of text in different fonts, for `--rich-text`. The runs are sent wrapped
+//! in numbered tags like `<r1>Bold</r1><r2> and plain</r2>`, which the translation brings back
+//! around the words they belong to, and the runs are rebuilt from them.
+
+use regex::Regex;
+use xlsxwriter::Format;
+
+/// Asks the model to keep the tags, for prompts of values with runs.
+pub(crate) const INSTRUCTION: &str =
+    "Keep the tags like <r1> and </r1> around the words they enclose.\n\n";
+
+/// A run of text, in the font of the cell unless it has one of its own.
+pub(crate) struct Run {
+    pub(crate) text: String,
+    pub(crate) format: Option<Format>,
+}
+
+fn tags() -> Regex {
+    Regex::new(r"</?r(\d+)>").unwrap()
+}
+
+pub(crate) fn has_tags(text: &str) -> bool {
+    text.contains("<r1>")
+}
+
+/// The text of the runs, each wrapped in its tags.
+pub(crate) fn marked(runs: &[Run]) -> String {
+    mark(runs.iter().map(|run| run.text.as_str()))
+}
+
+/// The texts wrapped in the tags of the runs they are the texts of, in their order.
+pub(crate) fn mark<'a>(texts: impl IntoIterator<Item = &'a str>) -> String {
+    texts
+        .into_iter()
+        .enumerate()
+        .map(|(i, text)| format!("<r{0}>{1}</r{0}>", i + 1, text))
+        .collect()
+}
+
+/// The text without the tags, for translations whose runs can't be rebuilt.
+pub(crate) fn strip_tags(text: &str) -> String {
+    tags().replace_all(text, "").into_owned()
+}
+
+/// Splits the translation of a marked text into fragments in the formats of the runs whose tags
+/// they are between, text outside of any tags going with the run before. `None` if the
+/// translation has no tags or tags of runs that don't exist.
+pub(crate) fn fragments<'a>(
+    runs: &'a [Run],
+    translation: &str,
+) -> Option<Vec<(String, Option<&'a Format>)>> {
+    let pieces = split(runs.len(), translation)?;
+
+    Some(
+        pieces
+            .into_iter()
+            .map(|(text, run)| (text, runs[run].format.as_ref()))
+            .collect(),
+    )
+}
+
+/// Splits a translation like [`fragments`], into the fragments with the indexes of their runs
+/// out of `runs`.
+pub(crate) fn split(runs: usize, translation: &str) -> Option<Vec<(String, usize)>> {
+    let mut pieces: Vec<(String, usize)> = vec![];
+    let mut run = 0;
+    let mut position = 0;
+    let mut tagged = false;
+
+    let mut push = |text: &str, run: usize| {
+        if text.is_empty() {
+            return;
+        }
+
+        match pieces.last_mut() {
+            Some((last, last_run)) if *last_run == run => last.push_str(text),
+            _ => pieces.push((text.to_string(), run)),
+        }
+    };
+
+    for captures in tags().captures_iter(translation) {
+        let tag = captures.get(0).unwrap();
+        let index = captures[1].parse::<usize>().ok()?.checked_sub(1)?;
+
+        if index >= runs {
+            return None;
+        }
+
+        push(&translation[position..tag.start()], run);
+        run = index;
+        position = tag.end();
+        tagged = true;
+    }
+
+    push(&translation[position..], run);
+
+    tagged.then_some(pieces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(text: &str) -> Run {
+        Run {
+            text: text.to_string(),
+            format: None,
+        }
+    }
+
+    fn pieces(texts: &[(&str, usize)]) -> Option<Vec<(String, usize)>> {
+        Some(
+            texts
+                .iter()
+                .map(|&(text, run)| (text.to_string(), run))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn marks_the_runs() {
+        let runs = [run("Bold"), run(" and plain")];
+
+        assert_eq!(marked(&runs), "<r1>Bold</r1><r2> and plain</r2>");
+        assert!(has_tags(&marked(&runs)));
+        assert!(!has_tags("plain"));
+    }
+
+    #[test]
+    fn strips_the_tags() {
+        assert_eq!(
+            strip_tags("<r1>Fett</r1><r2> und normal</r2>"),
+            "Fett und normal"
+        );
+        assert_eq!(strip_tags("no tags"), "no tags");
+    }
+
+    #[test]
+    fn splits_translations_at_the_tags() {
+        assert_eq!(
+            split(2, "<r2>Normal</r2><r1>fett</r1>"),
+            pieces(&[("Normal", 1), ("fett", 0)])
+        );
+    }
+
+    #[test]
+    fn text_outside_the_tags_goes_with_the_run_before() {
+        assert_eq!(
+            split(2, "Vor <r1>fett</r1> nach<r2>!</r2>"),
+            pieces(&[("Vor fett nach", 0), ("!", 1)])
+        );
+        assert_eq!(
+            split(2, "<r2>Normal</r2> und <r1>fett</r1>"),
+            pieces(&[("Normal und ", 1), ("fett", 0)])
+        );
+    }
+
+    #[test]
+    fn rejects_translations_without_tags_or_with_unknown_runs() {
+        assert_eq!(split(2, "No tags at all"), None);
+        assert_eq!(split(2, "<r3>Extra</r3>"), None);
+        assert_eq!(split(2, "<r0>Zero</r0>"), None);
+    }
+
+    #[test]
+    fn fragments_take_the_formats_of_their_runs() {
+        let runs = [run("Bold"), run(" plain")];
+        let translated = fragments(&runs, "<r1>Fett</r1><r2> normal</r2>").unwrap();
+        let texts = translated.iter().map(|(text, _)| text.as_str());
+
+        assert_eq!(texts.collect::<Vec<_>>(), ["Fett", " normal"]);
+        assert!(fragments(&runs, "Fett normal").is_none());
+    }
+}
@@ -0,0 +1,64 @@
+//! Ranges of worksheet rows, given by their 1-based numbers like `3:200` or `3:`.
+
+use std::{fmt, str::FromStr};
+
+use color_eyre::{
+    eyre::{bail, eyre},
+    Report, Result,
+};
+use serde::{Serialize, Serializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowRange {
+    pub first: u32,
+    /// Open ranges go on to the last row.
+    pub last: Option<u32>,
+}
+
+fn parse_row(s: &str) -> Result<u32> {
+    match s.trim().parse() {
+        Ok(row) if row > 0 => Ok(row),
+        _ => Err(eyre!("Invalid row number '{}'", s)),
+    }
+}
+
+impl RowRange {
+    /// Whether the zero-based row is part of the range.
+    pub fn contains(self, row: u32) -> bool {
+        let row = row + 1;
+        (self.first..=self.last.unwrap_or(u32::MAX)).contains(&row)
+    }
+}
+
+impl FromStr for RowRange {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (first, last) = match s.split_once(':') {
+            Some((first, "")) => (parse_row(first)?, None),
+            Some((first, last)) => (parse_row(first)?, Some(parse_row(last)?)),
+            None => (parse_row(s)?, Some(parse_row(s)?)),
+        };
+
+        if last.is_some_and(|last| last < first) {
+            bail!("Row range '{}' ends before it starts", s);
+        }
+
+        Ok(Self { first, last })
+    }
+}
+
+impl fmt::Display for RowRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.last {
+            Some(last) => write!(f, "{}:{}", self.first, last),
+            None => write!(f, "{}:", self.first),
+        }
+    }
+}
+
+impl Serialize for RowRange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
@@ -0,0 +1,173 @@
+//! The translation jobs and how fast they may be sent.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::Duration,
+};
+
+use color_eyre::Result;
+use tiktoken_rs::CoreBPE;
+use tokio::sync::mpsc;
+
+use crate::{
+    limiter::Limiter,
+    prompt::Segment,
+    translator::{Completion, OpenAi},
+    warnings::DedupLogger,
+};
+
+/// A unique cell value waiting to be sent to the API, together with the prompt built for it.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub key: String,
+    pub source: String,
+    pub prompt: String,
+    pub temperature: f32,
+    pub model: String,
+    pub estimated_tokens: usize,
+    pub language: String,
+    /// The adjacent values of the cell, for the services that take them separately from the
+    /// prompt.
+    pub context: Option<String>,
+    /// The first cell holding the value, used to point at it in error messages.
+    pub cell: (u32, u16),
+    /// The pieces a value too long for a single request is translated in, none for the others.
+    pub chunks: Vec<Chunk>,
+    /// The most characters the translation may have, from `--max-length`.
+    pub max_length: Option<usize>,
+}
+
+/// A piece of a long value, with the prompt it is translated with.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub source: String,
+    pub prompt: String,
+    /// The whitespace after the piece in the value, put back after its translation.
+    pub separator: String,
+    pub estimated_tokens: usize,
+    /// Found in the dictionary or the cache, for the piece not to be sent.
+    pub translation: Option<String>,
+}
+
+/// The key and source value of a job, together with the outcome of its request.
+pub type Translation = (String, String, Result<Completion>);
+
+pub(crate) const RPM: usize = 60;
+
+/// At most `requests` requests every `window`.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub requests: usize,
+    pub window: Duration,
+}
+
+/// The tokens a request for the prompt is expected to use, the prompt itself and a reply of
+/// about the same length, if there is a tokenizer to count them with.
+pub(crate) fn estimate_tokens(prompt: &str, bpe: Option<&CoreBPE>) -> usize {
+    bpe.map_or(0, |bpe| 2 * bpe.encode_with_special_tokens(prompt).len())
+}
+
+/// Receives the next translation, building the jobs of `feeding` in the meantime as far as there
+/// is room for them. The jobs are sent to no one anymore once `feed` runs out of them.
+pub(crate) async fn receive<F>(
+    rx: &mut mpsc::Receiver<Translation>,
+    feeding: &mut Option<(mpsc::Sender<Job>, F)>,
+) -> Result<Option<Translation>>
+where
+    F: FnMut() -> Option<Result<Job>>,
+{
+    loop {
+        let Some((jobs, feed)) = feeding else {
+            return Ok(rx.recv().await);
+        };
+
+        let fed = tokio::select! {
+            translation = rx.recv() => return Ok(translation),
+            permit = jobs.reserve() => {
+                match permit.ok().and_then(|permit| Some((permit, feed()?))) {
+                    Some((permit, job)) => {
+                        permit.send(job?);
+                        true
+                    }
+                    None => false,
+                }
+            }
+        };
+
+        if !fed {
+            *feeding = None;
+        }
+    }
+}
+
+/// The job put back in `queued` first, then the next one sent.
+pub(crate) async fn next_job(
+    queued: &mut VecDeque<Job>,
+    jobs: &mut mpsc::Receiver<Job>,
+) -> Option<Job> {
+    match queued.pop_front() {
+        Some(job) => Some(job),
+        None => jobs.recv().await,
+    }
+}
+
+pub(crate) const CLASSIFY_BATCH_SIZE: usize = 20;
+
+/// Sends the values to the API in batches and asks for a segment class for each of them.
+/// Values the model fails to classify are left out of the returned map.
+pub(crate) async fn classify(
+    values: &[(String, String)],
+    limiter: &Limiter,
+    bpe: Option<&CoreBPE>,
+    model: &str,
+    openai: &OpenAi,
+    log: &DedupLogger,
+) -> BTreeMap<String, Segment> {
+    let mut segments = BTreeMap::new();
+
+    for batch in values.chunks(CLASSIFY_BATCH_SIZE) {
+        let mut prompt = String::from(
+            "Classify each string as: technical | prose | ui | number | code | proper_noun\n\
+             Answer with one line per string in the form `<number>: <class>`.\n\n",
+        );
+
+        for (i, (_, value)) in batch.iter().enumerate() {
+            prompt.push_str(&format!("{}. {:?}\n", i + 1, value));
+        }
+
+        prompt.push_str("\nClassifications:\n");
+
+        limiter.acquire(estimate_tokens(&prompt, bpe)).await;
+
+        let text = match openai.complete(prompt, 0., model).await {
+            Ok(completion) => completion.text,
+            Err(e) => {
+                log.warn(None, format_args!("Classification failed: {:#}", e));
+                continue;
+            }
+        };
+
+        for line in text.lines() {
+            let Some((i, class)) = line.split_once(':') else {
+                continue;
+            };
+
+            let Ok(i) = i.trim().trim_end_matches('.').parse::<usize>() else {
+                continue;
+            };
+
+            let Some((key, _)) = i.checked_sub(1).and_then(|i| batch.get(i)) else {
+                continue;
+            };
+
+            match class.parse() {
+                Ok(segment) => {
+                    segments.insert(key.clone(), segment);
+                }
+                Err(e) => log.warn(None, format_args!("{:#}", e)),
+            }
+        }
+    }
+
+    segments
+}
@@ -0,0 +1,219 @@
+//! Per-column translation settings, loaded from a TOML schema file such as:
+//!
+//! ```toml
+//! version = 1
+//!
+//! [columns.B]
+//! translate = true
+//! target_language = "French"
+//! model = "gpt-4o"
+//! max_length = 30
+//! ```
+//!
+//! Columns that aren't mentioned, and settings that are left out, follow the global defaults.
+
+use std::{collections::BTreeMap, fmt, fs, path::Path, str::FromStr};
+
+use color_eyre::{
+    eyre::{bail, eyre, Context, ContextCompat},
+    Report, Result,
+};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{columns::parse_letters, prompt::language_name};
+
+/// The schema format version this build understands.
+pub const VERSION: u32 = 1;
+
+static DEFAULT: Column = Column {
+    translate: None,
+    target_language: None,
+    model: None,
+    max_length: None,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schema {
+    version: u32,
+    #[serde(default)]
+    columns: BTreeMap<String, Column>,
+}
+
+/// What cells get translated into, and by which model.
+#[derive(Debug, Clone, Copy)]
+pub struct Target<'a> {
+    pub language: &'a str,
+    pub model: &'a str,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Column {
+    translate: Option<bool>,
+    target_language: Option<String>,
+    model: Option<String>,
+    /// The most characters a translation may have, asked for again shorter if it has more.
+    max_length: Option<usize>,
+}
+
+impl Schema {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+
+        let mut schema: Self =
+            toml::from_str(&text).wrap_err_with(|| format!("Invalid schema {}", path.display()))?;
+
+        if schema.version != VERSION {
+            bail!(
+                "Unsupported schema version {} in {} (expected {})",
+                schema.version,
+                path.display(),
+                VERSION
+            );
+        }
+
+        schema.columns = schema
+            .columns
+            .into_iter()
+            .map(|(letter, column)| {
+                if letter.is_empty() || !letter.chars().all(|c| c.is_ascii_alphabetic()) {
+                    bail!("Invalid column '{}' in {}", letter, path.display());
+                }
+
+                Ok((letter.to_ascii_uppercase(), column))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(schema)
+    }
+
+    /// Translates the columns into the `--column-lang` languages, over the ones of the file.
+    pub fn set_languages(&mut self, languages: &[ColumnSetting<String>]) {
+        for ColumnSetting { letter, value } in languages {
+            self.columns
+                .entry(letter.clone())
+                .or_default()
+                .target_language = Some(language_name(value).to_string());
+        }
+    }
+
+    /// Limits the translations of the columns to the `--max-length` lengths, over the ones of the
+    /// file.
+    pub fn set_max_lengths(&mut self, lengths: &[ColumnSetting<usize>]) {
+        for ColumnSetting { letter, value } in lengths {
+            self.columns.entry(letter.clone()).or_default().max_length = Some(*value);
+        }
+    }
+
+    /// The settings of the column with the given index, defaulting to the global ones.
+    pub fn column(&self, column: u16) -> &Column {
+        self.columns
+            .get(&crate::workbook::column_letter(column))
+            .unwrap_or(&DEFAULT)
+    }
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            columns: BTreeMap::new(),
+        }
+    }
+}
+
+impl Column {
+    pub fn translates(&self) -> bool {
+        self.translate.unwrap_or(true)
+    }
+
+    pub fn max_length(&self) -> Option<usize> {
+        self.max_length
+    }
+
+    /// The target of the column, falling back to the global one for what it doesn't set.
+    pub fn target<'a>(&'a self, global: Target<'a>) -> Target<'a> {
+        Target {
+            language: self.target_language.as_deref().unwrap_or(global.language),
+            model: self.model.as_deref().unwrap_or(global.model),
+        }
+    }
+
+    /// Whether the column translates into something other than the global target language, in
+    /// which case the dictionary doesn't apply to it.
+    pub fn overrides_target_language(&self, global: Target) -> bool {
+        !self
+            .target(global)
+            .language
+            .eq_ignore_ascii_case(global.language)
+    }
+
+    /// Values of columns with their own target language or model are translated separately
+    /// from the same values elsewhere, so they are grouped under a key of their own.
+    pub fn group(&self, key: &str, global: Target) -> String {
+        if self.target_language.is_none() && self.model.is_none() {
+            key.to_string()
+        } else {
+            let target = self.target(global);
+            format!("{}\u{1f}{}\u{1f}{}", target.language, target.model, key)
+        }
+    }
+}
+
+/// The settings for a column when there may be no schema at all.
+pub fn column(schema: Option<&Schema>, column: u16) -> &Column {
+    schema.map_or(&DEFAULT, |schema| schema.column(column))
+}
+
+/// A setting of a single column given on the command line, like `C=ro` to `--column-lang` or
+/// `B=30` to `--max-length`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnSetting<T> {
+    letter: String,
+    value: T,
+}
+
+impl<T> FromStr for ColumnSetting<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (letter, value) = s
+            .split_once('=')
+            .wrap_err_with(|| format!("'{}' is not a COLUMN=VALUE pair", s))?;
+
+        let letter = letter.trim().to_ascii_uppercase();
+        let value = value.trim();
+
+        if parse_letters(&letter).is_none() {
+            bail!("Invalid column '{}'", letter);
+        }
+
+        if value.is_empty() {
+            bail!("No value for column {}", letter);
+        }
+
+        let value = value
+            .parse()
+            .map_err(|e| eyre!("Invalid value for column {}: {}", letter, e))?;
+
+        Ok(Self { letter, value })
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for ColumnSetting<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.letter, self.value)
+    }
+}
+
+impl<T: fmt::Display> Serialize for ColumnSetting<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
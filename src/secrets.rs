@@ -0,0 +1,107 @@
+//! API keys kept in the keyring of the OS by `auth login`, so that they don't have to be given on
+//! the command line, where they end up in the shell history and the process list.
+
+use std::{
+    env,
+    io::{self, IsTerminal},
+};
+
+use clap::ValueEnum;
+use color_eyre::{eyre::Context, Result};
+use console::Term;
+use keyring::Entry;
+use serde::Serialize;
+
+/// The keyring service the keys are kept under, each by the name of its environment variable.
+const SERVICE: &str = env!("CARGO_PKG_NAME");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Secret {
+    /// The OpenAI API key, of `--api-key`
+    #[value(name = "openai")]
+    OpenAi,
+    /// The DeepL authentication key, of `--deepl-auth-key`
+    Deepl,
+    /// The Anthropic API key, of `--anthropic-api-key`
+    Anthropic,
+    /// The Google Cloud API key, of `--google-api-key`
+    Google,
+}
+
+impl Secret {
+    fn variable(self) -> &'static str {
+        match self {
+            Self::OpenAi => "OPENAI_API_KEY",
+            Self::Deepl => "DEEPL_AUTH_KEY",
+            Self::Anthropic => "ANTHROPIC_API_KEY",
+            Self::Google => "GOOGLE_API_KEY",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::OpenAi => "OpenAI API key",
+            Self::Deepl => "DeepL authentication key",
+            Self::Anthropic => "Anthropic API key",
+            Self::Google => "Google Cloud API key",
+        }
+    }
+
+    fn entry(self) -> Result<Entry> {
+        Entry::new(SERVICE, self.variable()).wrap_err("Failed to open the keyring")
+    }
+}
+
+/// Sets the environment variable of every key in the keyring that isn't set already. Like
+/// `load_env_file` this runs before the arguments are parsed, so that a key given as a flag or
+/// in the environment is used instead of the stored one. Without a keyring nothing is set.
+pub fn load() {
+    for &secret in Secret::value_variants() {
+        if env::var_os(secret.variable()).is_some() {
+            continue;
+        }
+
+        if let Ok(key) = secret.entry().and_then(|entry| Ok(entry.get_password()?)) {
+            env::set_var(secret.variable(), key);
+        }
+    }
+}
+
+/// Stores the key read from the terminal without echoing it, or else from a line of stdin.
+pub(crate) fn login(secret: Secret) -> Result<()> {
+    let key = if io::stdin().is_terminal() {
+        eprint!("{}: ", secret.name());
+        Term::stderr().read_secure_line()?
+    } else {
+        let mut line = String::new();
+        io::stdin().read_line(&mut line)?;
+        line
+    };
+
+    let key = key.trim();
+
+    if key.is_empty() {
+        eprintln!("No key given, nothing stored");
+        return Ok(());
+    }
+
+    secret
+        .entry()?
+        .set_password(key)
+        .wrap_err("Failed to store the key in the keyring")?;
+
+    eprintln!("Stored the {} in the keyring", secret.name());
+
+    Ok(())
+}
+
+pub(crate) fn logout(secret: Secret) -> Result<()> {
+    match secret.entry()?.delete_password() {
+        Ok(()) => eprintln!("Removed the {} from the keyring", secret.name()),
+        Err(keyring::Error::NoEntry) => eprintln!("No {} in the keyring", secret.name()),
+        Err(e) => return Err(e).wrap_err("Failed to remove the key from the keyring"),
+    }
+
+    Ok(())
+}
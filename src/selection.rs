@@ -0,0 +1,166 @@
+//! Rectangular cell ranges in A1 notation, such as `B2:F50`, `$B$2:$F$50` or `Sheet2!C1:C100`.
+
+use std::{fmt, str::FromStr};
+
+use color_eyre::{
+    eyre::{bail, eyre},
+    Report, Result,
+};
+use serde::{Serialize, Serializer};
+
+use crate::workbook::column_letter;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    /// Selections without a sheet apply to every sheet.
+    pub sheet: Option<String>,
+    pub first: (u32, u16),
+    pub last: (u32, u16),
+}
+
+/// Parses a cell reference like `B2` or `$B$2` into zero-based row and column indices.
+pub(crate) fn parse_cell(s: &str) -> Result<(u32, u16)> {
+    let s = s.trim();
+    let rest = s.strip_prefix('$').unwrap_or(s);
+    let letters = rest.bytes().take_while(u8::is_ascii_alphabetic).count();
+    let (column, row) = rest.split_at(letters);
+    let row = row.strip_prefix('$').unwrap_or(row);
+
+    if column.is_empty() || column.len() > 3 || row.is_empty() {
+        bail!("Invalid cell reference '{}'", s);
+    }
+
+    let column = column.bytes().fold(0u32, |n, b| {
+        n * 26 + u32::from(b.to_ascii_uppercase() - b'A' + 1)
+    });
+
+    let row: u32 = row
+        .parse()
+        .map_err(|_| eyre!("Invalid cell reference '{}'", s))?;
+
+    if row == 0 || column > u32::from(u16::MAX) {
+        bail!("Invalid cell reference '{}'", s);
+    }
+
+    Ok((row - 1, (column - 1) as u16))
+}
+
+impl FromStr for Selection {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (sheet, range) = match s.rsplit_once('!') {
+            Some((sheet, range)) => {
+                let sheet = sheet
+                    .strip_prefix('\'')
+                    .and_then(|s| s.strip_suffix('\''))
+                    .map_or_else(|| sheet.to_string(), |s| s.replace("''", "'"));
+
+                (Some(sheet), range)
+            }
+            None => (None, s),
+        };
+
+        let (first, last) = match range.split_once(':') {
+            Some((first, last)) => (parse_cell(first)?, parse_cell(last)?),
+            None => (parse_cell(range)?, parse_cell(range)?),
+        };
+
+        Ok(Self {
+            sheet,
+            first: (first.0.min(last.0), first.1.min(last.1)),
+            last: (first.0.max(last.0), first.1.max(last.1)),
+        })
+    }
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(sheet) = &self.sheet {
+            write!(f, "'{}'!", sheet.replace('\'', "''"))?;
+        }
+
+        write!(
+            f,
+            "{}{}:{}{}",
+            column_letter(self.first.1),
+            self.first.0 + 1,
+            column_letter(self.last.1),
+            self.last.0 + 1
+        )
+    }
+}
+
+impl Serialize for Selection {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Selection {
+    pub fn contains(&self, sheet: &str, (row, column): (u32, u16)) -> bool {
+        self.sheet.iter().all(|s| s == sheet)
+            && (self.first.0..=self.last.0).contains(&row)
+            && (self.first.1..=self.last.1).contains(&column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cells() {
+        assert_eq!(parse_cell("A1").unwrap(), (0, 0));
+        assert_eq!(parse_cell("$B$2").unwrap(), (1, 1));
+        assert_eq!(parse_cell(" aa10 ").unwrap(), (9, 26));
+        assert_eq!(parse_cell("XFD1048576").unwrap(), (1_048_575, 16_383));
+    }
+
+    #[test]
+    fn rejects_invalid_cells() {
+        for cell in ["", "A", "1", "A0", "ABCD1", "A1B", "A-1"] {
+            assert!(parse_cell(cell).is_err(), "{}", cell);
+        }
+    }
+
+    #[test]
+    fn parses_ranges_in_any_order() {
+        let selection = "F50:B2".parse::<Selection>().unwrap();
+
+        assert_eq!(selection.sheet, None);
+        assert_eq!(selection.first, (1, 1));
+        assert_eq!(selection.last, (49, 5));
+        assert_eq!(selection.to_string(), "B2:F50");
+    }
+
+    #[test]
+    fn parses_single_cells_as_ranges() {
+        let selection = "C3".parse::<Selection>().unwrap();
+
+        assert_eq!((selection.first, selection.last), ((2, 2), (2, 2)));
+    }
+
+    #[test]
+    fn parses_sheets() {
+        let selection = "Sheet2!C1:C100".parse::<Selection>().unwrap();
+        assert_eq!(selection.sheet.as_deref(), Some("Sheet2"));
+
+        let selection = "'It''s a sheet'!A1:B2".parse::<Selection>().unwrap();
+        assert_eq!(selection.sheet.as_deref(), Some("It's a sheet"));
+        assert_eq!(selection.to_string(), "'It''s a sheet'!A1:B2");
+    }
+
+    #[test]
+    fn contains_the_cells_of_its_sheets() {
+        let everywhere = "B2:C3".parse::<Selection>().unwrap();
+        let prices = "Prices!B2:C3".parse::<Selection>().unwrap();
+
+        assert!(everywhere.contains("Any", (1, 1)));
+        assert!(everywhere.contains("Any", (2, 2)));
+        assert!(!everywhere.contains("Any", (0, 1)));
+        assert!(!everywhere.contains("Any", (1, 3)));
+        assert!(prices.contains("Prices", (2, 1)));
+        assert!(!prices.contains("Other", (2, 1)));
+    }
+}
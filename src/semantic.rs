@@ -0,0 +1,257 @@
+//! The semantic translation memory of `--semantic-index`: the entries of the dictionary, the
+//! translation memories and the cache are embedded, and the ones closest in meaning to a value go
+//! in its prompt as reference translations. Unlike the Levenshtein distance of
+//! `--fuzzy-threshold`, this finds the paraphrases of recurring boilerplate.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs, io,
+    path::Path,
+};
+
+use color_eyre::{
+    eyre::{bail, Context},
+    Result,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{retry, translator::OpenAi};
+
+/// The most texts sent in a single embeddings request.
+const BATCH_SIZE: usize = 256;
+
+/// The most reference translations given for a value.
+const REFERENCES: usize = 3;
+
+#[derive(Serialize)]
+struct Request<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct Response {
+    data: Vec<Embedding>,
+}
+
+#[derive(Deserialize)]
+struct Embedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    source: String,
+    translation: String,
+    /// Of unit length, for the cosine similarity to be a dot product.
+    embedding: Vec<f32>,
+}
+
+/// The index as it is saved, with the model the embeddings are of.
+#[derive(Serialize, Deserialize)]
+struct Stored {
+    model: String,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Reference<'a> {
+    pub(crate) source: &'a str,
+    pub(crate) translation: &'a str,
+    pub(crate) similarity: f32,
+}
+
+impl Reference<'_> {
+    /// The reference as context for the prompt.
+    pub(crate) fn hint(&self) -> String {
+        format!(
+            "A text with the same meaning, \"{}\", was translated as \"{}\"",
+            self.source, self.translation
+        )
+    }
+}
+
+fn normalized(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norm > 0. {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+
+    vector
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// The embeddings of the texts, in their order, from the embeddings endpoint of the API.
+async fn embed(openai: &OpenAi, model: &str, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+    let mut embeddings = Vec::with_capacity(texts.len());
+
+    for batch in texts.chunks(BATCH_SIZE) {
+        let url = format!("{}/embeddings", openai.api_base());
+        let request = openai.client().post(url).json(&Request {
+            model,
+            input: batch,
+        });
+        let response = retry::send(request, openai.retry()).await?;
+        let status = response.status();
+
+        if !status.is_success() {
+            bail!(
+                "The embeddings request failed with {}: {}",
+                status,
+                response.text().await?
+            );
+        }
+
+        let mut response = response.json::<Response>().await?;
+
+        if response.data.len() != batch.len() {
+            bail!(
+                "Expected {} embeddings, got {}",
+                batch.len(),
+                response.data.len()
+            );
+        }
+
+        response.data.sort_by_key(|data| data.index);
+        embeddings.extend(
+            response
+                .data
+                .into_iter()
+                .map(|data| normalized(data.embedding)),
+        );
+    }
+
+    Ok(embeddings)
+}
+
+pub(crate) struct Index {
+    model: String,
+    entries: Vec<Entry>,
+}
+
+impl Index {
+    /// Loads the index saved at the path, embeds the entries it doesn't have yet and saves it
+    /// again without the ones that are gone. The embeddings of another model are all made anew.
+    pub(crate) async fn build<'a>(
+        path: &Path,
+        model: &str,
+        entries: impl IntoIterator<Item = (&'a String, &'a String)>,
+        openai: &OpenAi,
+    ) -> Result<Self> {
+        let stored = match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str::<Stored>(&json)
+                .wrap_err_with(|| format!("Invalid semantic index {}", path.display()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Stored {
+                model: model.to_string(),
+                entries: vec![],
+            },
+            Err(e) => {
+                return Err(e).wrap_err_with(|| {
+                    format!("Failed to read the semantic index {}", path.display())
+                })
+            }
+        };
+
+        let mut embedded = match stored.model == model {
+            true => stored
+                .entries
+                .into_iter()
+                .map(|entry| ((entry.source, entry.translation), entry.embedding))
+                .collect(),
+            false => HashMap::new(),
+        };
+
+        let wanted = entries
+            .into_iter()
+            .filter(|(source, translation)| !source.is_empty() && !translation.is_empty())
+            .map(|(source, translation)| (source.clone(), translation.clone()))
+            .collect::<BTreeSet<_>>();
+
+        let new = wanted
+            .iter()
+            .filter(|pair| !embedded.contains_key(*pair))
+            .collect::<Vec<_>>();
+
+        if !new.is_empty() {
+            let sources = new
+                .iter()
+                .map(|(source, _)| source.clone())
+                .collect::<Vec<_>>();
+            let embeddings = embed(openai, model, &sources).await?;
+            eprintln!("Embedded {} entries for the semantic index", new.len());
+
+            for (pair, embedding) in new.into_iter().zip(embeddings) {
+                embedded.insert(pair.clone(), embedding);
+            }
+        }
+
+        let stored = Stored {
+            model: model.to_string(),
+            entries: wanted
+                .into_iter()
+                .filter_map(|pair| {
+                    let embedding = embedded.remove(&pair)?;
+                    let (source, translation) = pair;
+
+                    Some(Entry {
+                        source,
+                        translation,
+                        embedding,
+                    })
+                })
+                .collect(),
+        };
+
+        fs::write(path, serde_json::to_string(&stored)?)
+            .wrap_err_with(|| format!("Failed to write the semantic index {}", path.display()))?;
+
+        Ok(Self {
+            model: stored.model,
+            entries: stored.entries,
+        })
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The entries closest in meaning to every value, the closest first, as long as they are at
+    /// least `threshold` similar.
+    pub(crate) async fn references(
+        &self,
+        openai: &OpenAi,
+        values: &[String],
+        threshold: f32,
+    ) -> Result<Vec<Vec<Reference<'_>>>> {
+        if self.entries.is_empty() || values.is_empty() {
+            return Ok(vec![vec![]; values.len()]);
+        }
+
+        let embeddings = embed(openai, &self.model, values).await?;
+
+        Ok(embeddings
+            .iter()
+            .map(|embedding| {
+                let mut references = self
+                    .entries
+                    .iter()
+                    .map(|entry| Reference {
+                        source: &entry.source,
+                        translation: &entry.translation,
+                        similarity: dot(embedding, &entry.embedding),
+                    })
+                    .filter(|reference| reference.similarity >= threshold)
+                    .collect::<Vec<_>>();
+
+                references.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+                references.truncate(REFERENCES);
+                references
+            })
+            .collect())
+    }
+}
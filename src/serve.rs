@@ -0,0 +1,420 @@
+//! Translation as an HTTP service, for `serve`. Workbooks and dictionaries are uploaded as files
+//! and referred to by their IDs, and every job translates one workbook with the options the
+//! server was started with, its own files and its own progress:
+//!
+//! - `POST /files?name=prices.xlsx` stores the body and answers with its `id`;
+//! - `POST /jobs` with `{"source": "<id>", "dictionary": "<id>", "target_lang": "de"}` queues a
+//!   translation and answers with its `id`, the dictionary defaulting to the server's;
+//! - `GET /jobs` lists the jobs, and `GET /jobs/<id>` tells how far one got, what it has cost
+//!   and why it failed if it did;
+//! - `GET /jobs/<id>/result` downloads the translation once it is finished.
+//!
+//! The jobs are kept in a queue in the data directory. Those a restarted server finds queued or
+//! running are started again, resuming from their journals.
+
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use color_eyre::{eyre::Context, Result};
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{runtime::Handle, sync::Semaphore, task};
+use uuid::Uuid;
+
+use crate::{
+    budget::Spent,
+    cli::Args,
+    queue::{self, Queue, Status},
+    translate,
+};
+
+const XLSX: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+struct Job {
+    status: Status,
+    progress: ProgressBar,
+    spent: Arc<Mutex<Spent>>,
+    destination: PathBuf,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NewJob {
+    source: String,
+    dictionary: Option<String>,
+    target_lang: Option<String>,
+    model: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct JobStatus<'a> {
+    id: &'a str,
+    status: Status,
+    cells: u64,
+    total_cells: Option<u64>,
+    percent: f64,
+    /// The counts of the progress bar, like `2 in flight · 10 dictionary, 0 cache, 31 API`.
+    progress: String,
+    /// In USD, at the list price of the model.
+    cost: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+impl<'a> JobStatus<'a> {
+    fn new(id: &'a str, job: &'a Job) -> Self {
+        let cells = job.progress.position();
+        let total_cells = job.progress.length();
+
+        let percent = match (job.status, total_cells) {
+            (Status::Finished, _) => 100.,
+            (_, Some(total)) if total > 0 => (cells as f64 * 100. / total as f64).min(100.),
+            _ => 0.,
+        };
+
+        Self {
+            id,
+            status: job.status,
+            cells,
+            total_cells,
+            percent,
+            progress: job.progress.message(),
+            cost: job.spent.lock().unwrap().cost,
+            error: job.error.as_deref(),
+        }
+    }
+}
+
+struct State {
+    args: Args,
+    data_dir: PathBuf,
+    queue: Queue,
+    /// A permit for each job that may run at the same time.
+    running: Arc<Semaphore>,
+    jobs: Mutex<BTreeMap<String, Job>>,
+}
+
+fn respond(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string() + "\n"))
+        .unwrap()
+}
+
+fn error(status: StatusCode, message: impl ToString) -> Response<Body> {
+    respond(status, json!({ "error": message.to_string() }))
+}
+
+/// The file uploaded with the ID, which is the name of the directory it is kept in.
+fn uploaded(data_dir: &Path, id: &str) -> Option<PathBuf> {
+    // IDs are UUIDs, anything else could reach outside of the data directory.
+    if !id.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+        return None;
+    }
+
+    fs::read_dir(data_dir.join("files").join(id))
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .next()
+}
+
+/// Keeps the name of an upload, for its extension, without any directories.
+fn file_name(request: &Request<Body>) -> String {
+    request
+        .uri()
+        .query()
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|pair| pair.strip_prefix("name="))
+        .and_then(|name| Path::new(name).file_name())
+        .map_or_else(
+            || "upload".to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        )
+}
+
+async fn upload(state: &State, request: Request<Body>) -> Result<Response<Body>> {
+    let name = file_name(&request);
+    let body = hyper::body::to_bytes(request.into_body()).await?;
+    let id = Uuid::new_v4().to_string();
+    let dir = state.data_dir.join("files").join(&id);
+
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(name), body)?;
+
+    Ok(respond(StatusCode::CREATED, json!({ "id": id })))
+}
+
+/// Queues the job to run once one of the permits is free, recording when it starts and how it
+/// ends.
+fn run(state: Arc<State>, id: String, options: queue::Options) {
+    let progress = ProgressBar::hidden();
+    let spent = Arc::new(Mutex::new(Spent::default()));
+    let dir = options.destination.parent().unwrap_or(Path::new(""));
+
+    let mut args = state.args.clone();
+    args.command = None;
+    args.dictionary_path = Some(options.dictionary.clone());
+    args.source_path = Some(options.source.clone());
+    args.destination_path = Some(options.destination.clone());
+    args.progress = Some(progress.clone());
+    args.spent = Some(spent.clone());
+    // The journal is the checkpoint a restarted server resumes the job from.
+    args.resume = true;
+    args.yes = true;
+
+    // The files written next to the translation go with the job, rather than being shared.
+    let into_job = |path: &Option<PathBuf>| {
+        path.as_deref()
+            .and_then(Path::file_name)
+            .map(|name| dir.join(name))
+    };
+    args.report = into_job(&args.report);
+    args.failures = into_job(&args.failures);
+    args.qa_report = into_job(&args.qa_report);
+
+    if let Some(language) = options.target_lang {
+        args.target_lang = vec![language];
+    }
+
+    if let Some(model) = options.model {
+        args.model = model;
+    }
+
+    state.jobs.lock().unwrap().insert(
+        id.clone(),
+        Job {
+            status: Status::Queued,
+            progress,
+            spent: spent.clone(),
+            destination: options.destination,
+            error: None,
+        },
+    );
+
+    tokio::spawn(async move {
+        let Ok(_permit) = state.running.clone().acquire_owned().await else {
+            return;
+        };
+
+        let set_status = |status, error: Option<String>| {
+            if let Some(job) = state.jobs.lock().unwrap().get_mut(&id) {
+                job.status = status;
+                job.error = error;
+            }
+        };
+
+        set_status(Status::Running, None);
+
+        if let Err(e) = state.queue.set_status(&id, Status::Running) {
+            eprintln!("Failed to record job {}: {:#}", id, e);
+        }
+
+        // The translation holds on to workbooks that can't move between threads, so it runs by
+        // itself while its requests go through the shared runtime.
+        let handle = Handle::current();
+        let result = task::spawn_blocking(move || handle.block_on(translate(args))).await;
+
+        let error = match result {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(format!("{:#}", e)),
+            Err(e) => Some(e.to_string()),
+        };
+
+        let cost = spent.lock().unwrap().cost;
+
+        if let Err(e) = state.queue.end(&id, error.as_deref(), cost) {
+            eprintln!("Failed to record job {}: {:#}", id, e);
+        }
+
+        match error {
+            Some(error) => set_status(Status::Failed, Some(error)),
+            None => set_status(Status::Finished, None),
+        }
+    });
+}
+
+async fn start(state: Arc<State>, request: Request<Body>) -> Result<Response<Body>> {
+    let body = hyper::body::to_bytes(request.into_body()).await?;
+
+    let job = match serde_json::from_slice::<NewJob>(&body) {
+        Ok(job) => job,
+        Err(e) => return Ok(error(StatusCode::BAD_REQUEST, e)),
+    };
+
+    let Some(source) = uploaded(&state.data_dir, &job.source) else {
+        return Ok(error(StatusCode::NOT_FOUND, "No such source file"));
+    };
+
+    let dictionary = match &job.dictionary {
+        Some(id) => match uploaded(&state.data_dir, id) {
+            Some(path) => Some(path),
+            None => return Ok(error(StatusCode::NOT_FOUND, "No such dictionary file")),
+        },
+        None => state.args.dictionary_path.clone(),
+    };
+
+    let Some(dictionary) = dictionary else {
+        return Ok(error(
+            StatusCode::BAD_REQUEST,
+            "The server has no dictionary, upload one",
+        ));
+    };
+
+    let id = Uuid::new_v4().to_string();
+    let dir = state.data_dir.join("jobs").join(&id);
+    fs::create_dir_all(&dir)?;
+
+    let options = queue::Options {
+        destination: dir.join(source.file_name().unwrap_or_default()),
+        source,
+        dictionary,
+        target_lang: job.target_lang,
+        model: job.model,
+    };
+
+    state.queue.add(&id, &options)?;
+    run(state, id.clone(), options);
+
+    Ok(respond(StatusCode::ACCEPTED, json!({ "id": id })))
+}
+
+fn list(state: &State) -> Response<Body> {
+    let jobs = state.jobs.lock().unwrap();
+    let statuses = jobs
+        .iter()
+        .map(|(id, job)| JobStatus::new(id, job))
+        .collect::<Vec<_>>();
+
+    respond(StatusCode::OK, json!({ "jobs": statuses }))
+}
+
+fn status(state: &State, id: &str) -> Response<Body> {
+    let jobs = state.jobs.lock().unwrap();
+
+    match jobs.get(id) {
+        Some(job) => respond(StatusCode::OK, json!(JobStatus::new(id, job))),
+        None => error(StatusCode::NOT_FOUND, "No such job"),
+    }
+}
+
+fn result(state: &State, id: &str) -> Result<Response<Body>> {
+    let jobs = state.jobs.lock().unwrap();
+
+    let Some(job) = jobs.get(id) else {
+        return Ok(error(StatusCode::NOT_FOUND, "No such job"));
+    };
+
+    if job.status != Status::Finished {
+        return Ok(error(StatusCode::CONFLICT, "The job hasn't finished"));
+    }
+
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, XLSX)
+        .body(Body::from(fs::read(&job.destination)?))?)
+}
+
+async fn handle(state: Arc<State>, request: Request<Body>) -> Response<Body> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let segments = path
+        .trim_matches('/')
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+
+    let response = match (&method, segments.as_slice()) {
+        (&Method::POST, ["files"]) => upload(&state, request).await,
+        (&Method::POST, ["jobs"]) => start(state, request).await,
+        (&Method::GET, ["jobs"]) => Ok(list(&state)),
+        (&Method::GET, ["jobs", id]) => Ok(status(&state, id)),
+        (&Method::GET, ["jobs", id, "result"]) => result(&state, id),
+        _ => Ok(error(StatusCode::NOT_FOUND, "No such endpoint")),
+    };
+
+    response.unwrap_or_else(|e| error(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))
+}
+
+/// Serves translations on the address until the server fails, keeping the uploads, the queue
+/// and the translations in `data_dir` and running up to `max_jobs` jobs at a time.
+pub(crate) async fn serve(
+    args: Args,
+    address: SocketAddr,
+    data_dir: PathBuf,
+    max_jobs: usize,
+) -> Result<()> {
+    fs::create_dir_all(&data_dir)
+        .wrap_err_with(|| format!("Failed to create {}", data_dir.display()))?;
+
+    let state = Arc::new(State {
+        args,
+        queue: Queue::open(&data_dir.join("jobs.sqlite"))?,
+        data_dir,
+        running: Arc::new(Semaphore::new(max_jobs)),
+        jobs: Mutex::new(BTreeMap::new()),
+    });
+
+    let mut resumed = 0;
+
+    for record in state.queue.all()? {
+        if matches!(record.status, Status::Queued | Status::Running) {
+            run(state.clone(), record.id, record.options);
+            resumed += 1;
+            continue;
+        }
+
+        // Only what they cost is known of the jobs that ended before.
+        let spent = Spent {
+            cost: record.cost,
+            ..Spent::default()
+        };
+
+        state.jobs.lock().unwrap().insert(
+            record.id,
+            Job {
+                status: record.status,
+                progress: ProgressBar::hidden(),
+                spent: Arc::new(Mutex::new(spent)),
+                destination: record.options.destination,
+                error: record.error,
+            },
+        );
+    }
+
+    if resumed > 0 {
+        eprintln!("Resuming {} jobs", resumed);
+    }
+
+    let service = make_service_fn(move |_| {
+        let state = state.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(handle(state, request).await) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&address)
+        .wrap_err_with(|| format!("Failed to listen on {}", address))?
+        .serve(service);
+
+    eprintln!("Listening on http://{}", address);
+
+    Ok(server.await?)
+}
@@ -0,0 +1,86 @@
+//! Picking the worksheets to translate by name, position or glob pattern.
+
+use std::{fmt, str::FromStr};
+
+use color_eyre::{
+    eyre::{bail, eyre},
+    Report, Result,
+};
+use glob::Pattern;
+use serde::{Serialize, Serializer};
+
+#[derive(Debug, Clone)]
+pub enum SheetPattern {
+    /// The 1-based position of the worksheet in the workbook.
+    Index(usize),
+    /// A worksheet name, possibly with `*`, `?` and `[...]` wildcards.
+    Name(Pattern),
+}
+
+impl SheetPattern {
+    /// Whether the worksheet at the 1-based `position` with the given name is meant. Worksheets
+    /// that are named like a number match that number as well as their position.
+    pub fn matches(&self, position: usize, name: &str) -> bool {
+        match self {
+            Self::Index(index) => *index == position || name == index.to_string(),
+            Self::Name(pattern) => pattern.matches(name),
+        }
+    }
+}
+
+impl FromStr for SheetPattern {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.parse() {
+            Ok(index) if index > 0 => Ok(Self::Index(index)),
+            _ => Pattern::new(s)
+                .map(Self::Name)
+                .map_err(|e| eyre!("Invalid sheet pattern '{}': {}", s, e)),
+        }
+    }
+}
+
+impl fmt::Display for SheetPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "{}", index),
+            Self::Name(pattern) => write!(f, "{}", pattern),
+        }
+    }
+}
+
+impl Serialize for SheetPattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// The names of the worksheets to translate, in workbook order: the ones matching any of the
+/// patterns, all of them with `all`, or else just the first one.
+pub fn select(names: &[String], patterns: &[SheetPattern], all: bool) -> Result<Vec<String>> {
+    if all {
+        return Ok(names.to_vec());
+    }
+
+    if patterns.is_empty() {
+        return Ok(names.iter().take(1).cloned().collect());
+    }
+
+    for pattern in patterns {
+        if !names
+            .iter()
+            .enumerate()
+            .any(|(i, name)| pattern.matches(i + 1, name))
+        {
+            bail!("No worksheet matches '{}'", pattern);
+        }
+    }
+
+    Ok(names
+        .iter()
+        .enumerate()
+        .filter(|(i, name)| patterns.iter().any(|p| p.matches(i + 1, name)))
+        .map(|(_, name)| name.clone())
+        .collect())
+}
@@ -0,0 +1,64 @@
+//! The translated cells of `--sidecar` as JSON, for tools that want the translations without
+//! reading the workbook, like diffs between runs and localization QA.
+
+use std::{fs, path::Path};
+
+use calamine::{DataType, Range};
+use color_eyre::{eyre::Context, Result};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+struct Record {
+    /// Of the worksheet in the workbook, for the records to keep the order of the worksheets.
+    #[serde(skip)]
+    index: usize,
+    sheet: String,
+    /// 1-based, of the cell in the source worksheet.
+    row: u32,
+    /// 1-based, of the cell in the source worksheet.
+    col: u32,
+    source: String,
+    translation: String,
+    /// How the cell was translated, as with `--provenance`.
+    origin: String,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Sidecar(Vec<Record>);
+
+impl Sidecar {
+    /// Records the cell of the source worksheet `sheet`, at the `cell` of its `range`.
+    pub(crate) fn record(
+        &mut self,
+        (sheet, range, cell): (&str, &Range<DataType>, (u32, u16)),
+        source: &str,
+        translation: &str,
+        origin: &str,
+    ) {
+        let (first_row, first_column) = range.start().unwrap_or((0, 0));
+        let index = match self.0.last() {
+            Some(last) if last.sheet == sheet => last.index,
+            Some(last) => last.index + 1,
+            None => 0,
+        };
+
+        self.0.push(Record {
+            index,
+            sheet: sheet.to_string(),
+            row: first_row + cell.0 + 1,
+            col: first_column + u32::from(cell.1) + 1,
+            source: source.to_string(),
+            translation: translation.to_string(),
+            origin: origin.to_string(),
+        });
+    }
+
+    /// Writes the records as a JSON array, in the order of the worksheets and their cells.
+    pub(crate) fn write(mut self, path: &Path) -> Result<()> {
+        self.0
+            .sort_by_key(|record| (record.index, record.row, record.col));
+
+        fs::write(path, serde_json::to_string_pretty(&self.0)?)
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))
+    }
+}
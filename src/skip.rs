@@ -0,0 +1,56 @@
+//! Cell values copied through as they are rather than sent to be translated, for
+//! `--skip-pattern` and `--skip-content`: part numbers, addresses and amounts that models only
+//! ever get wrong.
+
+use color_eyre::{eyre::Context, Result};
+use regex::Regex;
+
+use crate::cli::SkipContent;
+
+impl SkipContent {
+    /// What the whole value has to match.
+    fn pattern(self) -> &'static str {
+        match self {
+            Self::Url => r"(?i)^(?:(?:https?|ftp)://|www\.)\S+$",
+            Self::Email => r"(?i)^(?:mailto:)?[\w.+-]+@[\w-]+(?:\.[\w-]+)+$",
+            // Letters and digits, with at least one digit, like `ABC-1234` or `X7/200B`.
+            Self::Code => concat!(
+                r"^[A-Z]*\d[A-Z\d]*(?:[-_./][A-Z\d]+)*$",
+                r"|^[A-Z]+(?:[-_./][A-Z]*\d[A-Z\d]*)+$",
+            ),
+            // A number with one of the units that are written the same in every language.
+            Self::Quantity => concat!(
+                r"^[-+]?\d+(?:[.,]\d+)*\s?(?:%|‰|°[CF]?",
+                r"|(?:[kKMGTmcµn]?(?:g|m|l|L|s|W|Wh|V|A|Hz|B|J|N|Pa)|mAh|px|pt|ft|lbs?|oz|mph|rpm)",
+                r"(?:[²³23]|/(?:s|h|min|m[²³23]?))?)?$",
+            ),
+        }
+    }
+}
+
+pub(crate) struct Skip {
+    patterns: Vec<Regex>,
+}
+
+impl Skip {
+    pub(crate) fn new(patterns: &[String], contents: &[SkipContent]) -> Result<Self> {
+        let mut compiled = patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).wrap_err_with(|| format!("Invalid skip pattern '{}'", pattern))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for content in contents {
+            compiled.push(Regex::new(content.pattern()).expect("a valid built-in pattern"));
+        }
+
+        Ok(Self { patterns: compiled })
+    }
+
+    /// Whether the value is left as it is. The patterns of `--skip-pattern` only have to match
+    /// part of it, unless they say otherwise with `^` and `$`.
+    pub(crate) fn matches(&self, value: &str) -> bool {
+        self.patterns.iter().any(|pattern| pattern.is_match(value))
+    }
+}
@@ -0,0 +1,373 @@
+//! The unique texts of a workbook for translating by hand, for `extract --strings`. Cells with the
+//! same text regardless of case are one string, as they are one request when translating, and
+//! the strings the dictionary already translates are left out. The file is written as CSV, xlsx
+//! or PO, going by its extension, with an empty translation for every string. Once filled in,
+//! `apply --translations` takes the translations back instead of the API's.
+
+use std::{borrow::Cow, collections::BTreeMap, fmt::Write, fs, mem, path::Path};
+
+use calamine::{open_workbook, DataType, Reader, Xlsx};
+use color_eyre::{
+    eyre::{bail, eyre, Context, ContextCompat},
+    Result,
+};
+use xlsxwriter::Workbook;
+
+use crate::{
+    dictionary::{csv_field, csv_records, normalize_whitespace, Dictionary, MatchMode},
+    prompt::language_code,
+    workbook::{cell_reference, Sheet},
+};
+
+const HEADERS: [&str; 4] = ["source", "translation", "occurrences", "cells"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Csv,
+    Xlsx,
+    Po,
+}
+
+impl Format {
+    /// The format going by the extension of the file, CSV for anything else.
+    pub(crate) fn detect(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+
+        match extension.as_deref() {
+            Some("xlsx") => Self::Xlsx,
+            Some("po" | "pot") => Self::Po,
+            _ => Self::Csv,
+        }
+    }
+}
+
+/// What is written for an extraction.
+#[derive(Debug)]
+pub(crate) struct Options<'a> {
+    pub(crate) target_lang: &'a str,
+    pub(crate) header_rows: u32,
+    pub(crate) translate_headers: bool,
+    pub(crate) preserve_whitespace: bool,
+    pub(crate) match_mode: MatchMode,
+}
+
+/// A text to translate, as it is first found, and the cells it is in, like `Sheet1!B7`.
+#[derive(Debug)]
+struct Entry {
+    source: String,
+    cells: Vec<String>,
+}
+
+/// The cell with the worksheet it's in, quoted as in formulas if the name has spaces, which
+/// separate the references in PO files.
+fn reference(sheet: &str, cell: String) -> String {
+    if sheet.contains(|c: char| c.is_whitespace() || c == '\'') {
+        format!("'{}'!{}", sheet.replace('\'', "''"), cell)
+    } else {
+        format!("{}!{}", sheet, cell)
+    }
+}
+
+/// The text as it is looked up, like the values of the cells.
+fn key(text: &str, preserve_whitespace: bool, match_mode: MatchMode) -> String {
+    if preserve_whitespace {
+        match_mode.key(text.trim())
+    } else {
+        match_mode.key(&normalize_whitespace(text))
+    }
+}
+
+fn collect(sheets: &[Sheet], dictionary: &Dictionary, options: &Options) -> Vec<Entry> {
+    let mut entries = BTreeMap::<String, Entry>::new();
+    let mut order = vec![];
+
+    for sheet in sheets {
+        let range = &sheet.range;
+
+        for (row, column, data) in range.cells() {
+            let DataType::String(value) = data else {
+                continue;
+            };
+
+            if row < options.header_rows as usize && !options.translate_headers {
+                continue;
+            }
+
+            let value = if options.preserve_whitespace {
+                Cow::Borrowed(value.trim())
+            } else {
+                Cow::Owned(normalize_whitespace(value))
+            };
+
+            let key = options.match_mode.key(&value);
+
+            if value.is_empty() || dictionary.contains_key(&key) {
+                continue;
+            }
+
+            let cell = reference(
+                &sheet.name,
+                cell_reference(range, (row as u32, column as u16)),
+            );
+
+            match entries.get_mut(&key) {
+                Some(entry) => entry.cells.push(cell),
+                None => {
+                    order.push(key.clone());
+                    entries.insert(
+                        key,
+                        Entry {
+                            source: value.into_owned(),
+                            cells: vec![cell],
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    order
+        .into_iter()
+        .filter_map(|key| entries.remove(&key))
+        .collect()
+}
+
+pub(crate) fn po_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+fn write_csv(path: &Path, entries: &[Entry]) -> Result<()> {
+    let mut text = HEADERS.join(",") + "\n";
+
+    for entry in entries {
+        let fields = [
+            entry.source.clone(),
+            String::new(),
+            entry.cells.len().to_string(),
+            entry.cells.join(" "),
+        ];
+
+        text.push_str(&fields.map(|field| csv_field(&field).into_owned()).join(","));
+        text.push('\n');
+    }
+
+    fs::write(path, text).wrap_err_with(|| format!("Failed to write {}", path.display()))
+}
+
+fn write_xlsx(path: &Path, entries: &[Entry]) -> Result<()> {
+    let workbook = Workbook::new(path.to_str().wrap_err("Invalid strings filename")?)?;
+    let mut worksheet = workbook.add_worksheet(Some("Strings"))?;
+
+    for (column, header) in HEADERS.into_iter().enumerate() {
+        worksheet.write_string(0, column as u16, header, None)?;
+    }
+
+    for (row, entry) in (1..).zip(entries) {
+        worksheet.write_string(row, 0, &entry.source, None)?;
+        worksheet.write_number(row, 2, entry.cells.len() as f64, None)?;
+        worksheet.write_string(row, 3, &entry.cells.join(" "), None)?;
+    }
+
+    workbook.close()?;
+
+    Ok(())
+}
+
+fn write_po(path: &Path, entries: &[Entry], target_lang: &str) -> Result<()> {
+    let mut text = String::new();
+
+    writeln!(text, "msgid \"\"")?;
+    writeln!(text, "msgstr \"\"")?;
+    writeln!(text, "\"Language: {}\\n\"", language_code(target_lang))?;
+    writeln!(text, "\"Content-Type: text/plain; charset=UTF-8\\n\"")?;
+
+    for entry in entries {
+        writeln!(text)?;
+        writeln!(text, "#. {} occurrences", entry.cells.len())?;
+        writeln!(text, "#: {}", entry.cells.join(" "))?;
+        writeln!(text, "msgid {}", po_string(&entry.source))?;
+        writeln!(text, "msgstr \"\"")?;
+    }
+
+    fs::write(path, text).wrap_err_with(|| format!("Failed to write {}", path.display()))
+}
+
+/// Writes the strings of the worksheets the dictionary doesn't translate and returns how many
+/// there were.
+pub(crate) fn extract(
+    path: &Path,
+    sheets: &[Sheet],
+    dictionary: &Dictionary,
+    options: &Options,
+) -> Result<usize> {
+    let entries = collect(sheets, dictionary, options);
+
+    match Format::detect(path) {
+        Format::Csv => write_csv(path, &entries)?,
+        Format::Xlsx => write_xlsx(path, &entries)?,
+        Format::Po => write_po(path, &entries, options.target_lang)?,
+    }
+
+    Ok(entries.len())
+}
+
+/// The translations of rows with a `source` and a `translation` column, the first and second
+/// ones if the header row names neither.
+fn rows(rows: Vec<Vec<String>>, preserve_whitespace: bool, match_mode: MatchMode) -> Dictionary {
+    let mut rows = rows.into_iter();
+    let header = rows.next().unwrap_or_default();
+    let column = |name: &str, default| {
+        header
+            .iter()
+            .position(|field| field.trim().eq_ignore_ascii_case(name))
+            .unwrap_or(default)
+    };
+    let (source, translation) = (column(HEADERS[0], 0), column(HEADERS[1], 1));
+
+    rows.filter_map(|row| {
+        let translation = row.get(translation)?.trim();
+
+        (!translation.is_empty()).then(|| {
+            let source = row.get(source).map_or("", String::as_str);
+            (
+                key(source, preserve_whitespace, match_mode),
+                translation.to_string(),
+            )
+        })
+    })
+    .filter(|(source, _)| !source.is_empty())
+    .collect()
+}
+
+/// The text of a PO string, without its quotes and escapes.
+pub(crate) fn po_unquote(text: &str) -> Option<String> {
+    let text = text.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut unquoted = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                'n' => unquoted.push('\n'),
+                't' => unquoted.push('\t'),
+                c => unquoted.push(c),
+            },
+            c => unquoted.push(c),
+        }
+    }
+
+    Some(unquoted)
+}
+
+/// The translated messages of a PO file, leaving out the fuzzy ones, which still need a review.
+fn po(text: &str, preserve_whitespace: bool, match_mode: MatchMode) -> Result<Dictionary> {
+    let mut translations = Dictionary::new();
+    let mut fuzzy = false;
+    let mut msgid = None::<String>;
+    let mut msgstr = None::<String>;
+
+    let mut add = |msgid: Option<String>, msgstr: Option<String>, fuzzy: bool| {
+        if let (Some(msgid), Some(msgstr)) = (msgid, msgstr) {
+            if !fuzzy && !msgid.is_empty() && !msgstr.trim().is_empty() {
+                let key = key(&msgid, preserve_whitespace, match_mode);
+                translations.insert(key, msgstr.trim().to_string());
+            }
+        }
+    };
+
+    for (i, line) in text.lines().enumerate() {
+        let invalid = || eyre!("Invalid string at line #{}", i + 1);
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            if line.starts_with("#,") && line.contains("fuzzy") {
+                fuzzy = true;
+            }
+
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix("msgid ") {
+            add(msgid.take(), msgstr.take(), mem::take(&mut fuzzy));
+            msgid = Some(po_unquote(rest).ok_or_else(invalid)?);
+        } else if let Some(rest) = line.strip_prefix("msgstr ") {
+            msgstr = Some(po_unquote(rest).ok_or_else(invalid)?);
+        } else if line.starts_with('"') {
+            let continued = po_unquote(line).ok_or_else(invalid)?;
+
+            match (&mut msgid, &mut msgstr) {
+                (_, Some(msgstr)) => msgstr.push_str(&continued),
+                (Some(msgid), None) => msgid.push_str(&continued),
+                (None, None) => return Err(invalid()),
+            }
+        } else {
+            return Err(invalid());
+        }
+    }
+
+    add(msgid, msgstr, fuzzy);
+
+    Ok(translations)
+}
+
+/// Reads the translations filled in a strings file, by the keys of their sources. Strings left
+/// without one are left out.
+pub(crate) fn load(
+    path: &Path,
+    preserve_whitespace: bool,
+    match_mode: MatchMode,
+) -> Result<Dictionary> {
+    let invalid = || format!("Invalid translations file {}", path.display());
+
+    if Format::detect(path) == Format::Xlsx {
+        let mut workbook = open_workbook::<Xlsx<_>, _>(path).wrap_err_with(invalid)?;
+
+        let Some(range) = workbook
+            .worksheet_range_at(0)
+            .transpose()
+            .wrap_err_with(invalid)?
+        else {
+            bail!("{} has no worksheet", path.display());
+        };
+
+        let cells = range
+            .rows()
+            .map(|row| {
+                row.iter()
+                    .map(|data| match data {
+                        DataType::String(text) => text.clone(),
+                        DataType::Empty => String::new(),
+                        data => data.to_string(),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        return Ok(rows(cells, preserve_whitespace, match_mode));
+    }
+
+    let text =
+        fs::read_to_string(path).wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+
+    match Format::detect(path) {
+        Format::Po => po(text, preserve_whitespace, match_mode).wrap_err_with(invalid),
+        _ => Ok(rows(csv_records(text), preserve_whitespace, match_mode)),
+    }
+}
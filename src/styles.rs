@@ -0,0 +1,821 @@
+//! The cell formats and sheet geometry of the source, read straight from the xlsx parts since
+//! calamine only hands out values, to be reproduced in the translated worksheets.
+//!
+//! Only explicit RGB colors are carried over, theme and indexed colors fall back to the
+//! defaults.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Read, Seek},
+};
+
+use calamine::{DataType, Range};
+use color_eyre::{
+    eyre::{bail, ContextCompat},
+    Result,
+};
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+use xlsxwriter::{
+    format::{
+        FormatAlignment, FormatBorder, FormatColor, FormatPatterns, FormatScript, FormatUnderline,
+        FormatVerticalAlignment,
+    },
+    worksheet::LXW_DEF_COL_WIDTH,
+    Format,
+};
+use zip::ZipArchive;
+
+use crate::{
+    columns::parse_reference,
+    package::{self, ListValidation},
+    rich_text::Run,
+    workbook::{Layout, Output},
+};
+
+/// The number formats Excel knows by their id alone.
+const BUILTIN_NUM_FORMATS: [(u32, &str); 28] = [
+    (1, "0"),
+    (2, "0.00"),
+    (3, "#,##0"),
+    (4, "#,##0.00"),
+    (9, "0%"),
+    (10, "0.00%"),
+    (11, "0.00E+00"),
+    (12, "# ?/?"),
+    (13, "# ??/??"),
+    (14, "mm-dd-yy"),
+    (15, "d-mmm-yy"),
+    (16, "d-mmm"),
+    (17, "mmm-yy"),
+    (18, "h:mm AM/PM"),
+    (19, "h:mm:ss AM/PM"),
+    (20, "h:mm"),
+    (21, "h:mm:ss"),
+    (22, "m/d/yy h:mm"),
+    (37, "#,##0 ;(#,##0)"),
+    (38, "#,##0 ;[Red](#,##0)"),
+    (39, "#,##0.00;(#,##0.00)"),
+    (40, "#,##0.00;[Red](#,##0.00)"),
+    (45, "mm:ss"),
+    (46, "[h]:mm:ss"),
+    (47, "mmss.0"),
+    (48, "##0.0E+0"),
+    (49, "@"),
+    (0, "General"),
+];
+
+/// A parsed XML element, for the small parts that are easier to walk as a tree.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn new(e: &BytesStart, reader: &Reader<&[u8]>) -> Result<Self> {
+        Ok(Self {
+            name: String::from_utf8_lossy(e.local_name().as_ref()).into_owned(),
+            attributes: package::attribute_map(e, reader)?,
+            children: vec![],
+            text: String::new(),
+        })
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// Whether a flag like `<b/>` is set, which it is unless its `val` says otherwise.
+    fn flag(&self, name: &str) -> bool {
+        self.child(name)
+            .is_some_and(|child| !matches!(child.attribute("val"), Some("0" | "false")))
+    }
+
+    fn color(&self, name: &str) -> Option<FormatColor> {
+        let rgb = package::parse_rgb(self.child(name)?.attribute("rgb")?)?;
+        Some(FormatColor::Custom(rgb))
+    }
+
+    /// The element of `list` at the index given by the attribute.
+    fn lookup<'a>(&self, attribute: &str, list: &'a [&'a Element]) -> Option<&'a Element> {
+        list.get(self.attribute(attribute)?.parse::<usize>().ok()?)
+            .copied()
+    }
+}
+
+fn parse_tree(xml: &[u8]) -> Result<Element> {
+    let mut reader = Reader::from_reader(xml);
+    let mut buf = vec![];
+    let mut stack = vec![Element::default()];
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => stack.push(Element::new(&e, &reader)?),
+            Event::Empty(e) => {
+                let element = Element::new(&e, &reader)?;
+                stack.last_mut().unwrap().children.push(element);
+            }
+            Event::End(_) => {
+                if stack.len() < 2 {
+                    bail!("Unbalanced XML");
+                }
+
+                let element = stack.pop().unwrap();
+                stack.last_mut().unwrap().children.push(element);
+            }
+            Event::Text(t) => stack.last_mut().unwrap().text.push_str(&t.unescape()?),
+            Event::Eof => break,
+            _ => {}
+        }
+
+        buf.clear();
+    }
+
+    stack.pop().wrap_err("Empty XML")
+}
+
+fn underline(value: Option<&str>) -> FormatUnderline {
+    match value {
+        Some("double") => FormatUnderline::Double,
+        Some("singleAccounting") => FormatUnderline::SingleAccounting,
+        Some("doubleAccounting") => FormatUnderline::DoubleAccounting,
+        _ => FormatUnderline::Single,
+    }
+}
+
+fn alignment(value: &str) -> Option<FormatAlignment> {
+    Some(match value {
+        "left" => FormatAlignment::Left,
+        "center" => FormatAlignment::Center,
+        "right" => FormatAlignment::Right,
+        "fill" => FormatAlignment::Fill,
+        "justify" => FormatAlignment::Justify,
+        "centerContinuous" => FormatAlignment::CenterAcross,
+        "distributed" => FormatAlignment::Distributed,
+        _ => return None,
+    })
+}
+
+fn vertical_alignment(value: &str) -> Option<FormatVerticalAlignment> {
+    Some(match value {
+        "top" => FormatVerticalAlignment::VerticalTop,
+        "center" => FormatVerticalAlignment::VerticalCenter,
+        "bottom" => FormatVerticalAlignment::VerticalBottom,
+        "justify" => FormatVerticalAlignment::VerticalJustify,
+        "distributed" => FormatVerticalAlignment::VerticalDistributed,
+        _ => return None,
+    })
+}
+
+fn pattern(value: &str) -> Option<FormatPatterns> {
+    Some(match value {
+        "solid" => FormatPatterns::Solid,
+        "mediumGray" => FormatPatterns::MediumGray,
+        "darkGray" => FormatPatterns::DarkGray,
+        "lightGray" => FormatPatterns::LightGray,
+        "darkHorizontal" => FormatPatterns::DarkHorizontal,
+        "darkVertical" => FormatPatterns::DarkVertical,
+        "darkDown" => FormatPatterns::DarkDown,
+        "darkUp" => FormatPatterns::DarkUp,
+        "darkGrid" => FormatPatterns::DarkGrid,
+        "darkTrellis" => FormatPatterns::DarkTrellis,
+        "lightHorizontal" => FormatPatterns::LightHorizontal,
+        "lightVertical" => FormatPatterns::LightVertical,
+        "lightDown" => FormatPatterns::LightDown,
+        "lightUp" => FormatPatterns::LightUp,
+        "lightGrid" => FormatPatterns::LightGrid,
+        "lightTrellis" => FormatPatterns::LightTrellis,
+        "gray125" => FormatPatterns::Gray125,
+        "gray0625" => FormatPatterns::Gray0625,
+        _ => return None,
+    })
+}
+
+fn border(value: &str) -> Option<FormatBorder> {
+    Some(match value {
+        "thin" => FormatBorder::Thin,
+        "medium" => FormatBorder::Medium,
+        "dashed" => FormatBorder::Dashed,
+        "dotted" => FormatBorder::Dotted,
+        "thick" => FormatBorder::Thick,
+        "double" => FormatBorder::Double,
+        "hair" => FormatBorder::Hair,
+        "mediumDashed" => FormatBorder::MediumDashed,
+        "dashDot" => FormatBorder::DashDot,
+        "mediumDashDot" => FormatBorder::MediumDashDot,
+        "dashDotDot" => FormatBorder::DashDotDot,
+        "mediumDashDotDot" => FormatBorder::MediumDashDotDot,
+        "slantDashDot" => FormatBorder::SlantDashDot,
+        _ => return None,
+    })
+}
+
+fn apply_font(format: &mut Format, font: &Element) {
+    if let Some(name) = font.child("name").and_then(|name| name.attribute("val")) {
+        format.set_font_name(name);
+    }
+
+    if let Some(size) = font
+        .child("sz")
+        .and_then(|size| size.attribute("val")?.parse().ok())
+    {
+        format.set_font_size(size);
+    }
+
+    if let Some(color) = font.color("color") {
+        format.set_font_color(color);
+    }
+
+    if font.flag("b") {
+        format.set_bold();
+    }
+
+    if font.flag("i") {
+        format.set_italic();
+    }
+
+    if let Some(u) = font
+        .child("u")
+        .filter(|u| u.attribute("val") != Some("none"))
+    {
+        format.set_underline(underline(u.attribute("val")));
+    }
+
+    if font.flag("strike") {
+        format.set_font_strikeout();
+    }
+
+    match font.child("vertAlign").and_then(|v| v.attribute("val")) {
+        Some("superscript") => {
+            format.set_font_script(FormatScript::SuperScript);
+        }
+        Some("subscript") => {
+            format.set_font_script(FormatScript::SubScript);
+        }
+        _ => {}
+    }
+}
+
+fn apply_fill(format: &mut Format, fill: &Element) {
+    let Some(pattern_fill) = fill.child("patternFill") else {
+        return;
+    };
+
+    let Some(pattern) = pattern_fill.attribute("patternType").and_then(pattern) else {
+        return;
+    };
+
+    format.set_pattern(pattern);
+
+    // Solid fills are painted in the foreground color, which xlsxwriter takes as the background
+    // color for them.
+    if pattern == FormatPatterns::Solid {
+        if let Some(color) = pattern_fill.color("fgColor") {
+            format.set_bg_color(color);
+        }
+    } else {
+        if let Some(color) = pattern_fill.color("fgColor") {
+            format.set_fg_color(color);
+        }
+
+        if let Some(color) = pattern_fill.color("bgColor") {
+            format.set_bg_color(color);
+        }
+    }
+}
+
+type Setter<T> = fn(&mut Format, T) -> &mut Format;
+
+fn apply_border(format: &mut Format, border_element: &Element) {
+    let sides: [(&str, Setter<FormatBorder>, Setter<FormatColor>); 4] = [
+        (
+            "left",
+            Format::set_border_left,
+            Format::set_border_left_color,
+        ),
+        (
+            "right",
+            Format::set_border_right,
+            Format::set_border_right_color,
+        ),
+        ("top", Format::set_border_top, Format::set_border_top_color),
+        (
+            "bottom",
+            Format::set_border_bottom,
+            Format::set_border_bottom_color,
+        ),
+    ];
+
+    for (side, set_style, set_color) in sides {
+        let Some(element) = border_element.child(side) else {
+            continue;
+        };
+
+        let Some(style) = element.attribute("style").and_then(border) else {
+            continue;
+        };
+
+        set_style(format, style);
+
+        if let Some(color) = element.color("color") {
+            set_color(format, color);
+        }
+    }
+}
+
+fn apply_alignment(format: &mut Format, alignment_element: &Element) {
+    if let Some(align) = alignment_element
+        .attribute("horizontal")
+        .and_then(alignment)
+    {
+        format.set_align(align);
+    }
+
+    if let Some(align) = alignment_element
+        .attribute("vertical")
+        .and_then(vertical_alignment)
+    {
+        format.set_vertical_align(align);
+    }
+
+    let flag = |name| matches!(alignment_element.attribute(name), Some("1" | "true"));
+
+    if flag("wrapText") {
+        format.set_text_wrap();
+    }
+
+    if flag("shrinkToFit") {
+        format.set_shrink();
+    }
+
+    if let Some(indent) = alignment_element
+        .attribute("indent")
+        .and_then(|indent| indent.parse().ok())
+        .filter(|&indent| indent > 0)
+    {
+        format.set_indent(indent);
+    }
+
+    // Angles above 90° are stored as 90° plus the downward angle, 255 means stacked text.
+    let rotation = match alignment_element
+        .attribute("textRotation")
+        .and_then(|rotation| rotation.parse::<i16>().ok())
+    {
+        Some(255) => Some(270),
+        Some(rotation @ 91..=180) => Some(90 - rotation),
+        Some(rotation @ 1..=90) => Some(rotation),
+        _ => None,
+    };
+
+    if let Some(rotation) = rotation {
+        format.set_rotation(rotation);
+    }
+}
+
+/// The cell formats of the source workbook, by the index cells refer to them with.
+#[derive(Default)]
+pub(crate) struct Styles {
+    formats: Vec<Format>,
+}
+
+impl Styles {
+    pub(crate) fn read<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Self> {
+        let Some(xml) = package::read(archive, package::STYLES)? else {
+            return Ok(Self::default());
+        };
+
+        let root = parse_tree(&xml)?;
+        let Some(style_sheet) = root.child("styleSheet") else {
+            return Ok(Self::default());
+        };
+
+        let list = |name: &str, item: &'static str| {
+            style_sheet
+                .child(name)
+                .map_or(vec![], |list| list.children(item).collect::<Vec<_>>())
+        };
+
+        let num_formats = list("numFmts", "numFmt")
+            .into_iter()
+            .filter_map(|num_format| {
+                let id = num_format.attribute("numFmtId")?.parse::<u32>().ok()?;
+                Some((id, num_format.attribute("formatCode")?))
+            })
+            .chain(BUILTIN_NUM_FORMATS)
+            .fold(BTreeMap::new(), |mut formats, (id, code)| {
+                formats.entry(id).or_insert(code);
+                formats
+            });
+
+        let fonts = list("fonts", "font");
+        let fills = list("fills", "fill");
+        let borders = list("borders", "border");
+
+        let formats = list("cellXfs", "xf")
+            .into_iter()
+            .map(|xf| {
+                let mut format = Format::new();
+
+                if let Some(code) = xf
+                    .attribute("numFmtId")
+                    .and_then(|id| num_formats.get(&id.parse().ok()?))
+                    .filter(|&&code| code != "General")
+                {
+                    format.set_num_format(code);
+                }
+
+                if let Some(font) = xf.lookup("fontId", &fonts) {
+                    apply_font(&mut format, font);
+                }
+
+                if let Some(fill) = xf.lookup("fillId", &fills) {
+                    apply_fill(&mut format, fill);
+                }
+
+                if let Some(border) = xf.lookup("borderId", &borders) {
+                    apply_border(&mut format, border);
+                }
+
+                if let Some(alignment) = xf.child("alignment") {
+                    apply_alignment(&mut format, alignment);
+                }
+
+                if let Some(protection) = xf.child("protection") {
+                    if protection.attribute("locked") == Some("0") {
+                        format.set_unlocked();
+                    }
+
+                    if protection.attribute("hidden") == Some("1") {
+                        format.set_hidden();
+                    }
+                }
+
+                format
+            })
+            .collect();
+
+        Ok(Self { formats })
+    }
+
+    fn get(&self, index: usize) -> Option<&Format> {
+        self.formats.get(index)
+    }
+}
+
+/// The shared strings of the source made of more than one run, for `--rich-text`.
+#[derive(Default)]
+pub(crate) struct RichText {
+    strings: HashMap<usize, Vec<Run>>,
+}
+
+impl RichText {
+    pub(crate) fn read<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Self> {
+        let Some(xml) = package::read(archive, package::SHARED_STRINGS)? else {
+            return Ok(Self::default());
+        };
+
+        let root = parse_tree(&xml)?;
+        let Some(shared_strings) = root.child("sst") else {
+            return Ok(Self::default());
+        };
+
+        let strings = shared_strings
+            .children("si")
+            .enumerate()
+            .filter_map(|(i, string)| {
+                let runs = string
+                    .children("r")
+                    .map(|run| Run {
+                        text: run.child("t").map_or(String::new(), |t| t.text.clone()),
+                        format: run.child("rPr").map(|properties| {
+                            let mut format = Format::new();
+                            apply_font(&mut format, properties);
+
+                            // Runs name their font differently from the fonts of the styles.
+                            if let Some(name) =
+                                properties.child("rFont").and_then(|f| f.attribute("val"))
+                            {
+                                format.set_font_name(name);
+                            }
+
+                            format
+                        }),
+                    })
+                    .collect::<Vec<_>>();
+
+                (runs.len() > 1).then_some((i, runs))
+            })
+            .collect();
+
+        Ok(Self { strings })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+/// Excel stores column widths with the padding around the characters, xlsxwriter adds that
+/// padding itself.
+fn character_width(stored: f64) -> f64 {
+    (stored - 5. / 7.).max(0.)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Column {
+    width: f64,
+    style: Option<usize>,
+}
+
+/// The column widths, row heights, merged regions, cell formats, hyperlinks and list validations
+/// of a source worksheet, at coordinates relative to its range like the values calamine reads.
+#[derive(Debug, Default)]
+pub(crate) struct Geometry {
+    columns: BTreeMap<u16, Column>,
+    /// Row heights in points, zero for hidden rows.
+    rows: BTreeMap<u32, f64>,
+    merged: Vec<((u32, u16), (u32, u16))>,
+    cells: HashMap<(u32, u16), usize>,
+    /// The cells holding strings of the [`RichText`], by their indices.
+    runs: HashMap<(u32, u16), usize>,
+    hyperlinks: BTreeMap<(u32, u16), String>,
+    validations: Vec<ListValidation>,
+}
+
+impl Geometry {
+    pub(crate) fn read<R: Read + Seek>(
+        archive: &mut ZipArchive<R>,
+        part: &str,
+        range: &Range<DataType>,
+    ) -> Result<Self> {
+        let xml = package::read(archive, part)?.wrap_err_with(|| format!("Missing {}", part))?;
+
+        let (first_row, first_column) = range.start().unwrap_or((0, 0));
+        let (height, width) = (range.height() as u32, range.width() as u32);
+        let relative = |row: u32, column: u32| {
+            let row = row.checked_sub(first_row).filter(|&row| row < height)?;
+            let column = column.checked_sub(first_column).filter(|&c| c < width)?;
+            Some((row, column as u16))
+        };
+
+        let mut geometry = Self::default();
+        let mut reader = Reader::from_reader(xml.as_slice());
+        let mut buf = vec![];
+        let mut row = 0;
+        let mut column = None;
+
+        loop {
+            buf.clear();
+
+            let e = match reader.read_event_into(&mut buf)? {
+                Event::Start(e) | Event::Empty(e) => e,
+                Event::Eof => break,
+                _ => continue,
+            };
+
+            let element = e.local_name();
+
+            if !matches!(element.as_ref(), b"col" | b"row" | b"c" | b"mergeCell") {
+                continue;
+            }
+
+            let attributes = package::attribute_map(&e, &reader)?;
+            let number = |name: &str| attributes.get(name)?.parse::<f64>().ok();
+            let hidden = matches!(
+                attributes.get("hidden").map(String::as_str),
+                Some("1" | "true")
+            );
+
+            match element.as_ref() {
+                b"col" => {
+                    let (Some(min), Some(max)) = (number("min"), number("max")) else {
+                        continue;
+                    };
+
+                    let width = if hidden {
+                        0.
+                    } else {
+                        number("width").map_or(LXW_DEF_COL_WIDTH, character_width)
+                    };
+                    let style = attributes.get("style").and_then(|style| style.parse().ok());
+
+                    for c in min as u32..=max as u32 {
+                        if let Some((_, c)) = relative(first_row, c.saturating_sub(1)) {
+                            geometry.columns.insert(c, Column { width, style });
+                        }
+                    }
+                }
+                b"row" => {
+                    if let Some(r) = number("r") {
+                        row = (r as u32).saturating_sub(1);
+                    }
+
+                    column = None;
+
+                    let height = if hidden { Some(0.) } else { number("ht") };
+
+                    if let (Some(height), Some((r, _))) = (height, relative(row, first_column)) {
+                        geometry.rows.insert(r, height);
+                    }
+                }
+                b"c" => {
+                    // The reference can be left out, for the cell right after the previous one.
+                    let (r, c) = match attributes.get("r").and_then(|r| parse_reference(r)) {
+                        Some(reference) => reference,
+                        None => (row, column.map_or(0, |column: u16| column + 1)),
+                    };
+
+                    column = Some(c);
+
+                    let style = attributes.get("s").and_then(|style| style.parse().ok());
+
+                    if let (Some(style), Some(cell)) = (style, relative(r, u32::from(c))) {
+                        geometry.cells.insert(cell, style);
+                    }
+                }
+                _ => {
+                    let corners = attributes
+                        .get("ref")
+                        .and_then(|reference| reference.split_once(':'))
+                        .and_then(|(first, last)| parse_reference(first).zip(parse_reference(last)))
+                        .and_then(|((r1, c1), (r2, c2))| {
+                            relative(r1, u32::from(c1)).zip(relative(r2, u32::from(c2)))
+                        });
+
+                    geometry.merged.extend(corners);
+                }
+            }
+        }
+
+        Ok(geometry)
+    }
+
+    /// Notes which cells of the worksheet part hold strings of the rich text.
+    pub(crate) fn read_runs<R: Read + Seek>(
+        &mut self,
+        archive: &mut ZipArchive<R>,
+        part: &str,
+        range: &Range<DataType>,
+        rich_text: &RichText,
+    ) -> Result<()> {
+        let worksheet =
+            package::read(archive, part)?.wrap_err_with(|| format!("Missing {}", part))?;
+        let (first_row, first_column) = range.start().unwrap_or((0, 0));
+
+        self.runs = package::shared_string_cells(&worksheet)?
+            .into_iter()
+            .filter(|(_, index)| rich_text.strings.contains_key(index))
+            .filter_map(|((row, column), index)| {
+                let row = row.checked_sub(first_row)?;
+                let column = u32::from(column).checked_sub(first_column)?;
+                Some(((row, column as u16), index))
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    /// Reads the hyperlinks of the worksheet part, to be attached to the same cells.
+    pub(crate) fn read_hyperlinks<R: Read + Seek>(
+        &mut self,
+        archive: &mut ZipArchive<R>,
+        part: &str,
+        range: &Range<DataType>,
+    ) -> Result<()> {
+        let (first_row, first_column) = range.start().unwrap_or((0, 0));
+
+        self.hyperlinks = package::hyperlinks(archive, part)?
+            .into_iter()
+            .filter_map(|((row, column), url)| {
+                let row = row.checked_sub(first_row)?;
+                let column = u32::from(column).checked_sub(first_column)?;
+                Some(((row, column as u16), url))
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    /// Reads the list validations of the worksheet part, with the parts of their ranges that
+    /// are within the range of the cells.
+    pub(crate) fn read_validations<R: Read + Seek>(
+        &mut self,
+        archive: &mut ZipArchive<R>,
+        part: &str,
+        range: &Range<DataType>,
+    ) -> Result<()> {
+        let (first_row, first_column) = range.start().unwrap_or((0, 0));
+        let relative = |(row, column): (u32, u16)| {
+            let row = row.checked_sub(first_row)?;
+            let column = u32::from(column).checked_sub(first_column)?;
+            Some((row, column as u16))
+        };
+
+        self.validations = package::list_validations(archive, part)?
+            .into_iter()
+            .map(|mut validation| {
+                validation.ranges = validation
+                    .ranges
+                    .into_iter()
+                    .filter_map(|((r1, c1), last)| {
+                        let first = (r1.max(first_row), c1.max(first_column as u16));
+                        relative(first).zip(relative(last))
+                    })
+                    .collect();
+                validation
+            })
+            .filter(|validation| !validation.ranges.is_empty())
+            .collect();
+
+        Ok(())
+    }
+
+    pub(crate) fn validations(&self) -> &[ListValidation] {
+        &self.validations
+    }
+
+    /// The hyperlinks by their cells, at coordinates relative to the range.
+    pub(crate) fn hyperlinks(&self) -> &BTreeMap<(u32, u16), String> {
+        &self.hyperlinks
+    }
+
+    /// The runs of the cell, if it holds a string of the rich text.
+    pub(crate) fn runs<'a>(&self, rich_text: &'a RichText, cell: (u32, u16)) -> Option<&'a [Run]> {
+        let index = self.runs.get(&cell)?;
+        rich_text.strings.get(index).map(Vec::as_slice)
+    }
+
+    /// The format of the cell, or the default one of its column.
+    pub(crate) fn format<'a>(
+        &self,
+        styles: &'a Styles,
+        (row, column): (u32, u16),
+    ) -> Option<&'a Format> {
+        let style = self
+            .cells
+            .get(&(row, column))
+            .copied()
+            .or_else(|| self.columns.get(&column)?.style)?;
+
+        styles.get(style)
+    }
+
+    /// Sizes the columns and rows of the worksheet like those of the source and merges the same
+    /// regions. Merges are left out when the source text is interleaved, as they would cover it.
+    pub(crate) fn apply(
+        &self,
+        worksheet: &mut Output,
+        styles: &Styles,
+        layout: Layout<'_>,
+    ) -> Result<()> {
+        for (&column, settings) in &self.columns {
+            let format = settings.style.and_then(|style| styles.get(style));
+            let (_, c) = layout.cell(0, column);
+            worksheet.set_column(c, c, settings.width, format)?;
+
+            if let Some((_, c)) = layout.source_cell(0, column) {
+                worksheet.set_column(c, c, settings.width, format)?;
+            }
+
+            let (_, t) = layout.translation_cell(0, column);
+
+            if t != c {
+                worksheet.set_column(t, t, settings.width, format)?;
+            }
+        }
+
+        for (&row, &height) in &self.rows {
+            let (r, _) = layout.cell(row, 0);
+            worksheet.set_row(r, height, None)?;
+        }
+
+        if layout.interleave_source || layout.bilingual.is_some() {
+            return Ok(());
+        }
+
+        for &(first, last) in &self.merged {
+            if first == last {
+                continue;
+            }
+
+            let (r1, c1) = layout.cell(first.0, first.1);
+            let (r2, c2) = layout.cell(last.0, last.1);
+            worksheet.merge_range(r1, c1, r2, c2, "", self.format(styles, first))?;
+        }
+
+        Ok(())
+    }
+}
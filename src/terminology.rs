@@ -0,0 +1,202 @@
+//! Sources translated in different ways across the workbook, for `--terminology-report` and
+//! `--harmonize`. Values are grouped regardless of case, whitespace and trailing punctuation,
+//! but only the cells with exactly the same source are harmonized.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use calamine::{DataType, Range};
+use color_eyre::{eyre::Context, Result};
+use serde::Serialize;
+
+use crate::{dictionary::normalize_whitespace, workbook::column_letter};
+
+/// The methods whose translations are given rather than made up, which harmonizing keeps to.
+const GIVEN: [&str; 4] = ["dictionary", "tmx", "xliff", "reviewed"];
+
+/// A translated cell.
+#[derive(Debug)]
+struct Occurrence {
+    sheet: usize,
+    /// The cell in the source and the one its translation went in.
+    cell: (u32, u16),
+    output: (u32, u16),
+    source: String,
+    translation: String,
+    given: bool,
+}
+
+/// A cell to write again with the translation the others with its source got.
+pub(crate) struct Rewrite<'a> {
+    pub(crate) sheet: usize,
+    pub(crate) cell: (u32, u16),
+    pub(crate) output: (u32, u16),
+    pub(crate) translation: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct Variant<'a> {
+    translation: &'a str,
+    cells: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Group<'a> {
+    source: &'a str,
+    translations: Vec<Variant<'a>>,
+}
+
+/// The form values are grouped by.
+fn normalize(text: &str) -> String {
+    normalize_whitespace(text.trim())
+        .trim_end_matches(|c: char| c.is_ascii_punctuation() && !matches!(c, ')' | ']' | '%'))
+        .trim_end()
+        .to_lowercase()
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Terminology {
+    /// The names of the worksheets by their positions, and the first cells of their ranges.
+    sheets: BTreeMap<usize, (String, (u32, u32))>,
+    groups: BTreeMap<String, Vec<Occurrence>>,
+}
+
+impl Terminology {
+    /// Starts recording the cells of the worksheet at `sheet`.
+    pub(crate) fn add_sheet(&mut self, sheet: usize, name: &str, range: &Range<DataType>) {
+        self.sheets
+            .insert(sheet, (name.to_string(), range.start().unwrap_or((0, 0))));
+    }
+
+    /// Records a cell of the worksheet at `sheet`, translated by `method`.
+    pub(crate) fn record(
+        &mut self,
+        sheet: usize,
+        cell: (u32, u16),
+        output: (u32, u16),
+        source: &str,
+        translation: &str,
+        method: &str,
+    ) {
+        self.groups
+            .entry(normalize(source))
+            .or_default()
+            .push(Occurrence {
+                sheet,
+                cell,
+                output,
+                source: source.trim().to_string(),
+                translation: translation.trim().to_string(),
+                given: GIVEN.contains(&method),
+            });
+    }
+
+    /// Where the cell is in its workbook, like `Sheet1!B7`.
+    fn reference(&self, occurrence: &Occurrence) -> String {
+        let (name, (first_row, first_column)) = &self.sheets[&occurrence.sheet];
+        let (row, column) = occurrence.cell;
+
+        format!(
+            "{}!{}{}",
+            name,
+            column_letter(*first_column as u16 + column),
+            first_row + row + 1
+        )
+    }
+
+    /// The groups whose translations differ by more than case, whitespace and punctuation.
+    fn divergent(&self) -> impl Iterator<Item = (&String, &Vec<Occurrence>)> {
+        self.groups.iter().filter(|(_, occurrences)| {
+            let first = normalize(&occurrences[0].translation);
+            occurrences
+                .iter()
+                .any(|occurrence| normalize(&occurrence.translation) != first)
+        })
+    }
+
+    /// Writes the divergent groups as JSON, with the cells of every translation.
+    pub(crate) fn write(&self, path: &Path) -> Result<usize> {
+        let groups = self
+            .divergent()
+            .map(|(source, occurrences)| {
+                let mut translations: Vec<Variant> = vec![];
+
+                for occurrence in occurrences {
+                    let reference = self.reference(occurrence);
+
+                    match translations
+                        .iter_mut()
+                        .find(|variant| variant.translation == occurrence.translation)
+                    {
+                        Some(variant) => variant.cells.push(reference),
+                        None => translations.push(Variant {
+                            translation: &occurrence.translation,
+                            cells: vec![reference],
+                        }),
+                    }
+                }
+
+                Group {
+                    source,
+                    translations,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        fs::write(path, serde_json::to_string_pretty(&groups)? + "\n")
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(groups.len())
+    }
+
+    /// The cells to give the translation of the others with the same source: the given one if
+    /// there is one, else the one of most cells, else the first.
+    pub(crate) fn harmonize(&self) -> Vec<Rewrite<'_>> {
+        let mut rewrites = vec![];
+
+        for (_, occurrences) in self.divergent() {
+            let mut sources = BTreeMap::<&str, Vec<&Occurrence>>::new();
+
+            for occurrence in occurrences {
+                sources
+                    .entry(&occurrence.source)
+                    .or_default()
+                    .push(occurrence);
+            }
+
+            for occurrences in sources.values() {
+                let count = |translation: &str| {
+                    occurrences
+                        .iter()
+                        .filter(|occurrence| occurrence.translation == translation)
+                        .count()
+                };
+
+                let chosen = occurrences
+                    .iter()
+                    .find(|occurrence| occurrence.given)
+                    .or_else(|| {
+                        occurrences
+                            .iter()
+                            .rev()
+                            .max_by_key(|occurrence| count(&occurrence.translation))
+                    })
+                    .map(|occurrence| occurrence.translation.as_str())
+                    .unwrap_or_default();
+
+                rewrites.extend(
+                    occurrences
+                        .iter()
+                        .filter(|occurrence| occurrence.translation != chosen)
+                        .map(|occurrence| Rewrite {
+                            sheet: occurrence.sheet,
+                            cell: occurrence.cell,
+                            output: occurrence.output,
+                            translation: chosen,
+                        }),
+                );
+            }
+        }
+
+        rewrites
+    }
+}
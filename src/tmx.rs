@@ -0,0 +1,117 @@
+//! TMX translation memories exported from CAT tools, whose exact matches are used like the
+//! entries of the dictionary but never show up in the prompts.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use color_eyre::{eyre::Context, Result};
+use quick_xml::{events::Event, Reader};
+
+use crate::{
+    dictionary::{normalize_whitespace, Dictionary, MatchMode},
+    package::attribute_map,
+    prompt::language_code,
+};
+
+/// Whether the `xml:lang` of a variant is the language given by name or code, with any region
+/// or script ignored, e.g. `en-US` for `English`.
+fn is_language(lang: &str, language: &str) -> bool {
+    let code = language_code(language);
+    let primary = lang.split(['-', '_']).next().unwrap_or(lang);
+
+    primary.eq_ignore_ascii_case(code) || lang.eq_ignore_ascii_case(code)
+}
+
+/// Inline elements holding the codes of the originating format rather than text, like `<b>`.
+const CODES: [&[u8]; 4] = [b"bpt", b"ept", b"ph", b"it"];
+
+/// Reads the translation units of the memory that have variants in both languages.
+pub(crate) fn load(
+    path: &Path,
+    source_lang: &str,
+    target_lang: &str,
+    preserve_whitespace: bool,
+    match_mode: MatchMode,
+) -> Result<Dictionary> {
+    let xml = fs::read(path)
+        .wrap_err_with(|| format!("Failed to read the translation memory {}", path.display()))?;
+
+    let mut reader = Reader::from_reader(xml.as_slice());
+    let mut buf = vec![];
+    let mut memory = Dictionary::new();
+
+    // The variants of the current unit by language, and the text of the current segment.
+    let mut variants = HashMap::new();
+    let mut lang = None;
+    let mut segment = None::<String>;
+    let mut codes = 0;
+
+    loop {
+        match reader
+            .read_event_into(&mut buf)
+            .wrap_err_with(|| format!("Invalid translation memory {}", path.display()))?
+        {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"tu" => variants.clear(),
+                b"tuv" => {
+                    let mut attributes = attribute_map(&e, &reader)?;
+                    lang = attributes
+                        .remove("xml:lang")
+                        .or_else(|| attributes.remove("lang"));
+                }
+                b"seg" => segment = Some(String::new()),
+                name if CODES.contains(&name) => codes += 1,
+                _ => {}
+            },
+            Event::Text(e) if codes == 0 => {
+                if let Some(segment) = &mut segment {
+                    segment.push_str(&e.unescape()?);
+                }
+            }
+            Event::CData(e) if codes == 0 => {
+                if let Some(segment) = &mut segment {
+                    segment.push_str(&String::from_utf8_lossy(&e));
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"seg" => {
+                    if let (Some(lang), Some(segment)) = (&lang, segment.take()) {
+                        variants.insert(lang.clone(), segment);
+                    }
+                }
+                b"tuv" => lang = None,
+                b"tu" => {
+                    let variant = |language| {
+                        variants
+                            .iter()
+                            .find(|(lang, _)| is_language(lang, language))
+                            .map(|(_, segment)| segment)
+                    };
+
+                    if let (Some(source), Some(translation)) =
+                        (variant(source_lang), variant(target_lang))
+                    {
+                        let key = if preserve_whitespace {
+                            match_mode.key(source.trim())
+                        } else {
+                            match_mode.key(&normalize_whitespace(source))
+                        };
+
+                        if !key.is_empty() && !translation.trim().is_empty() {
+                            memory
+                                .entry(key)
+                                .or_insert_with(|| translation.trim().to_string());
+                        }
+                    }
+                }
+                name if CODES.contains(&name) => codes -= 1,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+
+        buf.clear();
+    }
+
+    Ok(memory)
+}
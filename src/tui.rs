@@ -0,0 +1,224 @@
+//! The terminal UI of `--review`, where the translations of the API are accepted, edited or
+//! rejected before any of them is written.
+
+use std::{
+    io::{self, Stderr},
+    mem,
+};
+
+use color_eyre::Result;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    Frame, Terminal,
+};
+
+const HELP: &str = "a accept · e edit · r reject · A accept the undecided · ↑↓ move · q done";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Decision {
+    /// Not written, like the rejected ones.
+    Undecided,
+    Accepted,
+    /// Accepted as edited into this text.
+    Edited(String),
+    Rejected,
+}
+
+/// A translation to review.
+#[derive(Debug)]
+pub(crate) struct Item {
+    /// Where the first of the cells it is for is, like `Sheet1!B2`.
+    pub(crate) reference: String,
+    pub(crate) source: String,
+    pub(crate) translation: String,
+    pub(crate) decision: Decision,
+}
+
+impl Item {
+    pub(crate) fn new(reference: String, source: String, translation: String) -> Self {
+        Self {
+            reference,
+            source,
+            translation,
+            decision: Decision::Undecided,
+        }
+    }
+
+    /// The translation as it is to be written.
+    fn text(&self) -> &str {
+        match &self.decision {
+            Decision::Edited(text) => text,
+            _ => &self.translation,
+        }
+    }
+}
+
+/// The text on a single line of the list.
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Shows the items until the reviewer is done with them, with their decisions made in place.
+pub(crate) fn review(items: &mut [Item]) -> Result<()> {
+    if items.is_empty() {
+        return Ok(());
+    }
+
+    // On stderr like the progress bar, stdout being for the output of `translate-text`.
+    enable_raw_mode()?;
+    execute!(io::stderr(), EnterAlternateScreen)?;
+
+    let result = Terminal::new(CrosstermBackend::new(io::stderr()))
+        .map_err(Into::into)
+        .and_then(|mut terminal| run(&mut terminal, items));
+
+    execute!(io::stderr(), LeaveAlternateScreen)?;
+    disable_raw_mode()?;
+
+    result
+}
+
+fn run(terminal: &mut Terminal<CrosstermBackend<Stderr>>, items: &mut [Item]) -> Result<()> {
+    let mut list = ListState::default();
+    list.select(Some(0));
+
+    // The text of the selected item while it is being edited.
+    let mut editing = None::<String>;
+
+    loop {
+        terminal.draw(|frame| draw(frame, items, &mut list, editing.as_deref()))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        let selected = list.selected().unwrap_or(0);
+        let next = Some((selected + 1).min(items.len() - 1));
+
+        if let Some(text) = &mut editing {
+            match key.code {
+                KeyCode::Enter => {
+                    items[selected].decision = Decision::Edited(mem::take(text));
+                    editing = None;
+                    list.select(next);
+                }
+                KeyCode::Esc => editing = None,
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Char(c) => text.push(c),
+                _ => {}
+            }
+
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => list.select(Some(selected.saturating_sub(1))),
+            KeyCode::Down | KeyCode::Char('j') => list.select(next),
+            KeyCode::Char('a') => {
+                items[selected].decision = Decision::Accepted;
+                list.select(next);
+            }
+            KeyCode::Char('r') => {
+                items[selected].decision = Decision::Rejected;
+                list.select(next);
+            }
+            KeyCode::Char('e') => editing = Some(items[selected].text().to_string()),
+            KeyCode::Char('A') => {
+                for item in items.iter_mut() {
+                    if item.decision == Decision::Undecided {
+                        item.decision = Decision::Accepted;
+                    }
+                }
+            }
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+fn draw<B: Backend>(
+    frame: &mut Frame<B>,
+    items: &[Item],
+    list: &mut ListState,
+    editing: Option<&str>,
+) {
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(3),
+            Constraint::Length(10),
+            Constraint::Length(1),
+        ])
+        .split(frame.size());
+
+    let decided = items
+        .iter()
+        .filter(|item| item.decision != Decision::Undecided)
+        .count();
+
+    let rows = items
+        .iter()
+        .map(|item| {
+            let (mark, color) = match item.decision {
+                Decision::Undecided => ("·", Color::Reset),
+                Decision::Accepted => ("✓", Color::Green),
+                Decision::Edited(_) => ("✎", Color::Yellow),
+                Decision::Rejected => ("✗", Color::Red),
+            };
+
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{} ", mark), Style::default().fg(color)),
+                Span::raw(format!(
+                    "{}  {} → {}",
+                    item.reference,
+                    one_line(&item.source),
+                    one_line(item.text())
+                )),
+            ]))
+        })
+        .collect::<Vec<_>>();
+
+    let title = format!(" {} translations, {} decided ", items.len(), decided);
+
+    frame.render_stateful_widget(
+        List::new(rows)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+        areas[0],
+        list,
+    );
+
+    let item = &items[list.selected().unwrap_or(0)];
+
+    let (title, translation) = match editing {
+        Some(text) => (
+            " Editing: Enter to keep, Esc to cancel ",
+            format!("{}▏", text),
+        ),
+        None => (" Source and translation ", item.text().to_string()),
+    };
+
+    frame.render_widget(
+        Paragraph::new(format!("{}\n\n{}", item.source, translation))
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .wrap(Wrap { trim: false }),
+        areas[1],
+    );
+
+    frame.render_widget(Paragraph::new(HELP), areas[2]);
+}
@@ -0,0 +1,102 @@
+//! Warning output that can be throttled when the same warning keeps coming up for thousands of
+//! cells.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+use indicatif::ProgressBar;
+use regex::Regex;
+
+/// Prints above the progress bar, suppressing a warning once one of its kind has been printed
+/// `limit` times. Clones share their counts.
+#[derive(Debug, Clone)]
+pub struct DedupLogger {
+    bar: ProgressBar,
+    limit: Option<usize>,
+    /// The numbers and quoted texts left out of the kinds of the warnings.
+    details: Regex,
+    counts: Arc<Mutex<HashMap<String, Occurrences>>>,
+}
+
+#[derive(Debug)]
+struct Occurrences {
+    /// The first of the warnings of the kind.
+    message: String,
+    count: usize,
+}
+
+impl DedupLogger {
+    pub fn new(bar: ProgressBar, limit: Option<usize>) -> Self {
+        Self {
+            bar,
+            limit,
+            details: Regex::new(r#"\d+(?:[.,]\d+)*|'[^']*'|"[^"]*"|“[^”]*”"#).unwrap(),
+            counts: Default::default(),
+        }
+    }
+
+    /// Prints an informational message, which is never suppressed.
+    pub fn println(&self, message: impl AsRef<str>) {
+        self.bar.println(message);
+    }
+
+    /// The kind of a warning: its message up to the details after a colon, with the numbers and
+    /// the quoted texts in it left out, so that `'Foo' is 12 characters long` and `'Bar' is 7
+    /// characters long` are of the same kind.
+    fn kind(&self, message: &str) -> String {
+        let prefix = message.split(": ").next().unwrap_or(message);
+        self.details.replace_all(prefix, "…").into_owned()
+    }
+
+    /// Prints a warning, optionally about a cell. Warnings are told apart by their kind, so the
+    /// same problem in different cells, with different numbers or texts, counts as one.
+    pub fn warn(&self, cell: Option<(u32, u16)>, message: impl Display) {
+        let message = message.to_string();
+
+        // The log gets every one of them.
+        tracing::warn!(?cell, "{}", message);
+
+        let count = {
+            let mut counts = self.counts.lock().unwrap();
+
+            let occurrences = counts
+                .entry(self.kind(&message))
+                .or_insert_with(|| Occurrences {
+                    message: message.clone(),
+                    count: 0,
+                });
+
+            occurrences.count += 1;
+            occurrences.count
+        };
+
+        if self.limit.is_some_and(|limit| count > limit) {
+            return;
+        }
+
+        match cell {
+            Some(cell) => self.bar.println(format!("{:?}: {}", cell, message)),
+            None => self.bar.println(message),
+        }
+    }
+
+    /// Prints how many times each suppressed warning was left out.
+    pub fn summarize(&self) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+
+        let counts = self.counts.lock().unwrap();
+
+        for occurrences in counts.values().filter(|o| o.count > limit) {
+            eprintln!(
+                "Warnings like \"{}\" were suppressed {} more times",
+                occurrences.message,
+                occurrences.count - limit
+            );
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Translating the workbooks dropped into a folder, for `--watch`.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use color_eyre::{
+    eyre::{bail, Context, ContextCompat},
+    Result,
+};
+use notify::{RecursiveMode, Watcher};
+use tokio::{signal, sync::mpsc, time};
+
+/// How long a file has to be left alone before it is translated, since the first events
+/// usually arrive while it is still being written.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Whether the file is a workbook to translate, rather than e.g. the lock file Excel keeps next
+/// to the workbooks it has open.
+fn is_workbook(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("~$") || name.starts_with('.'));
+
+    !hidden
+        && path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("xlsx"))
+}
+
+/// A lock file next to a translation while it is being written, which keeps other watchers of
+/// the same folder from translating the file at the same time.
+struct Lock(PathBuf);
+
+impl Lock {
+    /// `None` if the file is locked already.
+    fn acquire(destination: &Path) -> Result<Option<Self>> {
+        let mut path = destination.as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => Ok(Some(Self(path))),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(None),
+            Err(e) => Err(e).wrap_err_with(|| format!("Failed to create {}", path.display())),
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Translates the workbooks that are added to or changed in `dir` into files of the same names
+/// in `output_dir` with `translate`, one at a time, until interrupted.
+pub(crate) async fn watch<F, T>(dir: &Path, output_dir: &Path, mut translate: F) -> Result<()>
+where
+    F: FnMut(PathBuf, PathBuf) -> T,
+    T: Future<Output = Result<()>>,
+{
+    fs::create_dir_all(output_dir)?;
+
+    // The translations would be picked up as changes themselves.
+    if fs::canonicalize(dir)? == fs::canonicalize(output_dir)? {
+        bail!("The output directory can't be the watched one");
+    }
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })?;
+
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .wrap_err_with(|| format!("Failed to watch {}", dir.display()))?;
+
+    eprintln!(
+        "Watching {} for workbooks to translate into {}",
+        dir.display(),
+        output_dir.display()
+    );
+
+    let mut changed = BTreeMap::new();
+
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let event: notify::Event = event.wrap_err("The watcher stopped")??;
+
+                if event.kind.is_create() || event.kind.is_modify() {
+                    for path in event.paths.into_iter().filter(|path| is_workbook(path)) {
+                        changed.insert(path, Instant::now());
+                    }
+                }
+            }
+            _ = time::sleep(DEBOUNCE), if !changed.is_empty() => {}
+            _ = signal::ctrl_c() => return Ok(()),
+        }
+
+        let settled = changed
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= DEBOUNCE)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+
+        for path in settled {
+            changed.remove(&path);
+
+            let Some(name) = path.file_name().filter(|_| path.exists()) else {
+                continue;
+            };
+
+            let destination = output_dir.join(name);
+
+            let Some(_lock) = Lock::acquire(&destination)? else {
+                eprintln!("{} is being translated already", path.display());
+                continue;
+            };
+
+            eprintln!("Translating {}", path.display());
+
+            match translate(path.clone(), destination.clone()).await {
+                Ok(()) => eprintln!("Wrote {}", destination.display()),
+                Err(e) => eprintln!("Failed to translate {}: {:#}", path.display(), e),
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! The whitespace of cell values that the translation would lose: the line breaks inside them,
+//! which `--preserve-line-breaks` keeps through the normalization, and the whitespace around
+//! them, put back around their translations.
+
+use std::borrow::Cow;
+
+/// Collapses the runs of whitespace within every line to a single space, keeping the line
+/// breaks, written as `\n`.
+pub(crate) fn normalize_lines(text: &str) -> String {
+    text.trim()
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The whitespace a cell value starts and ends with, and how its lines are broken.
+#[derive(Debug, Default)]
+pub(crate) struct Envelope {
+    leading: String,
+    trailing: String,
+    crlf: bool,
+}
+
+impl Envelope {
+    pub(crate) fn of(value: &str) -> Self {
+        let text = value.trim();
+
+        if text.is_empty() {
+            return Self::default();
+        }
+
+        Self {
+            leading: value[..value.len() - value.trim_start().len()].to_string(),
+            trailing: value[value.trim_end().len()..].to_string(),
+            crlf: text.contains("\r\n"),
+        }
+    }
+
+    /// The translation with the whitespace of the value around it instead of its own, and its
+    /// line breaks written like those of the value. Empty translations stay empty.
+    pub(crate) fn restore<'a>(&self, translation: &'a str) -> Cow<'a, str> {
+        if self.leading.is_empty() && self.trailing.is_empty() && !self.crlf {
+            return Cow::Borrowed(translation);
+        }
+
+        let text = translation.trim();
+
+        if text.is_empty() {
+            return Cow::Borrowed(text);
+        }
+
+        let text = match self.crlf {
+            true => Cow::Owned(text.replace("\r\n", "\n").replace('\n', "\r\n")),
+            false => Cow::Borrowed(text),
+        };
+
+        Cow::Owned(format!("{}{}{}", self.leading, text, self.trailing))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_lines_collapses_spaces_and_keeps_line_breaks() {
+        assert_eq!(
+            normalize_lines("  Total \t general\r\n  per   month  "),
+            "Total general\nper month"
+        );
+    }
+
+    #[test]
+    fn restore_puts_the_whitespace_of_the_value_around_the_translation() {
+        let envelope = Envelope::of("  Total\t");
+
+        assert_eq!(envelope.restore(" Gesamt "), "  Gesamt\t");
+    }
+
+    #[test]
+    fn restore_writes_line_breaks_like_the_value() {
+        let envelope = Envelope::of("Total\r\ngeneral");
+
+        assert_eq!(envelope.restore("Gesamt\nallgemein"), "Gesamt\r\nallgemein");
+        assert_eq!(
+            envelope.restore("Gesamt\r\nallgemein"),
+            "Gesamt\r\nallgemein"
+        );
+    }
+
+    #[test]
+    fn restore_leaves_translations_of_plain_values_alone() {
+        assert_eq!(Envelope::of("Total").restore(" Gesamt"), " Gesamt");
+    }
+
+    #[test]
+    fn empty_translations_stay_empty() {
+        assert_eq!(Envelope::of(" Total ").restore("  "), "");
+    }
+
+    #[test]
+    fn blank_values_have_no_envelope() {
+        assert_eq!(Envelope::of("   ").restore(" Gesamt"), " Gesamt");
+    }
+}
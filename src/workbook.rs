@@ -0,0 +1,1009 @@
+//! Reading the source worksheets and writing the translated ones.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{Read, Seek},
+    mem,
+    path::{Path, PathBuf},
+};
+
+use calamine::{open_workbook, DataType, Range, Reader, Xlsx};
+use chrono::Utc;
+use clap::ValueEnum;
+use color_eyre::{
+    eyre::{bail, Context, ContextCompat},
+    Result,
+};
+use serde::Serialize;
+use uuid::Uuid;
+use xlsxwriter::{
+    format::FormatColor, worksheet::validation::DataValidation, Format, Workbook, Worksheet,
+    XlsxError,
+};
+
+use crate::{
+    package::{self, CellTexts},
+    rich_text::{self, Run},
+    sheets::{self, SheetPattern},
+};
+
+pub(crate) const WORKSHEET: &str = "Worksheet";
+
+pub(crate) const HISTORY_WORKSHEET: &str = "_TranslationHistory";
+
+pub(crate) const PROVENANCE_WORKSHEET: &str = "_Provenance";
+
+/// The hidden worksheet behind `--version-history`, one row per translated cell.
+pub(crate) struct History<'a> {
+    worksheet: Worksheet<'a>,
+    run_id: String,
+    row: u32,
+}
+
+impl<'a> History<'a> {
+    const HEADERS: [&'static str; 8] = [
+        "run_id",
+        "timestamp",
+        "source_text",
+        "translated_text",
+        "model",
+        "method",
+        "tokens_used",
+        "request_id",
+    ];
+
+    pub(crate) fn new(workbook: &'a Workbook) -> Result<Self> {
+        let mut worksheet = workbook.add_worksheet(Some(HISTORY_WORKSHEET))?;
+        worksheet.hide();
+
+        for (column, header) in Self::HEADERS.into_iter().enumerate() {
+            worksheet.write_string(0, column as u16, header, None)?;
+        }
+
+        Ok(Self {
+            worksheet,
+            run_id: Uuid::new_v4().to_string(),
+            row: 1,
+        })
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        source: &str,
+        translation: &str,
+        model: &str,
+        method: &str,
+        tokens: usize,
+        request_id: Option<&str>,
+    ) -> Result<()> {
+        let timestamp = Utc::now().to_rfc3339();
+        let values = [&self.run_id, &timestamp, source, translation, model, method];
+
+        for (column, value) in values.into_iter().enumerate() {
+            self.worksheet
+                .write_string(self.row, column as u16, value, None)?;
+        }
+
+        self.worksheet
+            .write_number(self.row, values.len() as u16, tokens as f64, None)?;
+
+        if let Some(request_id) = request_id {
+            self.worksheet
+                .write_string(self.row, values.len() as u16 + 1, request_id, None)?;
+        }
+
+        self.row += 1;
+
+        Ok(())
+    }
+}
+
+/// The hidden worksheet behind `--provenance`, one row per written cell with where its
+/// translation came from.
+pub(crate) struct Provenance<'a> {
+    worksheet: Worksheet<'a>,
+    row: u32,
+}
+
+impl<'a> Provenance<'a> {
+    const HEADERS: [&'static str; 8] = [
+        "sheet",
+        "cell",
+        "source_text",
+        "translated_text",
+        "origin",
+        "model",
+        "timestamp",
+        "tokens_used",
+    ];
+
+    pub(crate) fn new(workbook: &'a Workbook) -> Result<Self> {
+        let mut worksheet = workbook.add_worksheet(Some(PROVENANCE_WORKSHEET))?;
+        worksheet.hide();
+
+        for (column, header) in Self::HEADERS.into_iter().enumerate() {
+            worksheet.write_string(0, column as u16, header, None)?;
+        }
+
+        Ok(Self { worksheet, row: 1 })
+    }
+
+    /// Records the cell of the source worksheet `sheet`, at the `cell` of its `range`. Only the
+    /// first of the cells sharing a request gets its tokens.
+    pub(crate) fn record(
+        &mut self,
+        (sheet, range, cell): (&str, &Range<DataType>, (u32, u16)),
+        source: &str,
+        translation: &str,
+        origin: &str,
+        model: &str,
+        tokens: usize,
+    ) -> Result<()> {
+        let reference = cell_reference(range, cell);
+        let timestamp = Utc::now().to_rfc3339();
+        let values = [
+            sheet,
+            &reference,
+            source,
+            translation,
+            origin,
+            model,
+            &timestamp,
+        ];
+
+        for (column, value) in values.into_iter().enumerate() {
+            self.worksheet
+                .write_string(self.row, column as u16, value, None)?;
+        }
+
+        self.worksheet
+            .write_number(self.row, values.len() as u16, tokens as f64, None)?;
+
+        self.row += 1;
+
+        Ok(())
+    }
+}
+
+/// Maps cell coordinates of the source worksheet to the destination one.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Layout<'a> {
+    /// Where the range of the source starts in its worksheet, which the translated cells start at
+    /// as well, so that the formulas copied over still point at the same cells.
+    pub(crate) origin: (u32, u16),
+    pub(crate) interleave_source: bool,
+    /// The columns whose translations go in a column of their own to the right of them, for
+    /// `--bilingual`. The other columns move along to make room.
+    pub(crate) bilingual: Option<&'a BTreeSet<u16>>,
+    pub(crate) column_labels: bool,
+    /// The header row the column labels are taken from.
+    pub(crate) label_row: Option<u32>,
+    /// How many rows below its source a translation goes in, for `--pivot-translate`.
+    pub(crate) pivot_offset: u32,
+}
+
+impl Layout<'_> {
+    pub(crate) fn cell(self, row: u32, column: u16) -> (u32, u16) {
+        let row = self.origin.0 + row + u32::from(self.column_labels);
+
+        let column = if self.interleave_source {
+            column * 2
+        } else if let Some(bilingual) = self.bilingual {
+            column + bilingual.range(..column).count() as u16
+        } else {
+            column
+        };
+
+        (row, self.origin.1 + column)
+    }
+
+    fn is_bilingual(self, column: u16) -> bool {
+        self.bilingual
+            .is_some_and(|bilingual| bilingual.contains(&column))
+    }
+
+    /// Where the translation of a cell goes.
+    pub(crate) fn translation_cell(self, row: u32, column: u16) -> (u32, u16) {
+        let (row, translation) = self.cell(row + self.pivot_offset, column);
+        (row, translation + u16::from(self.is_bilingual(column)))
+    }
+
+    /// Where the original text of a cell goes, if it is kept next to the translation.
+    pub(crate) fn source_cell(self, row: u32, column: u16) -> Option<(u32, u16)> {
+        let (row, source) = self.cell(row, column);
+
+        if self.interleave_source {
+            Some((row, source + 1))
+        } else {
+            self.is_bilingual(column).then_some((row, source))
+        }
+    }
+
+    /// Where the label of a column goes, if there is a label row.
+    pub(crate) fn label_cell(self, column: u16) -> Option<(u32, u16)> {
+        self.column_labels
+            .then_some((self.origin.0, self.cell(0, column).1))
+    }
+}
+
+/// Converts a zero-based column index into its letter, e.g. `27` into `AB`.
+pub(crate) fn column_letter(column: u16) -> String {
+    let mut column = u32::from(column) + 1;
+    let mut letter = vec![];
+
+    while column > 0 {
+        column -= 1;
+        letter.push(char::from(b'A' + (column % 26) as u8));
+        column /= 26;
+    }
+
+    letter.into_iter().rev().collect()
+}
+
+/// Where a cell of the range is in its worksheet, like `B7`.
+pub(crate) fn cell_reference(range: &Range<DataType>, (row, column): (u32, u16)) -> String {
+    let (first_row, first_column) = range.start().unwrap_or((0, 0));
+
+    format!(
+        "{}{}",
+        column_letter(first_column as u16 + column),
+        first_row + row + 1
+    )
+}
+
+pub(crate) fn column_label(header: &str) -> String {
+    format!("[{}]", header)
+}
+
+/// A write into a worksheet put off until the rows above it are written.
+type Held<'a> = Box<dyn FnOnce(&mut Worksheet<'a>) -> Result<(), XlsxError>>;
+
+/// A translated worksheet of the destination. With `--stream-rows` the workbook is written in
+/// constant memory, where a row can't be written into anymore once a later one is, so the cells
+/// are held back by their rows until [`Output::flush`] writes those above a block in order.
+pub(crate) struct Output<'w, 'a> {
+    worksheet: &'w mut Worksheet<'a>,
+    held: Option<BTreeMap<u32, Vec<Held<'a>>>>,
+}
+
+impl<'w, 'a> Output<'w, 'a> {
+    pub(crate) fn new(worksheet: &'w mut Worksheet<'a>, streamed: bool) -> Self {
+        Self {
+            worksheet,
+            held: streamed.then(BTreeMap::new),
+        }
+    }
+
+    fn hold(
+        held: &mut BTreeMap<u32, Vec<Held<'a>>>,
+        row: u32,
+        write: impl FnOnce(&mut Worksheet<'a>) -> Result<(), XlsxError> + 'static,
+    ) -> Result<(), XlsxError> {
+        held.entry(row).or_default().push(Box::new(write));
+        Ok(())
+    }
+
+    /// Writes the rows held back above `row`.
+    pub(crate) fn flush(&mut self, row: u32) -> Result<(), XlsxError> {
+        let Some(held) = &mut self.held else {
+            return Ok(());
+        };
+
+        let below = held.split_off(&row);
+
+        for write in mem::replace(held, below).into_values().flatten() {
+            write(self.worksheet)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes all the rows held back, once the worksheet is translated.
+    pub(crate) fn finish(&mut self) -> Result<(), XlsxError> {
+        let held = self.held.take().into_iter().flat_map(BTreeMap::into_values);
+
+        for write in held.flatten() {
+            write(self.worksheet)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn write_string(
+        &mut self,
+        row: u32,
+        column: u16,
+        text: &str,
+        format: Option<&Format>,
+    ) -> Result<(), XlsxError> {
+        let Some(held) = &mut self.held else {
+            return self.worksheet.write_string(row, column, text, format);
+        };
+
+        let (text, format) = (text.to_string(), format.cloned());
+        Self::hold(held, row, move |worksheet| {
+            worksheet.write_string(row, column, &text, format.as_ref())
+        })
+    }
+
+    pub(crate) fn write_rich_string(
+        &mut self,
+        row: u32,
+        column: u16,
+        fragments: &[(&str, Option<&Format>)],
+        format: Option<&Format>,
+    ) -> Result<(), XlsxError> {
+        let Some(held) = &mut self.held else {
+            return self
+                .worksheet
+                .write_rich_string(row, column, fragments, format);
+        };
+
+        let fragments = fragments
+            .iter()
+            .map(|(text, format)| (text.to_string(), format.cloned()))
+            .collect::<Vec<_>>();
+        let format = format.cloned();
+
+        Self::hold(held, row, move |worksheet| {
+            let fragments = fragments
+                .iter()
+                .map(|(text, format)| (text.as_str(), format.as_ref()))
+                .collect::<Vec<_>>();
+            worksheet.write_rich_string(row, column, &fragments, format.as_ref())
+        })
+    }
+
+    pub(crate) fn write_number(
+        &mut self,
+        row: u32,
+        column: u16,
+        number: f64,
+        format: Option<&Format>,
+    ) -> Result<(), XlsxError> {
+        let Some(held) = &mut self.held else {
+            return self.worksheet.write_number(row, column, number, format);
+        };
+
+        let format = format.cloned();
+        Self::hold(held, row, move |worksheet| {
+            worksheet.write_number(row, column, number, format.as_ref())
+        })
+    }
+
+    pub(crate) fn write_boolean(
+        &mut self,
+        row: u32,
+        column: u16,
+        value: bool,
+        format: Option<&Format>,
+    ) -> Result<(), XlsxError> {
+        let Some(held) = &mut self.held else {
+            return self.worksheet.write_boolean(row, column, value, format);
+        };
+
+        let format = format.cloned();
+        Self::hold(held, row, move |worksheet| {
+            worksheet.write_boolean(row, column, value, format.as_ref())
+        })
+    }
+
+    pub(crate) fn write_formula(
+        &mut self,
+        row: u32,
+        column: u16,
+        formula: &str,
+        format: Option<&Format>,
+    ) -> Result<(), XlsxError> {
+        let Some(held) = &mut self.held else {
+            return self.worksheet.write_formula(row, column, formula, format);
+        };
+
+        let (formula, format) = (formula.to_string(), format.cloned());
+        Self::hold(held, row, move |worksheet| {
+            worksheet.write_formula(row, column, &formula, format.as_ref())
+        })
+    }
+
+    pub(crate) fn write_formula_num(
+        &mut self,
+        row: u32,
+        column: u16,
+        formula: &str,
+        format: Option<&Format>,
+        number: f64,
+    ) -> Result<(), XlsxError> {
+        let Some(held) = &mut self.held else {
+            return self
+                .worksheet
+                .write_formula_num(row, column, formula, format, number);
+        };
+
+        let (formula, format) = (formula.to_string(), format.cloned());
+        Self::hold(held, row, move |worksheet| {
+            worksheet.write_formula_num(row, column, &formula, format.as_ref(), number)
+        })
+    }
+
+    pub(crate) fn write_formula_str(
+        &mut self,
+        row: u32,
+        column: u16,
+        formula: &str,
+        format: Option<&Format>,
+        result: &str,
+    ) -> Result<(), XlsxError> {
+        let Some(held) = &mut self.held else {
+            return self
+                .worksheet
+                .write_formula_str(row, column, formula, format, result);
+        };
+
+        let (formula, format, result) = (formula.to_string(), format.cloned(), result.to_string());
+        Self::hold(held, row, move |worksheet| {
+            worksheet.write_formula_str(row, column, &formula, format.as_ref(), &result)
+        })
+    }
+
+    pub(crate) fn write_url(
+        &mut self,
+        row: u32,
+        column: u16,
+        url: &str,
+        format: Option<&Format>,
+    ) -> Result<(), XlsxError> {
+        let Some(held) = &mut self.held else {
+            return self.worksheet.write_url(row, column, url, format);
+        };
+
+        let (url, format) = (url.to_string(), format.cloned());
+        Self::hold(held, row, move |worksheet| {
+            worksheet.write_url(row, column, &url, format.as_ref())
+        })
+    }
+
+    pub(crate) fn write_comment(
+        &mut self,
+        row: u32,
+        column: u16,
+        text: &str,
+    ) -> Result<(), XlsxError> {
+        let Some(held) = &mut self.held else {
+            return self.worksheet.write_comment(row, column, text);
+        };
+
+        let text = text.to_string();
+        Self::hold(held, row, move |worksheet| {
+            worksheet.write_comment(row, column, &text)
+        })
+    }
+
+    pub(crate) fn set_row(
+        &mut self,
+        row: u32,
+        height: f64,
+        format: Option<&Format>,
+    ) -> Result<(), XlsxError> {
+        let Some(held) = &mut self.held else {
+            return self.worksheet.set_row(row, height, format);
+        };
+
+        let format = format.cloned();
+        Self::hold(held, row, move |worksheet| {
+            worksheet.set_row(row, height, format.as_ref())
+        })
+    }
+
+    /// Held back until the last row of the region, whose blank cells it writes.
+    pub(crate) fn merge_range(
+        &mut self,
+        first_row: u32,
+        first_column: u16,
+        last_row: u32,
+        last_column: u16,
+        text: &str,
+        format: Option<&Format>,
+    ) -> Result<(), XlsxError> {
+        let Some(held) = &mut self.held else {
+            return self.worksheet.merge_range(
+                first_row,
+                first_column,
+                last_row,
+                last_column,
+                text,
+                format,
+            );
+        };
+
+        let (text, format) = (text.to_string(), format.cloned());
+        Self::hold(held, last_row, move |worksheet| {
+            worksheet.merge_range(
+                first_row,
+                first_column,
+                last_row,
+                last_column,
+                &text,
+                format.as_ref(),
+            )
+        })
+    }
+
+    pub(crate) fn set_column(
+        &mut self,
+        first_column: u16,
+        last_column: u16,
+        width: f64,
+        format: Option<&Format>,
+    ) -> Result<(), XlsxError> {
+        self.worksheet
+            .set_column(first_column, last_column, width, format)
+    }
+
+    pub(crate) fn set_tab_color(&mut self, color: FormatColor) {
+        self.worksheet.set_tab_color(color);
+    }
+
+    pub(crate) fn data_validation_range(
+        &mut self,
+        first_row: u32,
+        first_column: u16,
+        last_row: u32,
+        last_column: u16,
+        validation: &DataValidation,
+    ) -> Result<(), XlsxError> {
+        self.worksheet.data_validation_range(
+            first_row,
+            first_column,
+            last_row,
+            last_column,
+            validation,
+        )
+    }
+}
+
+/// Writes a translation, rebuilding the runs of the source from its tags if it had any.
+pub(crate) fn write_text(
+    worksheet: &mut Output,
+    (row, column): (u32, u16),
+    text: &str,
+    runs: Option<&[Run]>,
+    format: Option<&Format>,
+) -> Result<()> {
+    let Some(runs) = runs else {
+        worksheet.write_string(row, column, text, format)?;
+        return Ok(());
+    };
+
+    match rich_text::fragments(runs, text) {
+        // xlsxwriter only takes rich strings of two fragments or more.
+        Some(fragments) if fragments.len() > 1 => {
+            let fragments = fragments
+                .iter()
+                .map(|(text, format)| (text.as_str(), *format))
+                .collect::<Vec<_>>();
+            worksheet.write_rich_string(row, column, &fragments, format)?;
+        }
+        _ => worksheet.write_string(row, column, &rich_text::strip_tags(text), format)?,
+    }
+
+    Ok(())
+}
+
+/// Labels the column after its header if the cell is the labelling header and there is a label
+/// row.
+pub(crate) fn write_label(
+    worksheet: &mut Output,
+    layout: Layout<'_>,
+    (row, column): (u32, u16),
+    header: &str,
+    format: &Format,
+) -> Result<()> {
+    if let Some((r, c)) = layout
+        .label_cell(column)
+        .filter(|_| layout.label_row == Some(row))
+    {
+        worksheet.write_string(r, c, &column_label(header), Some(format))?;
+    }
+
+    Ok(())
+}
+
+/// The kinds of files that can be translated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileFormat {
+    Xlsx,
+    /// OpenDocument spreadsheets, which can only be read
+    Ods,
+    /// A single worksheet of comma-separated values
+    Csv,
+    /// A single worksheet of tab-separated values
+    Tsv,
+    /// Gettext PO and POT files, a worksheet of their messages
+    Po,
+    /// Word documents, a worksheet of their paragraphs
+    Docx,
+    /// PowerPoint presentations, a worksheet of the paragraphs of their slides and notes
+    Pptx,
+}
+
+impl FileFormat {
+    /// The format going by the extension of the file, xlsx for anything else.
+    pub fn detect(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+
+        match extension.as_deref() {
+            Some("ods") => Self::Ods,
+            Some("csv") => Self::Csv,
+            Some("tsv" | "tab") => Self::Tsv,
+            Some("po" | "pot") => Self::Po,
+            Some("docx") => Self::Docx,
+            Some("pptx") => Self::Pptx,
+            _ => Self::Xlsx,
+        }
+    }
+
+    pub(crate) fn is_delimited(self) -> bool {
+        matches!(self, Self::Csv | Self::Tsv)
+    }
+
+    /// Whether the format is written from the first worksheet of a translated xlsx workbook.
+    pub(crate) fn is_converted(self) -> bool {
+        self.is_delimited() || self.is_filled_in()
+    }
+
+    /// Whether the format is written by filling in a source of the same format, not from the
+    /// values of a workbook.
+    pub(crate) fn is_filled_in(self) -> bool {
+        matches!(self, Self::Po | Self::Docx | Self::Pptx)
+    }
+}
+
+/// A worksheet of the source, read into memory.
+#[derive(Clone)]
+pub struct Sheet {
+    pub name: String,
+    pub range: Range<DataType>,
+    pub formulas: Range<String>,
+    pub tab_color: Option<u32>,
+    /// The notes and comments of the cells by their positions, read for `--translate-comments`.
+    pub comments: BTreeMap<(u32, u16), String>,
+}
+
+/// Reads the values and formulas of the selected worksheets, of any workbook calamine reads.
+pub fn read_worksheets<R, W>(
+    mut workbook: W,
+    patterns: &[SheetPattern],
+    all: bool,
+) -> Result<Vec<Sheet>>
+where
+    R: Read + Seek,
+    W: Reader<R>,
+    W::Error: std::error::Error + Send + Sync + 'static,
+{
+    let names = sheets::select(workbook.sheet_names(), patterns, all)?;
+
+    names
+        .into_iter()
+        .map(|name| {
+            let range = workbook
+                .worksheet_range(&name)
+                .wrap_err_with(|| format!("No worksheet named '{}'", name))??;
+
+            let formulas = workbook
+                .worksheet_formula(&name)
+                .wrap_err_with(|| format!("No worksheet named '{}'", name))??;
+
+            Ok(Sheet {
+                name,
+                range,
+                formulas,
+                tab_color: None,
+                comments: BTreeMap::new(),
+            })
+        })
+        .collect()
+}
+
+/// Reads the values and formulas of a worksheet by its name.
+type ReadSheet = Box<dyn FnMut(&str) -> Result<(Range<DataType>, Range<String>)>>;
+
+/// The worksheets of a workbook that are only read once they are translated, for `--stream-rows`.
+/// calamine reads a worksheet as a whole, so the one being translated is still held in memory at
+/// once.
+pub(crate) struct Unread {
+    read: ReadSheet,
+    formulas: bool,
+}
+
+impl Unread {
+    /// Leaves the formulas out, for the workbooks xlsx can't take them of.
+    pub(crate) fn without_formulas(self) -> Self {
+        Self {
+            formulas: false,
+            ..self
+        }
+    }
+
+    /// Reads the values and formulas of the worksheet.
+    pub(crate) fn read(&mut self, sheet: &Sheet) -> Result<Sheet> {
+        let (range, formulas) = (self.read)(&sheet.name)?;
+
+        Ok(Sheet {
+            range,
+            formulas: if self.formulas {
+                formulas
+            } else {
+                Range::empty()
+            },
+            ..sheet.clone()
+        })
+    }
+}
+
+/// Reads the selected worksheets like [`read_worksheets`], or with `streamed` only their names,
+/// for the returned [`Unread`] to read the rest of them.
+pub(crate) fn select_worksheets<R, W>(
+    mut workbook: W,
+    patterns: &[SheetPattern],
+    all: bool,
+    streamed: bool,
+) -> Result<(Vec<Sheet>, Option<Unread>)>
+where
+    R: Read + Seek,
+    W: Reader<R> + 'static,
+    W::Error: std::error::Error + Send + Sync + 'static,
+{
+    if !streamed {
+        return Ok((read_worksheets(workbook, patterns, all)?, None));
+    }
+
+    let sheets = sheets::select(workbook.sheet_names(), patterns, all)?
+        .into_iter()
+        .map(|name| Sheet {
+            name,
+            range: Range::empty(),
+            formulas: Range::empty(),
+            tab_color: None,
+            comments: BTreeMap::new(),
+        })
+        .collect();
+
+    let read = move |name: &str| {
+        let range = workbook
+            .worksheet_range(name)
+            .wrap_err_with(|| format!("No worksheet named '{}'", name))??;
+
+        let formulas = workbook
+            .worksheet_formula(name)
+            .wrap_err_with(|| format!("No worksheet named '{}'", name))??;
+
+        Ok((range, formulas))
+    };
+
+    let unread = Unread {
+        read: Box::new(read),
+        formulas: true,
+    };
+
+    Ok((sheets, Some(unread)))
+}
+
+/// Number formats for the values xlsxwriter can only write as formatted numbers.
+pub(crate) struct ValueFormats {
+    pub(crate) date: Format,
+    pub(crate) datetime: Format,
+    pub(crate) duration: Format,
+}
+
+impl ValueFormats {
+    pub(crate) fn new() -> Self {
+        let formats = ["yyyy-mm-dd", "yyyy-mm-dd hh:mm:ss", "[h]:mm:ss"].map(|num_format| {
+            let mut format = Format::new();
+            format.set_num_format(num_format);
+            format
+        });
+
+        let [date, datetime, duration] = formats;
+
+        Self {
+            date,
+            datetime,
+            duration,
+        }
+    }
+}
+
+/// Copies a value that doesn't get translated as what it is, rather than as text, in the format
+/// of its source cell if it has one.
+pub(crate) fn write_value(
+    worksheet: &mut Output,
+    (row, column): (u32, u16),
+    data: &DataType,
+    format: Option<&Format>,
+    formats: &ValueFormats,
+) -> Result<()> {
+    match data {
+        DataType::Int(n) => worksheet.write_number(row, column, *n as f64, format)?,
+        DataType::Float(n) => worksheet.write_number(row, column, *n, format)?,
+        DataType::Bool(b) => worksheet.write_boolean(row, column, *b, format)?,
+        // Excel stores dates as the days since its epoch, which is what calamine hands out. The
+        // format of the source cell has the date format it was recognized by.
+        DataType::DateTime(days) if days.fract() == 0. => {
+            let format = format.unwrap_or(&formats.date);
+            worksheet.write_number(row, column, *days, Some(format))?
+        }
+        DataType::DateTime(days) => {
+            let format = format.unwrap_or(&formats.datetime);
+            worksheet.write_number(row, column, *days, Some(format))?
+        }
+        DataType::Duration(days) => {
+            let format = format.unwrap_or(&formats.duration);
+            worksheet.write_number(row, column, *days, Some(format))?
+        }
+        DataType::DateTimeIso(s) | DataType::DurationIso(s) | DataType::String(s) => {
+            worksheet.write_string(row, column, s, format)?
+        }
+        DataType::Error(e) => worksheet.write_string(row, column, &e.to_string(), format)?,
+        DataType::Empty => {}
+    }
+
+    Ok(())
+}
+
+/// Writes a formula along with the value it was last calculated to, so that readers which don't
+/// calculate formulas themselves still show it.
+pub(crate) fn write_formula(
+    worksheet: &mut Output,
+    (row, column): (u32, u16),
+    formula: &str,
+    result: Option<&DataType>,
+    format: Option<&Format>,
+) -> Result<()> {
+    let formula = format!("={}", formula);
+
+    match result {
+        Some(DataType::Int(n)) => {
+            worksheet.write_formula_num(row, column, &formula, format, *n as f64)?
+        }
+        Some(DataType::Float(n) | DataType::DateTime(n) | DataType::Duration(n)) => {
+            worksheet.write_formula_num(row, column, &formula, format, *n)?
+        }
+        Some(DataType::String(s)) => {
+            worksheet.write_formula_str(row, column, &formula, format, s)?
+        }
+        _ => worksheet.write_formula(row, column, &formula, format)?,
+    }
+
+    Ok(())
+}
+
+/// The translations written into a worksheet of the destination, to check it against once it
+/// is closed.
+#[derive(Debug)]
+pub(crate) struct Written {
+    pub(crate) name: String,
+    pub(crate) cells: Vec<(u32, u16)>,
+}
+
+/// Reads the closed destination back and makes sure that every worksheet is there, reaches as
+/// far as its translations and still holds each of them.
+pub(crate) fn verify(path: &Path, written: &[Written]) -> Result<()> {
+    let mut workbook: Xlsx<_> =
+        open_workbook(path).wrap_err_with(|| format!("Failed to read back {}", path.display()))?;
+
+    for Written { name, cells } in written {
+        let range = workbook
+            .worksheet_range(name)
+            .wrap_err_with(|| format!("No worksheet named '{}' in {}", name, path.display()))??;
+
+        let last_row = cells.iter().map(|&(row, _)| row).max();
+        let last_column = cells.iter().map(|&(_, column)| u32::from(column)).max();
+
+        if let (Some(last_row), Some(last_column)) = (last_row, last_column) {
+            let (rows, columns) = range.end().map_or((0, 0), |(r, c)| (r + 1, c + 1));
+
+            if rows <= last_row || columns <= last_column {
+                bail!(
+                    "Worksheet '{}' of {} is {}×{} cells, too small for its translations up to \
+                     {}{}",
+                    name,
+                    path.display(),
+                    rows,
+                    columns,
+                    column_letter(last_column as u16),
+                    last_row + 1
+                );
+            }
+        }
+
+        let found = cells
+            .iter()
+            .filter(|&&(row, column)| {
+                matches!(
+                    range.get_value((row, u32::from(column))),
+                    Some(DataType::String(s)) if !s.is_empty()
+                )
+            })
+            .count();
+
+        if found != cells.len() {
+            bail!(
+                "Worksheet '{}' of {} holds {} of its {} translated cells",
+                name,
+                path.display(),
+                found,
+                cells.len()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the translations back from the closed destination for `--preserve-structure`, by the
+/// positions of their sources, which the `written` cells are at as well.
+pub(crate) fn read_translations(path: &Path, written: &[Written]) -> Result<Vec<CellTexts>> {
+    let mut workbook: Xlsx<_> =
+        open_workbook(path).wrap_err_with(|| format!("Failed to read back {}", path.display()))?;
+
+    let mut translations = vec![];
+
+    for Written { name, cells } in written {
+        let range = workbook
+            .worksheet_range(name)
+            .wrap_err_with(|| format!("No worksheet named '{}' in {}", name, path.display()))??;
+
+        let mut texts = CellTexts::new();
+
+        for &cell in cells {
+            if let Some(DataType::String(text)) = range.get_value((cell.0, u32::from(cell.1))) {
+                texts.insert(cell, text.clone());
+            }
+        }
+
+        translations.push(texts);
+    }
+
+    Ok(translations)
+}
+
+/// Reads the color → label mapping from the first worksheet of a tab color legend workbook.
+pub(crate) fn load_tab_color_legend(path: &Path) -> Result<BTreeMap<u32, String>> {
+    let mut workbook: Xlsx<_> = open_workbook(path)?;
+
+    let range = workbook
+        .worksheet_range_at(0)
+        .wrap_err("The tab color legend has no worksheets")??;
+
+    let mut legend = BTreeMap::new();
+
+    for (i, row) in range.rows().enumerate() {
+        let [DataType::String(color), DataType::String(label), ..] = row else {
+            continue;
+        };
+
+        let Some(color) = package::parse_rgb(color) else {
+            eprintln!("Invalid color at legend row #{}", i + 1);
+            continue;
+        };
+
+        legend.insert(color, label.trim().to_string());
+    }
+
+    Ok(legend)
+}
+
+/// The temporary workbook written before it replaces the file at `path`, for in-place
+/// translations and CSV or TSV destinations.
+pub(crate) fn in_place_path(path: &Path) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .wrap_err("Invalid filename")?;
+
+    Ok(path.with_file_name(format!(".{}.tmp", file_name)))
+}
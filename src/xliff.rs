@@ -0,0 +1,179 @@
+//! XLIFF 2.0 files of the cell texts, for reviewing translations in CAT tools. `extract` writes
+//! the texts of a workbook into one, with a group per worksheet and a unit per cell identified
+//! by its position, and `apply` takes the translated targets back instead of the API's.
+
+use std::{borrow::Cow, collections::BTreeMap, fmt::Write, fs, path::Path};
+
+use calamine::DataType;
+use color_eyre::{
+    eyre::{bail, Context},
+    Result,
+};
+use quick_xml::{
+    escape::escape,
+    events::{BytesStart, Event},
+    Reader,
+};
+
+use crate::{
+    dictionary::normalize_whitespace, package::attribute_map, prompt::language_code,
+    selection::parse_cell, workbook::column_letter, workbook::Sheet,
+};
+
+/// Translations by worksheet name and the position of their cell in it.
+pub(crate) type Targets = BTreeMap<String, BTreeMap<(u32, u16), String>>;
+
+/// What is written for an extraction.
+#[derive(Debug)]
+pub(crate) struct Options<'a> {
+    pub(crate) source_lang: &'a str,
+    pub(crate) target_lang: &'a str,
+    pub(crate) header_rows: u32,
+    pub(crate) translate_headers: bool,
+    pub(crate) preserve_whitespace: bool,
+}
+
+/// Writes the text cells of the worksheets to an XLIFF file and returns how many there were.
+pub(crate) fn extract(
+    path: &Path,
+    original: &Path,
+    sheets: &[Sheet],
+    options: &Options,
+) -> Result<usize> {
+    let mut xml = String::new();
+    let mut units = 0;
+
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        xml,
+        r#"<xliff xmlns="urn:oasis:names:tc:xliff:document:2.0" version="2.0" srcLang="{}" trgLang="{}">"#,
+        escape(language_code(options.source_lang)),
+        escape(language_code(options.target_lang))
+    )?;
+    writeln!(
+        xml,
+        r#"  <file id="f1" original="{}">"#,
+        escape(&original.to_string_lossy())
+    )?;
+
+    for (i, sheet) in sheets.iter().enumerate() {
+        let (first_row, first_column) = sheet.range.start().unwrap_or((0, 0));
+
+        writeln!(
+            xml,
+            r#"    <group id="g{}" name="{}">"#,
+            i + 1,
+            escape(&sheet.name)
+        )?;
+
+        for (row, column, data) in sheet.range.cells() {
+            let DataType::String(value) = data else {
+                continue;
+            };
+
+            if row < options.header_rows as usize && !options.translate_headers {
+                continue;
+            }
+
+            let value = if options.preserve_whitespace {
+                Cow::Borrowed(value.trim())
+            } else {
+                Cow::Owned(normalize_whitespace(value))
+            };
+
+            if value.is_empty() {
+                continue;
+            }
+
+            let id = format!(
+                "{}{}",
+                column_letter(first_column as u16 + column as u16),
+                first_row as usize + row + 1
+            );
+
+            writeln!(xml, r#"      <unit id="{}">"#, id)?;
+            writeln!(xml, "        <segment>")?;
+            writeln!(xml, "          <source>{}</source>", escape(&value))?;
+            writeln!(xml, "        </segment>")?;
+            writeln!(xml, "      </unit>")?;
+            units += 1;
+        }
+
+        writeln!(xml, "    </group>")?;
+    }
+
+    writeln!(xml, "  </file>")?;
+    writeln!(xml, "</xliff>")?;
+
+    fs::write(path, xml).wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(units)
+}
+
+fn attribute(e: &BytesStart, reader: &Reader<&[u8]>, name: &str) -> Result<Option<String>> {
+    Ok(attribute_map(e, reader)?.remove(name))
+}
+
+/// Reads the targets of the units of an XLIFF file written by [`extract`]. Units that weren't
+/// translated are left out.
+pub(crate) fn load(path: &Path) -> Result<Targets> {
+    let xml = fs::read(path).wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+
+    let mut reader = Reader::from_reader(xml.as_slice());
+    let mut buf = vec![];
+    let mut targets = Targets::new();
+
+    let mut sheet = None;
+    let mut unit = None;
+    let mut target = None::<String>;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"group" => sheet = attribute(&e, &reader, "name")?,
+                b"unit" => unit = attribute(&e, &reader, "id")?,
+                b"target" => target = Some(String::new()),
+                _ => {}
+            },
+            Event::Text(e) => {
+                if let Some(target) = &mut target {
+                    target.push_str(&e.unescape()?);
+                }
+            }
+            Event::CData(e) => {
+                if let Some(target) = &mut target {
+                    target.push_str(&String::from_utf8_lossy(&e));
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"group" => sheet = None,
+                b"unit" => unit = None,
+                b"target" => {
+                    let text = target.take().unwrap_or_default();
+
+                    let (Some(sheet), Some(unit)) = (&sheet, &unit) else {
+                        bail!(
+                            "A target of {} is outside of a worksheet group",
+                            path.display()
+                        );
+                    };
+
+                    let cell = parse_cell(unit).wrap_err_with(|| {
+                        format!("Unit '{}' of {} is not a cell", unit, path.display())
+                    })?;
+
+                    if !text.trim().is_empty() {
+                        targets.entry(sheet.clone()).or_default().insert(cell, text);
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+
+        buf.clear();
+    }
+
+    Ok(targets)
+}
@@ -0,0 +1,45 @@
+//! Runs a whole job offline from the API responses in `tests/fixtures/replay/responses`, saved
+//! the way `--record` does for `source.xlsx` translated into German with `dictionary.txt`.
+
+use std::{env, fs, path::PathBuf, process};
+
+use calamine::{open_workbook, DataType, Reader, Xlsx};
+use xlsx_translator::TranslateJob;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/replay")
+        .join(name)
+}
+
+#[tokio::test]
+async fn translates_a_workbook_from_the_recorded_responses() {
+    let destination = env::temp_dir().join(format!("replay-{}.xlsx", process::id()));
+
+    let mut job = TranslateJob::new(fixture("dictionary.txt"), fixture("source.xlsx"))
+        .unwrap()
+        .destination(&destination)
+        .target_language("German")
+        .api_key("sk-replay")
+        // The default, whatever `OPENAI_BASE_URL` says, for the requests to be those saved.
+        .api_base("https://api.openai.com/v1");
+    job.args_mut().replay = Some(fixture("responses"));
+    job.run().await.unwrap();
+
+    let mut workbook: Xlsx<_> = open_workbook(&destination).unwrap();
+    let range = workbook.worksheet_range("Sheet1").unwrap().unwrap();
+    fs::remove_file(&destination).unwrap();
+
+    let cells = range.rows().map(|row| row[0].clone()).collect::<Vec<_>>();
+
+    assert_eq!(
+        cells,
+        [
+            DataType::String("Phrase".to_string()),
+            DataType::String("Gesamt".to_string()),
+            DataType::String("Guten Morgen".to_string()),
+            DataType::String("Danke".to_string()),
+            DataType::Float(42.0),
+        ]
+    );
+}